@@ -3,28 +3,144 @@ use egui_extras::{Column, TableBuilder};
 use egui_phosphor::regular::MOUSE_LEFT_CLICK;
 use rfd::FileDialog;
 use std::{
-    fs::File,
-    io::{BufReader, Read, Write},
+    collections::HashSet,
     path::{Path, PathBuf},
-    sync::atomic::Ordering,
-    time::Instant,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
-use uuid::Uuid;
-use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+use uuid::{Bytes, Uuid};
 
 use crate::{
-    keyframe::{Keyframe, KeyframeType},
-    sequencer::{Sequencer, SequencerState},
-    settings::{MonitorEdge, Settings, SettingsPage}, util::string_to_keys,
+    console::Console,
+    file,
+    import,
+    input::{self, InputBackendKind},
+    keyframe::{kind_name, CounterAction, Keyframe, KeyframeType},
+    layout::{self, KeyboardLayout},
+    plugin,
+    remote::{self, EventHub, RemoteCommand, RemoteHandle, RemoteReply, RemoteRequest, RemoteStatus},
+    sequencer::{PlaybackOutcome, Sequencer, SequencerState},
+    settings::{default_keybinds, KeybindType, InsertAt, KeyframeTemplate, MonitorEdge, OffsetProfile, Settings, SettingsPage, PANIC_KEYS}, theme::ThemeMode, util::{char_to_keys, display_scale, monitor_names, refresh_monitors, set_calibration, set_capture_backend, set_screenshot_quality, string_to_keys, CaptureBackendKind, PngCompression, SCREENSHOT_SCALES},
 };
 
+/// Most steps listed when some steps of an imported script couldn't be imported
+const MAX_LISTED_STEPS: usize = 10;
+/// Smallest size of the editor window, the same as the minimum set in main
+const EDITOR_MIN_SIZE: egui::Vec2 = egui::vec2(740.0, 407.0);
+/// Size of the window while it is the mini player
+const MINI_PLAYER_SIZE: egui::Vec2 = egui::vec2(300.0, 64.0);
+
 /// Determines the outcome of closing the "Save" dialog
+#[derive(Clone, Copy)]
 pub enum DialogPurpose{
     Close,
     Open,
     New, 
 }
 
+/// Options given on the command line when launching Automate
+#[derive(Default)]
+pub struct LaunchOptions {
+    /// File to open instead of the last opened file
+    pub file: Option<PathBuf>,
+    /// Start playing as soon as the file has loaded, then exit once playback ends
+    pub play: bool,
+    /// Where to write a JSON summary of the playback started by `play`
+    pub result_file: Option<PathBuf>,
+    /// Exit code of the process, set when playback started by `play` ends
+    pub exit_code: Arc<AtomicI32>,
+    /// Serve JSON-RPC on stdin and stdout instead of opening the editor
+    pub serve_stdio: bool,
+}
+
+/// Exit code when playing on launch reaches the end
+const EXIT_SUCCESS: i32 = 0;
+/// Exit code when the file to play on launch could not be loaded
+const EXIT_LOAD_FAILED: i32 = 1;
+/// Exit code when playing on launch is stopped by fail detection
+const EXIT_FAIL_DETECTED: i32 = 2;
+/// Exit code when playing on launch is stopped by the user
+const EXIT_USER_ABORT: i32 = 3;
+/// Exit code when playing on launch is stopped by an abort keyframe
+const EXIT_ABORT_KEYFRAME: i32 = 4;
+/// Exit code when playing on launch is stopped by input the system refused to simulate
+const EXIT_SIMULATION_FAILED: i32 = 5;
+/// Exit code when playing on launch doesn't start because of errors in the sequence
+const EXIT_INVALID: i32 = 6;
+/// Exit code when playing on launch stops because playback couldn't go on, e.g. a jump to a missing label
+const EXIT_FAILED: i32 = 7;
+
+/// Playback started by "--play"
+struct PlayOnLaunch {
+    result_file: Option<PathBuf>,
+    exit_code: Arc<AtomicI32>,
+    /// When playback started, `None` until the file has loaded
+    started: Option<Instant>,
+}
+
+/// Summary of a playback started by "--play", written to the "--result" file
+#[derive(serde::Serialize)]
+struct PlaybackResult {
+    file: String,
+    outcome: &'static str,
+    exit_code: i32,
+    /// Seconds spent playing
+    duration: f32,
+    message: String,
+    /// Lines added by log keyframes
+    log: Vec<String>,
+}
+
+impl LaunchOptions {
+    /// Parses the command line arguments (without the program name), e.g. `automate path/to/file.auto --play --result result.json`
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let mut options = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--play" => options.play = true,
+                "--serve-stdio" => options.serve_stdio = true,
+                "--result" => match args.next() {
+                    Some(path) => options.result_file = Some(PathBuf::from(path)),
+                    None => log::warn!("Missing path after --result"),
+                },
+                _ if arg.starts_with("--") => log::warn!("Unknown argument: {:?}", arg),
+                _ => options.file = Some(PathBuf::from(arg)),
+            }
+        }
+        options
+    }
+}
+
+/// What the file thread is doing
+#[derive(PartialEq)]
+enum FileTaskKind {
+    Save,
+    Load,
+    Export,
+}
+
+/// Outcome of a save, load or export run on the file thread
+enum FileTaskResult {
+    Saved(Result<(), String>),
+    Loaded(Result<file::LoadedFile, String>),
+    Exported(Result<(), String>),
+}
+
+/// A save, load or export running on the file thread
+struct FileTask {
+    path: PathBuf,
+    kind: FileTaskKind,
+    /// Screenshots written by the save, they are unchanged once it finishes
+    saved_images: HashSet<Bytes>,
+    progress: Arc<file::Progress>,
+    receiver: Receiver<FileTaskResult>,
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)]
 pub struct App {
@@ -33,6 +149,7 @@ pub struct App {
     #[serde(skip)]
     last_instant: Instant,
     file: String,
+    last_directory: Option<PathBuf>,
     #[serde(skip)]
     file_uptodate: bool,
     #[serde(skip)]
@@ -42,6 +159,42 @@ pub struct App {
     #[serde(skip)]
     // weird name, basically determines whether the save before exiting dialog closes the window or creates a new file
     dialog_purpose: DialogPurpose,
+    #[serde(skip)]
+    file_task: Option<FileTask>,
+    #[serde(skip)]
+    pending_load: Option<PathBuf>,
+    #[serde(skip)]
+    // what to do once the running save has finished successfully
+    after_save: Option<DialogPurpose>,
+    #[serde(skip)]
+    status: Option<(String, Instant)>,
+    #[serde(skip)]
+    last_autosave: Instant,
+    #[serde(skip)]
+    // autosaves newer than their source file, offered for recovery on startup
+    recoverable_autosaves: Vec<(PathBuf, String)>,
+    #[serde(skip)]
+    // source file of the autosave being recovered
+    recover_to: Option<String>,
+    #[serde(skip)]
+    // source file of the last autosave, which may have been renamed since by saving
+    autosaved: Option<String>,
+    #[serde(skip)]
+    play_on_launch: Option<PlayOnLaunch>,
+    #[serde(skip)]
+    // commands from remote and local control
+    remote: Option<Receiver<RemoteRequest>>,
+    #[serde(skip)]
+    // playback events are passed on to remote control through this
+    events: EventHub,
+    #[serde(skip)]
+    // token remote control requests have to carry this session, if remote control is on
+    remote_token: Option<String>,
+    #[serde(skip)]
+    console: Console,
+    #[serde(skip)]
+    // size of the editor to go back to, while it is replaced by the mini player
+    mini_player: Option<egui::Vec2>,
     settings: Settings,
 }
 
@@ -51,10 +204,25 @@ impl Default for App {
             sequencer: Sequencer::new(),
             last_instant: Instant::now(),
             file: "untitled.auto".to_string(),
+            last_directory: None,
             file_uptodate: true,
             allowed_to_close: false,
             show_save_dialog: false,
             dialog_purpose: DialogPurpose::Close,
+            file_task: None,
+            pending_load: None,
+            after_save: None,
+            status: None,
+            last_autosave: Instant::now(),
+            recoverable_autosaves: vec![],
+            recover_to: None,
+            autosaved: None,
+            play_on_launch: None,
+            remote: None,
+            events: EventHub::default(),
+            remote_token: None,
+            console: Console::default(),
+            mini_player: None,
             settings: Settings::default(),
         }
     }
@@ -62,7 +230,7 @@ impl Default for App {
 
 impl App {
     /// Called once before the first frame.
-    pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    pub fn new(cc: &eframe::CreationContext<'_>, launch: LaunchOptions) -> Self {
 
         // Add Phosphor icons to fonts
         let mut fonts = egui::FontDefinitions::default();
@@ -70,10 +238,59 @@ impl App {
         cc.egui_ctx.set_fonts(fonts);
 
         // Load previous app state if any
-        if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+        let mut app: Self = match cc.storage {
+            Some(storage) => eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default(),
+            None => Default::default(),
+        };
+        app.recoverable_autosaves = file::recoverable_autosaves(&app.settings.autosave_directory);
+        input::set_backend(app.settings.input_backend);
+        input::set_retries(app.settings.simulation_retries);
+        set_capture_backend(app.settings.capture_backend);
+        set_screenshot_quality(app.settings.screenshot_quality);
+        layout::set_layout(app.settings.keyboard_layout);
+        app.settings.theme.apply(&cc.egui_ctx);
+        app.sequencer.set_failsafe_monitor(app.sequencer.failsafe_monitor.clone());
+        app.sequencer.set_panic_key(app.settings.panic_key);
+        app.sequencer.set_global_keys(&app.settings);
+        set_calibration(app.settings.monitor_calibration.clone());
+        refresh_monitors();
+        log::info!("Display scale: {}", display_scale());
+        let (sender, receiver) = mpsc::channel();
+        let handle = RemoteHandle::new(sender, cc.egui_ctx.clone());
+        app.remote = Some(receiver);
+        if app.settings.remote_control {
+            let token = remote::session_token();
+            app.remote_token = Some(token.clone());
+            if let Err(err) = remote::start_http_server(app.settings.remote_port, token.clone(), handle.clone()) {
+                log::error!("Failed to start remote control on port {} - {}", app.settings.remote_port, err);
+                app.sequencer.modal = (true, "Failed to start remote control".to_string(), err);
+            }
+            if let Err(err) = remote::start_event_server(app.settings.remote_event_port, token, app.events.clone()) {
+                log::error!("Failed to stream events on port {} - {}", app.settings.remote_event_port, err);
+                app.sequencer.modal = (true, "Failed to start remote control".to_string(), err);
+            }
+        }
+        if app.settings.local_control {
+            if let Err(err) = remote::start_local_server(handle) {
+                log::error!("Failed to start local control - {}", err);
+                app.sequencer.modal = (true, "Failed to start local control".to_string(), err);
+            }
+        }
+        if let Some(path) = launch.file {
+            // Loaded on the first frame like the last opened file
+            app.file = app.picked_path(path).to_str().unwrap().to_string();
         }
-        Default::default()
+        if launch.play {
+            app.play_on_launch = Some(PlayOnLaunch {
+                result_file: launch.result_file,
+                exit_code: launch.exit_code,
+                started: None,
+            });
+            if app.file == "untitled.auto" {
+                app.finish_play_on_launch(&cc.egui_ctx, "load_failed", EXIT_LOAD_FAILED, "Nothing to play, no file was given".to_string());
+            }
+        }
+        app
     }
     /// Safely creates a new file
     ///
@@ -86,8 +303,11 @@ impl App {
             self.sequencer.loaded_file = self.file.clone();
             self.file_uptodate = true;
             self.sequencer.changed.swap(false, Ordering::Relaxed);
-            self.sequencer.keyframes.clear();
-            self.sequencer.keyframe_state.clear();
+            self.sequencer.clear_keyframes();
+            self.sequencer.missing_assets.clear();
+            self.sequencer.saved_images.clear();
+            self.sequencer.images.lock().unwrap().clear();
+            self.sequencer.evict_textures();
             log::info!("New file: {:?}", "untitled.auto");
         } else {
             // offer to save the current file before making a new one
@@ -95,131 +315,624 @@ impl App {
             self.dialog_purpose = DialogPurpose::New;
         }
     }
+    /// Creates a native file dialog for ".auto" files, opened in the last used directory
+    fn file_dialog(&self) -> FileDialog {
+        FileDialog::new()
+            .add_filter("automate", &["auto"])
+            .set_directory(self.last_directory.clone().unwrap_or(PathBuf::from("/")))
+    }
+    /// Remembers the directory of a path picked in a file dialog and returns the path made absolute
+    fn picked_path(&mut self, path: PathBuf) -> PathBuf {
+        let path = if path.is_absolute() {
+            path
+        } else {
+            std::env::current_dir().unwrap_or_default().join(path)
+        };
+        self.last_directory = path.parent().map(Path::to_path_buf);
+        path
+    }
     /// Safely saves the current file
     ///
     /// Overwrites the current file if it already exists otherwise allows the creation of a new file.
-    fn save_file(&mut self) {
+    ///
+    /// Returns `false` if the file was not saved, e.g. the file dialog was cancelled
+    fn save_file(&mut self) -> bool {
         // No need to save if the file is up to date (Just ensure this is accurate)
         if self.file_uptodate {
-            return;
+            return true;
+        }
+        if self.file_task.is_some() {
+            log::warn!("Cannot save while another file is being saved or loaded");
+            return false;
         }
         if self.file == "untitled.auto" {
-            self.file = FileDialog::new()
-                .add_filter("automate", &["auto"])
-                .set_directory("/")
-                .save_file()
-                .unwrap()
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string();
-        }
-
-        if let Ok(state) = bincode::serialize(&self.sequencer.save_to_state()) {
-            // save the current file (if it was "untitled.auto", it has now been replaced)
-            let now = Instant::now();
-            let file = File::create(self.file.clone());
-            if let Ok(file) = file {
-                // write
-                let mut zip = ZipWriter::new(file);
-                let options =
-                    SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
-                zip.start_file("data", options).unwrap();
-                zip.write_all(&state).unwrap();
-
-                let images = self.sequencer.images.lock().unwrap();
-                for uid in images.keys() {
-                    zip.start_file(Uuid::from_bytes_le(*uid).to_string(), options)
-                        .unwrap();
-                    zip.write_all(images.get(uid).unwrap().as_slice()).unwrap();
+            match self.file_dialog().save_file() {
+                Some(path) => {
+                    self.file = self.picked_path(path).to_str().unwrap().to_string();
+                }
+                None => {
+                    log::info!("Save cancelled");
+                    return false;
                 }
-                zip.finish().unwrap();
+            }
+        }
 
-                self.sequencer.loaded_file = self.file.clone();
-                self.file_uptodate = true;
-                self.sequencer.changed.swap(false, Ordering::Relaxed);
-                log::info!("Save file: {:?} - {:?}", self.file, now.elapsed());
-            } else {
-                log::error!("Failed to save {:?}", file);
+        // save the current file (if it was "untitled.auto", it has now been replaced)
+        // Snapshot everything so the file thread never touches the sequencer while it is being edited
+        let path = PathBuf::from(&self.file);
+        let state = self.save_state();
+        let images = self.sequencer.images.lock().unwrap().clone();
+        let unchanged = self.sequencer.saved_images.clone();
+        let backups = self.settings.backup_count;
+        let saved_images = images.keys().copied().collect();
+        let progress = Arc::new(file::Progress::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let shared_path = path.clone();
+        let shared_progress = Arc::clone(&progress);
+        let _ = thread::Builder::new()
+            .name("File Thread".to_owned())
+            .spawn(move || {
+                let now = Instant::now();
+                let result = file::save(&shared_path, &state, &images, &unchanged, backups, &shared_progress);
+                log::info!("Save file: {:?} - {:?}", shared_path, now.elapsed());
+                let _ = sender.send(FileTaskResult::Saved(result));
+            });
+        self.file_task = Some(FileTask {
+            path,
+            kind: FileTaskKind::Save,
+            saved_images,
+            progress,
+            receiver,
+        });
+        // Assume the save succeeds, so any edits made while saving mark the file as changed again
+        self.sequencer.loaded_file = self.file.clone();
+        self.file_uptodate = true;
+        self.sequencer.changed.swap(false, Ordering::Relaxed);
+        true
+    }
+    /// Closes, creates or opens a file once the current file has been saved for the "Save" dialog
+    fn continue_after_save(&mut self, purpose: DialogPurpose, ctx: &egui::Context) {
+        match purpose {
+            DialogPurpose::Close => {
+                self.allowed_to_close = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            DialogPurpose::New => {
+                self.new_file();
+            }
+            DialogPurpose::Open => {
+                self.open_file();
             }
         }
     }
+    /// Autosaves unsaved changes in the background once the autosave interval has passed
+    ///
+    /// The autosave is written to the autosave directory and never replaces the current file
+    fn autosave(&mut self) {
+        if !self.settings.autosave || self.file_uptodate || self.file_task.is_some() {
+            return;
+        }
+        if self.last_autosave.elapsed().as_secs_f32() < self.settings.autosave_interval * 60. {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        let directory = self.settings.autosave_directory.clone();
+        let source = self.file.clone();
+        self.autosaved = Some(source.clone());
+        let state = self.save_state();
+        let images = self.sequencer.images.lock().unwrap().clone();
+        let _ = thread::Builder::new()
+            .name("Autosave Thread".to_owned())
+            .spawn(move || match file::autosave(&directory, &source, &state, &images) {
+                Ok(path) => log::info!("Autosaved {:?} to {:?}", source, path),
+                Err(err) => log::error!("Failed to autosave {:?} - {}", source, err),
+            });
+    }
     /// Saves the current file always asking where and under what name to save it as
     fn save_as(&mut self) {
-        self.file = FileDialog::new()
-            .add_filter("automate", &["auto"])
-            .set_directory("/")
-            .save_file()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
+        if self.file_task.is_some() {
+            log::warn!("Cannot save while another file is being saved or loaded");
+            return;
+        }
+        let Some(path) = self.file_dialog().save_file() else {
+            log::info!("Save as cancelled");
+            return;
+        };
+        self.file = self.picked_path(path).to_str().unwrap().to_string();
         self.file_uptodate = false;
+        // Screenshots can only be copied from the file they were saved to
+        self.sequencer.saved_images.clear();
         self.save_file();
     }
+    /// Exports the current sequence as a standalone executable that plays it without the editor
+    fn export_runnable(&mut self) {
+        if self.file_task.is_some() {
+            log::warn!("Cannot export while another file is being saved or loaded");
+            return;
+        }
+        let mut dialog = FileDialog::new()
+            .set_directory(self.last_directory.clone().unwrap_or(PathBuf::from("/")))
+            .set_file_name(Path::new(&self.file).file_stem().unwrap().to_str().unwrap());
+        if cfg!(windows) {
+            dialog = dialog.add_filter("executable", &["exe"]);
+        }
+        let Some(path) = dialog.save_file() else {
+            log::info!("Export cancelled");
+            return;
+        };
+        let path = self.picked_path(path);
+        if self.sequencer.keyframes.iter().filter_map(|keyframe| keyframe.keyframe_type.target()).any(|target| !Path::new(target).is_file()) {
+            // Targets are embedded from disk, so ones that can't be found, e.g. paths with environment variables, are read when played
+            self.sequencer.modal = (
+                true,
+                "Exporting targets".to_string(),
+                "Some targets could not be found so are not included in the runnable, their images must exist at the same paths on the machine running it.".to_string(),
+            );
+        }
+        let state = self.save_state();
+        let images = self.sequencer.images.lock().unwrap().clone();
+        let progress = Arc::new(file::Progress::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let shared_path = path.clone();
+        let shared_progress = Arc::clone(&progress);
+        let _ = thread::Builder::new()
+            .name("File Thread".to_owned())
+            .spawn(move || {
+                let result = file::export_runnable(&shared_path, &state, &images, &shared_progress);
+                let _ = sender.send(FileTaskResult::Exported(result));
+            });
+        self.file_task = Some(FileTask {
+            path,
+            kind: FileTaskKind::Export,
+            saved_images: HashSet::new(),
+            progress,
+            receiver,
+        });
+    }
+    /// Exports a step by step guide of the sequence as an HTML page or a Markdown document
+    fn export_guide(&mut self) {
+        if self.file_task.is_some() {
+            log::warn!("Cannot export while another file is being saved or loaded");
+            return;
+        }
+        let Some(path) = FileDialog::new()
+            .add_filter("html", &["html"])
+            .add_filter("markdown", &["md"])
+            .set_directory(self.last_directory.clone().unwrap_or(PathBuf::from("/")))
+            .set_file_name(format!("{}.html", Path::new(&self.file).file_stem().unwrap().to_str().unwrap()))
+            .save_file()
+        else {
+            log::info!("Export cancelled");
+            return;
+        };
+        let path = self.picked_path(path);
+        let state = self.save_state();
+        let images = self.sequencer.images.lock().unwrap().clone();
+        let progress = Arc::new(file::Progress::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let shared_path = path.clone();
+        let shared_progress = Arc::clone(&progress);
+        let _ = thread::Builder::new()
+            .name("File Thread".to_owned())
+            .spawn(move || {
+                let result = file::export_guide(&shared_path, &state, &images, &shared_progress);
+                let _ = sender.send(FileTaskResult::Exported(result));
+            });
+        self.file_task = Some(FileTask {
+            path,
+            kind: FileTaskKind::Export,
+            saved_images: HashSet::new(),
+            progress,
+            receiver,
+        });
+    }
+    /// Saves the selected keyframes as an ".autosnippet" file that can be imported into other sequences
+    fn export_snippet(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("snippet", &["autosnippet"])
+            .set_directory(self.last_directory.clone().unwrap_or(PathBuf::from("/")))
+            .set_file_name("selection.autosnippet")
+            .save_file()
+        else {
+            log::info!("Export cancelled");
+            return;
+        };
+        let path = self.picked_path(path);
+        let snippet = self.sequencer.selection_snippet();
+        match file::export_snippet(&path, &snippet) {
+            Ok(()) => log::info!("Exported {} keyframes to {:?}", snippet.keyframes.len(), path),
+            Err(err) => {
+                log::error!("Failed to export snippet {:?}: {}", path, err);
+                self.sequencer.modal = (true, "Failed to export snippet".to_string(), err);
+            }
+        }
+    }
+    /// Inserts the keyframes of an ".autosnippet" file at the playhead
+    fn import_snippet(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("snippet", &["autosnippet"])
+            .set_directory(self.last_directory.clone().unwrap_or(PathBuf::from("/")))
+            .pick_file()
+        else {
+            return;
+        };
+        let path = self.picked_path(path);
+        if let Err(err) = file::import_snippet(&path).and_then(|snippet| self.sequencer.insert_snippet(snippet)) {
+            log::error!("Failed to import snippet {:?}: {}", path, err);
+            self.sequencer.modal = (true, "Failed to import snippet".to_string(), err);
+        }
+    }
+    /// Inserts the keyframes made from a SikuliX script, Selenium IDE project or AutoHotkey script at the playhead, listing the steps that couldn't be imported
+    fn import_script(&mut self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("script", &["py", "side", "ahk"])
+            .add_filter("SikuliX", &["py"])
+            .add_filter("Selenium IDE", &["side"])
+            .add_filter("AutoHotkey", &["ahk"])
+            .set_directory(self.last_directory.clone().unwrap_or(PathBuf::from("/")))
+            .pick_file()
+        else {
+            return;
+        };
+        let path = self.picked_path(path);
+        let imported = match import::script(&path) {
+            Ok(imported) => imported,
+            Err(err) => {
+                log::error!("Failed to import script {:?}: {}", path, err);
+                self.sequencer.modal = (true, "Failed to import script".to_string(), err);
+                return;
+            }
+        };
+        let unsupported = imported.unsupported;
+        if let Err(err) = self.sequencer.insert_snippet(imported.snippet) {
+            log::error!("Failed to import script {:?}: {}", path, err);
+            self.sequencer.modal = (true, "Failed to import script".to_string(), err);
+            return;
+        }
+        if !unsupported.is_empty() {
+            for step in &unsupported {
+                log::warn!("Not imported from {:?}: {}", path, step);
+            }
+            let mut message = format!("{} steps couldn't be imported:\n", unsupported.len());
+            for step in unsupported.iter().take(MAX_LISTED_STEPS) {
+                message.push_str(&format!("\n{}", step));
+            }
+            if unsupported.len() > MAX_LISTED_STEPS {
+                message.push_str(&format!("\n...and {} more, see the log", unsupported.len() - MAX_LISTED_STEPS));
+            }
+            self.sequencer.modal = (true, "Some steps weren't imported".to_string(), message);
+        }
+    }
     /// Open a file using the native file dialog
     fn open_file(&mut self) {
         if self.file_uptodate {
-            FileDialog::new()
-                .add_filter("automate", &["auto"])
-                .set_directory("/")
-                .pick_file()
-                .and_then(|path| {
-                    self.load_file(&path);
-                    Some(())
-                });
+            if let Some(path) = self.file_dialog().pick_file() {
+                let path = self.picked_path(path);
+                self.load_file(&path);
+            }
         } else {
             // offer to save the current file before making a new one
             self.show_save_dialog = true;
             self.dialog_purpose = DialogPurpose::Open;
         }
     }
+    /// Switches between the editor and the mini player, a small window with only the playback controls that stays on top
+    fn toggle_mini_player(&mut self, ctx: &egui::Context) {
+        match self.mini_player.take() {
+            Some(size) => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(EDITOR_MIN_SIZE));
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(size));
+                ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(egui::viewport::WindowLevel::Normal));
+            }
+            None => {
+                let size = ctx.input(|i| i.viewport().inner_rect).map(|rect| rect.size()).unwrap_or(EDITOR_MIN_SIZE);
+                self.mini_player = Some(size);
+                ctx.send_viewport_cmd(egui::ViewportCommand::MinInnerSize(MINI_PLAYER_SIZE));
+                ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(MINI_PLAYER_SIZE));
+                ctx.send_viewport_cmd(egui::ViewportCommand::WindowLevel(egui::viewport::WindowLevel::AlwaysOnTop));
+            }
+        }
+    }
+    /// The sequencer's state along with the panels that are open, to be written to a file
+    fn save_state(&self) -> SequencerState {
+        let mut state = self.sequencer.save_to_state();
+        if let Some(view) = &mut state.view {
+            view.show_console = self.console.show;
+        }
+        state
+    }
     ///Load an ".auto" file from the given path
+    ///
+    /// Files saved with older versions of the format are migrated while loading
     fn load_file(&mut self, path: &PathBuf) {
-        let now = Instant::now();
-        let stream = File::open(path.clone());
-        if let Ok(file) = stream {
-            let reader = BufReader::new(file);
-            let mut zip = ZipArchive::new(reader).unwrap();
-
-            // File of index 0 stores keyframes and general sequencer state
-            let mut state = zip.by_index(0).unwrap();
-            let mut bytes = Vec::new();
-            state.read_to_end(&mut bytes).unwrap();
-            if let Ok(data) = bincode::deserialize::<SequencerState>(bytes.as_slice()) {
-                self.sequencer.load_from_state(data);
+        if let Some(task) = &self.file_task {
+            // Wait for the running save to finish before loading (e.g. "Save" then "Open" in the save dialog)
+            // Loads are ignored since this is called every frame until the load finishes
+            if task.kind == FileTaskKind::Save {
+                self.pending_load = Some(path.clone());
+            }
+            return;
+        }
+        let progress = Arc::new(file::Progress::default());
+        let (sender, receiver) = mpsc::channel();
+
+        let shared_path = path.clone();
+        let shared_progress = Arc::clone(&progress);
+        let _ = thread::Builder::new()
+            .name("File Thread".to_owned())
+            .spawn(move || {
+                let now = Instant::now();
+                let result = file::load(&shared_path, &shared_progress);
+                log::info!("Loaded file: {:?} - {:?}", shared_path, now.elapsed());
+                let _ = sender.send(FileTaskResult::Loaded(result));
+            });
+        self.file_task = Some(FileTask {
+            path: path.clone(),
+            kind: FileTaskKind::Load,
+            saved_images: HashSet::new(),
+            progress,
+            receiver,
+        });
+    }
+    /// Checks whether the running save or load has finished and applies its result
+    fn poll_file_task(&mut self, ctx: &egui::Context) {
+        let result = match &self.file_task {
+            Some(task) => match task.receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return,
+                // The file thread panicked before sending its result
+                Err(TryRecvError::Disconnected) => {
+                    let err = "The file thread stopped unexpectedly".to_string();
+                    match task.kind {
+                        FileTaskKind::Save => FileTaskResult::Saved(Err(err)),
+                        FileTaskKind::Load => FileTaskResult::Loaded(Err(err)),
+                        FileTaskKind::Export => FileTaskResult::Exported(Err(err)),
+                    }
+                }
+            },
+            None => return,
+        };
+        let task = self.file_task.take().unwrap();
+        let path = task.path;
+        match result {
+            FileTaskResult::Saved(Ok(())) => {
+                self.sequencer.saved_images = task.saved_images;
+                // The autosave is older than the file now, as is one made before the file was saved under this path, e.g. while untitled or before "Save As"
+                for source in self.autosaved.take().into_iter().chain([path.to_str().unwrap().to_string()]) {
+                    file::remove_autosave(&file::autosave_path(&self.settings.autosave_directory, &source));
+                }
+                self.status = Some((
+                    format!("Saved {}", path.file_name().unwrap().to_str().unwrap()),
+                    Instant::now(),
+                ));
+                if let Some(purpose) = self.after_save.take() {
+                    self.continue_after_save(purpose, ctx);
+                }
+            }
+            FileTaskResult::Saved(Err(err)) => {
+                log::error!("Failed to save {:?} - {}", path, err);
+                self.sequencer.modal = (true, "Failed to save file".to_string(), err);
+                // The file was assumed saved when the save started
+                self.sequencer.changed.swap(true, Ordering::Relaxed);
+                // Whatever the save was for would lose the unsaved changes
+                self.after_save = None;
+                self.pending_load = None;
+            }
+            FileTaskResult::Loaded(Ok(loaded)) => {
+                if let Some(view) = &loaded.state.view {
+                    self.console.show = view.show_console;
+                }
+                self.sequencer.load_from_state(loaded.state);
+                self.sequencer.saved_images = loaded.images.keys().copied().collect();
+                *self.sequencer.images.lock().unwrap() = loaded.images;
                 self.file = path.to_str().unwrap().to_string();
                 self.sequencer.loaded_file = self.file.clone();
                 self.file_uptodate = true;
-                std::mem::drop(state);
-                // Load images, all other entries (excluding index: 0) are files named the UUID of the keyframe their image refers to
-                for i in 1..zip.len() {
-                    let mut image = zip.by_index(i).unwrap();
-                    let mut bytes = Vec::new();
-                    image.read_to_end(&mut bytes).unwrap();
-                    self.sequencer
-                        .images
-                        .lock()
-                        .unwrap()
-                        .insert(Uuid::parse_str(image.name()).unwrap().to_bytes_le(), bytes);
+                self.sequencer.changed.swap(false, Ordering::Relaxed);
+                if let Some(source) = self.recover_to.take() {
+                    // Recovered changes belong to the source file and still need saving there
+                    self.file = source;
+                    self.sequencer.loaded_file = self.file.clone();
+                    self.sequencer.saved_images.clear();
+                    self.sequencer.changed.swap(true, Ordering::Relaxed);
                 }
-                log::info!("Loaded file: {:?} - {:?}", path, now.elapsed());
-            } else {
-                self.new_file();
-                log::info!(
-                    "Failed to load file: {:?}, most likely the file was created with an older version of Automate",
-                    path,
+                if !loaded.defaulted.is_empty() {
+                    // Mark the file as changed so saving upgrades it to the current format
+                    self.sequencer.changed.swap(true, Ordering::Relaxed);
+                    log::warn!("Imported legacy file: {:?}, defaulted {:?}", path, loaded.defaulted);
+                    self.sequencer.modal = (
+                        true,
+                        "Imported legacy file".to_string(),
+                        format!(
+                            "This file was created with an older version of Automate. The following fields were missing and set to their defaults: {}.\n\nSave the file to upgrade it.",
+                            loaded.defaulted.join(", ")
+                        ),
+                    );
+                }
+                self.status = Some((
+                    format!("Opened {}", path.file_name().unwrap().to_str().unwrap()),
+                    Instant::now(),
+                ));
+                if let Some(launch) = &mut self.play_on_launch {
+                    if launch.started.is_none() {
+                        launch.started = Some(Instant::now());
+                        self.sequencer.reset_time();
+                        self.sequencer.skip_input_prompt();
+                        self.sequencer.toggle_play();
+                    }
+                }
+            }
+            FileTaskResult::Exported(Ok(())) => {
+                self.status = Some((
+                    format!("Exported {}", path.file_name().unwrap().to_str().unwrap()),
+                    Instant::now(),
+                ));
+            }
+            FileTaskResult::Exported(Err(err)) => {
+                log::error!("Failed to export {:?} - {}", path, err);
+                self.sequencer.modal = (true, "Failed to export".to_string(), err);
+            }
+            FileTaskResult::Loaded(Err(err)) => {
+                self.recover_to = None;
+                self.finish_play_on_launch(ctx, "load_failed", EXIT_LOAD_FAILED, err.clone());
+                log::error!("Failed to load file: {:?} - {}", path, err);
+                self.sequencer.modal = (
+                    true,
+                    "Failed to load file".to_string(),
+                    format!("{:?} could not be opened: {}", path, err),
                 );
+                // Keep whatever was open before, or start a new file if nothing was
+                if self.sequencer.loaded_file.is_empty() {
+                    self.file_uptodate = true;
+                    self.new_file();
+                } else {
+                    self.file = self.sequencer.loaded_file.clone();
+                }
+            }
+        }
+        if let Some(path) = self.pending_load.take() {
+            self.load_file(&path);
+        }
+        self.update_title(ctx);
+    }
+    /// Handles the commands sent by remote control since the last frame
+    fn poll_remote(&mut self) {
+        let Some(receiver) = &self.remote else {
+            return;
+        };
+        let requests: Vec<RemoteRequest> = receiver.try_iter().collect();
+        for request in requests {
+            let reply = match request.command {
+                RemoteCommand::Load(path) => {
+                    if self.file_task.is_some() {
+                        RemoteReply::Error("Another file is being saved or loaded".to_string())
+                    } else if !self.file_uptodate {
+                        RemoteReply::Error("The current file has unsaved changes".to_string())
+                    } else {
+                        let path = if path.is_absolute() {
+                            path
+                        } else {
+                            std::env::current_dir().unwrap_or_default().join(path)
+                        };
+                        self.load_file(&path);
+                        RemoteReply::Done
+                    }
+                }
+                RemoteCommand::Play => {
+                    if self.file_task.is_some() {
+                        RemoteReply::Error("The file is still loading".to_string())
+                    } else {
+                        if !self.sequencer.is_playing() {
+                            self.sequencer.skip_input_prompt();
+                            self.sequencer.toggle_play();
+                        }
+                        RemoteReply::Done
+                    }
+                }
+                RemoteCommand::Pause => {
+                    if self.sequencer.is_playing() {
+                        self.sequencer.toggle_play();
+                    }
+                    RemoteReply::Done
+                }
+                RemoteCommand::Stop => {
+                    if self.sequencer.is_playing() {
+                        self.sequencer.toggle_play();
+                    }
+                    self.sequencer.reset_time();
+                    RemoteReply::Done
+                }
+                RemoteCommand::Status => RemoteReply::Status(self.remote_status()),
+                RemoteCommand::FailureScreenshot => match &self.sequencer.failure_screenshot {
+                    Some(screenshot) => RemoteReply::Screenshot(screenshot.clone()),
+                    None => RemoteReply::Error("Fail detection has not stopped playback".to_string()),
+                },
+            };
+            let _ = request.reply.send(reply);
+        }
+    }
+    /// Current state of the app as reported to remote control
+    fn remote_status(&self) -> RemoteStatus {
+        let (outcome, fail_error, abort_message, simulation_error, failure) = match &self.sequencer.outcome {
+            Some(PlaybackOutcome::Finished) => (Some("finished"), None, None, None, None),
+            Some(PlaybackOutcome::FailDetected(percentage_err)) => (Some("fail_detected"), Some(*percentage_err), None, None, None),
+            Some(PlaybackOutcome::Aborted(message)) => (Some("abort_keyframe"), None, Some(message.clone()), None, None),
+            Some(PlaybackOutcome::SimulationFailed(err)) => (Some("simulation_failed"), None, None, Some(err.clone()), None),
+            Some(PlaybackOutcome::Failed(err)) => (Some("failed"), None, None, None, Some(err.clone())),
+            Some(PlaybackOutcome::Invalid(_)) => (Some("invalid"), None, None, None, None),
+            None => (None, None, None, None, None),
+        };
+        RemoteStatus {
+            file: self.file.clone(),
+            loading: self.file_task.as_ref().is_some_and(|task| task.kind == FileTaskKind::Load),
+            playing: self.sequencer.is_playing(),
+            recording: self.sequencer.recording.load(Ordering::Relaxed),
+            time: self.sequencer.get_time(),
+            outcome,
+            fail_error,
+            abort_message,
+            simulation_error,
+            failure,
+        }
+    }
+    /// Checks whether playback started by "--play" has ended
+    fn poll_play_on_launch(&mut self, ctx: &egui::Context) {
+        let started = self.play_on_launch.as_ref().is_some_and(|launch| launch.started.is_some());
+        if !started || self.sequencer.is_playing() {
+            return;
+        }
+        match self.sequencer.outcome.clone() {
+            Some(PlaybackOutcome::Finished) => {
+                self.finish_play_on_launch(ctx, "finished", EXIT_SUCCESS, "Played to the end".to_string());
+            }
+            Some(PlaybackOutcome::FailDetected(percentage_err)) => {
+                self.finish_play_on_launch(ctx, "fail_detected", EXIT_FAIL_DETECTED, format!("Fail detected: {}% error", percentage_err));
+            }
+            Some(PlaybackOutcome::Aborted(message)) => {
+                self.finish_play_on_launch(ctx, "abort_keyframe", EXIT_ABORT_KEYFRAME, message);
+            }
+            Some(PlaybackOutcome::SimulationFailed(err)) => {
+                self.finish_play_on_launch(ctx, "simulation_failed", EXIT_SIMULATION_FAILED, err);
+            }
+            Some(PlaybackOutcome::Failed(err)) => {
+                self.finish_play_on_launch(ctx, "failed", EXIT_FAILED, err);
+            }
+            Some(PlaybackOutcome::Invalid(errors)) => {
+                self.finish_play_on_launch(ctx, "invalid", EXIT_INVALID, format!("The sequence has {} errors", errors));
+            }
+            None => {
+                self.finish_play_on_launch(ctx, "aborted", EXIT_USER_ABORT, "Stopped by the user".to_string());
+            }
+        }
+    }
+    /// Ends playback started by "--play", writing the result file if one was asked for and closing Automate
+    fn finish_play_on_launch(&mut self, ctx: &egui::Context, outcome: &'static str, exit_code: i32, message: String) {
+        let Some(launch) = self.play_on_launch.take() else {
+            return;
+        };
+        log::info!("Play on launch {}: {}", outcome, message);
+        if let Some(path) = &launch.result_file {
+            let result = PlaybackResult {
+                file: self.file.clone(),
+                outcome,
+                exit_code,
+                duration: launch.started.map_or(0., |started| started.elapsed().as_secs_f32()),
+                message,
+                log: self.sequencer.run_log.clone(),
+            };
+            let written = serde_json::to_string_pretty(&result)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(err) = written {
+                log::error!("Failed to write result file: {:?} - {}", path, err);
             }
-        } else {
-            self.new_file();
-            log::info!(
-                "Failed to load file: {:?} - {:?}",
-                path,
-                stream.err().unwrap()
-            );
         }
+        launch.exit_code.store(exit_code, Ordering::Relaxed);
+        self.allowed_to_close = true;
+        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
     }
     /// Set the title of the window dependant on the current file status
     ///
@@ -235,6 +948,24 @@ impl App {
             saved,
         )));
     }
+    /// Binds the action at `index` to `shortcut`, refusing shortcuts used by another action as only one of them could ever run
+    fn bind_shortcut(&mut self, ctx: &egui::Context, index: usize, shortcut: egui::KeyboardShortcut) {
+        let conflicts = self.settings.conflicts(index, &shortcut);
+        if conflicts.is_empty() {
+            log::info!("Bound {} to {}", self.settings.keybinds[index].text, ctx.format_shortcut(&shortcut));
+            self.settings.keybinds[index].keybind = shortcut;
+            self.settings.keybind_conflict = None;
+            self.sequencer.set_global_keys(&self.settings);
+        } else {
+            let free: Vec<String> = self.settings.free_shortcuts(shortcut.logical_key).iter().map(|free| ctx.format_shortcut(free)).collect();
+            self.settings.keybind_conflict = Some(format!(
+                "{} is already used by {}.{}",
+                ctx.format_shortcut(&shortcut),
+                conflicts.join(", "),
+                if free.is_empty() { String::new() } else { format!(" Try {} instead.", free.join(", ")) },
+            ));
+        }
+    }
 }
 
 impl eframe::App for App {
@@ -244,6 +975,9 @@ impl eframe::App for App {
     }
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_file_task(ctx);
+        self.poll_remote();
+        self.autosave();
         if self.file != self.sequencer.loaded_file && self.file != "untitled.auto" {
             let file = self.file.clone();
             self.load_file(&PathBuf::from(file));
@@ -251,117 +985,104 @@ impl eframe::App for App {
         }
         let mut cancel_close = false;
         ctx.input(|i| {
-            // Make sure that mouse scrolling only zooms/scrolls when sequencer is in focus
+            // Make sure that mouse scrolling and gestures only zoom/scroll when sequencer is in focus
             if !self.show_save_dialog && !self.settings.show && !self.settings.add_keyframe_data.show {
-                self.sequencer.zoom(i.smooth_scroll_delta.x);
-                self.sequencer.scroll(i.smooth_scroll_delta.y);
+                self.sequencer.navigate(i);
             }
             // Todo(addis): check which of the following keybinds should only work when focused on the sequencer, and move them to sequencer.sense() if so
             // Todo(addis): change necessary keybinds to use consume_key instead of key_pressed, for those that should not repeat
-            // Handle keybinds within app with focus
-            if i.modifiers.ctrl {
-                // Keybind(ctrl+s): Save file
-                if i.key_pressed(egui::Key::S) {
-                    self.save_file();
-                }
-                // Keybind(ctrl+n): Create a new file
-                else if i.key_pressed(egui::Key::N) {
-                    self.new_file();
-                }
-                // Keybind(ctrl+o): Open a file
-                else if i.key_pressed(egui::Key::O) {
-                    self.open_file();
-                    println!("opend, setting title");
-                }
-                // Keybind(ctrl+z): Undo last change
-                else if i.key_pressed(egui::Key::Z) {
-                    self.sequencer.undo();
-                }
-                // Keybind(ctrl+y): Redo last change
-                else if i.key_pressed(egui::Key::Y) {
-                    self.sequencer.redo();
-                }
-                // Keybind(ctrl+,): Toggle settings window
-                else if i.key_pressed(egui::Key::Comma) {
-                    self.settings.show = !self.settings.show;
-                }
+            // Handle keybinds within app with focus, as bound in the settings
+            // A shortcut being rebound stops waiting once the settings are closed
+            if !self.settings.show {
+                self.settings.capturing_keybind = None;
+            }
+            // Keybind(ctrl+s): Save file
+            if self.settings.pressed(i, KeybindType::SaveFile) {
+                self.save_file();
+            }
+            // Keybind(ctrl+n): Create a new file
+            else if self.settings.pressed(i, KeybindType::NewFile) {
+                self.new_file();
+            }
+            // Keybind(ctrl+o): Open a file
+            else if self.settings.pressed(i, KeybindType::OpenFile) {
+                self.open_file();
+            }
+            // Keybind(ctrl+z): Undo last change
+            else if self.settings.pressed(i, KeybindType::Undo) {
+                self.sequencer.undo();
+            }
+            // Keybind(ctrl+y): Redo last change
+            else if self.settings.pressed(i, KeybindType::Redo) {
+                self.sequencer.redo();
+            }
+            // Keybind(ctrl+,): Toggle settings window
+            else if self.settings.pressed(i, KeybindType::ToggleSettings) {
+                self.settings.show = !self.settings.show;
+            }
 
-                // Keybind(ctrl+right): Select the next keyframe to the right
-                if i.key_pressed(egui::Key::ArrowRight) {
-                    let mut last_index = 0;
-
-                    if !self.sequencer.keyframe_state.is_empty() {
-                        if let Some(last_uuid) = self.sequencer.selected_keyframes.last() {
-                            let mut next = 0;
-                            for i in 0..self.sequencer.keyframes.len() {
-                                if self.sequencer.keyframes[i].uid == *last_uuid {
-                                    next = i;
-                                    break;
-                                }
-                            }
-                            if self.sequencer.keyframe_state.len() > next + 1 {
-                                last_index = next + 1;
-                            } else {
-                                last_index = next;
-                            }
-                        }
-                        let uid = self.sequencer.keyframes[last_index].uid;
-                        if i.modifiers.shift {
-                            match self.sequencer.selected_keyframes.binary_search(&uid) {
-                                Ok(_) => {}
-                                Err(index) => self.sequencer.selected_keyframes.insert(index, uid),
-                            }
+            // Keybind(ctrl+right): Select the next keyframe to the right
+            if self.settings.pressed(i, KeybindType::NextKeyframe) {
+                let mut last_index = 0;
+
+                if !self.sequencer.keyframes.is_empty() {
+                    if let Some(last_uuid) = self.sequencer.selected_keyframes.last() {
+                        let next = self.sequencer.index_of(last_uuid).unwrap_or(0);
+                        if self.sequencer.keyframes.len() > next + 1 {
+                            last_index = next + 1;
                         } else {
-                            self.sequencer.selected_keyframes = vec![uid];
+                            last_index = next;
                         }
                     }
-                }
-                // Keybind(ctrl+left): Select the next keyframe to the left
-                if i.key_pressed(egui::Key::ArrowLeft) {
-                    let mut last_index = 0;
-                    if !self.sequencer.keyframe_state.is_empty() {
-                        let last_uuid = self.sequencer.selected_keyframes.last().unwrap().clone();
-                        let mut next = 0;
-                        for i in 0..self.sequencer.keyframes.len() {
-                            if self.sequencer.keyframes[i].uid == last_uuid {
-                                next = i;
-                                break;
-                            }
-                        }
-                        if next > last_index {
-                            last_index = next - 1;
-                        } else {
-                            last_index = 0;
-                        }
-                        let uid = self.sequencer.keyframes[last_index].uid;
-                        if i.modifiers.shift {
-                            match self.sequencer.selected_keyframes.binary_search(&uid) {
-                                Ok(_) => {}
-                                Err(index) => self.sequencer.selected_keyframes.insert(index, uid),
-                            }
-                        } else {
-                            self.sequencer.selected_keyframes = vec![uid];
+                    let uid = self.sequencer.keyframes[last_index].uid;
+                    if i.modifiers.shift {
+                        match self.sequencer.selected_keyframes.binary_search(&uid) {
+                            Ok(_) => {}
+                            Err(index) => self.sequencer.selected_keyframes.insert(index, uid),
                         }
+                    } else {
+                        self.sequencer.selected_keyframes = vec![uid];
                     }
                 }
-            } else {
-                // Keybind(right): Step forward 0.1 seconds in time
-                if i.key_pressed(egui::Key::ArrowRight) {
-                    self.sequencer.step_time();
-                }
-                // Keybind(space): Toggle play
-                else if i.key_pressed(egui::Key::Space) {
-                    self.sequencer.toggle_play();
-                }
-                // Keybind(left): Reset the playhead/time to 0 seconds
-                else if i.key_pressed(egui::Key::ArrowLeft) {
-                    self.sequencer.reset_time();
-                }
-                // Keybind(F8): Toggle recording
-                else if i.key_released(egui::Key::F8) {
-                    self.sequencer.toggle_recording();
+            }
+            // Keybind(ctrl+left): Select the next keyframe to the left
+            if self.settings.pressed(i, KeybindType::PreviousKeyframe) {
+                let mut last_index = 0;
+                if !self.sequencer.keyframes.is_empty() {
+                    let last_uuid = self.sequencer.selected_keyframes.last().cloned().unwrap_or(self.sequencer.keyframes[0].uid);
+                    let next = self.sequencer.index_of(&last_uuid).unwrap_or(0);
+                    if next > last_index {
+                        last_index = next - 1;
+                    } else {
+                        last_index = 0;
+                    }
+                    let uid = self.sequencer.keyframes[last_index].uid;
+                    if i.modifiers.shift {
+                        match self.sequencer.selected_keyframes.binary_search(&uid) {
+                            Ok(_) => {}
+                            Err(index) => self.sequencer.selected_keyframes.insert(index, uid),
+                        }
+                    } else {
+                        self.sequencer.selected_keyframes = vec![uid];
+                    }
                 }
             }
+            // Keybind(right): Step forward 0.1 seconds in time
+            if self.settings.pressed(i, KeybindType::StepTime) {
+                self.sequencer.step_time();
+            }
+            // Keybind(space): Toggle play
+            else if self.settings.pressed(i, KeybindType::TogglePlay) {
+                self.sequencer.toggle_play();
+            }
+            // Keybind(left): Reset the playhead/time to 0 seconds
+            else if self.settings.pressed(i, KeybindType::ResetTime) {
+                self.sequencer.reset_time();
+            }
+            // Keybind(F8): Toggle recording
+            else if self.settings.capturing_keybind.is_none() && self.settings.shortcut(KeybindType::ToggleRecording).is_some_and(|shortcut| i.key_released(shortcut.logical_key)) {
+                self.sequencer.toggle_recording();
+            }
 
             if i.viewport().close_requested() && !self.file_uptodate {
                 if !self.allowed_to_close {
@@ -387,20 +1108,16 @@ impl eframe::App for App {
                     ui.label(format!("Do you want to save changes to {:?}{}?", Path::new(&self.file).file_name().unwrap().to_str().unwrap().to_string(),before_saving));
                     ui.horizontal(|ui| {
                         if ui.button("Save").clicked() {
-                            self.save_file();
                             self.show_save_dialog = false;
-                            match self.dialog_purpose {
-                                DialogPurpose::Close => {
-                                    self.allowed_to_close = true;
-                                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
-                                }
-                                DialogPurpose::New => {
-                                    self.new_file();
-                                }
-                                DialogPurpose::Open => {
-                                    self.open_file();
+                            // Cancelling the file dialog cancels whatever the save was for
+                            if self.save_file() {
+                                if self.file_task.is_some() {
+                                    // Continue once the save has finished writing the file
+                                    self.after_save = Some(self.dialog_purpose);
+                                } else {
+                                    self.continue_after_save(self.dialog_purpose, ctx);
                                 }
-                            } 
+                            }
                             self.update_title(ctx);
                         }
                         if ui.button("Don't Save").clicked() {
@@ -431,27 +1148,84 @@ impl eframe::App for App {
                 });
         }
 
-        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            egui::menu::bar(ui, |ui| {
-                ui.menu_button("File", |ui| {
-                    if ui
-                        .add(egui::Button::new("New File...").shortcut_text("Ctrl+N"))
-                        .clicked()
-                    {
-                        self.new_file();
-                        self.update_title(ctx);
-                        ui.close_menu();
-                    }
-                    if ui
-                        .add(egui::Button::new("Open File...").shortcut_text("Ctrl+O"))
-                        .clicked()
-                    {
+        if !self.recoverable_autosaves.is_empty() {
+            egui::Window::new("Recover Autosaves")
+                .resizable(false)
+                .movable(true)
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label("Automate closed with unsaved changes. The following autosaves are newer than their files:");
+                    ui.add_space(6.);
+                    let mut handled = None;
+                    for (i, (autosave, source)) in self.recoverable_autosaves.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(Path::new(source).file_name().unwrap().to_str().unwrap()).on_hover_text(source);
+                            // Recovering replaces the open file, so wait for any running save or load
+                            if ui.add_enabled(self.file_task.is_none(), egui::Button::new("Recover")).clicked() {
+                                handled = Some((i, true));
+                            }
+                            if ui.button("Discard").clicked() {
+                                file::remove_autosave(autosave);
+                                handled = Some((i, false));
+                            }
+                        });
+                    }
+                    if let Some((i, recover)) = handled {
+                        let (autosave, source) = self.recoverable_autosaves.remove(i);
+                        if recover {
+                            self.recover_to = Some(source);
+                            self.load_file(&autosave);
+                        }
+                    }
+                    ui.add_space(6.);
+                    if ui.button("Discard All").clicked() {
+                        for (autosave, _) in self.recoverable_autosaves.drain(..) {
+                            file::remove_autosave(&autosave);
+                        }
+                    }
+                });
+        }
+
+        // The mini player replaces the whole editor until it is expanded again, or something needs the editor
+        if self.mini_player.is_some() {
+            self.sequencer.update(&mut self.last_instant, ctx, &self.settings);
+            for event in self.sequencer.events.drain(..) {
+                self.events.broadcast(&event);
+            }
+            if self.sequencer.mini_player(ctx) || self.show_save_dialog || self.sequencer.modal.0 {
+                self.toggle_mini_player(ctx);
+            }
+            let offset = self.sequencer.playback_settings(&self.settings).offset;
+            self.sequencer.trail_overlay(ctx, &offset);
+            self.sequencer.key_osd(ctx);
+            if cancel_close {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            }
+            ctx.request_repaint();
+            return;
+        }
+
+        egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            egui::menu::bar(ui, |ui| {
+                ui.menu_button("File", |ui| {
+                    if ui
+                        .add(egui::Button::new("New File...").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::NewFile)))
+                        .clicked()
+                    {
+                        self.new_file();
+                        self.update_title(ctx);
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add(egui::Button::new("Open File...").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::OpenFile)))
+                        .clicked()
+                    {
                         self.open_file();
                         self.update_title(ctx);
                         ui.close_menu();
                     }
                     if ui
-                        .add(egui::Button::new("Save").shortcut_text("Ctrl+S"))
+                        .add(egui::Button::new("Save").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::SaveFile)))
                         .clicked()
                     {
                         self.save_file();
@@ -466,9 +1240,32 @@ impl eframe::App for App {
                         self.update_title(ctx);
                         ui.close_menu();
                     }
+                    if ui.button("Export Runnable...").clicked() {
+                        self.export_runnable();
+                        ui.close_menu();
+                    }
+                    if ui.button("Export Guide...").on_hover_text("Writes a step by step guide with a screenshot of each click and key press").clicked() {
+                        self.export_guide();
+                        ui.close_menu();
+                    }
+                    ui.separator(); 
+                    if ui.add_enabled(!self.sequencer.selected_keyframes.is_empty(), egui::Button::new("Export Selection...")).clicked() {
+                        self.export_snippet();
+                        ui.close_menu();
+                    }
+                    if ui.button("Import Snippet...").clicked() {
+                        self.import_snippet();
+                        self.update_title(ctx);
+                        ui.close_menu();
+                    }
+                    if ui.button("Import Script...").on_hover_text("Turns a SikuliX script, Selenium IDE project or AutoHotkey script into keyframes").clicked() {
+                        self.import_script();
+                        self.update_title(ctx);
+                        ui.close_menu();
+                    }
                     ui.separator(); 
                     if ui
-                        .add(egui::Button::new("Settings").shortcut_text("Ctrl+,"))
+                        .add(egui::Button::new("Settings").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::ToggleSettings)))
                         .clicked()
                     {
                         self.settings.show = true;
@@ -497,16 +1294,43 @@ impl eframe::App for App {
                         ui.close_menu();
                     }
 
+                    ui.separator();
+                    ui.add_enabled_ui(!self.sequencer.keyframes.is_empty(), |ui|{
+                        ui.menu_button("Select by Type", |ui| {
+                            let mut kinds: Vec<u8> = self.sequencer.keyframes.iter().map(|kf| kf.kind).collect();
+                            kinds.sort();
+                            kinds.dedup();
+                            for kind in kinds {
+                                let count = self.sequencer.keyframes.iter().filter(|kf| kf.kind == kind).count();
+                                if ui.button(format!("{} ({})", kind_name(kind), count)).clicked() {
+                                    self.sequencer.select_kind(kind);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                        if ui.add(egui::Button::new("Invert Selection").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::InvertSelection))).clicked(){
+                            self.sequencer.invert_selection();
+                            ui.close_menu();
+                        }
+                    });
                     ui.separator();
                     if ui.add_enabled(!self.sequencer.keyframes.is_empty(),egui::Button::new("Cull Minor Moves")).on_hover_text("Remove all unnecessary mouse move keyframes").clicked(){
-                        self.sequencer.cull_minor_movement_keyframes(); 
+                        self.sequencer.cull_minor_movement_keyframes(&self.settings);
+                    }
+                    if ui.add_enabled(!self.sequencer.keyframes.is_empty(),egui::Button::new("Simplify Paths")).on_hover_text("Reduce recorded mouse paths to the fewest movements within the path tolerance").clicked(){
+                        self.sequencer.simplify_movement_keyframes(self.settings.simplify_tolerance);
+                    }
+                    if ui.add_enabled(!self.sequencer.keyframes.is_empty(),egui::Button::new("Check for Problems")).on_hover_text("Look for keyframes that would make playback fail or misbehave").clicked(){
+                        self.sequencer.check_problems(&self.settings);
+                        self.sequencer.show_problems = true;
+                        ui.close_menu();
                     }
                     self.sequencer.context_menu(ui, None);
 
                 });
                 ui.menu_button("Record", |ui| {
                     if ui
-                        .add(egui::Button::new(if self.sequencer.recording.load(Ordering::Relaxed) {"Stop Recording"} else { "Start Recording"}).shortcut_text("F8"))
+                        .add(egui::Button::new(if self.sequencer.recording.load(Ordering::Relaxed) {"Stop Recording"} else { "Start Recording"}).shortcut_text(self.settings.shortcut_text(ctx, KeybindType::ToggleRecording)))
                         .clicked()
                     {
                         self.sequencer.toggle_recording();
@@ -514,11 +1338,98 @@ impl eframe::App for App {
                     }
                     ui.add(egui::Checkbox::new(&mut self.sequencer.clear_before_recording, "Overwrite Recording"));
                     ui.add(egui::Checkbox::new(&mut self.settings.retake_screenshots, "Retake Screenshots"));
+                    // Only offered once the listener has stopped, as restarting a working one would drop input
+                    if let Some(err) = self.sequencer.record_thread_error() {
+                        ui.separator();
+                        if ui.button("Restart Input Listener").on_hover_text(err).clicked() {
+                            self.sequencer.restart_record_thread();
+                            ui.close_menu();
+                        }
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    if ui.button("Mini Player").on_hover_text("Replace the editor with a small window that stays on top, with only the playback controls").clicked() {
+                        self.toggle_mini_player(ctx);
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    ui.add(egui::Checkbox::new(&mut self.console.show, "Console"));
+                    ui.add(egui::Checkbox::new(&mut self.sequencer.follow_playhead, "Follow Playhead"));
+                    ui.add(egui::Checkbox::new(&mut self.sequencer.show_hud, "Playback Overlay"));
+                    ui.add(egui::Checkbox::new(&mut self.sequencer.show_key_osd, "Show Pressed Keys"));
+                    ui.add(egui::Checkbox::new(&mut self.sequencer.show_mouse_trail, "Mouse Trail"));
+                    ui.add_enabled(self.sequencer.show_mouse_trail, egui::Checkbox::new(&mut self.sequencer.show_trail_overlay, "Mouse Trail During Playback"));
+                    ui.menu_button("Tracks", |ui| {
+                        let visible = self.settings.tracks.iter().filter(|layout| layout.visible).count();
+                        let count = self.settings.tracks.len();
+                        let mut swap = None;
+                        for i in 0..count {
+                            ui.horizontal(|ui| {
+                                let layout = &mut self.settings.tracks[i];
+                                // The last visible track can't be hidden, there would be nowhere to click
+                                ui.add_enabled(!layout.visible || visible > 1, egui::Checkbox::new(&mut layout.visible, layout.track.name()));
+                                if ui.add_enabled(i > 0, egui::Button::new("⏶").small()).on_hover_text("Move up").clicked() {
+                                    swap = Some(i - 1);
+                                }
+                                if ui.add_enabled(i + 1 < count, egui::Button::new("⏷").small()).on_hover_text("Move down").clicked() {
+                                    swap = Some(i);
+                                }
+                            });
+                        }
+                        if let Some(i) = swap {
+                            self.settings.tracks.swap(i, i + 1);
+                        }
+                    });
+                    if !self.settings.offset_profiles.is_empty() {
+                        ui.menu_button("Monitor Offset", |ui| {
+                            let current = self.settings.offset_profile();
+                            for i in 0..self.settings.offset_profiles.len() {
+                                if ui.selectable_label(current == Some(i), &self.settings.offset_profiles[i].name).clicked() {
+                                    self.settings.use_offset_profile(i);
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    }
+                    ui.separator();
+                    if ui.add(egui::Button::new("Zoom to Fit").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::ZoomToFit))).clicked() {
+                        self.sequencer.zoom_to_fit();
+                        ui.close_menu();
+                    }
+                    if ui.add(egui::Button::new("Zoom to Selection").shortcut_text(self.settings.shortcut_text(ctx, KeybindType::ZoomToSelection))).clicked() {
+                        self.sequencer.zoom_to_selection();
+                        ui.close_menu();
+                    }
+                });
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    // Show the progress of the running save/load, or briefly show that it finished
+                    if let Some(task) = &self.file_task {
+                        ui.add(
+                            egui::ProgressBar::new(task.progress.fraction())
+                                .desired_width(120.)
+                                .text(match task.kind {
+                                    FileTaskKind::Save => "Saving...",
+                                    FileTaskKind::Load => "Loading...",
+                                    FileTaskKind::Export => "Exporting...",
+                                }),
+                        );
+                    } else if let Some((status, instant)) = &self.status {
+                        if instant.elapsed() < Duration::from_secs(3) {
+                            ui.small(status);
+                        }
+                    }
                 });
             });
         });
 
         let mut should_close = false;
+        // Where the Add Keyframe window inserts keyframes, marked on the timeline while it is open
+        let insert_time = match self.settings.add_keyframe_data.insert_at {
+            InsertAt::Playhead => self.sequencer.get_time(),
+            InsertAt::AfterSelection => self.sequencer.selection_end().unwrap_or(self.sequencer.get_time()),
+            InsertAt::Timestamp => self.settings.add_keyframe_data.insert_timestamp.max(0.),
+        };
+        self.sequencer.insert_marker = self.settings.add_keyframe_data.show.then_some(insert_time);
         egui::Window::new("Add Keyframe")
             .resizable(false)
             .movable(true)
@@ -526,7 +1437,60 @@ impl eframe::App for App {
             .open(&mut self.settings.add_keyframe_data.show)
             .show(ctx, |ui| {
                 ui.set_max_height(250.);
+                ui.horizontal(|ui|{
+                    ui.label("Insert");
+                    ui.selectable_value(&mut self.settings.add_keyframe_data.insert_at, InsertAt::Playhead, "At playhead");
+                    ui.selectable_value(&mut self.settings.add_keyframe_data.insert_at, InsertAt::AfterSelection, "After selection").on_hover_text("At the end of the selected keyframes, or at the playhead if none are selected");
+                    ui.selectable_value(&mut self.settings.add_keyframe_data.insert_at, InsertAt::Timestamp, "At");
+                    ui.add_enabled(self.settings.add_keyframe_data.insert_at == InsertAt::Timestamp, egui::DragValue::new(&mut self.settings.add_keyframe_data.insert_timestamp).speed(0.1).range(0.0..=f32::MAX).suffix("s"));
+                });
+                ui.separator();
                 egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.add_space(6.);
+                    // Add from a template
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Templates 📌");
+                            ui.add(egui::TextEdit::singleline(&mut self.settings.add_keyframe_data.template_name).hint_text("Click OK...").desired_width(80.));
+                            let name = self.settings.add_keyframe_data.template_name.trim().to_string();
+                            let selected = self.sequencer.selected_keyframes.last().and_then(|uid| self.sequencer.keyframes.iter().find(|keyframe| keyframe.uid == *uid));
+                            if ui.add_enabled(!name.is_empty() && selected.is_some(), egui::Button::new("Save Selected")).on_hover_text("Saves the selected keyframe under this name, replacing a template with the same name").clicked() {
+                                if let Some(selected) = selected {
+                                    // Templates don't start after another keyframe, as it won't be in other sequences
+                                    let template = KeyframeTemplate { name, keyframe: Keyframe { after: None, ..selected.clone() } };
+                                    match self.settings.keyframe_templates.iter_mut().find(|t| t.name == template.name) {
+                                        Some(existing) => *existing = template,
+                                        None => self.settings.keyframe_templates.push(template),
+                                    }
+                                    self.settings.add_keyframe_data.template_name.clear();
+                                }
+                            }
+                        });
+                        // Description
+                        ui.label("Templates are keyframes saved along with their settings, added at the playhead with one click. e.g. a magic move to an OK button.");
+                        ui.add_space(4.);
+                        ui.horizontal_wrapped(|ui|{
+                            let mut removed = None;
+                            for (i, template) in self.settings.keyframe_templates.iter().enumerate() {
+                                if ui.button(&template.name).on_hover_text(kind_name(template.keyframe.kind)).clicked() {
+                                    self.sequencer.add_keyframe(&Keyframe {
+                                        timestamp: insert_time,
+                                        uid: Uuid::new_v4().to_bytes_le(),
+                                        ..template.keyframe.clone()
+                                    });
+                                }
+                                if ui.small_button("🗑").on_hover_text("Delete this template").clicked() {
+                                    removed = Some(i);
+                                }
+                            }
+                            if let Some(i) = removed {
+                                self.settings.keyframe_templates.remove(i);
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
                     ui.add_space(6.);
                     // Add Wait
                     ui.vertical(|ui| {
@@ -544,103 +1508,563 @@ impl eframe::App for App {
                         ui.horizontal(|ui|{
                             if ui.add(egui::Button::new("Add")).clicked(){
                                 self.sequencer.add_keyframe(&Keyframe {
-                                    timestamp: self.sequencer.get_time(),
+                                    timestamp: insert_time,
                                     duration: 1.,
                                     keyframe_type: KeyframeType::Wait(self.settings.add_keyframe_data.wait),
                                     kind: 4,
                                     enabled: true,
                                     uid: Uuid::new_v4().to_bytes_le(),
+                                    ..Default::default()
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Magic Move 
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Magic Move 🔮");
+                            ui.label(format!("{:?}",self.settings.add_keyframe_data.magic_move_path));
+                            if ui.button("Find").clicked() {
+                                    rfd::FileDialog::new()
+                                        .add_filter("Images", &["png"])
+                                        .set_directory("/")
+                                        .pick_file()
+                                        .and_then(|p| {
+                                            self.settings.add_keyframe_data.magic_move_path = p.to_str().unwrap().to_string();
+                                            Some(())
+                                        });
+                                }
+                        });
+                        // Description
+                        ui.label("This keyframe uses a target image to accurately locate it on your screen during execution.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::MagicMove(self.settings.add_keyframe_data.magic_move_path.clone()),
+                                    kind: 6,
+                                    enabled: true,
+                                    uid: Uuid::new_v4().to_bytes_le(),
+                                    ..Default::default()
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Magic Move 
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Loop ⟳");
+                            ui.add(
+                                egui::DragValue::new(&mut self.settings.add_keyframe_data.loop_iterations).speed(1),
+                            )
+                            .on_hover_text("Iterations");
+                        });
+                        // Description
+                        ui.label("This keyframe loops over keyframes within it for number of iterations.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 5.,
+                                    keyframe_type: KeyframeType::Loop(self.settings.add_keyframe_data.loop_iterations,1),
+                                    kind: 7,
+                                    enabled: true,
+                                    uid: Uuid::new_v4().to_bytes_le(),
+                                    ..Default::default()
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add While Image
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("While Image ⟳");
+                            ui.selectable_value(&mut self.settings.add_keyframe_data.while_present, true, "While found");
+                            ui.selectable_value(&mut self.settings.add_keyframe_data.while_present, false, "Until found");
+                            if ui.button("Find").clicked() {
+                                if let Some(p) = rfd::FileDialog::new().add_filter("Images", &["png"]).pick_file() {
+                                    self.settings.add_keyframe_data.while_path = p.to_string_lossy().to_string();
+                                }
+                            }
+                        });
+                        // Description
+                        ui.label("This keyframe repeats the keyframes within it for as long as the target image is on screen, or until it appears, checking at the start of each iteration. e.g. keep clicking Next while there is a Next button.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                if self.settings.add_keyframe_data.while_path.is_empty() {
+                                    self.sequencer.modal = (true,"Failed to add keyframe".to_string(),"Find the target image to look for first".to_string());
+                                } else {
+                                    self.sequencer.add_keyframe(&Keyframe {
+                                        timestamp: insert_time,
+                                        duration: 5.,
+                                        keyframe_type: KeyframeType::WhileImage(self.settings.add_keyframe_data.while_path.clone(), self.settings.add_keyframe_data.while_present, None),
+                                        kind: 19,
+                                        ..Default::default()
+                                    });
+                                }
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add If Image
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("If Image ❓");
+                            ui.selectable_value(&mut self.settings.add_keyframe_data.if_present, true, "If found");
+                            ui.selectable_value(&mut self.settings.add_keyframe_data.if_present, false, "If not found");
+                            if ui.button("Find").clicked() {
+                                if let Some(p) = rfd::FileDialog::new().add_filter("Images", &["png"]).pick_file() {
+                                    self.settings.add_keyframe_data.if_path = p.to_string_lossy().to_string();
+                                }
+                            }
+                        });
+                        // Description
+                        ui.label("This keyframe checks once whether the target image is on screen, then plays the keyframes within its first half (then) or its second half (else). e.g. accept a dialog if it appeared, otherwise carry on.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                if self.settings.add_keyframe_data.if_path.is_empty() {
+                                    self.sequencer.modal = (true,"Failed to add keyframe".to_string(),"Find the target image to look for first".to_string());
+                                } else {
+                                    self.sequencer.add_keyframe(&Keyframe {
+                                        timestamp: insert_time,
+                                        duration: 5.,
+                                        keyframe_type: KeyframeType::If(self.settings.add_keyframe_data.if_path.clone(), self.settings.add_keyframe_data.if_present, None),
+                                        kind: 20,
+                                        ..Default::default()
+                                    });
+                                }
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Random
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Random 🎲");
+                            ui.add(
+                                egui::DragValue::new(&mut self.settings.add_keyframe_data.random_branches).speed(1).range(2..=10),
+                            )
+                            .on_hover_text("Branches");
+                        });
+                        // Description
+                        ui.label("This keyframe plays one of its branches at random each time, split evenly across its timeframe.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                let branches = self.settings.add_keyframe_data.random_branches.max(2);
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 5.,
+                                    keyframe_type: KeyframeType::Random(vec![1.; branches], None),
+                                    kind: 9,
+                                    ..Default::default()
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Label and Jump
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Label / Jump ↪");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(80.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.label);
+                            });
+                            ui.add(
+                                egui::DragValue::new(&mut self.settings.add_keyframe_data.max_jumps).speed(1),
+                            )
+                            .on_hover_text("Max jumps");
+                        });
+                        // Description
+                        ui.label("A label marks a point in time, and a jump sends the playhead to the label with the same name, up to a maximum number of times.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add Label")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Label(self.settings.add_keyframe_data.label.clone()),
+                                    kind: 10,
+                                    ..Default::default()
+                                });
+                            }
+                            if ui.add(egui::Button::new("Add Jump")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Jump(self.settings.add_keyframe_data.label.clone(), self.settings.add_keyframe_data.max_jumps, 0),
+                                    kind: 11,
+                                    ..Default::default()
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Subroutine and Call
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Subroutine / Call 📞");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(80.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.subroutine);
+                            });
+                        });
+                        // Description
+                        ui.label("A subroutine names the keyframes within it, which are skipped until a call with the same name plays them and then carries on from the end of the call. e.g. log in once and call it wherever it is needed.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add Subroutine")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 5.,
+                                    keyframe_type: KeyframeType::Subroutine(self.settings.add_keyframe_data.subroutine.clone()),
+                                    kind: 21,
+                                    ..Default::default()
+                                });
+                            }
+                            if ui.add(egui::Button::new("Add Call")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Call(self.settings.add_keyframe_data.subroutine.clone()),
+                                    kind: 22,
+                                    ..Default::default()
+                                });
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Abort
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Abort ⛔");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(120.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.abort_message);
+                            });
+                        });
+                        // Description
+                        ui.label("This keyframe stops playback and shows its message, as a guard for when something upstream went wrong.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Abort(std::mem::take(&mut self.settings.add_keyframe_data.abort_message)),
+                                    kind: 12,
+                                    ..Default::default()
+                                });
+                                should_close = true;
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Notification
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Notification 🔔");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(120.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.notification);
+                            });
+                        });
+                        // Description
+                        ui.label("This keyframe shows a desktop notification, replacing {expression} with its value, e.g. batch {i + 1}/{n} complete.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Notify(std::mem::take(&mut self.settings.add_keyframe_data.notification)),
+                                    kind: 13,
+                                    ..Default::default()
+                                });
+                                should_close = true;
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Beep
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Beep 🔊");
+                            ui.label(if self.settings.add_keyframe_data.sound_path.is_empty() { "Tone".to_string() } else { format!("{:?}", self.settings.add_keyframe_data.sound_path) });
+                            if ui.button("Find").clicked() {
+                                if let Some(p) = rfd::FileDialog::new().add_filter("Sounds", &["wav", "mp3", "ogg", "flac"]).pick_file() {
+                                    self.settings.add_keyframe_data.sound_path = p.to_string_lossy().to_string();
+                                }
+                            }
+                        });
+                        // Description
+                        ui.label("This keyframe plays a sound file, or a short tone without one, to audibly signal checkpoints in long runs.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Beep(std::mem::take(&mut self.settings.add_keyframe_data.sound_path)),
+                                    kind: 14,
+                                    ..Default::default()
+                                });
+                                should_close = true;
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Plugin
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Plugin 🧩");
+                            let selected = Path::new(&self.settings.add_keyframe_data.plugin_path).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+                            egui::ComboBox::from_id_source("add_plugin").selected_text(selected).show_ui(ui, |ui| {
+                                // The registry is only read while the list is open
+                                let installed = plugin::registry(&self.settings.plugin_directory);
+                                if installed.is_empty() {
+                                    ui.label("No plugins installed");
+                                }
+                                for entry in installed {
+                                    let path = entry.path.to_string_lossy().to_string();
+                                    ui.selectable_value(&mut self.settings.add_keyframe_data.plugin_path, path, entry.name);
+                                }
+                            });
+                            if ui.button("Find").clicked() {
+                                if let Some(p) = rfd::FileDialog::new().add_filter("WASM Plugins", &["wasm"]).pick_file() {
+                                    self.settings.add_keyframe_data.plugin_path = p.to_string_lossy().to_string();
+                                }
+                            }
+                        });
+                        // Description
+                        ui.label("This keyframe runs a WASM plugin in a sandbox, where it can only move, click, scroll and type through Automate, to add actions without recompiling. Plugins installed in the plugin folder set in the preferences are listed above.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add_enabled(!self.settings.add_keyframe_data.plugin_path.is_empty(), egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Plugin(std::mem::take(&mut self.settings.add_keyframe_data.plugin_path), String::new()),
+                                    kind: 23,
+                                    ..Default::default()
+                                });
+                                should_close = true;
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Log
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Log 📝");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(120.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.log_message);
+                            });
+                        });
+                        // Description
+                        ui.label("This keyframe adds a line to the run log, replacing {expression} with its value, so the result reads like an account of what was attempted.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Log(std::mem::take(&mut self.settings.add_keyframe_data.log_message)),
+                                    kind: 15,
+                                    ..Default::default()
                                 });
+                                should_close = true;
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
+                    // Add Counter
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Counter #");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(80.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.counter_name);
+                            });
+                            ui.selectable_value(&mut self.settings.add_keyframe_data.counter_reset, false, "Increment");
+                            ui.selectable_value(&mut self.settings.add_keyframe_data.counter_reset, true, "Reset");
+                            ui.add(egui::DragValue::new(&mut self.settings.add_keyframe_data.counter_value).speed(0.1));
+                        });
+                        // Description
+                        ui.label("This keyframe adds to a counter or sets it back to a value. Counters start at 0 and can be used like variables in expressions and text, e.g. export {count}.csv across the iterations of a loop.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                // Fields are read one at a time, the window holds on to whether it is open
+                                let name = self.settings.add_keyframe_data.counter_name.trim().to_string();
+                                let value = self.settings.add_keyframe_data.counter_value;
+                                if name.is_empty() {
+                                    self.sequencer.modal = (true,"Failed to add keyframe".to_string(),"The counter needs a name".to_string());
+                                } else {
+                                    let action = match self.settings.add_keyframe_data.counter_reset {
+                                        true => CounterAction::Reset(value),
+                                        false => CounterAction::Increment(value),
+                                    };
+                                    self.sequencer.add_keyframe(&Keyframe {
+                                        timestamp: insert_time,
+                                        duration: 0.2,
+                                        keyframe_type: KeyframeType::Counter(name, action),
+                                        kind: 18,
+                                        ..Default::default()
+                                    });
+                                    should_close = true;
+                                }
                             }
                         });
                     });
                     ui.add_space(6.);
                     ui.separator();
                     ui.add_space(6.);
-                    // Add Magic Move 
+                    // Add Switch Desktop
                     ui.vertical(|ui| {
                         ui.horizontal(|ui|{
                             // Title
-                            ui.strong("Magic Move 🔮");
-                            ui.label(format!("{:?}",self.settings.add_keyframe_data.magic_move_path));
-                            if ui.button("Find").clicked() {
-                                    rfd::FileDialog::new()
-                                        .add_filter("Images", &["png"])
-                                        .set_directory("/")
-                                        .pick_file()
-                                        .and_then(|p| {
-                                            self.settings.add_keyframe_data.magic_move_path = p.to_str().unwrap().to_string();
-                                            Some(())
-                                        });
-                                }
+                            ui.strong("Switch Desktop 🖵");
+                            ui.add(egui::DragValue::new(&mut self.settings.add_keyframe_data.desktop).range(1..=20).prefix("desktop "));
                         });
                         // Description
-                        ui.label("This keyframe uses a target image to accurately locate it on your screen during execution.");
+                        ui.label("This keyframe switches to another virtual desktop before the following keyframes run. On Linux this needs wmctrl, on macOS the \"Switch to Desktop\" shortcuts have to be enabled.");
                         ui.add_space(4.);
                         ui.horizontal(|ui|{
                             if ui.add(egui::Button::new("Add")).clicked(){
                                 self.sequencer.add_keyframe(&Keyframe {
-                                    timestamp: self.sequencer.get_time(),
-                                    duration: 0.2,
-                                    keyframe_type: KeyframeType::MagicMove(self.settings.add_keyframe_data.magic_move_path.clone()),
-                                    kind: 6,
-                                    enabled: true,
-                                    uid: Uuid::new_v4().to_bytes_le(),
+                                    timestamp: insert_time,
+                                    duration: 0.5,
+                                    keyframe_type: KeyframeType::Desktop(self.settings.add_keyframe_data.desktop.max(1)),
+                                    kind: 16,
+                                    ..Default::default()
                                 });
+                                should_close = true;
                             }
                         });
                     });
                     ui.add_space(6.);
                     ui.separator();
                     ui.add_space(6.);
-                    // Add Magic Move 
+                    // Add Key
                     ui.vertical(|ui| {
                         ui.horizontal(|ui|{
                             // Title
-                            ui.strong("Loop ⟳");
-                            ui.add(
-                                egui::DragValue::new(&mut self.settings.add_keyframe_data.loop_iterations).speed(1),
-                            )
-                            .on_hover_text("Iterations");
+                            ui.strong("Key 🖮");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(40.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.key_str);
+                            });
                         });
                         // Description
-                        ui.label("This keyframe loops over keyframes within it for number of iterations.");
+                        ui.label("This keyframe simulates a single key press from your keyboard, characters that need shift are added as key strokes with shift held.");
                         ui.add_space(4.);
                         ui.horizontal(|ui|{
                             if ui.add(egui::Button::new("Add")).clicked(){
-                                self.sequencer.add_keyframe(&Keyframe {
-                                    timestamp: self.sequencer.get_time(),
-                                    duration: 5.,
-                                    keyframe_type: KeyframeType::Loop(self.settings.add_keyframe_data.loop_iterations,1),
-                                    kind: 7,
-                                    enabled: true,
-                                    uid: Uuid::new_v4().to_bytes_le(),
-                                });
+                                let key_str = &self.settings.add_keyframe_data.key_str;
+                                let mut chars = key_str.chars();
+                                // A single character that needs shift, e.g. "A", is typed with shift held
+                                let shifted_keys = match (chars.next(), chars.next()) {
+                                    (Some(c), None) => char_to_keys(c).filter(|keys| keys.len() > 1),
+                                    _ => None,
+                                };
+                                if let Some(keys) = shifted_keys {
+                                    self.sequencer.add_keyframe(&Keyframe {
+                                        timestamp: insert_time,
+                                        duration: 0.1,
+                                        keyframe_type: KeyframeType::KeyStrokes(keys),
+                                        kind: 5,
+                                        ..Default::default()
+                                    });
+                                    self.settings.add_keyframe_data.key_str = "".to_string();
+                                    should_close = true;
+                                }else if let Some(key) = string_to_keys(key_str){
+                                    self.sequencer.add_keyframe(&Keyframe::key_btn(insert_time, 0.1, key));
+                                    self.settings.add_keyframe_data.key_str = "".to_string();
+                                    should_close = true;
+                                }else{
+                                    self.sequencer.modal = (true,"Failed to add keyframe".to_string(),"The input given was invalid".to_string());
+                                }
                             }
                         });
                     });
                     ui.add_space(6.);
                     ui.separator();
                     ui.add_space(6.);
-                    // Add Key
+                    // Add Hold Key
                     ui.vertical(|ui| {
                         ui.horizontal(|ui|{
                             // Title
-                            ui.strong("Key 🖮");
+                            ui.strong("Hold Key ⏬");
                             ui.horizontal(|ui|{
                                 ui.set_max_width(40.);
-                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.key_str);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.hold_key_str);
                             });
+                            ui.add(egui::DragValue::new(&mut self.settings.add_keyframe_data.hold_duration).speed(0.1).range(0.1..=f32::MAX).suffix("s"));
                         });
                         // Description
-                        ui.label("This keyframe simulates a single key press from your keyboard.");
+                        ui.label("This keyframe holds a key down for its whole duration, repeating it like a held key, for games and tools that need sustained input.");
                         ui.add_space(4.);
                         ui.horizontal(|ui|{
                             if ui.add(egui::Button::new("Add")).clicked(){
-                                let key = string_to_keys(&self.settings.add_keyframe_data.key_str);
-                                if let Some(key) = key{
-                                    self.sequencer.add_keyframe(&Keyframe::key_btn(self.sequencer.get_time(), 0.1, key));
-                                    self.settings.add_keyframe_data.key_str = "".to_string();
+                                if let Some(key) = string_to_keys(&self.settings.add_keyframe_data.hold_key_str){
+                                    self.sequencer.add_keyframe(&Keyframe {
+                                        timestamp: insert_time,
+                                        duration: self.settings.add_keyframe_data.hold_duration.max(0.1),
+                                        keyframe_type: KeyframeType::Hold(key),
+                                        kind: 17,
+                                        ..Default::default()
+                                    });
+                                    self.settings.add_keyframe_data.hold_key_str = "".to_string();
                                     should_close = true;
                                 }else{
                                     self.sequencer.modal = (true,"Failed to add keyframe".to_string(),"The input given was invalid".to_string());
@@ -651,6 +2075,35 @@ impl eframe::App for App {
                     ui.add_space(6.);
                     ui.separator();
                     ui.add_space(6.);
+                    // Add Text
+                    ui.vertical(|ui| {
+                        ui.horizontal(|ui|{
+                            // Title
+                            ui.strong("Text 🖹");
+                            ui.horizontal(|ui|{
+                                ui.set_max_width(120.);
+                                ui.text_edit_singleline(&mut self.settings.add_keyframe_data.text);
+                            });
+                        });
+                        // Description
+                        ui.label("This keyframe types out text, replacing {expression} with its value, e.g. row {i + 1} inside a loop.");
+                        ui.add_space(4.);
+                        ui.horizontal(|ui|{
+                            if ui.add(egui::Button::new("Add")).clicked(){
+                                self.sequencer.add_keyframe(&Keyframe {
+                                    timestamp: insert_time,
+                                    duration: 0.2,
+                                    keyframe_type: KeyframeType::Text(std::mem::take(&mut self.settings.add_keyframe_data.text)),
+                                    kind: 8,
+                                    ..Default::default()
+                                });
+                                should_close = true;
+                            }
+                        });
+                    });
+                    ui.add_space(6.);
+                    ui.separator();
+                    ui.add_space(6.);
                     // Add Move
                     ui.vertical(|ui| {
                         ui.horizontal(|ui|{
@@ -670,7 +2123,7 @@ impl eframe::App for App {
                         ui.add_space(4.);
                         ui.horizontal(|ui|{
                             if ui.add(egui::Button::new("Add")).clicked(){
-                                self.sequencer.add_keyframe(&Keyframe::mouse_move(self.sequencer.get_time(), self.settings.add_keyframe_data.move_pos));
+                                self.sequencer.add_keyframe(&Keyframe::mouse_move(insert_time, self.settings.add_keyframe_data.move_pos));
                                 self.settings.add_keyframe_data.move_pos = Vec2::ZERO;
                                 should_close = true;
                             }
@@ -697,7 +2150,7 @@ impl eframe::App for App {
                         ui.add_space(4.);
                         ui.horizontal(|ui|{
                             if ui.add(egui::Button::new("Add")).clicked(){
-                                self.sequencer.add_keyframe(&Keyframe::mouse_button(self.sequencer.get_time(),0.1, self.settings.add_keyframe_data.mouse_btn));
+                                self.sequencer.add_keyframe(&Keyframe::mouse_button(insert_time,0.1, self.settings.add_keyframe_data.mouse_btn));
                                 self.settings.add_keyframe_data.mouse_btn = rdev::Button::Left;
                                 should_close = true;
                             }
@@ -710,11 +2163,13 @@ impl eframe::App for App {
         if should_close {
             self.settings.add_keyframe_data.show = false;
         }
+        // The window is given a copy of whether it is open, so the settings can be used as a whole inside it
+        let mut show_settings = self.settings.show;
         egui::Window::new("Settings")
             .resizable(false)
             .movable(true)
             .collapsible(false)
-            .open(&mut self.settings.show)
+            .open(&mut show_settings)
             .show(ctx, |ui| {
                 ui.horizontal(|ui| {
                     // Side panel for settings list
@@ -760,6 +2215,10 @@ impl eframe::App for App {
                                 ui.separator();
                                 ui.add_space(4.);
                                 egui::ScrollArea::vertical().show(ui, |ui| {
+                                    if self.sequencer.playback_overrides.is_some() {
+                                        ui.label(egui::RichText::new("The open file overrides the monitor offset, fail safe and fail detection below").weak());
+                                        ui.add_space(4.);
+                                    }
                                     // Monitor offset
                                     ui.vertical(|ui| {
                                         ui.horizontal(|ui|{
@@ -774,24 +2233,73 @@ impl eframe::App for App {
                                             .on_hover_text("Y");
                                         });
                                         ui.label("Monitor Offset is used to correctly simulate mouse movements when using multiple monitors.");
+                                        ui.label(format!("Display scaling of the primary monitor is {:.0}%, screenshots are mapped onto the screen accounting for it.", display_scale() * 100.));
+                                        ui.add_space(4.);
+                                        // Saved offsets, so each display setup only needs calibrating once
+                                        ui.horizontal(|ui|{
+                                            ui.label("Profile");
+                                            let current = self.settings.offset_profile();
+                                            let mut selected = current;
+                                            egui::ComboBox::from_id_source("offset_profile")
+                                                .selected_text(current.map_or("Custom".to_string(), |i| self.settings.offset_profiles[i].name.clone()))
+                                                .show_ui(ui, |ui| {
+                                                    for (i, profile) in self.settings.offset_profiles.iter().enumerate() {
+                                                        ui.selectable_value(&mut selected, Some(i), &profile.name);
+                                                    }
+                                                });
+                                            if let Some(index) = selected.filter(|_| selected != current) {
+                                                self.settings.use_offset_profile(index);
+                                            }
+                                            if let Some(index) = current {
+                                                if ui.small_button("🗑").on_hover_text("Delete this profile").clicked() {
+                                                    self.settings.offset_profiles.remove(index);
+                                                }
+                                            }
+                                        });
+                                        ui.horizontal(|ui|{
+                                            ui.add(egui::TextEdit::singleline(&mut self.settings.offset_profile_name).hint_text("Docked, Laptop...").desired_width(120.));
+                                            let name = self.settings.offset_profile_name.trim().to_string();
+                                            if ui.add_enabled(!name.is_empty() && self.settings.offset.is_finite(), egui::Button::new("Save Profile")).on_hover_text("Saves the current offset under this name, replacing a profile with the same name").clicked() {
+                                                let profile = OffsetProfile { name, offset: self.settings.offset, monitors: self.settings.monitor_calibration.clone() };
+                                                match self.settings.offset_profiles.iter_mut().find(|p| p.name == profile.name) {
+                                                    Some(existing) => *existing = profile,
+                                                    None => self.settings.offset_profiles.push(profile),
+                                                }
+                                                self.settings.offset_profile_name.clear();
+                                            }
+                                        });
                                         ui.add_space(4.);
                                         ui.horizontal(|ui|{
                                             if ui.add(egui::Button::new("Calibrate")).on_hover_text("Calibrates the offset necessary to correctly move the mouse when using multiple monitors").clicked() {
                                                 self.sequencer.calibrate.swap(true, Ordering::Relaxed);
-                                                rdev::simulate(&rdev::EventType::MouseMove { x: 0., y: 0. }).unwrap();
-                                                let mut recording_keyframes = self.sequencer.recording_keyframes.lock().unwrap();
-                                                if let Some(last) = recording_keyframes.last(){
-                                                    // Keyframe kind of 255 is used only for calibrating monitor offset
-                                                    if last.kind == u8::MAX{
-                                                        if let KeyframeType::MouseMove(pos) = last.keyframe_type{
+                                                match input::simulate(&rdev::EventType::MouseMove { x: 0., y: 0. }) {
+                                                    Ok(()) => {
+                                                        if let Some(pos) = self.sequencer.receive_recorded_events() {
                                                             // Invert the pos so it brings us back to (0,0)
                                                             self.settings.offset = pos * egui::Vec2::new(-1.,-1.);
                                                         }
+                                                        log::info!("Calibrated Monitor Offset: {:?}", self.settings.offset);
                                                     }
+                                                    Err(err) => self.sequencer.modal = (true, "Calibration Failed".to_string(), err),
                                                 }
-                                                recording_keyframes.pop();
                                                 self.sequencer.calibrate.swap(false, Ordering::Relaxed);
-                                                log::info!("Calibrated Monitor Offset: {:?}", self.settings.offset);
+                                            }
+                                            if ui.button("Calibrate Monitors").on_hover_text("Moves the mouse over several points on each monitor to find their offset and display scaling").clicked() {
+                                                match self.sequencer.calibrate_monitors() {
+                                                    Ok(calibration) => {
+                                                        log::info!("Calibrated Monitors: {:?}", calibration);
+                                                        self.settings.monitor_calibration = calibration;
+                                                        set_calibration(self.settings.monitor_calibration.clone());
+                                                    }
+                                                    Err(err) => self.sequencer.modal = (true, "Calibration Failed".to_string(), err),
+                                                }
+                                            }
+                                            if !self.settings.monitor_calibration.is_empty() {
+                                                ui.label(format!("{} calibrated", self.settings.monitor_calibration.len()));
+                                                if ui.small_button("🗑").on_hover_text("Use the offset for every monitor again").clicked() {
+                                                    self.settings.monitor_calibration.clear();
+                                                    set_calibration(vec![]);
+                                                }
                                             }
                                         });
                                     });
@@ -833,17 +2341,187 @@ impl eframe::App for App {
                                             let mut monitor_edge = *self.sequencer.failsafe_edge.lock().unwrap();
                                             
                                             egui::ComboBox::from_label("")
-                                                .selected_text(format!("{:?}", monitor_edge))
+                                                .selected_text(monitor_edge.name())
                                                 .show_ui(ui, |ui| {
-                                                    ui.selectable_value(&mut monitor_edge, MonitorEdge::Left, "Left");
-                                                    ui.selectable_value(&mut monitor_edge, MonitorEdge::Right, "Right");
-                                                    ui.selectable_value(&mut monitor_edge, MonitorEdge::Bottom, "Bottom");
-                                                    ui.selectable_value(&mut monitor_edge, MonitorEdge::Top, "Top");
+                                                    for edge in MonitorEdge::ALL {
+                                                        ui.selectable_value(&mut monitor_edge, edge, edge.name());
+                                                    }
                                                 });
                                             *self.sequencer.failsafe_edge.lock().unwrap() = monitor_edge;
+                                            let mut monitor = self.sequencer.failsafe_monitor.clone();
+                                            egui::ComboBox::from_id_source("failsafe_monitor")
+                                                .selected_text(monitor.clone().unwrap_or("Primary".to_string()))
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(&mut monitor, None, "Primary");
+                                                    for name in monitor_names() {
+                                                        ui.selectable_value(&mut monitor, Some(name.clone()), name);
+                                                    }
+                                                });
+                                            if monitor != self.sequencer.failsafe_monitor {
+                                                self.sequencer.set_failsafe_monitor(monitor);
+                                            }
                                         });
-                                        ui.label("Incase of failure during playback, quickly slam the mouse into the selected edge to stop.");
-                                        ui.small("Only works for main monitor");
+                                        ui.label("Incase of failure during playback, quickly slam the mouse into the selected edge or corner of the selected monitor to stop. Corners are harder to hit by accident.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Start delay
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Start Delay");
+                                            ui.add(egui::DragValue::new(&mut self.settings.start_delay).range(0.0..=60.0).speed(0.1).suffix("s"));
+                                        });
+                                        ui.label("Counts down before playback starts, to give you time to focus the window being automated. The countdown is shown in the playback overlay.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Minimize on play
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Minimize During Playback");
+                                            ui.checkbox(&mut self.settings.minimize_on_play, "");
+                                        });
+                                        ui.label("Minimizes Automate when playback starts so it doesn't cover what is being automated, and brings it back when playback stops or fails.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Panic key
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Panic Key");
+                                            let old_key = self.settings.panic_key;
+                                            egui::ComboBox::from_id_source("panic_key")
+                                                .selected_text(format!("{:?}", self.settings.panic_key))
+                                                .show_ui(ui, |ui| {
+                                                    for key in PANIC_KEYS {
+                                                        ui.selectable_value(&mut self.settings.panic_key, key, format!("{:?}", key));
+                                                    }
+                                                });
+                                            if self.settings.panic_key != old_key {
+                                                self.sequencer.set_panic_key(self.settings.panic_key);
+                                            }
+                                        });
+                                        ui.label("Stops playback from anywhere and releases any held keys, then brings Automate back into focus.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Input backend
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Input Backend");
+                                            let old_backend = self.settings.input_backend;
+                                            egui::ComboBox::from_id_source("input_backend")
+                                                .selected_text(format!("{:?}", self.settings.input_backend))
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(&mut self.settings.input_backend, InputBackendKind::Rdev, "Rdev");
+                                                    ui.selectable_value(&mut self.settings.input_backend, InputBackendKind::Enigo, "Enigo");
+                                                    ui.selectable_value(&mut self.settings.input_backend, InputBackendKind::Ydotool, "Ydotool");
+                                                });
+                                            if self.settings.input_backend != old_backend {
+                                                input::set_backend(self.settings.input_backend);
+                                            }
+                                        });
+                                        ui.label("The library used to simulate input during playback, some applications only respond to one of them. Ydotool works on Wayland but needs ydotoold running.");
+                                        ui.add_space(4.);
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Retries");
+                                            if ui.add(egui::DragValue::new(&mut self.settings.simulation_retries).range(0..=10)).changed() {
+                                                input::set_retries(self.settings.simulation_retries);
+                                            }
+                                        });
+                                        ui.label("How many times an input event refused by the system (anti-cheat, missing permissions) is tried again before playback stops.");
+                                        ui.add_space(4.);
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Capture Backend");
+                                            let old_backend = self.settings.capture_backend;
+                                            egui::ComboBox::from_id_source("capture_backend")
+                                                .selected_text(format!("{:?}", self.settings.capture_backend))
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(&mut self.settings.capture_backend, CaptureBackendKind::Xcap, "Xcap");
+                                                    ui.selectable_value(&mut self.settings.capture_backend, CaptureBackendKind::Grim, "Grim");
+                                                });
+                                            if self.settings.capture_backend != old_backend {
+                                                set_capture_backend(self.settings.capture_backend);
+                                            }
+                                        });
+                                        ui.label("The program used to take screenshots. Grim works on Wayland compositors that xcap can't capture.");
+                                        ui.add_space(4.);
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Screenshot Quality");
+                                            let old_quality = self.settings.screenshot_quality;
+                                            let quality = &mut self.settings.screenshot_quality;
+                                            egui::ComboBox::from_id_source("screenshot_scale")
+                                                .selected_text(format!("{:.0}%", quality.scale * 100.))
+                                                .show_ui(ui, |ui| {
+                                                    for scale in SCREENSHOT_SCALES {
+                                                        ui.selectable_value(&mut quality.scale, scale, format!("{:.0}%", scale * 100.));
+                                                    }
+                                                });
+                                            egui::ComboBox::from_id_source("screenshot_compression")
+                                                .selected_text(format!("{:?} compression", quality.compression))
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(&mut quality.compression, PngCompression::Fast, "Fast");
+                                                    ui.selectable_value(&mut quality.compression, PngCompression::Default, "Default");
+                                                    ui.selectable_value(&mut quality.compression, PngCompression::Best, "Best");
+                                                });
+                                            if *quality != old_quality {
+                                                set_screenshot_quality(*quality);
+                                            }
+                                        });
+                                        ui.label("Smaller screenshots use less memory and make smaller files but show less detail. Stronger compression makes smaller files but saves slower.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Keyboard layout
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Keyboard Layout");
+                                            let old_layout = self.settings.keyboard_layout;
+                                            egui::ComboBox::from_id_source("keyboard_layout")
+                                                .selected_text(format!("{:?}", self.settings.keyboard_layout))
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(&mut self.settings.keyboard_layout, KeyboardLayout::Qwerty, "Qwerty");
+                                                    ui.selectable_value(&mut self.settings.keyboard_layout, KeyboardLayout::Azerty, "Azerty");
+                                                    ui.selectable_value(&mut self.settings.keyboard_layout, KeyboardLayout::Qwertz, "Qwertz");
+                                                    ui.selectable_value(&mut self.settings.keyboard_layout, KeyboardLayout::Dvorak, "Dvorak");
+                                                });
+                                            if self.settings.keyboard_layout != old_layout {
+                                                layout::set_layout(self.settings.keyboard_layout);
+                                            }
+                                        });
+                                        ui.label("Decides which keys type the characters in text and key keyframes, and which characters recorded keys are shown as.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Theme
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Theme");
+                                            let old_theme = self.settings.theme;
+                                            egui::ComboBox::from_id_source("theme_mode")
+                                                .selected_text(format!("{:?}", self.settings.theme.mode))
+                                                .show_ui(ui, |ui| {
+                                                    ui.selectable_value(&mut self.settings.theme.mode, ThemeMode::Dark, "Dark");
+                                                    ui.selectable_value(&mut self.settings.theme.mode, ThemeMode::Light, "Light");
+                                                });
+                                            let mut custom_accent = self.settings.theme.accent.is_some();
+                                            let mut accent = self.settings.theme.accent.unwrap_or(ui.visuals().selection.bg_fill);
+                                            ui.checkbox(&mut custom_accent, "Accent");
+                                            if custom_accent {
+                                                ui.color_edit_button_srgba(&mut accent);
+                                            }
+                                            self.settings.theme.accent = custom_accent.then_some(accent);
+                                            if self.settings.theme != old_theme {
+                                                self.settings.theme.apply(ui.ctx());
+                                            }
+                                        });
+                                        ui.label("Colors of the app and the keyframes in the timeline, with an optional accent for selections.");
                                     });
                                     ui.add_space(6.);
                                     ui.separator();
@@ -864,6 +2542,155 @@ impl eframe::App for App {
                                         ui.small("Only works for main monitor");
                                     });
                                     ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Movement culling thresholds
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Cull Minor Moves");
+                                            ui.add(egui::DragValue::new(&mut self.settings.cull_min_distance)
+                                                .suffix("px")
+                                                .speed(1)
+                                                .range(0.0..=1000.0))
+                                                .on_hover_text("Minimum distance");
+                                            ui.add(egui::DragValue::new(&mut self.settings.cull_min_angle)
+                                                .suffix("°")
+                                                .speed(1)
+                                                .range(0.0..=180.0))
+                                                .on_hover_text("Minimum change in direction");
+                                            ui.add(egui::DragValue::new(&mut self.settings.cull_min_pause)
+                                                .suffix("s")
+                                                .speed(0.05)
+                                                .range(0.0..=10.0))
+                                                .on_hover_text("Minimum pause");
+                                        });
+                                        ui.label("Movements are kept when the cursor pauses on them, or when they are far enough from the last kept movement and change direction enough.");
+                                        ui.small("Hover-dependent menus usually need a pause to open");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Path simplification tolerance
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Path Tolerance");
+                                            ui.add(egui::DragValue::new(&mut self.settings.simplify_tolerance)
+                                                .suffix("px")
+                                                .speed(0.5)
+                                                .range(0.0..=100.0));
+                                        });
+                                        ui.label("Simplify Paths removes movements while keeping the path within this many pixels of the recording.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Longest a keyframe can take before it is flagged
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Keyframe Limit");
+                                            ui.add(egui::DragValue::new(&mut self.settings.max_keyframe_duration)
+                                                .suffix("s")
+                                                .speed(1.)
+                                                .range(1.0..=86400.0));
+                                        });
+                                        ui.label("Keyframes that take longer than this are flagged when checking for problems before playback.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Autosave
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Autosave");
+                                            ui.checkbox(&mut self.settings.autosave, "");
+                                            ui.add_enabled(self.settings.autosave, egui::DragValue::new(&mut self.settings.autosave_interval)
+                                                .suffix(" min")
+                                                .speed(0.5)
+                                                .range(0.5..=120.0));
+                                        });
+                                        ui.horizontal(|ui|{
+                                            ui.label(self.settings.autosave_directory.to_str().unwrap_or_default());
+                                            if ui.button("Change").clicked() {
+                                                if let Some(directory) = FileDialog::new().set_directory(&self.settings.autosave_directory).pick_folder() {
+                                                    self.settings.autosave_directory = directory;
+                                                }
+                                            }
+                                        });
+                                        ui.label("Periodically saves unsaved changes to the folder above, they can be recovered the next time Automate starts.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Backups
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Backups");
+                                            ui.add(egui::DragValue::new(&mut self.settings.backup_count)
+                                                .speed(0.1)
+                                                .range(0..=20));
+                                        });
+                                        ui.label("Keeps this many previous versions of a file (file.auto.bak1 being the newest) whenever it is saved over.");
+                                        ui.small("Set to 0 to disable backups");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Plugins
+                                    ui.vertical(|ui|{
+                                        ui.strong("Plugins");
+                                        ui.horizontal(|ui|{
+                                            ui.label(self.settings.plugin_directory.to_str().unwrap_or_default());
+                                            if ui.button("Change").clicked() {
+                                                if let Some(directory) = FileDialog::new().set_directory(&self.settings.plugin_directory).pick_folder() {
+                                                    self.settings.plugin_directory = directory;
+                                                }
+                                            }
+                                        });
+                                        ui.label("WASM plugins copied into this folder are listed when adding a plugin keyframe.");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Remote control
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Remote Control");
+                                            ui.checkbox(&mut self.settings.remote_control, "");
+                                            ui.add_enabled(self.settings.remote_control, egui::DragValue::new(&mut self.settings.remote_port)
+                                                .prefix("port ")
+                                                .speed(1));
+                                            ui.add_enabled(self.settings.remote_control, egui::DragValue::new(&mut self.settings.remote_event_port)
+                                                .prefix("events ")
+                                                .speed(1));
+                                        });
+                                        ui.label("Lets other programs load files, start and stop playback and check its status over HTTP on localhost, and streams playback events over a WebSocket.");
+                                        if let Some(token) = &self.remote_token {
+                                            ui.horizontal(|ui|{
+                                                ui.label(format!("Requests need the {} header, which changes each session:", remote::TOKEN_HEADER));
+                                                ui.monospace(token);
+                                                if ui.small_button("Copy").clicked() {
+                                                    ui.ctx().copy_text(token.clone());
+                                                }
+                                            });
+                                            if let Some(path) = remote::token_path() {
+                                                ui.small(format!("It is also written to {}", path.display()));
+                                            }
+                                        }
+                                        ui.small("Takes effect after restarting Automate");
+                                    });
+                                    ui.add_space(6.);
+                                    ui.separator();
+                                    ui.add_space(6.);
+                                    // Local control
+                                    ui.vertical(|ui|{
+                                        ui.horizontal(|ui|{
+                                            ui.strong("Local Control");
+                                            ui.checkbox(&mut self.settings.local_control, "");
+                                        });
+                                        ui.label("Lets other programs on this computer play, pause, stop and load files through a named pipe (Windows) or Unix socket.");
+                                        ui.small("Takes effect after restarting Automate");
+                                    });
+                                    ui.add_space(6.);
                                 });
                             }
                             SettingsPage::Shortcuts => {
@@ -877,9 +2704,31 @@ impl eframe::App for App {
                                             .hint_text("Type to search keybindings"),
                                         );
                                     });
-                                    if ui.button("X").clicked() {}
+                                    if ui.button("X").clicked() {
+                                        self.settings.keybind_search.clear();
+                                    }
                                 });
                                 ui.spacing();
+                                // The next shortcut pressed replaces the one being captured, without triggering any action
+                                if let Some(index) = self.settings.capturing_keybind {
+                                    let pressed = ui.input_mut(|i| {
+                                        let shortcut = i.events.iter().find_map(|event| match event {
+                                            egui::Event::Key { key, pressed: true, modifiers, .. } => Some(egui::KeyboardShortcut::new(*modifiers, *key)),
+                                            _ => None,
+                                        })?;
+                                        i.consume_shortcut(&shortcut);
+                                        Some(shortcut)
+                                    });
+                                    if let Some(shortcut) = pressed {
+                                        self.bind_shortcut(ctx, index, shortcut);
+                                        self.settings.capturing_keybind = None;
+                                    }
+                                }
+                                if let Some(conflict) = &self.settings.keybind_conflict {
+                                    ui.colored_label(ui.visuals().error_fg_color, conflict);
+                                }
+                                let search = self.settings.keybind_search.to_lowercase();
+                                let rows: Vec<usize> = (0..self.settings.keybinds.len()).filter(|i| self.settings.keybinds[*i].text.to_lowercase().contains(&search)).collect();
                                 TableBuilder::new(ui)
                                     .striped(false)
                                     .resizable(true)
@@ -899,38 +2748,66 @@ impl eframe::App for App {
                                         });
                                     })
                                     .body(|body| {
-                                        body.rows(22., self.settings.keybinds.len(), |mut row| {
-                                            let keybind = &self.settings.keybinds[row.index()];
+                                        body.rows(22., rows.len(), |mut row| {
+                                            let index = rows[row.index()];
+                                            let keybind = self.settings.keybinds[index].clone();
+                                            // Conflicts can still come from settings saved by hand or before they were refused
+                                            let conflicts = self.settings.conflicts(index, &keybind.keybind).join(", ");
                                             row.col(|ui| {
-                                                ui.label(format!("{}", keybind.text));
+                                                if conflicts.is_empty() {
+                                                    ui.label(&keybind.text);
+                                                } else {
+                                                    ui.colored_label(ui.visuals().error_fg_color, &keybind.text).on_hover_text(format!("Same shortcut as {}", conflicts));
+                                                }
                                             });
                                             row.col(|ui| {
-                                                let keybind = keybind.keybind;
-                                                let ctrl = if keybind.modifiers.ctrl {
-                                                    "Ctrl+".to_string()
-                                                } else {
-                                                    "".to_string()
-                                                };
-                                                ui.label(format!(
-                                                    "{}{:#?}",
-                                                    ctrl, keybind.logical_key,
-                                                ));
+                                                let capturing = self.settings.capturing_keybind == Some(index);
+                                                let text = if capturing { "Press keys...".to_string() } else { ctx.format_shortcut(&keybind.keybind) };
+                                                if ui.selectable_label(capturing, text).on_hover_text("Click then press the new shortcut, click again to cancel").clicked() {
+                                                    self.settings.capturing_keybind = if capturing { None } else { Some(index) };
+                                                    self.settings.keybind_conflict = None;
+                                                }
+                                                if let Some(default) = Settings::default_shortcut(keybind.kind).filter(|default| *default != keybind.keybind) {
+                                                    // The default may have been taken by another action since
+                                                    if ui.small_button("⟲").on_hover_text(format!("Reset to {}", ctx.format_shortcut(&default))).clicked() {
+                                                        self.bind_shortcut(ctx, index, default);
+                                                    }
+                                                }
                                             });
                                         });
                                     });
+                                if ui.button("Reset All").clicked() {
+                                    self.settings.keybinds = default_keybinds();
+                                    self.settings.capturing_keybind = None;
+                                    self.sequencer.set_global_keys(&self.settings);
+                                }
                             }
                         }
                     });
                 });
             });
+        self.settings.show = show_settings;
         self.sequencer
             .update(&mut self.last_instant, ctx, &self.settings);
+        self.poll_play_on_launch(ctx);
+        for event in self.sequencer.events.drain(..) {
+            self.events.broadcast(&event);
+        }
         
-        self.sequencer.show(ctx);
+        self.sequencer.show(ctx, &self.settings);
         self.sequencer.debug_panel(ctx, &mut self.settings);
         self.sequencer.selected_panel(ctx, &self.settings);
         self.sequencer.central_panel(ctx);
         self.sequencer.modal(ctx);
+        self.sequencer.hud(ctx);
+        self.sequencer.problems_window(ctx, &self.settings);
+        self.sequencer.input_prompt_window(ctx);
+        self.sequencer.relink_window(ctx);
+        // The open file may use a different offset to the global one
+        let offset = self.sequencer.playback_settings(&self.settings).offset;
+        self.sequencer.trail_overlay(ctx, &offset);
+        self.sequencer.key_osd(ctx);
+        self.console.show(ctx, offset);
 
         // If sequencer has changed or the file is not uptodate
         self.file_uptodate = !self.sequencer.changed.load(Ordering::Relaxed);
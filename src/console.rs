@@ -0,0 +1,267 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use eframe::egui::{self, Vec2};
+
+use crate::{
+    keyframe::{Keyframe, KeyframeType},
+    player::play_keyframe,
+    util::{string_to_keys, strings_to_keys, template_match_opencv},
+};
+
+const HELP: &str = "\
+move X Y        move the mouse to X, Y
+click [BUTTON]  click left (default), right or middle
+press KEY       press and release a key, e.g. return
+type TEXT       type the given text
+scroll DX DY    scroll by DX, DY
+wait SECONDS    wait before running the next command
+find PATH       find the target image on screen
+magic PATH      find the target image and move the mouse to it
+clear           clear the console
+Separate commands with ; to run them one after the other, e.g. wait 2; type hello";
+
+/// A single step typed into the console
+enum ConsoleCommand {
+    Move(Vec2),
+    Click(rdev::Button),
+    Press(rdev::Key),
+    Type(Vec<rdev::Key>),
+    Scroll(Vec2),
+    Wait(Duration),
+    Find(String),
+    Magic(String),
+}
+
+/// Runs one-off commands through the same simulation as playback, for trying out steps before adding them as keyframes
+pub struct Console {
+    pub show: bool,
+    input: String,
+    output: Vec<String>,
+    sender: Sender<String>,
+    receiver: Receiver<String>,
+    running: Arc<AtomicBool>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            show: false,
+            input: String::new(),
+            output: vec!["Type help for a list of commands".to_string()],
+            sender,
+            receiver,
+            running: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Console {
+    /// Renders the console window
+    ///
+    /// `offset` is the calibration offset used for playback
+    pub fn show(&mut self, ctx: &egui::Context, offset: Vec2) {
+        self.output.extend(self.receiver.try_iter());
+        let mut show = self.show;
+        egui::Window::new("Console")
+            .open(&mut show)
+            .default_size([420., 260.])
+            .show(ctx, |ui| {
+                egui::TopBottomPanel::bottom("console_input")
+                    .show_separator_line(false)
+                    .show_inside(ui, |ui| {
+                        let response = ui.add(
+                            egui::TextEdit::singleline(&mut self.input)
+                                .font(egui::TextStyle::Monospace)
+                                .hint_text("move 960 540")
+                                .desired_width(f32::INFINITY),
+                        );
+                        if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                            let line = std::mem::take(&mut self.input);
+                            self.run(&line, offset);
+                            response.request_focus();
+                        }
+                    });
+                egui::ScrollArea::vertical()
+                    .auto_shrink([false, false])
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &self.output {
+                            ui.monospace(line);
+                        }
+                    });
+            });
+        self.show = show;
+    }
+    /// Runs a line of commands on the console thread
+    fn run(&mut self, line: &str, offset: Vec2) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.output.push(format!("> {}", line));
+        match line {
+            "clear" => {
+                self.output.clear();
+                return;
+            }
+            "help" => {
+                self.output.extend(HELP.lines().map(str::to_string));
+                return;
+            }
+            _ => {}
+        }
+        if self.running.load(Ordering::Relaxed) {
+            self.output
+                .push("Wait for the previous commands to finish".to_string());
+            return;
+        }
+        let commands = match line.split(';').map(parse).collect::<Result<Vec<_>, _>>() {
+            Ok(commands) => commands,
+            Err(err) => {
+                self.output.push(err);
+                return;
+            }
+        };
+
+        self.running.store(true, Ordering::Relaxed);
+        // Dropped once the commands are done, or right away if the thread can't be spawned
+        let running = Running(Arc::clone(&self.running));
+        let sender = self.sender.clone();
+        let _ = thread::Builder::new()
+            .name("Console Thread".to_owned())
+            .spawn(move || {
+                let _running = running;
+                for command in commands {
+                    let _ = sender.send(execute(command, &offset));
+                }
+            });
+    }
+}
+
+/// Lets the console run commands again once dropped, even if a command panics
+struct Running(Arc<AtomicBool>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Parses a single command, returning an error message if it is invalid
+fn parse(command: &str) -> Result<ConsoleCommand, String> {
+    let command = command.trim();
+    let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+    let args = args.trim();
+    let numbers = || -> Result<Vec2, String> {
+        let values: Vec<f32> = args
+            .split_whitespace()
+            .map(|arg| arg.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("{} expects two numbers", name))?;
+        match values[..] {
+            [x, y] => Ok(Vec2::new(x, y)),
+            _ => Err(format!("{} expects two numbers", name)),
+        }
+    };
+    match name {
+        "move" => Ok(ConsoleCommand::Move(numbers()?)),
+        "scroll" => Ok(ConsoleCommand::Scroll(numbers()?)),
+        "click" => match args {
+            "" | "left" => Ok(ConsoleCommand::Click(rdev::Button::Left)),
+            "right" => Ok(ConsoleCommand::Click(rdev::Button::Right)),
+            "middle" => Ok(ConsoleCommand::Click(rdev::Button::Middle)),
+            _ => Err(format!("Unknown mouse button {:?}", args)),
+        },
+        "press" => string_to_keys(&args.to_lowercase())
+            .map(ConsoleCommand::Press)
+            .ok_or_else(|| format!("Unknown key {:?}", args)),
+        "type" => Ok(ConsoleCommand::Type(strings_to_keys(&args.to_string()))),
+        "wait" => args
+            .parse::<f32>()
+            .ok()
+            .and_then(|secs| Duration::try_from_secs_f32(secs).ok())
+            .map(ConsoleCommand::Wait)
+            .ok_or_else(|| "wait expects a number of seconds".to_string()),
+        "find" if !args.is_empty() => Ok(ConsoleCommand::Find(args.to_string())),
+        "magic" if !args.is_empty() => Ok(ConsoleCommand::Magic(args.to_string())),
+        "find" | "magic" => Err(format!("{} expects the path of a target image", name)),
+        _ => Err(format!(
+            "Unknown command {:?}, type help for a list of commands",
+            name
+        )),
+    }
+}
+
+/// Runs a command and describes what happened
+fn execute(command: ConsoleCommand, offset: &Vec2) -> String {
+    let simulate = |keyframe_type: KeyframeType, release: bool| -> Result<(), String> {
+        let keyframe = Keyframe {
+            keyframe_type,
+            ..Default::default()
+        };
+        play_keyframe(&keyframe, true, offset)
+            .and_then(|()| match release {
+                true => play_keyframe(&keyframe, false, offset),
+                false => Ok(()),
+            })
+            .map_err(|err| format!("Failed to simulate - {}", err))
+    };
+    match command {
+        ConsoleCommand::Move(pos) => match simulate(KeyframeType::MouseMove(pos), false) {
+            Ok(()) => format!("Moved to {}, {}", pos.x, pos.y),
+            Err(err) => err,
+        },
+        ConsoleCommand::Click(button) => match simulate(KeyframeType::MouseBtn(button), true) {
+            Ok(()) => format!("Clicked {:?}", button),
+            Err(err) => err,
+        },
+        ConsoleCommand::Press(key) => match simulate(KeyframeType::KeyBtn(key), true) {
+            Ok(()) => format!("Pressed {:?}", key),
+            Err(err) => err,
+        },
+        ConsoleCommand::Type(keys) => {
+            let count = keys.len();
+            match simulate(KeyframeType::KeyStrokes(keys), false) {
+                Ok(()) => format!("Typed {} keys", count),
+                Err(err) => err,
+            }
+        }
+        ConsoleCommand::Scroll(delta) => match simulate(KeyframeType::Scroll(delta), false) {
+            Ok(()) => format!("Scrolled {}, {}", delta.x, delta.y),
+            Err(err) => err,
+        },
+        ConsoleCommand::Wait(duration) => {
+            thread::sleep(duration);
+            format!("Waited {}s", duration.as_secs_f32())
+        }
+        ConsoleCommand::Find(path) => match find(&path) {
+            Ok(center) => format!("Found target at {}, {}", center.x, center.y),
+            Err(err) => err,
+        },
+        ConsoleCommand::Magic(path) => match find(&path) {
+            Ok(center) => match simulate(KeyframeType::MouseMove(center), false) {
+                Ok(()) => format!("Found target at {}, {} and moved to it", center.x, center.y),
+                Err(err) => err,
+            },
+            Err(err) => err,
+        },
+    }
+}
+
+/// Locates the target image at `path` on screen
+fn find(path: &str) -> Result<Vec2, String> {
+    let target = image::ImageReader::open(path)
+        .map_err(|e| format!("Failed to open {:?}: {}", path, e))?
+        .decode()
+        .map_err(|e| format!("Failed to decode {:?}: {}", path, e))?;
+    template_match_opencv(target).ok_or_else(|| "Target not found".to_string())
+}
@@ -0,0 +1,626 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use uuid::{Bytes, Uuid};
+
+use crate::{
+    expression::{self, Variables},
+    keyframe::{
+        label_time, next_event, pick_branch, reset_jumps, subroutine_index, Keyframe, KeyframeType,
+    },
+    plugin::Running,
+    sequencer::PlaybackEvent,
+};
+
+/// How long sequential playback waits for the target of a magic move to appear before giving up
+const TARGET_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time between looking for the target of a magic move while waiting for it
+const TARGET_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Longest sleep between checks of whether playback was stopped during a delay
+const DELAY_SLICE: Duration = Duration::from_millis(10);
+
+/// What playback is played on, the editor or the headless player
+///
+/// The engine decides which keyframes start and end and where the playhead goes,
+/// the host does everything else, from simulating the input to showing what happened.
+pub trait Host {
+    /// Variables shared by every keyframe while playing the given row of the data source, without the counters
+    fn variables(&self, row: usize) -> Variables;
+    /// Whether keyframes are shown instead of simulated, which also means the screen never changes
+    fn dry_run(&self) -> bool {
+        false
+    }
+    /// Whether the playhead steps from one keyframe to the next instead of following the clock
+    fn sequential(&self) -> bool;
+    /// Called as the playhead enters the keyframe at `index`, returns whether to play it now
+    /// or to hold the playhead there until [`Engine::step`] is called again
+    fn entering(
+        &mut self,
+        _keyframes: &[Keyframe],
+        _index: usize,
+        _time: f32,
+    ) -> Result<bool, Stop> {
+        Ok(true)
+    }
+    /// Waits for the random delay of a keyframe before it plays, see [`sleep_unless_stopped`]
+    ///
+    /// Fails with [`Stop::Halted`] if playback is stopped while waiting, so the keyframe isn't played
+    fn delay(&mut self, delay: Duration) -> Result<(), Stop>;
+    /// Whether the target image at `path` is on screen right now
+    fn target_found(&mut self, path: &str) -> Result<bool, String>;
+    /// Starts the keyframe when `start`, or ends it
+    fn play(&mut self, keyframe: &Keyframe, start: bool) -> Result<(), String>;
+    /// Starts the WASM plugin at `path` on its own thread, see [`crate::plugin::spawn`]
+    fn run_plugin(&mut self, path: &str, argument: &str) -> Result<Running, String>;
+    fn event(&mut self, event: PlaybackEvent);
+}
+
+/// Why playback stopped before the end
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// An abort keyframe stopped playback on purpose, with its message
+    Aborted(String),
+    /// The system refused the input simulated for the keyframe at the index
+    SimulationFailed(usize, String),
+    /// Playback couldn't go on, with a title and message describing why
+    Failed(&'static str, String),
+    /// The host stopped playback and has already dealt with it, e.g. when a fail was detected
+    Halted,
+}
+
+/// Whether the playhead can move on after a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    Played,
+    /// A keyframe is holding the playhead where it is
+    Held,
+}
+
+/// A magic move holding the playhead until its target appears on screen
+struct Waiting {
+    uid: Bytes,
+    since: Instant,
+    /// When the screen was last checked for the target
+    checked: Option<Instant>,
+}
+
+/// The playback state machine shared by the editor and the headless player
+///
+/// Moves the playhead through the keyframes each step, starting the keyframes it enters, ending the ones it leaves
+/// and sending it elsewhere for loops, branches, jumps and calls.
+#[derive(Default)]
+pub struct Engine {
+    /// Keyframes the playhead is currently inside of
+    pub playing: HashSet<Bytes>,
+    /// Values of the counters changed by counter keyframes since playback started,
+    /// they keep counting across repeats and rows unless a counter keyframe resets them
+    pub counters: HashMap<String, i64>,
+    /// Row of the data source being played
+    pub row: usize,
+    /// Repeats finished so far
+    repeat: u32,
+    /// The subroutines being played and where to go back to once each ends, innermost last
+    calls: Vec<(Bytes, f32)>,
+    /// The magic move waiting for its target during sequential playback, if any
+    waiting: Option<Waiting>,
+    /// The plugin holding the playhead until it returns and the index of its keyframe, if any
+    plugin: Option<(usize, Running)>,
+}
+
+/// End of the last enabled keyframe, where a pass through the sequence is done
+pub fn end(keyframes: &[Keyframe]) -> f32 {
+    keyframes
+        .iter()
+        .filter(|keyframe| keyframe.enabled)
+        .map(|keyframe| keyframe.timestamp + keyframe.duration)
+        .fold(0., f32::max)
+}
+
+/// Sleeps for `duration` a slice at a time, waking early once `stopped` returns true, and returns how long it slept
+///
+/// Random delays last up to a minute, which would otherwise hold up stopping playback for as long
+pub fn sleep_unless_stopped(duration: Duration, stopped: impl Fn() -> bool) -> Duration {
+    let start = Instant::now();
+    while !stopped() {
+        let left = duration.saturating_sub(start.elapsed());
+        if left.is_zero() {
+            break;
+        }
+        std::thread::sleep(left.min(DELAY_SLICE));
+    }
+    start.elapsed().min(duration)
+}
+
+impl Engine {
+    /// Starts playback over from the first row and repeat, with the counters back at 0
+    pub fn start(&mut self, keyframes: &mut [Keyframe]) {
+        self.counters.clear();
+        self.row = 0;
+        self.repeat = 0;
+        self.restart(keyframes);
+    }
+    /// Gets ready for another pass through the sequence
+    fn restart(&mut self, keyframes: &mut [Keyframe]) {
+        self.playing.clear();
+        self.calls.clear();
+        self.stop();
+        reset_jumps(keyframes);
+        for keyframe in keyframes.iter_mut() {
+            keyframe.clear_branch();
+        }
+    }
+    /// Moves on to the next row of the data source, or the next repeat once every row has been played
+    ///
+    /// Returns `false` once every row has been played `repeats` times, unless playback loops `forever`.
+    /// Otherwise the playhead goes back to the start.
+    pub fn next_pass(
+        &mut self,
+        keyframes: &mut [Keyframe],
+        time: &mut f32,
+        rows: usize,
+        repeats: u32,
+        forever: bool,
+    ) -> bool {
+        if self.row + 1 < rows {
+            self.row += 1;
+        } else if forever || self.repeat + 1 < repeats {
+            self.repeat = self.repeat.saturating_add(1);
+            self.row = 0;
+        } else {
+            return false;
+        }
+        *time = 0.;
+        self.restart(keyframes);
+        true
+    }
+    /// Whether a magic move is holding the playhead until its target appears, or a plugin until it returns,
+    /// [`Engine::step`] should keep being called to check again
+    pub fn is_waiting(&self) -> bool {
+        self.waiting.is_some() || self.plugin.is_some()
+    }
+    /// Stops waiting for targets and stops the running plugin, for when playback stops part way
+    pub fn stop(&mut self) {
+        self.waiting = None;
+        self.plugin = None;
+    }
+    /// Where the playhead goes next, straight to the next keyframe when sequential or along with the clock otherwise
+    ///
+    /// The playhead stays put while a magic move is waiting for its target or a plugin is running.
+    pub fn advance(&self, keyframes: &[Keyframe], time: &mut f32, elapsed: f32, sequential: bool) {
+        if self.is_waiting() {
+            return;
+        }
+        match sequential {
+            true => *time = next_event(keyframes, *time).unwrap_or(end(keyframes)),
+            false => *time += elapsed,
+        }
+    }
+    /// Starts the keyframes the playhead at `time` has entered and ends the ones it has left,
+    /// moving the playhead for those that send it elsewhere
+    pub fn step(
+        &mut self,
+        keyframes: &mut [Keyframe],
+        time: &mut f32,
+        host: &mut dyn Host,
+    ) -> Result<Progress, Stop> {
+        if let Some((i, plugin)) = &self.plugin {
+            let i = *i;
+            match plugin.finished() {
+                None => return Ok(Progress::Held),
+                Some(result) => {
+                    self.plugin = None;
+                    result.map_err(|err| Stop::SimulationFailed(i, err))?;
+                }
+            }
+        }
+        for i in 0..keyframes.len() {
+            if !keyframes[i].enabled {
+                continue;
+            }
+            let uid = keyframes[i].uid;
+            let timestamp = keyframes[i].timestamp;
+            let inside = *time >= timestamp && *time <= timestamp + keyframes[i].duration;
+            let was_playing = self.playing.contains(&uid);
+            // Once the picked branch of a random or if keyframe ends, the other branches are skipped
+            if inside && was_playing {
+                if let Some((branch, branches)) = keyframes[i].picked_branch() {
+                    if *time > keyframes[i].branch_range(branch, branches).1 {
+                        *time = timestamp + keyframes[i].duration;
+                    }
+                }
+            }
+            if inside == was_playing {
+                continue;
+            }
+            match inside {
+                true => {
+                    if !self.target_ready(keyframes, i, host)? {
+                        return Ok(Progress::Held);
+                    }
+                    if !host.entering(keyframes, i, *time)? {
+                        return Ok(Progress::Held);
+                    }
+                    self.playing.insert(uid);
+                    self.start_keyframe(keyframes, i, time, host)?;
+                    // Nothing else starts until the plugin returns
+                    if self.plugin.is_some() {
+                        return Ok(Progress::Held);
+                    }
+                }
+                false => {
+                    self.playing.remove(&uid);
+                    self.end_keyframe(keyframes, i, time, host)?;
+                }
+            }
+        }
+        Ok(Progress::Played)
+    }
+    fn start_keyframe(
+        &mut self,
+        keyframes: &mut [Keyframe],
+        i: usize,
+        time: &mut f32,
+        host: &mut dyn Host,
+    ) -> Result<(), Stop> {
+        let delay = keyframes[i].random_delay();
+        if !delay.is_zero() {
+            host.delay(delay)?;
+        }
+        let resolved = self.resolve(keyframes, i, host)?;
+        match resolved.keyframe_type {
+            KeyframeType::Abort(message) => return Err(Stop::Aborted(message)),
+            KeyframeType::Log(message) => {
+                log::info!("Log: {}", message);
+                host.event(PlaybackEvent::Log { message });
+            }
+            KeyframeType::Counter(name, action) => {
+                action.apply(self.counters.entry(name).or_default())
+            }
+            KeyframeType::WhileImage(path, present, iterations) => {
+                // The screen doesn't change during a dry run, so the keyframes within play once
+                let holds = match host.dry_run() {
+                    true => iterations.is_none(),
+                    false => {
+                        host.target_found(&path)
+                            .map_err(|err| target_not_found(i, err))?
+                            == present
+                    }
+                };
+                if let KeyframeType::WhileImage(_, _, iterations) = &mut keyframes[i].keyframe_type
+                {
+                    let next = iterations.map_or(1, |n| n + 1);
+                    *iterations = holds.then_some(next);
+                }
+                // The keyframes within are skipped once the condition no longer holds
+                if !holds {
+                    *time = keyframes[i].after_end();
+                }
+            }
+            KeyframeType::If(path, present, _) => {
+                // Then is the first branch and else the second, a dry run always takes the first
+                let found = match host.dry_run() {
+                    true => present,
+                    false => host
+                        .target_found(&path)
+                        .map_err(|err| target_not_found(i, err))?,
+                };
+                let branch = if found == present { 0 } else { 1 };
+                if let KeyframeType::If(_, _, picked) = &mut keyframes[i].keyframe_type {
+                    *picked = Some(branch);
+                }
+                *time = keyframes[i].branch_range(branch, 2).0;
+            }
+            // Plugins run on their own thread so whoever is playing can still stop them
+            KeyframeType::Plugin(path, argument) if !host.dry_run() => {
+                let running = host
+                    .run_plugin(&path, &argument)
+                    .map_err(|err| Stop::SimulationFailed(i, err))?;
+                self.plugin = Some((i, running));
+            }
+            _ => {
+                host.play(&resolved, true)
+                    .map_err(|err| Stop::SimulationFailed(i, err))?;
+            }
+        }
+        host.event(PlaybackEvent::KeyframeStarted {
+            index: i,
+            uid: Uuid::from_bytes_le(keyframes[i].uid).to_string(),
+            kind: keyframes[i].kind,
+        });
+        match &mut keyframes[i].keyframe_type {
+            // Random keyframes send the playhead to the branch picked for this pass
+            KeyframeType::Random(weights, picked) => {
+                let branch = pick_branch(weights);
+                *picked = Some(branch);
+                let branches = weights.len();
+                *time = keyframes[i].branch_range(branch, branches).0;
+            }
+            // Jump keyframes send the playhead to their label until they run out of jumps
+            KeyframeType::Jump(label, max_jumps, jumps) if *jumps < *max_jumps => {
+                *jumps += 1;
+                let label = label.clone();
+                *time = label_time(keyframes, &label).ok_or_else(|| {
+                    Stop::Failed(
+                        "Missing Label",
+                        format!(
+                            "Stopped playback, there is no label named {:?} to jump to",
+                            label
+                        ),
+                    )
+                })?;
+            }
+            // Subroutines only play when called, otherwise the playhead skips over them
+            KeyframeType::Subroutine(_) => {
+                if self.calls.last().map(|(uid, _)| *uid) != Some(keyframes[i].uid) {
+                    *time = keyframes[i].after_end();
+                }
+            }
+            // Call keyframes send the playhead to their subroutine, which sends it back once it ends
+            KeyframeType::Call(name) => {
+                let name = name.clone();
+                match subroutine_index(keyframes, &name) {
+                    Some(j) if self.calls.iter().any(|(uid, _)| *uid == keyframes[j].uid) => {
+                        return Err(Stop::Failed(
+                            "Recursive Call",
+                            format!(
+                                "Stopped playback, the subroutine named {:?} calls itself",
+                                name
+                            ),
+                        ));
+                    }
+                    Some(j) => {
+                        self.calls
+                            .push((keyframes[j].uid, keyframes[i].after_end()));
+                        *time = keyframes[j].timestamp;
+                    }
+                    None => {
+                        return Err(Stop::Failed(
+                            "Missing Subroutine",
+                            format!(
+                                "Stopped playback, there is no subroutine named {:?} to call",
+                                name
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+    /// The keyframe at `i` with its expressions replaced by their values
+    fn resolve(&self, keyframes: &[Keyframe], i: usize, host: &dyn Host) -> Result<Keyframe, Stop> {
+        let variables = expression::with_counters(&host.variables(self.row), &self.counters);
+        expression::resolve(keyframes, i, &variables).map_err(|err| {
+            Stop::Failed(
+                "Invalid Expression",
+                format!("Stopped playback at keyframe {}: {}", i, err),
+            )
+        })
+    }
+    /// Whether the keyframe at `i` can start, which during sequential playback means
+    /// the target of a magic move is on screen
+    ///
+    /// Only looks at the screen every [`TARGET_POLL_INTERVAL`] so the thread playing is never blocked,
+    /// and gives up once the target hasn't appeared within [`TARGET_TIMEOUT`].
+    fn target_ready(
+        &mut self,
+        keyframes: &[Keyframe],
+        i: usize,
+        host: &mut dyn Host,
+    ) -> Result<bool, Stop> {
+        if !host.sequential() || host.dry_run() || keyframes[i].kind != 6 {
+            return Ok(true);
+        }
+        let KeyframeType::MagicMove(path) = self.resolve(keyframes, i, host)?.keyframe_type else {
+            return Ok(true);
+        };
+        let uid = keyframes[i].uid;
+        let now = Instant::now();
+        if self
+            .waiting
+            .as_ref()
+            .is_some_and(|waiting| waiting.uid != uid)
+        {
+            self.waiting = None;
+        }
+        let waiting = self.waiting.get_or_insert_with(|| Waiting {
+            uid,
+            since: now,
+            checked: None,
+        });
+        if waiting
+            .checked
+            .is_some_and(|checked| now - checked < TARGET_POLL_INTERVAL)
+        {
+            return Ok(false);
+        }
+        waiting.checked = Some(now);
+        let since = waiting.since;
+        match host.target_found(&path) {
+            Ok(true) => {
+                log::info!("Target {:?} appeared after {:?}", path, since.elapsed());
+                self.waiting = None;
+                Ok(true)
+            }
+            Ok(false) if since.elapsed() < TARGET_TIMEOUT => Ok(false),
+            Ok(false) => {
+                self.waiting = None;
+                Err(target_not_found(
+                    i,
+                    format!(
+                        "Target image {:?} didn't appear within {} seconds",
+                        path,
+                        TARGET_TIMEOUT.as_secs()
+                    ),
+                ))
+            }
+            Err(err) => {
+                self.waiting = None;
+                Err(target_not_found(i, err))
+            }
+        }
+    }
+    fn end_keyframe(
+        &mut self,
+        keyframes: &mut [Keyframe],
+        i: usize,
+        time: &mut f32,
+        host: &mut dyn Host,
+    ) -> Result<(), Stop> {
+        host.play(&keyframes[i], false)
+            .map_err(|err| Stop::SimulationFailed(i, err))?;
+        host.event(PlaybackEvent::KeyframeFinished {
+            index: i,
+            uid: Uuid::from_bytes_le(keyframes[i].uid).to_string(),
+            kind: keyframes[i].kind,
+        });
+        if let KeyframeType::Loop(repeats, j) = keyframes[i].keyframe_type {
+            if j < repeats {
+                keyframes[i].keyframe_type = KeyframeType::Loop(repeats, j + 1);
+                *time = keyframes[i].timestamp;
+            } else {
+                keyframes[i].keyframe_type = KeyframeType::Loop(repeats, 1);
+            }
+        }
+        keyframes[i].clear_branch();
+        // While image loops go back to their start to check the condition again
+        if matches!(
+            keyframes[i].keyframe_type,
+            KeyframeType::WhileImage(_, _, Some(_))
+        ) {
+            *time = keyframes[i].timestamp;
+        }
+        // Called subroutines send the playhead back to the end of the call
+        if self.calls.last().map(|(uid, _)| *uid) == Some(keyframes[i].uid) {
+            if let Some((_, back)) = self.calls.pop() {
+                *time = back;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn target_not_found(i: usize, err: String) -> Stop {
+    Stop::Failed(
+        "Target Not Found",
+        format!("Stopped playback at keyframe {}: {}", i, err),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A host that only records the log keyframes played, with the target image either always or never on screen
+    #[derive(Default)]
+    struct Recorder {
+        found: bool,
+        sequential: bool,
+        logs: Vec<String>,
+    }
+
+    impl Host for Recorder {
+        fn variables(&self, _row: usize) -> Variables {
+            Variables::new()
+        }
+        fn sequential(&self) -> bool {
+            self.sequential
+        }
+        fn delay(&mut self, _delay: Duration) -> Result<(), Stop> {
+            Ok(())
+        }
+        fn target_found(&mut self, _path: &str) -> Result<bool, String> {
+            Ok(self.found)
+        }
+        fn play(&mut self, _keyframe: &Keyframe, _start: bool) -> Result<(), String> {
+            Ok(())
+        }
+        fn run_plugin(&mut self, _path: &str, _argument: &str) -> Result<Running, String> {
+            Err("Plugins aren't run by these tests".to_string())
+        }
+        fn event(&mut self, event: PlaybackEvent) {
+            if let PlaybackEvent::Log { message } = event {
+                self.logs.push(message);
+            }
+        }
+    }
+
+    fn log(timestamp: f32, message: &str) -> Keyframe {
+        Keyframe {
+            timestamp,
+            duration: 0.1,
+            keyframe_type: KeyframeType::Log(message.to_string()),
+            kind: 15,
+            ..Default::default()
+        }
+    }
+
+    /// Plays an if keyframe looking for a target image, with a log keyframe in each branch
+    fn play_if(found: bool) -> Vec<String> {
+        let mut keyframes = vec![
+            Keyframe {
+                duration: 2.,
+                keyframe_type: KeyframeType::If("target.png".to_string(), true, None),
+                kind: 20,
+                ..Default::default()
+            },
+            log(0.2, "then"),
+            log(1.2, "else"),
+        ];
+        let mut host = Recorder {
+            found,
+            ..Default::default()
+        };
+        let mut engine = Engine::default();
+        let mut time = 0.;
+        engine.start(&mut keyframes);
+        while time <= end(&keyframes) + 0.1 {
+            engine.step(&mut keyframes, &mut time, &mut host).unwrap();
+            engine.advance(&keyframes, &mut time, 0.05, false);
+        }
+        host.logs
+    }
+
+    #[test]
+    fn takes_the_then_branch_when_the_target_is_found() {
+        assert_eq!(play_if(true), vec!["then"]);
+    }
+
+    #[test]
+    fn takes_the_else_branch_when_the_target_is_missing() {
+        assert_eq!(play_if(false), vec!["else"]);
+    }
+
+    #[test]
+    fn holds_a_magic_move_until_its_target_appears() {
+        let mut keyframes = vec![Keyframe {
+            timestamp: 0.5,
+            duration: 0.1,
+            keyframe_type: KeyframeType::MagicMove("target.png".to_string()),
+            kind: 6,
+            ..Default::default()
+        }];
+        let mut host = Recorder {
+            sequential: true,
+            ..Default::default()
+        };
+        let mut engine = Engine::default();
+        let mut time = 0.5;
+        engine.start(&mut keyframes);
+        let step = engine.step(&mut keyframes, &mut time, &mut host);
+        assert_eq!(step, Ok(Progress::Held));
+        assert!(engine.is_waiting());
+        engine.advance(&keyframes, &mut time, 0., true);
+        assert_eq!(time, 0.5);
+
+        host.found = true;
+        std::thread::sleep(TARGET_POLL_INTERVAL);
+        let step = engine.step(&mut keyframes, &mut time, &mut host);
+        assert_eq!(step, Ok(Progress::Played));
+        assert!(!engine.is_waiting());
+        assert!(engine.playing.contains(&keyframes[0].uid));
+    }
+}
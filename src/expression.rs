@@ -0,0 +1,406 @@
+use std::{collections::HashMap, iter::Peekable, str::Chars};
+
+use egui::Vec2;
+use serde::{Deserialize, Serialize};
+
+use crate::keyframe::{Keyframe, KeyframeType};
+
+/// Variables available to expressions during playback, by name
+pub type Variables = HashMap<String, String>;
+
+/// What kind of value an input asks for
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum InputKind {
+    Text,
+    Number,
+    File,
+}
+
+impl InputKind {
+    pub const ALL: [InputKind; 3] = [InputKind::Text, InputKind::Number, InputKind::File];
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputKind::Text => "Text",
+            InputKind::Number => "Number",
+            InputKind::File => "File",
+        }
+    }
+}
+
+/// A variable whose value is asked for each time playback starts
+///
+/// `value` is the last value given, which is offered again next time and used when playing without the editor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Input {
+    pub name: String,
+    pub kind: InputKind,
+    pub value: String,
+}
+
+impl Input {
+    /// Whether `value` is something this input accepts
+    pub fn accepts(&self, value: &str) -> bool {
+        match self.kind {
+            InputKind::Number => value.trim().parse::<f64>().is_ok(),
+            InputKind::Text | InputKind::File => true,
+        }
+    }
+}
+
+/// Names of the fields of a keyframe type that can be given as expressions
+pub fn expression_fields(keyframe_type: &KeyframeType) -> &'static [&'static str] {
+    match keyframe_type {
+        KeyframeType::MouseMove(_) => &["x", "y"],
+        KeyframeType::Scroll(_) => &["dx", "dy"],
+        KeyframeType::Wait(_) => &["seconds"],
+        _ => &[],
+    }
+}
+
+/// Variables shared by every keyframe during a run of the sequence
+///
+/// These are the sequence's own variables, overridden by the values given to its `inputs`
+/// and then by the columns of the current `row` of the data source along with `row`, the 0 based index of that row
+pub fn base_variables(
+    variables: &[(String, String)],
+    inputs: &[Input],
+    row: Option<(usize, &Variables)>,
+) -> Variables {
+    let mut vars: Variables = variables.iter().cloned().collect();
+    vars.extend(
+        inputs
+            .iter()
+            .map(|input| (input.name.clone(), input.value.clone())),
+    );
+    if let Some((index, row)) = row {
+        vars.extend(row.iter().map(|(k, v)| (k.clone(), v.clone())));
+        vars.insert("row".to_string(), index.to_string());
+    }
+    vars
+}
+
+/// `base` along with the current value of each counter, which takes the place of a variable with the same name
+pub fn with_counters(base: &Variables, counters: &HashMap<String, i64>) -> Variables {
+    let mut vars = base.clone();
+    vars.extend(
+        counters
+            .iter()
+            .map(|(name, value)| (name.clone(), value.to_string())),
+    );
+    vars
+}
+
+/// Variables visible to the keyframe at `index`
+///
+/// These are the `base` variables plus `i`, the 0 based iteration of the innermost loop around the keyframe,
+/// and `n`, the number of times that loop repeats
+pub fn variables_for(keyframes: &[Keyframe], index: usize, base: &Variables) -> Variables {
+    let mut vars = base.clone();
+    let timestamp = keyframes[index].timestamp;
+    let innermost = keyframes
+        .iter()
+        .enumerate()
+        .filter_map(|(j, keyframe)| match keyframe.keyframe_type {
+            KeyframeType::Loop(repeats, iteration)
+                if j != index
+                    && keyframe.enabled
+                    && timestamp >= keyframe.timestamp
+                    && timestamp <= keyframe.timestamp + keyframe.duration =>
+            {
+                Some((keyframe.duration, repeats, iteration))
+            }
+            _ => None,
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    let (n, i) = innermost.map_or((1, 0), |(_, repeats, iteration)| {
+        (repeats, iteration.saturating_sub(1))
+    });
+    vars.insert("i".to_string(), i.to_string());
+    vars.insert("n".to_string(), n.to_string());
+    vars
+}
+
+/// Returns a copy of the keyframe at `index` with every field that has an expression replaced by its value,
+/// any text or message interpolated and environment variables in file paths expanded
+pub fn resolve(keyframes: &[Keyframe], index: usize, base: &Variables) -> Result<Keyframe, String> {
+    let keyframe = &keyframes[index];
+    let mut resolved = keyframe.clone();
+    if keyframe.expressions.is_empty()
+        && !matches!(
+            keyframe.keyframe_type,
+            KeyframeType::Text(_)
+                | KeyframeType::MagicMove(_)
+                | KeyframeType::WhileImage(_, _, _)
+                | KeyframeType::If(_, _, _)
+                | KeyframeType::Abort(_)
+                | KeyframeType::Notify(_)
+                | KeyframeType::Beep(_)
+                | KeyframeType::Log(_)
+                | KeyframeType::Plugin(_, _)
+        )
+    {
+        return Ok(resolved);
+    }
+    let variables = variables_for(keyframes, index, base);
+    let field = |name: &str, recorded: f32| -> Result<f32, String> {
+        match keyframe.expressions.get(name) {
+            Some(expression) => evaluate(expression, &variables)
+                .map(|value| value as f32)
+                .map_err(|err| format!("{} = {}: {}", name, expression, err)),
+            None => Ok(recorded),
+        }
+    };
+    match &mut resolved.keyframe_type {
+        KeyframeType::MouseMove(pos) => *pos = Vec2::new(field("x", pos.x)?, field("y", pos.y)?),
+        KeyframeType::Scroll(delta) => {
+            *delta = Vec2::new(field("dx", delta.x)?, field("dy", delta.y)?)
+        }
+        KeyframeType::Wait(secs) => {
+            *secs = field("seconds", *secs)?;
+            if !secs.is_finite() || *secs < 0. {
+                return Err(format!("Can't wait for {} seconds", secs));
+            }
+        }
+        KeyframeType::Text(text)
+        | KeyframeType::Abort(text)
+        | KeyframeType::Notify(text)
+        | KeyframeType::Log(text) => *text = interpolate(text, &variables)?,
+        KeyframeType::MagicMove(path)
+        | KeyframeType::WhileImage(path, _, _)
+        | KeyframeType::If(path, _, _)
+        | KeyframeType::Beep(path) => *path = expand_env(path)?,
+        KeyframeType::Plugin(path, argument) => {
+            *path = expand_env(path)?;
+            *argument = interpolate(argument, &variables)?;
+        }
+        _ => {}
+    }
+    Ok(resolved)
+}
+
+/// Replaces each `{expression}` in `text` with its value, `{{` and `}}` are literal braces
+///
+/// A variable on its own is replaced as is, so variables don't have to be numbers to be typed out.
+/// Environment variables are expanded as in [`expand_env`]
+pub fn interpolate(text: &str, variables: &Variables) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("Missing }} in {:?}", text))?;
+                let expression = &rest[..end];
+                match variables.get(expression.trim()) {
+                    Some(value) => result.push_str(value),
+                    None => {
+                        let value = evaluate(expression, variables)
+                            .map_err(|err| format!("{{{}}}: {}", expression, err))?;
+                        result.push_str(&value.to_string());
+                    }
+                }
+                chars = rest[end + 1..].chars();
+            }
+            '$' => result.push_str(&env_reference(&mut chars)?),
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// Replaces each `$NAME` or `${NAME}` in `text` with the value of that environment variable, `$$` is a literal `$`
+///
+/// A `$` that isn't followed by a name, e.g. `$5`, is left as is
+pub fn expand_env(text: &str) -> Result<String, String> {
+    let mut result = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' => result.push_str(&env_reference(&mut chars)?),
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// Reads the environment variable referenced after a `$`, consuming its name and returning its value
+fn env_reference(chars: &mut Chars<'_>) -> Result<String, String> {
+    let rest = chars.as_str();
+    if let Some(remaining) = rest.strip_prefix('$') {
+        *chars = remaining.chars();
+        return Ok("$".to_string());
+    }
+    let (name, remaining) = match rest.strip_prefix('{') {
+        Some(braced) => {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| "Missing } after ${".to_string())?;
+            (&braced[..end], &braced[end + 1..])
+        }
+        None => {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        }
+    };
+    if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        return Ok("$".to_string());
+    }
+    let value =
+        std::env::var(name).map_err(|_| format!("Environment variable {} is not set", name))?;
+    *chars = remaining.chars();
+    Ok(value)
+}
+
+/// Evaluates an arithmetic expression such as `300 + i * 24`
+///
+/// Supports `+ - * / % ^`, parentheses, variables and the functions `min`, `max`, `abs`, `floor`, `ceil` and `round`
+pub fn evaluate(expression: &str, variables: &Variables) -> Result<f64, String> {
+    let mut parser = Parser {
+        chars: expression.chars().peekable(),
+        variables,
+    };
+    let value = parser.expression()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        Some(c) => Err(format!("Unexpected {:?}", c)),
+        None => Ok(value),
+    }
+}
+
+/// Recursive descent parser that evaluates as it parses
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    variables: &'a Variables,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+    /// Consumes `c` if it is the next character
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if_eq(&c).is_some()
+    }
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        loop {
+            if self.eat('*') {
+                value *= self.factor()?;
+            } else if self.eat('/') {
+                value /= self.factor()?;
+            } else if self.eat('%') {
+                value %= self.factor()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+    fn factor(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            return Ok(-self.factor()?);
+        }
+        if self.eat('+') {
+            return self.factor();
+        }
+        let value = self.primary()?;
+        if self.eat('^') {
+            return Ok(value.powf(self.factor()?));
+        }
+        Ok(value)
+    }
+    fn primary(&mut self) -> Result<f64, String> {
+        self.skip_whitespace();
+        if self.eat('(') {
+            let value = self.expression()?;
+            if !self.eat(')') {
+                return Err("Missing )".to_string());
+            }
+            return Ok(value);
+        }
+        match self.chars.peek() {
+            Some(c) if c.is_ascii_digit() || *c == '.' => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                number
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid number {:?}", number))
+            }
+            Some(c) if c.is_alphabetic() || *c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                if self.eat('(') {
+                    self.function(&name)
+                } else {
+                    self.variable(&name)
+                }
+            }
+            Some(c) => Err(format!("Unexpected {:?}", c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+    fn variable(&self, name: &str) -> Result<f64, String> {
+        let value = self
+            .variables
+            .get(name)
+            .ok_or_else(|| format!("Unknown variable {:?}", name))?;
+        value
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("Variable {:?} is not a number ({:?})", name, value))
+    }
+    /// Evaluates a call to a built in function, the opening parenthesis has already been consumed
+    fn function(&mut self, name: &str) -> Result<f64, String> {
+        let mut args = vec![];
+        if !self.eat(')') {
+            loop {
+                args.push(self.expression()?);
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(format!("Missing ) after arguments to {}", name));
+                }
+            }
+        }
+        match (name, args.as_slice()) {
+            ("min", [a, b]) => Ok(a.min(*b)),
+            ("max", [a, b]) => Ok(a.max(*b)),
+            ("abs", [a]) => Ok(a.abs()),
+            ("floor", [a]) => Ok(a.floor()),
+            ("ceil", [a]) => Ok(a.ceil()),
+            ("round", [a]) => Ok(a.round()),
+            ("min" | "max", _) => Err(format!("{} takes 2 arguments", name)),
+            ("abs" | "floor" | "ceil" | "round", _) => Err(format!("{} takes 1 argument", name)),
+            _ => Err(format!("Unknown function {:?}", name)),
+        }
+    }
+}
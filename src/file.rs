@@ -0,0 +1,925 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bincode::Options;
+use image::{
+    codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder},
+    ImageEncoder,
+};
+use serde::Deserialize;
+use uuid::{Bytes, Uuid};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+use crate::{
+    expression::Variables,
+    guide,
+    keyframe::{Keyframe, KeyframeType},
+    sequencer::SequencerState,
+    util::{screenshot_dimensions, screenshot_quality, PngCompression},
+};
+
+/// Current version of the ".auto" file format
+///
+/// Since version 3 the keyframe data is stored as JSON, so fields added with `#[serde(default)]` don't need a new version.
+/// Increment this whenever `SequencerState` or `Keyframe` change shape in any other way and add a migration to [`migrate`]
+pub const FILE_VERSION: u32 = 3;
+/// Entry storing the file format version as text
+const VERSION_ENTRY: &str = "version";
+/// Entry storing the keyframes and general sequencer state
+const DATA_ENTRY: &str = "data";
+/// Entry of an ".autosnippet" file storing its keyframes
+const SNIPPET_ENTRY: &str = "keyframes";
+/// Folder of an ".autosnippet" file storing the target images of its magic moves,
+/// each as "targets/<keyframe uuid>/<file name>"
+const TARGETS_FOLDER: &str = "targets/";
+/// Folder of the ".auto" file embedded in a runnable storing the files its keyframes read while playing,
+/// each as "assets/<keyframe uuid>/<file name>", or "assets/data/<file name>" for the data source
+const ASSETS_FOLDER: &str = "assets/";
+/// How long an autosave of a file that was never saved, or has since been deleted, is kept for recovery
+const AUTOSAVE_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Ends an executable exported by [`export_runnable`], preceded by the length of the embedded ".auto" file
+const RUNNABLE_MAGIC: &[u8; 8] = b"AUTORUN1";
+
+/// Progress of a save or load, shared with the thread doing it
+#[derive(Default)]
+pub struct Progress {
+    done: AtomicUsize,
+    total: AtomicUsize,
+}
+
+impl Progress {
+    fn start(&self, total: usize) {
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+    fn step(&self) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Returns how much of the work is done, from `0.0` to `1.0`
+    pub fn fraction(&self) -> f32 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.;
+        }
+        self.done.load(Ordering::Relaxed) as f32 / total as f32
+    }
+}
+
+/// The contents of a loaded ".auto" file
+pub struct LoadedFile {
+    pub state: SequencerState,
+    pub images: HashMap<Bytes, Vec<u8>>,
+    /// Fields that were missing from a legacy file and were given default values
+    pub defaulted: Vec<&'static str>,
+}
+
+/// Keyframes shared between sequences as an ".autosnippet" file
+#[derive(Default)]
+pub struct Snippet {
+    pub keyframes: Vec<Keyframe>,
+    /// Screenshots keyed by the UUID of their keyframe, as raw RGBA
+    pub images: HashMap<Bytes, Vec<u8>>,
+    /// Target images of magic moves keyed by the UUID of their keyframe, as the file name and contents of the image
+    pub targets: HashMap<Bytes, (String, Vec<u8>)>,
+}
+
+/// Keyframe layout of versions 1 and 2, stored with bincode
+#[derive(Deserialize)]
+struct KeyframeV2 {
+    timestamp: f32,
+    duration: f32,
+    keyframe_type: KeyframeType,
+    kind: u8,
+    enabled: bool,
+    uid: Bytes,
+}
+
+impl From<KeyframeV2> for Keyframe {
+    fn from(kf: KeyframeV2) -> Self {
+        Self {
+            timestamp: kf.timestamp,
+            duration: kf.duration,
+            keyframe_type: kf.keyframe_type,
+            kind: kf.kind,
+            enabled: kf.enabled,
+            uid: kf.uid,
+            ..Default::default()
+        }
+    }
+}
+
+/// Keyframe layout from before keyframes could be disabled
+#[derive(Deserialize)]
+struct KeyframeWithoutEnabled {
+    timestamp: f32,
+    duration: f32,
+    keyframe_type: KeyframeType,
+    kind: u8,
+    uid: Bytes,
+}
+
+impl From<KeyframeWithoutEnabled> for Keyframe {
+    fn from(kf: KeyframeWithoutEnabled) -> Self {
+        Self {
+            timestamp: kf.timestamp,
+            duration: kf.duration,
+            keyframe_type: kf.keyframe_type,
+            kind: kf.kind,
+            uid: kf.uid,
+            ..Default::default()
+        }
+    }
+}
+
+/// Keyframe layout from before keyframes had UIDs
+#[derive(Deserialize)]
+struct KeyframeWithoutUid {
+    timestamp: f32,
+    duration: f32,
+    keyframe_type: KeyframeType,
+    kind: u8,
+}
+
+impl From<KeyframeWithoutUid> for Keyframe {
+    fn from(kf: KeyframeWithoutUid) -> Self {
+        Self {
+            timestamp: kf.timestamp,
+            duration: kf.duration,
+            keyframe_type: kf.keyframe_type,
+            kind: kf.kind,
+            ..Default::default()
+        }
+    }
+}
+
+/// Sequencer state layout shared by all legacy files that stored repeats and speed
+#[derive(Deserialize)]
+struct LegacyState<K> {
+    repeats: i32,
+    speed: f32,
+    keyframes: Vec<K>,
+}
+
+impl<K: Into<Keyframe>> From<LegacyState<K>> for SequencerState {
+    fn from(state: LegacyState<K>) -> Self {
+        Self {
+            repeats: state.repeats,
+            speed: state.speed,
+            keyframes: state.keyframes.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Writes the sequencer state and its screenshots to an ".auto" file at `path`
+///
+/// Screenshots are stored as PNG entries named after the UUID of the keyframe they belong to.
+/// Screenshots in `unchanged` are copied as is from the file already at `path` instead of being encoded again,
+/// which keeps saving screenshot heavy files fast.
+///
+/// The last `backups` versions of the file are kept as "file.auto.bak1" (newest) to "file.auto.bakN" (oldest).
+pub fn save(
+    path: &Path,
+    state: &SequencerState,
+    images: &HashMap<Bytes, Vec<u8>>,
+    unchanged: &HashSet<Bytes>,
+    backups: u32,
+    progress: &Progress,
+) -> Result<(), String> {
+    progress.start(images.len());
+    let mut state = state.clone();
+    if let Some(directory) = path.parent() {
+        relativize_assets(&mut state, directory);
+    }
+    let data = serde_json::to_vec(&state).map_err(|e| e.to_string())?;
+    // Write to a temporary file first so the previous save stays intact until the new one is complete
+    let tmp_path = path.with_extension("auto.tmp");
+    let file = File::create(&tmp_path).map_err(|e| e.to_string())?;
+    // Declared before the zip so the file is closed by the time a failed save removes it
+    let mut tmp = TmpFile {
+        path: &tmp_path,
+        renamed: false,
+    };
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    zip.start_file(VERSION_ENTRY, options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(FILE_VERSION.to_string().as_bytes())
+        .map_err(|e| e.to_string())?;
+    zip.start_file(DATA_ENTRY, options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(&data).map_err(|e| e.to_string())?;
+
+    let mut previous = File::open(path)
+        .ok()
+        .and_then(|file| ZipArchive::new(BufReader::new(file)).ok());
+    let mut copied = 0;
+    for (uid, image) in images {
+        let name = format!("{}.png", Uuid::from_bytes_le(*uid));
+        if unchanged.contains(uid) {
+            if let Some(entry) = previous
+                .as_mut()
+                .and_then(|previous| previous.by_name(&name).ok())
+            {
+                zip.raw_copy_file(entry).map_err(|e| e.to_string())?;
+                copied += 1;
+                progress.step();
+                continue;
+            }
+        }
+        zip.start_file(name, options).map_err(|e| e.to_string())?;
+        zip.write_all(&encode_png(image)?)
+            .map_err(|e| e.to_string())?;
+        progress.step();
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    // The previous file has to be closed before it can be replaced
+    drop(previous);
+    if backups > 0 && path.exists() {
+        rotate_backups(path, backups)?;
+    }
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())?;
+    tmp.renamed = true;
+    log::info!("Saved {} screenshots, {} unchanged", images.len(), copied);
+    Ok(())
+}
+
+/// Temporary file written by [`save`], removed when dropped unless it was renamed into place
+struct TmpFile<'a> {
+    path: &'a Path,
+    renamed: bool,
+}
+
+impl Drop for TmpFile<'_> {
+    fn drop(&mut self) {
+        if !self.renamed {
+            let _ = fs::remove_file(self.path);
+        }
+    }
+}
+
+/// Path of the `n`th backup of the file at `path`, e.g. "file.auto.bak1"
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak{}", n));
+    PathBuf::from(name)
+}
+
+/// Shifts each backup of `path` one place older, dropping the oldest, and moves the file itself to the first backup
+fn rotate_backups(path: &Path, backups: u32) -> Result<(), String> {
+    let _ = fs::remove_file(backup_path(path, backups));
+    for n in (1..backups).rev() {
+        let backup = backup_path(path, n);
+        if backup.exists() {
+            fs::rename(&backup, backup_path(path, n + 1)).map_err(|e| e.to_string())?;
+        }
+    }
+    fs::rename(path, backup_path(path, 1)).map_err(|e| e.to_string())
+}
+
+/// Reads an ".auto" file from `path`, migrating it from older versions of the format if needed
+pub fn load(path: &Path, progress: &Progress) -> Result<LoadedFile, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut loaded = read(BufReader::new(file), progress)?;
+    if let Some(directory) = path.parent() {
+        resolve_assets(&mut loaded.state, directory);
+    }
+    Ok(loaded)
+}
+
+/// Target image paths of the magic moves and while image loops in `state`
+fn asset_paths(state: &mut SequencerState) -> impl Iterator<Item = &mut String> {
+    state
+        .keyframes
+        .iter_mut()
+        .filter_map(|keyframe| keyframe.keyframe_type.target_mut())
+}
+
+/// Stores target images inside `directory` relative to it, so a project folder can be moved or synced as a whole
+///
+/// Images elsewhere keep their absolute path. Relative paths always use "/" to stay valid on every platform.
+fn relativize_assets(state: &mut SequencerState, directory: &Path) {
+    if !directory.is_absolute() {
+        return;
+    }
+    for path in asset_paths(state) {
+        if let Ok(relative) = Path::new(path.as_str()).strip_prefix(directory) {
+            *path = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+        }
+    }
+}
+
+/// Turns target images stored relative to the file back into full paths
+///
+/// Absolute paths that no longer exist, e.g. from a file saved before paths were relative,
+/// fall back to an image with the same name next to the file.
+/// Paths with environment variables are left for playback to expand.
+fn resolve_assets(state: &mut SequencerState, directory: &Path) {
+    for path in asset_paths(state) {
+        if path.contains('$') {
+            continue;
+        }
+        let stored = PathBuf::from(path.as_str());
+        let resolved = if !stored.is_absolute() {
+            directory.join(&stored)
+        } else if stored.exists() {
+            continue;
+        } else {
+            match stored.file_name().map(|name| directory.join(name)) {
+                Some(nearby) if nearby.exists() => nearby,
+                _ => continue,
+            }
+        };
+        *path = resolved.to_string_lossy().to_string();
+    }
+}
+
+/// Reads the contents of an ".auto" file from `reader`
+fn read<R: Read + Seek>(reader: R, progress: &Progress) -> Result<LoadedFile, String> {
+    let mut zip = ZipArchive::new(reader).map_err(|e| e.to_string())?;
+    progress.start(zip.len());
+
+    let version = match zip.by_name(VERSION_ENTRY) {
+        Ok(mut entry) => {
+            let mut text = String::new();
+            entry.read_to_string(&mut text).map_err(|e| e.to_string())?;
+            text.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid file version {:?}", text))?
+        }
+        // Files saved before the format was versioned have no version entry
+        Err(_) => 0,
+    };
+
+    let mut data = Vec::new();
+    match zip.by_name(DATA_ENTRY) {
+        Ok(mut entry) => entry.read_to_end(&mut data).map_err(|e| e.to_string())?,
+        Err(_) => return Err("The file has no keyframe data".to_string()),
+    };
+    let (state, defaulted) = migrate(version, &data)?;
+
+    // All entries named after a UUID are screenshots of the keyframe with that UUID,
+    // stored as PNGs since version 2 and as raw RGBA before that
+    let mut images = HashMap::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+        let name = entry.name().to_string();
+        let (name, is_png) = match name.strip_suffix(".png") {
+            Some(name) => (name, true),
+            None => (name.as_str(), false),
+        };
+        if let Ok(uid) = Uuid::parse_str(name) {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+            if is_png {
+                bytes = decode_png(&bytes)?;
+            }
+            images.insert(uid.to_bytes_le(), bytes);
+        }
+        progress.step();
+    }
+    Ok(LoadedFile {
+        state,
+        images,
+        defaulted,
+    })
+}
+
+/// Writes a copy of this executable with the sequence embedded in it to `path`
+///
+/// Started on its own the copy plays the sequence without opening the editor, see [`embedded_sequence`].
+/// Target images, sounds, plugins and the data source are stored inside so it works on other machines.
+/// Screenshots are left out unless the file's playback settings detect fails, the only time the player uses them.
+pub fn export_runnable(
+    path: &Path,
+    state: &SequencerState,
+    images: &HashMap<Bytes, Vec<u8>>,
+    progress: &Progress,
+) -> Result<(), String> {
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let mut runnable = fs::read(exe).map_err(|e| e.to_string())?;
+
+    let mut state = state.clone();
+    let assets = embed_assets(&mut state);
+    let no_images = HashMap::new();
+    let images = match state
+        .playback
+        .is_some_and(|playback| playback.fail_detection)
+    {
+        true => images,
+        false => &no_images,
+    };
+    // The ".auto" file is written out as usual with the assets added to it, then appended to the executable
+    let tmp_path = std::env::temp_dir().join(format!("{}.auto", Uuid::new_v4()));
+    let result = save(&tmp_path, &state, images, &HashSet::new(), 0, progress)
+        .and_then(|_| append_assets(&tmp_path, &assets))
+        .and_then(|_| fs::read(&tmp_path).map_err(|e| e.to_string()));
+    let _ = fs::remove_file(&tmp_path);
+    let sequence = result?;
+
+    runnable.extend_from_slice(&sequence);
+    runnable.extend_from_slice(&(sequence.len() as u64).to_le_bytes());
+    runnable.extend_from_slice(RUNNABLE_MAGIC);
+    fs::write(path, runnable).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Reads the sequence embedded in this executable by [`export_runnable`], if there is one
+pub fn embedded_sequence() -> Option<LoadedFile> {
+    let mut exe = File::open(std::env::current_exe().ok()?).ok()?;
+    let mut footer = [0; 16];
+    let end = exe.seek(SeekFrom::End(-16)).ok()?;
+    exe.read_exact(&mut footer).ok()?;
+    if &footer[8..] != RUNNABLE_MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(footer[..8].try_into().unwrap());
+    exe.seek(SeekFrom::Start(end.checked_sub(len)?)).ok()?;
+    let mut sequence = vec![0; len as usize];
+    exe.read_exact(&mut sequence).ok()?;
+    let mut loaded = match read(Cursor::new(&sequence), &Progress::default()) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            log::error!("Failed to read embedded sequence - {}", err);
+            return None;
+        }
+    };
+    if let Err(err) = extract_assets(&sequence, &mut loaded.state) {
+        log::error!("Failed to extract the embedded assets - {}", err);
+        return None;
+    }
+    Some(loaded)
+}
+
+/// Path of the file a keyframe reads while playing, if it reads one
+fn played_file(keyframe_type: &mut KeyframeType) -> Option<&mut String> {
+    match keyframe_type {
+        KeyframeType::Beep(path) | KeyframeType::Plugin(path, _) => Some(path),
+        keyframe_type => keyframe_type.target_mut(),
+    }
+}
+
+/// Points the files read while playing `state` at entries of the [`ASSETS_FOLDER`],
+/// returning each entry with the file to store in it
+///
+/// Files that don't exist, e.g. paths with expressions that are only known while playing, keep their path.
+fn embed_assets(state: &mut SequencerState) -> Vec<(String, PathBuf)> {
+    let mut assets = vec![];
+    let mut embed = |folder: String, path: &Path| {
+        let name = path.file_name()?.to_string_lossy().to_string();
+        if !path.is_file() {
+            log::warn!(
+                "{:?} is not included in the runnable, it must exist at the same path on the machine running it",
+                path
+            );
+            return None;
+        }
+        let entry = format!("{}{}/{}", ASSETS_FOLDER, folder, name);
+        assets.push((entry.clone(), path.to_path_buf()));
+        Some(entry)
+    };
+    for keyframe in &mut state.keyframes {
+        let folder = Uuid::from_bytes_le(keyframe.uid).to_string();
+        if let Some(path) = played_file(&mut keyframe.keyframe_type) {
+            if let Some(entry) = embed(folder, Path::new(path.as_str())) {
+                *path = entry;
+            }
+        }
+    }
+    if let Some(source) = &mut state.data_source {
+        if let Some(entry) = embed("data".to_string(), source) {
+            *source = PathBuf::from(entry);
+        }
+    }
+    assets
+}
+
+/// Adds the `assets` found by [`embed_assets`] to the ".auto" file at `path`
+fn append_assets(path: &Path, assets: &[(String, PathBuf)]) -> Result<(), String> {
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new_append(file).map_err(|e| e.to_string())?;
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (entry, source) in assets {
+        let bytes = fs::read(source).map_err(|e| format!("{:?} - {}", source, e))?;
+        zip.start_file(entry.as_str(), options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&bytes).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Writes the assets of an embedded `sequence` to a temporary folder and points the keyframes of `state` at them
+fn extract_assets(sequence: &[u8], state: &mut SequencerState) -> Result<(), String> {
+    let directory = runnable_assets_directory();
+    let mut zip = ZipArchive::new(Cursor::new(sequence)).map_err(|e| e.to_string())?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+        let Some(asset) = entry.name().strip_prefix(ASSETS_FOLDER) else {
+            continue;
+        };
+        // Only the file name is used so an entry can't write outside the folder
+        let Some((folder, name)) = asset.split_once('/') else {
+            continue;
+        };
+        let (Some(folder), Some(name)) =
+            (Path::new(folder).file_name(), Path::new(name).file_name())
+        else {
+            continue;
+        };
+        let folder = directory.join(folder);
+        fs::create_dir_all(&folder).map_err(|e| e.to_string())?;
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+        fs::write(folder.join(name), bytes).map_err(|e| e.to_string())?;
+    }
+    let extracted = |path: &str| {
+        path.strip_prefix(ASSETS_FOLDER)
+            .map(|asset| directory.join(asset))
+    };
+    for keyframe in &mut state.keyframes {
+        if let Some(path) = played_file(&mut keyframe.keyframe_type) {
+            if let Some(asset) = extracted(path) {
+                *path = asset.to_string_lossy().to_string();
+            }
+        }
+    }
+    if let Some(source) = &mut state.data_source {
+        if let Some(asset) = source.to_str().and_then(extracted) {
+            *source = asset;
+        }
+    }
+    Ok(())
+}
+
+/// Folder the assets of the sequence embedded in this executable are extracted to, unique to this run
+pub fn runnable_assets_directory() -> PathBuf {
+    static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+    DIRECTORY
+        .get_or_init(|| std::env::temp_dir().join(format!("automate-runnable-{}", Uuid::new_v4())))
+        .clone()
+}
+
+/// Writes a step by step guide of the sequence to `path`, see [`guide::steps`]
+///
+/// An ".html" path gets a single page with the screenshots embedded in it,
+/// any other path gets Markdown with the screenshots written to a folder next to it.
+pub fn export_guide(
+    path: &Path,
+    state: &SequencerState,
+    images: &HashMap<Bytes, Vec<u8>>,
+    progress: &Progress,
+) -> Result<(), String> {
+    let steps = guide::steps(&state.keyframes);
+    progress.start(steps.len());
+    let mut pngs = vec![];
+    for step in &steps {
+        let png = match images.get(&step.uid) {
+            Some(image) => match step.cursor {
+                Some(cursor) => Some(encode_png(&guide::annotate(image, cursor))?),
+                None => Some(encode_png(image)?),
+            },
+            None => None,
+        };
+        pngs.push(png);
+        progress.step();
+    }
+    let title = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let is_html = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("html"));
+    let document = if is_html {
+        guide::to_html(&title, &steps, |i| {
+            pngs[i]
+                .as_ref()
+                .map(|png| format!("data:image/png;base64,{}", STANDARD.encode(png)))
+        })
+    } else {
+        let folder = format!("{}_images", title);
+        let directory = path.with_file_name(&folder);
+        for (i, png) in pngs.iter().enumerate() {
+            if let Some(png) = png {
+                fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+                fs::write(directory.join(format!("step_{}.png", i + 1)), png)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+        // Angle brackets keep links to folders with spaces in their name working
+        guide::to_markdown(&title, &steps, |i| {
+            pngs[i]
+                .as_ref()
+                .map(|_| format!("<{}/step_{}.png>", folder, i + 1))
+        })
+    };
+    fs::write(path, document).map_err(|e| e.to_string())?;
+    log::info!("Exported a guide of {} steps to {:?}", steps.len(), path);
+    Ok(())
+}
+
+/// Writes `snippet` to an ".autosnippet" file at `path`
+///
+/// Snippets are zip archives laid out like ".auto" files, with the keyframes stored on their own
+/// and the target images of magic moves stored inside so they work on other machines.
+pub fn export_snippet(path: &Path, snippet: &Snippet) -> Result<(), String> {
+    let data = serde_json::to_vec(&snippet.keyframes).map_err(|e| e.to_string())?;
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    zip.start_file(VERSION_ENTRY, options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(FILE_VERSION.to_string().as_bytes())
+        .map_err(|e| e.to_string())?;
+    zip.start_file(SNIPPET_ENTRY, options)
+        .map_err(|e| e.to_string())?;
+    zip.write_all(&data).map_err(|e| e.to_string())?;
+    for (uid, image) in &snippet.images {
+        zip.start_file(format!("{}.png", Uuid::from_bytes_le(*uid)), options)
+            .map_err(|e| e.to_string())?;
+        zip.write_all(&encode_png(image)?)
+            .map_err(|e| e.to_string())?;
+    }
+    for (uid, (name, image)) in &snippet.targets {
+        zip.start_file(
+            format!("{}{}/{}", TARGETS_FOLDER, Uuid::from_bytes_le(*uid), name),
+            options,
+        )
+        .map_err(|e| e.to_string())?;
+        zip.write_all(image).map_err(|e| e.to_string())?;
+    }
+    zip.finish().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Reads an ".autosnippet" file written by [`export_snippet`]
+pub fn import_snippet(path: &Path) -> Result<Snippet, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    let mut version = String::new();
+    zip.by_name(VERSION_ENTRY)
+        .and_then(|mut entry| Ok(entry.read_to_string(&mut version)?))
+        .map_err(|_| "The file is not a snippet".to_string())?;
+    let version = version
+        .trim()
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid snippet version {:?}", version))?;
+    if version > FILE_VERSION {
+        return Err(format!(
+            "The snippet was created with a newer version of Automate (file version {}, supported up to {})",
+            version, FILE_VERSION
+        ));
+    }
+
+    let mut snippet = Snippet::default();
+    let mut data = Vec::new();
+    match zip.by_name(SNIPPET_ENTRY) {
+        Ok(mut entry) => entry.read_to_end(&mut data).map_err(|e| e.to_string())?,
+        Err(_) => return Err("The snippet has no keyframes".to_string()),
+    };
+    snippet.keyframes = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+        let name = entry.name().to_string();
+        let mut bytes = Vec::new();
+        if let Some(target) = name.strip_prefix(TARGETS_FOLDER) {
+            let Some((uid, file_name)) = target.split_once('/') else {
+                continue;
+            };
+            if let Ok(uid) = Uuid::parse_str(uid) {
+                entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+                snippet
+                    .targets
+                    .insert(uid.to_bytes_le(), (file_name.to_string(), bytes));
+            }
+        } else if let Some(Ok(uid)) = name.strip_suffix(".png").map(Uuid::parse_str) {
+            entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+            snippet
+                .images
+                .insert(uid.to_bytes_le(), decode_png(&bytes)?);
+        }
+    }
+    Ok(snippet)
+}
+
+/// Path of the autosave for the file at `source` inside the autosave `directory`
+///
+/// The full source path is hashed into the name so files with the same name in different directories don't collide
+pub fn autosave_path(directory: &Path, source: &str) -> PathBuf {
+    let stem = Path::new(source)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("untitled");
+    directory.join(format!("{}-{:016x}.auto", stem, fnv1a(source)))
+}
+
+/// 64-bit FNV-1a hash of `text`
+///
+/// Unlike `DefaultHasher` it is the same in every build, so autosaves are still found after updating
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Writes an autosave of `source` into the autosave `directory`
+///
+/// The path of the source file is stored next to the autosave so it can be recovered to the right place
+pub fn autosave(
+    directory: &Path,
+    source: &str,
+    state: &SequencerState,
+    images: &HashMap<Bytes, Vec<u8>>,
+) -> Result<PathBuf, String> {
+    fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+    let path = autosave_path(directory, source);
+    save(
+        &path,
+        state,
+        images,
+        &HashSet::new(),
+        0,
+        &Progress::default(),
+    )?;
+    fs::write(path.with_extension("source"), source).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// Removes an autosave and the record of its source file
+pub fn remove_autosave(path: &Path) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(path.with_extension("source"));
+}
+
+/// Lists the autosaves in `directory` that are newer than their source file, along with the source path
+///
+/// Autosaves of files that were never saved or have since been deleted are recoverable for [`AUTOSAVE_EXPIRY`],
+/// after which they are removed. So are autosaves older than their source file.
+pub fn recoverable_autosaves(directory: &Path) -> Vec<(PathBuf, String)> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return vec![];
+    };
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut autosaves = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "auto") {
+            let Ok(source) = fs::read_to_string(path.with_extension("source")) else {
+                continue;
+            };
+            let recoverable = match (modified(&path), modified(Path::new(&source))) {
+                (Some(autosave), Some(source)) => autosave > source,
+                (Some(autosave), None) => autosave.elapsed().is_ok_and(|age| age < AUTOSAVE_EXPIRY),
+                (None, _) => false,
+            };
+            if recoverable {
+                autosaves.push((path, source));
+            } else {
+                remove_autosave(&path);
+            }
+        }
+    }
+    autosaves
+}
+
+/// Reads the rows of a CSV file, or a TSV file if it has a ".tsv" extension, for data driven playback
+///
+/// Each row maps the column headers in the first line to the values in that row
+pub fn read_table(path: &Path) -> Result<Vec<Variables>, String> {
+    let tsv = path.extension().is_some_and(|ext| ext == "tsv");
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(if tsv { b'\t' } else { b',' })
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|e| e.to_string())?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let mut rows = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        rows.push(
+            headers
+                .iter()
+                .zip(record.iter())
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+/// Encodes a raw RGBA screenshot as a PNG, compressed as set in the screenshot quality
+pub fn encode_png(image: &[u8]) -> Result<Vec<u8>, String> {
+    let [width, height] = screenshot_dimensions(image);
+    let compression = match screenshot_quality().compression {
+        PngCompression::Fast => CompressionType::Fast,
+        PngCompression::Default => CompressionType::Default,
+        PngCompression::Best => CompressionType::Best,
+    };
+    let mut png = Vec::new();
+    PngEncoder::new_with_quality(&mut png, compression, PngFilterType::Adaptive)
+        .write_image(
+            image,
+            width as u32,
+            height as u32,
+            image::ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+/// Decodes a PNG screenshot back into raw RGBA
+pub fn decode_png(png: &[u8]) -> Result<Vec<u8>, String> {
+    image::load_from_memory_with_format(png, image::ImageFormat::Png)
+        .map(|image| image.into_rgba8().into_raw())
+        .map_err(|e| e.to_string())
+}
+
+/// Deserializes the keyframe data of a file saved with format `version` into the current `SequencerState`
+///
+/// Also returns the fields that had to be defaulted when importing a legacy file
+fn migrate(version: u32, data: &[u8]) -> Result<(SequencerState, Vec<&'static str>), String> {
+    match version {
+        0 => import_legacy(data),
+        // Version 2 only changed how screenshots are stored
+        1 | 2 => bincode::deserialize::<LegacyState<KeyframeV2>>(data)
+            .map(|state| (state.into(), vec![]))
+            .map_err(|e| e.to_string()),
+        3 => serde_json::from_slice::<SequencerState>(data)
+            .map(|state| (state, vec![]))
+            .map_err(|e| e.to_string()),
+        _ => Err(format!(
+            "The file was created with a newer version of Automate (file version {}, supported up to {})",
+            version, FILE_VERSION
+        )),
+    }
+}
+
+/// Best effort import of files saved before the format was versioned
+///
+/// Bincode is not self describing, so each known layout is tried from newest to oldest, rejecting any with leftover bytes
+fn import_legacy(data: &[u8]) -> Result<(SequencerState, Vec<&'static str>), String> {
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes();
+
+    if let Ok(state) = options.deserialize::<LegacyState<KeyframeV2>>(data) {
+        return Ok((state.into(), vec![]));
+    }
+    if let Ok(state) = options.deserialize::<LegacyState<KeyframeWithoutEnabled>>(data) {
+        return Ok((state.into(), vec!["keyframe enabled"]));
+    }
+    if let Ok(state) = options.deserialize::<LegacyState<KeyframeWithoutUid>>(data) {
+        return Ok((state.into(), vec!["keyframe enabled", "keyframe uid"]));
+    }
+    if let Ok(keyframes) = options.deserialize::<Vec<KeyframeV2>>(data) {
+        return Ok((
+            LegacyState {
+                repeats: 1,
+                speed: 1.,
+                keyframes,
+            }
+            .into(),
+            vec!["repeats", "speed"],
+        ));
+    }
+    if let Ok(keyframes) = options.deserialize::<Vec<KeyframeWithoutUid>>(data) {
+        return Ok((
+            LegacyState {
+                repeats: 1,
+                speed: 1.,
+                keyframes,
+            }
+            .into(),
+            vec!["repeats", "speed", "keyframe enabled", "keyframe uid"],
+        ));
+    }
+    Err("The file does not match any known layout of the .auto format".to_string())
+}
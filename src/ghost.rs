@@ -0,0 +1,84 @@
+use egui::{pos2, vec2, Align2, Color32, FontId, Rect, Stroke, Ui, Vec2};
+
+use crate::{
+    keyframe::{Keyframe, KeyframeType},
+    util::{button_to_char, key_to_char, keys_to_string, screen_to_rect, scroll_to_char},
+};
+
+/// What dry run playback would have done, drawn over the current screenshot instead of being simulated
+#[derive(Default)]
+pub struct Ghost {
+    /// Where the cursor would be, in screen coordinates
+    cursor: Option<Vec2>,
+    /// Mouse buttons that would be held down
+    buttons: Vec<rdev::Button>,
+    /// Keys that would be held down
+    keys: Vec<rdev::Key>,
+    /// What the last key strokes, text or scroll keyframe would have done
+    last_action: String,
+}
+
+impl Ghost {
+    /// Records what playing the keyframe would have done, in place of [`crate::player::play_keyframe`]
+    ///
+    /// Keyframes without an effect on the cursor or keyboard, like notifications and sounds, are skipped entirely.
+    /// Magic moves can't be followed as finding their target needs the real screen
+    pub fn play_keyframe(&mut self, keyframe: &Keyframe, start: bool) {
+        match &keyframe.keyframe_type {
+            KeyframeType::KeyBtn(key) | KeyframeType::Hold(key) => match start {
+                true => self.keys.push(*key),
+                false => self.keys.retain(|k| k != key),
+            },
+            KeyframeType::MouseBtn(button) => match start {
+                true => self.buttons.push(*button),
+                false => self.buttons.retain(|b| b != button),
+            },
+            KeyframeType::MouseMove(pos) if start => self.cursor = Some(*pos),
+            KeyframeType::Scroll(delta) if start => self.last_action = scroll_to_char(delta),
+            KeyframeType::KeyStrokes(keys) if start => self.last_action = keys_to_string(keys),
+            KeyframeType::Text(text) if start => self.last_action = text.clone(),
+            _ => {}
+        }
+    }
+    /// Forgets everything, for when playback starts again
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+    /// Draws the cursor and the held keys and buttons over the screenshot shown in `rect`
+    pub fn draw(&self, ui: &Ui, rect: Rect) {
+        let painter = ui.painter_at(rect);
+        if let Some(cursor) = self.cursor {
+            let pos = screen_to_rect(cursor, rect);
+            let color = match self.buttons.is_empty() {
+                true => Color32::from_white_alpha(200),
+                false => Color32::LIGHT_RED,
+            };
+            painter.circle(pos, 6., color.gamma_multiply(0.5), Stroke::new(1.5, color));
+        }
+        let held = self
+            .keys
+            .iter()
+            .map(key_to_char)
+            .chain(self.buttons.iter().map(button_to_char))
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let text = [held, self.last_action.clone()]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("   ");
+        if !text.is_empty() {
+            let galley = painter.layout_no_wrap(text, FontId::proportional(16.), Color32::WHITE);
+            let text_rect = Align2::LEFT_BOTTOM.anchor_size(
+                rect.left_bottom() + vec2(12., -12.),
+                galley.size() + vec2(12., 8.),
+            );
+            painter.rect_filled(text_rect, 4., Color32::from_black_alpha(180));
+            painter.galley(
+                pos2(text_rect.min.x + 6., text_rect.min.y + 4.),
+                galley,
+                Color32::WHITE,
+            );
+        }
+    }
+}
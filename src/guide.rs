@@ -0,0 +1,366 @@
+use std::path::Path;
+
+use egui::Vec2;
+
+use crate::{
+    keyframe::{kind_name, Keyframe, KeyframeType},
+    layout::layout,
+    util::{screen_to_screenshot, screenshot_dimensions, SCREENSHOT_SIZE},
+};
+
+/// Clicks of the same button closer together than this (in seconds) are described as a double click
+const DOUBLE_CLICK_TIME: f32 = 0.5;
+/// Radius in pixels of the ring marking where the mouse was on a step's screenshot
+const MARKER_RADIUS: f32 = 18.;
+
+/// One step of a guide made from a sequence, describing one interaction in words
+pub struct Step {
+    /// UID of the keyframe the step starts at, whose screenshot is shown alongside it
+    pub uid: uuid::Bytes,
+    pub description: String,
+    /// Where the mouse was during the step, marked on the screenshot
+    pub cursor: Option<Vec2>,
+    /// Notes of the keyframes the step was made from
+    pub notes: Vec<String>,
+    /// Keys typed so far, while consecutive key presses are gathered into one step
+    typed: Vec<rdev::Key>,
+    /// Button and time of the last click, while a click may still become a double click
+    clicked: Option<(rdev::Button, f32)>,
+}
+
+/// Turns the enabled keyframes into steps, one for each click, scroll, key press or magic move
+///
+/// Mouse moves only decide where the next click happens, consecutive key presses are gathered into a single step
+/// and keyframes without an interaction are only included if they have a note.
+pub fn steps(keyframes: &[Keyframe]) -> Vec<Step> {
+    let mut steps: Vec<Step> = vec![];
+    let mut cursor = None;
+    for keyframe in keyframes.iter().filter(|keyframe| keyframe.enabled) {
+        let step = |description: String| Step {
+            uid: keyframe.uid,
+            description,
+            cursor,
+            notes: vec![],
+            typed: vec![],
+            clicked: None,
+        };
+        let last = steps.last_mut();
+        let new_step = match &keyframe.keyframe_type {
+            KeyframeType::MouseMove(pos) => {
+                cursor = Some(*pos);
+                None
+            }
+            KeyframeType::KeyBtn(key) => match last {
+                Some(last) if !last.typed.is_empty() && keyframe.note.is_empty() => {
+                    last.typed.push(*key);
+                    last.description = format!("Type {}", typed_text(&last.typed));
+                    None
+                }
+                _ => Some(Step {
+                    typed: vec![*key],
+                    ..step(format!("Type {}", typed_text(&[*key])))
+                }),
+            },
+            KeyframeType::MouseBtn(button) => match last {
+                Some(last)
+                    if last.clicked.is_some_and(|(previous, time)| {
+                        previous == *button && keyframe.timestamp - time < DOUBLE_CLICK_TIME
+                    }) && last.cursor == cursor =>
+                {
+                    last.description = format!("Double {}", last.description.to_lowercase());
+                    last.clicked = None;
+                    None
+                }
+                _ => Some(Step {
+                    clicked: Some((*button, keyframe.timestamp)),
+                    ..step(match cursor {
+                        Some(pos) => format!(
+                            "{} click at ({:.0}, {:.0})",
+                            button_name(button),
+                            pos.x,
+                            pos.y
+                        ),
+                        None => format!("{} click", button_name(button)),
+                    })
+                }),
+            },
+            KeyframeType::Scroll(delta) => {
+                let direction = match (delta.x, delta.y) {
+                    (_, y) if y > 0. => "up",
+                    (_, y) if y < 0. => "down",
+                    (x, _) if x > 0. => "right",
+                    _ => "left",
+                };
+                Some(step(format!("Scroll {}", direction)))
+            }
+            KeyframeType::KeyStrokes(keys) => Some(step(format!(
+                "Press {}",
+                keys.iter().map(key_name).collect::<Vec<_>>().join("+")
+            ))),
+            KeyframeType::Hold(key) => Some(step(format!(
+                "Hold {} for {:.1}s",
+                key_name(key),
+                keyframe.duration
+            ))),
+            KeyframeType::Text(text) => Some(step(format!("Type \"{}\"", text))),
+            KeyframeType::MagicMove(path) => {
+                let moved = step(format!(
+                    "Move the mouse to {}",
+                    Path::new(path)
+                        .file_name()
+                        .map(|name| name.to_string_lossy().to_string())
+                        .unwrap_or_default()
+                ));
+                // Where the target is found isn't known until it is played
+                cursor = None;
+                Some(Step {
+                    cursor: None,
+                    ..moved
+                })
+            }
+            _ if !keyframe.note.is_empty() => Some(step(kind_name(keyframe.kind).to_string())),
+            _ => None,
+        };
+        if let Some(new_step) = new_step {
+            steps.push(new_step);
+        }
+        if !keyframe.note.is_empty() {
+            if let Some(last) = steps.last_mut() {
+                last.notes.push(keyframe.note.clone());
+            }
+        }
+    }
+    steps
+}
+
+/// Draws a ring around where the mouse was on a copy of the raw RGBA `screenshot`
+pub fn annotate(screenshot: &[u8], cursor: Vec2) -> Vec<u8> {
+    let mut image = screenshot.to_vec();
+    let [width, height] = screenshot_dimensions(screenshot);
+    let center =
+        screen_to_screenshot(cursor) / SCREENSHOT_SIZE * Vec2::new(width as f32, height as f32);
+    let reach = MARKER_RADIUS as i32 + 2;
+    for y in (center.y as i32 - reach).max(0)..(center.y as i32 + reach).min(height as i32) {
+        for x in (center.x as i32 - reach).max(0)..(center.x as i32 + reach).min(width as i32) {
+            let distance = Vec2::new(x as f32 - center.x, y as f32 - center.y).length();
+            if (distance - MARKER_RADIUS).abs() <= 1.5 {
+                let i = (y as usize * width + x as usize) * 4;
+                image[i..i + 4].copy_from_slice(&[230, 40, 40, 255]);
+            }
+        }
+    }
+    image
+}
+
+/// Writes the steps as a Markdown document, with `image` giving the path of each step's screenshot if it has one
+pub fn to_markdown(title: &str, steps: &[Step], image: impl Fn(usize) -> Option<String>) -> String {
+    let mut markdown = format!("# {}\n\n", title);
+    for (i, step) in steps.iter().enumerate() {
+        markdown.push_str(&format!("## Step {}\n\n{}\n\n", i + 1, step.description));
+        if let Some(path) = image(i) {
+            markdown.push_str(&format!("![Step {}]({})\n\n", i + 1, path));
+        }
+        for note in &step.notes {
+            for line in note.lines() {
+                // Headings of notes would otherwise outrank the steps
+                match line.strip_prefix('#') {
+                    Some(heading) => markdown.push_str(&format!(
+                        "> **{}**\n",
+                        heading.trim_start_matches('#').trim()
+                    )),
+                    None => markdown.push_str(&format!("> {}\n", line)),
+                }
+            }
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+/// Writes the steps as a standalone HTML page, with `image` giving the source of each step's screenshot if it has one
+pub fn to_html(title: &str, steps: &[Step], image: impl Fn(usize) -> Option<String>) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n<style>\n\
+        body {{ font-family: sans-serif; max-width: 960px; margin: auto; padding: 1em; }}\n\
+        img {{ max-width: 100%; border: 1px solid #ccc; }}\n\
+        .note {{ background: #f4f4f4; border-left: 4px solid #999; padding: 0.5em 1em; }}\n\
+        </style>\n</head>\n<body>\n<h1>{0}</h1>\n",
+        escape(title)
+    );
+    for (i, step) in steps.iter().enumerate() {
+        html.push_str(&format!(
+            "<h2>Step {}</h2>\n<p>{}</p>\n",
+            i + 1,
+            escape(&step.description)
+        ));
+        if let Some(src) = image(i) {
+            html.push_str(&format!(
+                "<img src=\"{}\" alt=\"Step {}\">\n",
+                escape(&src),
+                i + 1
+            ));
+        }
+        for note in &step.notes {
+            html.push_str(&format!(
+                "<div class=\"note\">\n{}</div>\n",
+                note_to_html(note)
+            ));
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Converts a note to HTML, following the formatting notes are shown with in the editor
+fn note_to_html(note: &str) -> String {
+    let mut html = String::new();
+    for line in note.lines() {
+        if let Some(heading) = line.strip_prefix('#') {
+            html.push_str(&format!(
+                "<h4>{}</h4>\n",
+                escape(heading.trim_start_matches('#').trim())
+            ));
+            continue;
+        }
+        // Every other part between `**` is bold
+        let text: String = line
+            .trim_start()
+            .strip_prefix("- ")
+            .unwrap_or(line)
+            .split("**")
+            .enumerate()
+            .map(|(j, part)| match j % 2 {
+                1 => format!("<b>{}</b>", escape(part)),
+                _ => escape(part),
+            })
+            .collect();
+        match line.trim_start().starts_with("- ") {
+            true => html.push_str(&format!("<li>{}</li>\n", text)),
+            false => html.push_str(&format!("<p>{}</p>\n", text)),
+        }
+    }
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Text typed by the keys, with keys that don't type a character named in brackets
+fn typed_text(keys: &[rdev::Key]) -> String {
+    let mut text = String::new();
+    let mut keys = keys.iter().peekable();
+    while let Some(key) = keys.next() {
+        // Shift followed by a character key is shown as the character it types
+        if matches!(key, rdev::Key::ShiftLeft | rdev::Key::ShiftRight) {
+            if let Some(c) = keys.peek().and_then(|next| layout().shifted_char_for(next)) {
+                text.push(c);
+                keys.next();
+                continue;
+            }
+        }
+        match layout().char_for(key) {
+            Some(c) => text.push(c),
+            None => text.push_str(&format!("[{}]", key_name(key))),
+        }
+    }
+    format!("\"{}\"", text)
+}
+
+/// Name of a key for shortcuts, the character it types or its name otherwise
+fn key_name(key: &rdev::Key) -> String {
+    match layout().char_for(key) {
+        Some(c) if !c.is_whitespace() => c.to_uppercase().to_string(),
+        _ => match key {
+            rdev::Key::ControlLeft | rdev::Key::ControlRight => "Ctrl".to_string(),
+            rdev::Key::ShiftLeft | rdev::Key::ShiftRight => "Shift".to_string(),
+            rdev::Key::Alt | rdev::Key::AltGr => "Alt".to_string(),
+            rdev::Key::MetaLeft | rdev::Key::MetaRight => "Meta".to_string(),
+            key => format!("{:?}", key),
+        },
+    }
+}
+
+fn button_name(button: &rdev::Button) -> String {
+    match button {
+        rdev::Button::Left => "Left".to_string(),
+        rdev::Button::Right => "Right".to_string(),
+        rdev::Button::Middle => "Middle".to_string(),
+        rdev::Button::Unknown(n) => format!("Button {}", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(timestamp: f32, keyframe_type: KeyframeType) -> Keyframe {
+        Keyframe {
+            timestamp,
+            keyframe_type,
+            ..Default::default()
+        }
+    }
+
+    fn descriptions(steps: &[Step]) -> Vec<&str> {
+        steps.iter().map(|step| step.description.as_str()).collect()
+    }
+
+    #[test]
+    fn merges_quick_clicks_in_the_same_place_into_a_double_click() {
+        let left = || KeyframeType::MouseBtn(rdev::Button::Left);
+        let keyframes = [
+            keyframe(0., KeyframeType::MouseMove(Vec2::new(100., 200.))),
+            keyframe(0.1, left()),
+            keyframe(0.3, left()),
+            // Too long after the double click to be part of it
+            keyframe(1.5, left()),
+            // Quick enough but somewhere else
+            keyframe(1.6, KeyframeType::MouseMove(Vec2::new(10., 20.))),
+            keyframe(1.7, left()),
+            // Quick enough but another button
+            keyframe(1.8, KeyframeType::MouseBtn(rdev::Button::Right)),
+        ];
+        assert_eq!(
+            descriptions(&steps(&keyframes)),
+            [
+                "Double left click at (100, 200)",
+                "Left click at (100, 200)",
+                "Left click at (10, 20)",
+                "Right click at (10, 20)",
+            ]
+        );
+    }
+
+    #[test]
+    fn gathers_consecutive_key_presses_into_one_typed_step() {
+        let keyframes = [
+            keyframe(0., KeyframeType::KeyBtn(rdev::Key::KeyH)),
+            keyframe(0.1, KeyframeType::KeyBtn(rdev::Key::ShiftLeft)),
+            keyframe(0.2, KeyframeType::KeyBtn(rdev::Key::KeyI)),
+            keyframe(0.3, KeyframeType::KeyBtn(rdev::Key::Escape)),
+            // A click ends the typing
+            keyframe(0.4, KeyframeType::MouseBtn(rdev::Button::Left)),
+            keyframe(0.5, KeyframeType::KeyBtn(rdev::Key::KeyA)),
+            // A note starts a step of its own
+            Keyframe {
+                note: "Then".to_string(),
+                ..keyframe(0.6, KeyframeType::KeyBtn(rdev::Key::KeyB))
+            },
+        ];
+        let steps = steps(&keyframes);
+        assert_eq!(
+            descriptions(&steps),
+            [
+                "Type \"hI[Escape]\"",
+                "Left click",
+                "Type \"a\"",
+                "Type \"b\""
+            ]
+        );
+        assert_eq!(steps[3].notes, ["Then"]);
+    }
+}
@@ -0,0 +1,949 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use egui::Vec2;
+use uuid::Bytes;
+
+use crate::{
+    file::Snippet,
+    keyframe::{Keyframe, KeyframeType},
+    layout::layout,
+};
+
+/// Seconds between the keyframes of an imported script
+const STEP_GAP: f32 = 0.1;
+/// Seconds between checks while waiting for an image to appear or vanish
+const POLL_INTERVAL: f32 = 0.5;
+/// Seconds SikuliX waits for an image to appear or vanish when not given a timeout
+const SIKULIX_WAIT_TIMEOUT: f32 = 3.;
+/// Seconds given to a page opened by a Selenium IDE project to load
+const PAGE_LOAD_TIME: f32 = 2.;
+/// Most times an AutoHotkey script can repeat a click or key press in one step
+const MAX_REPEATS: usize = 100;
+
+/// Keyframes made from a script of another automation tool, ready to be inserted like a snippet
+pub struct Imported {
+    pub snippet: Snippet,
+    /// Steps of the script that couldn't be turned into keyframes, each saying where in the script it is
+    pub unsupported: Vec<String>,
+}
+
+/// Imports the script at `path`, picking the importer from its extension
+pub fn script(path: &Path) -> Result<Imported, String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("py") => sikulix(path),
+        Some("side") => selenium(path),
+        Some("ahk") => autohotkey(path),
+        _ => Err(format!(
+            "{:?} isn't a SikuliX script, Selenium IDE project or AutoHotkey script",
+            path.file_name().unwrap_or_default()
+        )),
+    }
+}
+
+/// Lays out imported keyframes one after the other, collecting the steps that couldn't be imported
+#[derive(Default)]
+struct Builder {
+    keyframes: Vec<Keyframe>,
+    targets: HashMap<Bytes, (String, Vec<u8>)>,
+    time: f32,
+    unsupported: Vec<String>,
+}
+
+impl Builder {
+    /// Adds a keyframe after the previous one, returning its index
+    fn push(&mut self, duration: f32, kind: u8, keyframe_type: KeyframeType) -> usize {
+        self.keyframes.push(Keyframe {
+            timestamp: self.time,
+            duration,
+            keyframe_type,
+            kind,
+            ..Default::default()
+        });
+        self.time += duration + STEP_GAP;
+        self.keyframes.len() - 1
+    }
+    fn wait(&mut self, secs: f32) {
+        self.push(0.1, 4, KeyframeType::Wait(secs.max(0.)));
+    }
+    /// Types `text` as it is, without treating braces as expressions or `$` as environment variables
+    fn text(&mut self, text: &str) {
+        if !text.is_empty() {
+            let text = text
+                .replace('{', "{{")
+                .replace('}', "}}")
+                .replace('$', "$$");
+            self.push(0.2, 8, KeyframeType::Text(text));
+        }
+    }
+    fn key(&mut self, key: rdev::Key) {
+        self.push(0.1, 0, KeyframeType::KeyBtn(key));
+    }
+    fn keys(&mut self, keys: Vec<rdev::Key>) {
+        self.push(0.1, 5, KeyframeType::KeyStrokes(keys));
+    }
+    fn mouse_move(&mut self, pos: Vec2) {
+        self.keyframes.push(Keyframe::mouse_move(self.time, pos));
+        self.time += 0.1 + STEP_GAP;
+    }
+    fn click(&mut self, button: rdev::Button) {
+        self.keyframes
+            .push(Keyframe::mouse_button(self.time, 0.1, button));
+        self.time += 0.1 + STEP_GAP;
+    }
+    /// Moves the mouse to the image at `path`, which is copied into the sequence along with the keyframe
+    fn magic_move(&mut self, path: &Path) -> Result<(), String> {
+        let image = read_target(path)?;
+        let i = self.push(0.2, 6, KeyframeType::MagicMove(path_string(path)));
+        self.targets.insert(self.keyframes[i].uid, image);
+        Ok(())
+    }
+    /// Waits until the image at `path` is on screen, or until it isn't when `vanish`,
+    /// as a while image loop around a short wait
+    ///
+    /// With a `timeout` playback is aborted once it has passed, like SikuliX does. Within the loop a jump
+    /// skips over an abort keyframe for as many checks as fit in the timeout, then runs out of jumps.
+    fn wait_for(&mut self, path: &Path, vanish: bool, timeout: Option<f32>) -> Result<(), String> {
+        let image = read_target(path)?;
+        let start = self.time;
+        let mut within = vec![];
+        if let Some(timeout) = timeout {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            // Unique so that importing more scripts into the same sequence doesn't add another label with the name
+            let uid = uuid::Uuid::new_v4().simple().to_string();
+            let label = format!("waiting for {} {}", name, &uid[..8]);
+            let checks = (timeout.max(0.) / POLL_INTERVAL).ceil() as u32;
+            let message = match vanish {
+                true => format!("{} was still on screen after {}s", name, timeout),
+                false => format!("{} didn't appear within {}s", name, timeout),
+            };
+            within.push((11, KeyframeType::Jump(label.clone(), checks, 0)));
+            within.push((
+                12,
+                KeyframeType::Abort(message.replace('{', "{{").replace('}', "}}")),
+            ));
+            within.push((10, KeyframeType::Label(label)));
+        }
+        within.push((4, KeyframeType::Wait(POLL_INTERVAL)));
+
+        let i = self.push(
+            STEP_GAP + within.len() as f32 * (0.1 + STEP_GAP),
+            19,
+            KeyframeType::WhileImage(path_string(path), vanish, None),
+        );
+        self.targets.insert(self.keyframes[i].uid, image);
+        for (n, (kind, keyframe_type)) in within.into_iter().enumerate() {
+            self.keyframes.push(Keyframe {
+                timestamp: start + STEP_GAP + n as f32 * (0.1 + STEP_GAP),
+                duration: 0.1,
+                keyframe_type,
+                kind,
+                ..Default::default()
+            });
+        }
+        Ok(())
+    }
+    fn flag(&mut self, step: String) {
+        self.unsupported.push(step);
+    }
+    fn finish(self) -> Result<Imported, String> {
+        if self.keyframes.is_empty() {
+            return Err("None of the steps in the script could be imported".to_string());
+        }
+        Ok(Imported {
+            snippet: Snippet {
+                keyframes: self.keyframes,
+                images: HashMap::new(),
+                targets: self.targets,
+            },
+            unsupported: self.unsupported,
+        })
+    }
+}
+
+/// Imports a SikuliX script, the ".py" file inside a ".sikuli" folder
+///
+/// Clicks, hovers, typing, waits and key presses become keyframes, with images clicked on turned into magic moves.
+/// Control flow is flagged and the lines within it are imported as if they ran once.
+pub fn sikulix(path: &Path) -> Result<Imported, String> {
+    let script = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let mut builder = Builder::default();
+    for (n, line) in script.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() || line.starts_with("import ") || line.starts_with("from ") {
+            continue;
+        }
+        let step = format!("Line {}: {}", n + 1, line);
+        let keyword = line
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default();
+        if matches!(
+            keyword,
+            "if" | "elif"
+                | "else"
+                | "for"
+                | "while"
+                | "def"
+                | "try"
+                | "except"
+                | "finally"
+                | "with"
+        ) {
+            builder.flag(format!(
+                "{} (the lines within it are imported as if it ran once)",
+                step
+            ));
+            continue;
+        }
+        let Some((name, args)) = call(line) else {
+            builder.flag(step);
+            continue;
+        };
+        let args = split_top_level(args, ',');
+        if let Err(err) = sikulix_call(&mut builder, directory, name, &args) {
+            builder.flag(format!("{} ({})", step, err));
+        }
+    }
+    builder.finish()
+}
+
+/// Adds the keyframes for a call to one of the SikuliX functions, or says why it can't be imported
+fn sikulix_call(
+    builder: &mut Builder,
+    directory: &Path,
+    name: &str,
+    args: &[&str],
+) -> Result<(), String> {
+    let first = args.first().copied().unwrap_or_default();
+    match name {
+        "click" | "doubleClick" | "rightClick" | "hover" | "mouseMove" => {
+            // Without a target the click happens wherever the mouse is
+            if let Some(image) = image(first) {
+                builder.magic_move(&directory.join(image))?;
+            } else if let Some(pos) = location(first) {
+                builder.mouse_move(pos);
+            } else if !first.is_empty() {
+                return Err("the target isn't an image or location".to_string());
+            }
+            match name {
+                "click" => builder.click(rdev::Button::Left),
+                "doubleClick" => {
+                    builder.click(rdev::Button::Left);
+                    builder.click(rdev::Button::Left);
+                }
+                "rightClick" => builder.click(rdev::Button::Right),
+                _ => {}
+            }
+        }
+        "type" | "paste" => {
+            // Typing into a target clicks it first
+            let (text, modifiers) = match args {
+                [target, text, rest @ ..] if image(target).is_some() => {
+                    builder.magic_move(&directory.join(image(target).unwrap()))?;
+                    builder.click(rdev::Button::Left);
+                    (*text, rest.first().copied())
+                }
+                [text, modifiers] => (*text, Some(*modifiers)),
+                [text] => (*text, None),
+                _ => return Err("unexpected arguments".to_string()),
+            };
+            match modifiers {
+                Some(modifiers) => {
+                    let mut keys = vec![];
+                    for modifier in split_top_level(modifiers, '+') {
+                        let name = modifier
+                            .trim()
+                            .trim_start_matches("KeyModifier.")
+                            .trim_start_matches("Key.");
+                        keys.push(key_named(name).ok_or(format!("unknown modifier {}", name))?);
+                    }
+                    match text.trim().strip_prefix("Key.") {
+                        Some(name) => {
+                            keys.push(key_named(name).ok_or(format!("unknown key {}", name))?)
+                        }
+                        None => {
+                            let text = string_literal(text).ok_or("the text isn't a string")?;
+                            for c in text.chars() {
+                                let (shifted, key) =
+                                    char_key(c).ok_or(format!("no key types {:?}", c))?;
+                                if shifted {
+                                    keys.push(rdev::Key::ShiftLeft);
+                                }
+                                keys.push(key);
+                            }
+                        }
+                    }
+                    builder.keys(keys);
+                }
+                None => {
+                    // Text and special keys can be joined with `+`
+                    for part in split_top_level(text, '+') {
+                        match part.trim().strip_prefix("Key.") {
+                            Some(name) => {
+                                builder.key(key_named(name).ok_or(format!("unknown key {}", name))?)
+                            }
+                            None => builder
+                                .text(&string_literal(part).ok_or("the text isn't a string")?),
+                        }
+                    }
+                }
+            }
+        }
+        "wait" | "sleep" | "waitVanish" => {
+            if let Ok(secs) = first.trim().parse::<f32>() {
+                builder.wait(secs);
+            } else if let Some(image) = image(first) {
+                // Waiting FOREVER never gives up
+                let timeout = match args.get(1).map(|arg| arg.trim()) {
+                    None => Some(SIKULIX_WAIT_TIMEOUT),
+                    Some("FOREVER") => None,
+                    Some(secs) => Some(
+                        secs.parse::<f32>()
+                            .map_err(|_| "the timeout isn't a number".to_string())?,
+                    ),
+                };
+                builder.wait_for(&directory.join(image), name == "waitVanish", timeout)?;
+            } else {
+                return Err("it doesn't wait for a time or an image".to_string());
+            }
+        }
+        _ => return Err(format!("{} isn't supported", name)),
+    }
+    Ok(())
+}
+
+/// Imports the tests of a Selenium IDE project, a ".side" file, one after the other
+///
+/// Each test starts with a label named after it. Pages are opened through the address bar of the browser in focus,
+/// and typing goes to whichever field has focus, as elements can't be located by their selectors.
+pub fn selenium(path: &Path) -> Result<Imported, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let project: serde_json::Value = serde_json::from_str(&data)
+        .map_err(|e| format!("The file is not a Selenium IDE project: {}", e))?;
+    let base = project["url"].as_str().unwrap_or_default();
+    let tests = project["tests"]
+        .as_array()
+        .ok_or("The project has no tests".to_string())?;
+    let mut builder = Builder::default();
+    for test in tests {
+        let test_name = test["name"].as_str().unwrap_or("Untitled");
+        builder.push(0.1, 10, KeyframeType::Label(test_name.to_string()));
+        for (i, command) in test["commands"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let name = command["command"].as_str().unwrap_or_default();
+            let target = command["target"].as_str().unwrap_or_default();
+            let value = command["value"].as_str().unwrap_or_default();
+            match name {
+                // Commented out commands start with "//"
+                "" => {}
+                name if name.starts_with("//") => {}
+                "open" => {
+                    let url = match target {
+                        url if url.contains("://") => url.to_string(),
+                        "" => base.to_string(),
+                        path => format!(
+                            "{}/{}",
+                            base.trim_end_matches('/'),
+                            path.trim_start_matches('/')
+                        ),
+                    };
+                    builder.keys(vec![rdev::Key::ControlLeft, rdev::Key::KeyL]);
+                    builder.text(&url);
+                    builder.key(rdev::Key::Return);
+                    builder.wait(PAGE_LOAD_TIME);
+                }
+                "type" | "sendKeys" => selenium_keys(&mut builder, value),
+                "pause" => {
+                    // Older projects keep the milliseconds in the target
+                    let ms = value.parse::<f32>().or_else(|_| target.parse::<f32>());
+                    match ms {
+                        Ok(ms) => builder.wait(ms / 1000.),
+                        Err(_) => builder.flag(format!(
+                            "{}, step {}: pause without a time",
+                            test_name,
+                            i + 1
+                        )),
+                    }
+                }
+                "echo" => {
+                    builder.push(0.1, 15, KeyframeType::Log(target.to_string()));
+                }
+                name => builder.flag(format!(
+                    "{}, step {}: {} {}",
+                    test_name,
+                    i + 1,
+                    name,
+                    target
+                )),
+            }
+        }
+    }
+    builder.finish()
+}
+
+/// Types a Selenium IDE value, pressing the keys written as `${KEY_ENTER}` and the like
+fn selenium_keys(builder: &mut Builder, value: &str) {
+    let mut rest = value;
+    while let Some(start) = rest.find("${KEY_") {
+        let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+            break;
+        };
+        builder.text(&rest[..start]);
+        match key_named(&rest[start + 2..end]) {
+            Some(key) => builder.key(key),
+            None => builder.text(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    builder.text(rest);
+}
+
+/// Imports an AutoHotkey script, best effort
+///
+/// `Send`, `Click`, `MouseClick`, `MouseMove` and `Sleep` lines become keyframes, in both the v1 and v2 syntax.
+/// Hotkeys are dropped, leaving the lines they run, and control flow is flagged with the lines within it imported once.
+pub fn autohotkey(path: &Path) -> Result<Imported, String> {
+    let script = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut builder = Builder::default();
+    let mut in_comment = false;
+    // Positions are relative to the active window unless the script says otherwise
+    let mut screen_coordinates = false;
+    let mut warned_coordinates = false;
+    for (n, line) in script.lines().enumerate() {
+        let mut line = strip_ahk_comment(line).trim();
+        if in_comment {
+            in_comment = !line.contains("*/");
+            continue;
+        }
+        if line.starts_with("/*") {
+            in_comment = !line.contains("*/");
+            continue;
+        }
+        let step = format!("Line {}: {}", n + 1, line);
+        if line.starts_with("::") {
+            builder.flag(format!("{} (hotstrings aren't supported)", step));
+            continue;
+        }
+        if let Some((hotkey, rest)) = line.split_once("::") {
+            builder.flag(format!(
+                "Line {}: {}:: (imported without the hotkey)",
+                n + 1,
+                hotkey
+            ));
+            line = rest.trim();
+        }
+        // Braces only group lines, so `} else {` is read as `else {`
+        let line = line.trim_start_matches('}').trim();
+        if line.is_empty() || line.starts_with('#') || line == "{" {
+            continue;
+        }
+        let name_end = line
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(line.len());
+        let (name, rest) = line.split_at(name_end);
+        let args = rest.trim_start().trim_start_matches(',').trim();
+        // v2 calls them like functions
+        let args = match args
+            .strip_prefix('(')
+            .and_then(|args| args.strip_suffix(')'))
+        {
+            Some(args) => args.trim(),
+            None => args,
+        };
+        let result = match name.to_lowercase().as_str() {
+            "return" | "exitapp" | "sendmode" | "setworkingdir" => Ok(()),
+            "coordmode" => {
+                let args = args.to_lowercase();
+                if args.contains("mouse") {
+                    screen_coordinates = args.contains("screen");
+                }
+                Ok(())
+            }
+            "if" | "else" | "loop" | "while" | "for" | "try" | "catch" | "finally" | "until" => {
+                Err("the lines within it are imported as if it ran once".to_string())
+            }
+            "send" | "sendinput" | "sendevent" | "sendplay" => {
+                ahk_text(args).and_then(|text| ahk_send(&mut builder, &text, false))
+            }
+            "sendraw" | "sendtext" => {
+                ahk_text(args).and_then(|text| ahk_send(&mut builder, &text, true))
+            }
+            "sleep" => args
+                .parse::<f32>()
+                .map(|ms| builder.wait(ms / 1000.))
+                .map_err(|_| "the time isn't a number".to_string()),
+            "click" | "mouseclick" | "mousemove" => {
+                let moves = ahk_click(&mut builder, args, name.eq_ignore_ascii_case("mousemove"));
+                if matches!(moves, Ok(true)) && !screen_coordinates && !warned_coordinates {
+                    warned_coordinates = true;
+                    builder.flag(format!(
+                        "{} (positions are relative to the active window in AutoHotkey unless CoordMode \
+                        sets them to the screen, but Automate uses screen positions)",
+                        step
+                    ));
+                }
+                moves.map(|_| ())
+            }
+            _ => Err(format!("{} isn't supported", name)),
+        };
+        if let Err(err) = result {
+            builder.flag(format!("{} ({})", step, err));
+        }
+    }
+    builder.finish()
+}
+
+/// Adds the keyframes for a `Click`, `MouseClick` or `MouseMove` line, returning whether it moves the mouse
+///
+/// The arguments can come in any order, with the first two numbers as the position and the third as how many
+/// times to click, except for mouse moves which never click.
+fn ahk_click(builder: &mut Builder, args: &str, only_move: bool) -> Result<bool, String> {
+    let mut numbers = vec![];
+    let mut button = rdev::Button::Left;
+    for token in args
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+    {
+        if let Ok(number) = token.parse::<f32>() {
+            numbers.push(number);
+            continue;
+        }
+        button = match token.to_lowercase().as_str() {
+            "left" | "l" => rdev::Button::Left,
+            "right" | "r" if !only_move => rdev::Button::Right,
+            "middle" | "m" => rdev::Button::Middle,
+            _ => return Err(format!("{} isn't supported", token)),
+        };
+    }
+    let (pos, clicks) = match numbers[..] {
+        [x, y, ..] if only_move => (Some(Vec2::new(x, y)), 0.),
+        [x, y] => (Some(Vec2::new(x, y)), 1.),
+        [x, y, clicks, ..] => (Some(Vec2::new(x, y)), clicks),
+        [clicks] if !only_move => (None, clicks),
+        [] if !only_move => (None, 1.),
+        _ => return Err("expected a position".to_string()),
+    };
+    if clicks > MAX_REPEATS as f32 {
+        return Err(format!("can't click more than {} times", MAX_REPEATS));
+    }
+    if let Some(pos) = pos {
+        builder.mouse_move(pos);
+    }
+    for _ in 0..clicks.max(0.) as usize {
+        builder.click(button);
+    }
+    Ok(pos.is_some())
+}
+
+/// The text of a `Send` line, unquoted if it is a v2 string, with its escape sequences replaced
+fn ahk_text(args: &str) -> Result<String, String> {
+    let quote = args.chars().next().filter(|c| matches!(c, '"' | '\''));
+    let text = match quote.and_then(|quote| args[1..].strip_suffix(quote)) {
+        Some(quoted) => quoted.to_string(),
+        None if args.contains('%') => {
+            return Err("variables aren't supported".to_string());
+        }
+        None => args.to_string(),
+    };
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some('t') => unescaped.push('\t'),
+                Some('r') => {}
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('`'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    Ok(unescaped)
+}
+
+/// A key press written in the text of a `Send` line
+enum Press {
+    Char(char),
+    Key(rdev::Key),
+}
+
+/// Types the text of a `Send` line, where `^`, `!`, `+` and `#` hold Ctrl, Alt, Shift and Win for the next key
+/// and keys are named in braces like `{Enter}` or `{Tab 3}`, unless it is `raw`
+fn ahk_send(builder: &mut Builder, text: &str, mut raw: bool) -> Result<(), String> {
+    let mut typed = String::new();
+    let mut modifiers = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        let (press, count) = match c {
+            '^' | '!' | '+' | '#' if !raw => {
+                modifiers.push(match c {
+                    '^' => rdev::Key::ControlLeft,
+                    '!' => rdev::Key::Alt,
+                    '+' => rdev::Key::ShiftLeft,
+                    _ => rdev::Key::MetaLeft,
+                });
+                continue;
+            }
+            '{' if !raw => {
+                // The first character is part of the name so `{}}` types a brace
+                let first = chars.next().ok_or("missing }")?;
+                let mut name = first.to_string();
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                    name.push(c);
+                }
+                let (name, count) = match name.rsplit_once(' ') {
+                    Some((name, count)) => (
+                        name.to_string(),
+                        count
+                            .parse::<usize>()
+                            .map_err(|_| format!("{{{} {}}} isn't supported", name, count))?,
+                    ),
+                    None => (name, 1),
+                };
+                if count > MAX_REPEATS {
+                    return Err(format!(
+                        "can't press {{{}}} more than {} times",
+                        name, MAX_REPEATS
+                    ));
+                }
+                if name.eq_ignore_ascii_case("raw") || name.eq_ignore_ascii_case("text") {
+                    raw = true;
+                    continue;
+                }
+                let mut name_chars = name.chars();
+                let press = match (name_chars.next(), name_chars.next()) {
+                    (Some(c), None) => Press::Char(c),
+                    _ => {
+                        Press::Key(key_named(&name).ok_or(format!("{{{}}} isn't supported", name))?)
+                    }
+                };
+                (press, count)
+            }
+            '\n' => (Press::Key(rdev::Key::Return), 1),
+            '\t' => (Press::Key(rdev::Key::Tab), 1),
+            c => (Press::Char(c), 1),
+        };
+        if modifiers.is_empty() {
+            match press {
+                Press::Char(c) => (0..count).for_each(|_| typed.push(c)),
+                Press::Key(key) => {
+                    builder.text(&std::mem::take(&mut typed));
+                    for _ in 0..count {
+                        builder.key(key);
+                    }
+                }
+            }
+            continue;
+        }
+        builder.text(&std::mem::take(&mut typed));
+        let mut keys = std::mem::take(&mut modifiers);
+        match press {
+            Press::Char(c) => {
+                let (shifted, key) = char_key(c).ok_or(format!("no key types {:?}", c))?;
+                if shifted && !keys.contains(&rdev::Key::ShiftLeft) {
+                    keys.push(rdev::Key::ShiftLeft);
+                }
+                keys.push(key);
+            }
+            Press::Key(key) => keys.push(key),
+        }
+        for _ in 0..count {
+            builder.keys(keys.clone());
+        }
+    }
+    builder.text(&typed);
+    Ok(())
+}
+
+/// Removes a `;` comment from the end of a line of AutoHotkey, which starts the line or follows a space
+fn strip_ahk_comment(line: &str) -> &str {
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        if c == ';' && previous.is_whitespace() {
+            return &line[..i];
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Reads a target image to be copied into the sequence, as its file name and contents
+fn read_target(path: &Path) -> Result<(String, Vec<u8>), String> {
+    let image = fs::read(path).map_err(|e| format!("can't read {:?}: {}", path, e))?;
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok((name, image))
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().to_string()
+}
+
+/// The key named `name` in a script, ignoring case and any "KEY_" prefix
+pub fn key_named(name: &str) -> Option<rdev::Key> {
+    let name = name.trim().to_uppercase();
+    let key = match name.strip_prefix("KEY_").unwrap_or(&name) {
+        "ENTER" | "RETURN" => rdev::Key::Return,
+        "TAB" => rdev::Key::Tab,
+        "ESC" | "ESCAPE" => rdev::Key::Escape,
+        "BACKSPACE" | "BACK_SPACE" | "BS" => rdev::Key::Backspace,
+        "DELETE" | "DEL" => rdev::Key::Delete,
+        "INSERT" | "INS" => rdev::Key::Insert,
+        "SPACE" => rdev::Key::Space,
+        "UP" => rdev::Key::UpArrow,
+        "DOWN" => rdev::Key::DownArrow,
+        "LEFT" => rdev::Key::LeftArrow,
+        "RIGHT" => rdev::Key::RightArrow,
+        "HOME" => rdev::Key::Home,
+        "END" => rdev::Key::End,
+        "PAGE_UP" | "PGUP" => rdev::Key::PageUp,
+        "PAGE_DOWN" | "PGDN" => rdev::Key::PageDown,
+        "CAPS_LOCK" | "CAPSLOCK" => rdev::Key::CapsLock,
+        "CTRL" | "CONTROL" | "LCTRL" => rdev::Key::ControlLeft,
+        "RCTRL" => rdev::Key::ControlRight,
+        "SHIFT" | "LSHIFT" => rdev::Key::ShiftLeft,
+        "RSHIFT" => rdev::Key::ShiftRight,
+        "ALT" | "LALT" => rdev::Key::Alt,
+        "RALT" | "ALTGR" => rdev::Key::AltGr,
+        "CMD" | "META" | "WIN" | "LWIN" | "COMMAND" => rdev::Key::MetaLeft,
+        "RWIN" => rdev::Key::MetaRight,
+        "F1" => rdev::Key::F1,
+        "F2" => rdev::Key::F2,
+        "F3" => rdev::Key::F3,
+        "F4" => rdev::Key::F4,
+        "F5" => rdev::Key::F5,
+        "F6" => rdev::Key::F6,
+        "F7" => rdev::Key::F7,
+        "F8" => rdev::Key::F8,
+        "F9" => rdev::Key::F9,
+        "F10" => rdev::Key::F10,
+        "F11" => rdev::Key::F11,
+        "F12" => rdev::Key::F12,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The key that types `c` on the current layout, and whether shift has to be held for it
+pub fn char_key(c: char) -> Option<(bool, rdev::Key)> {
+    match c {
+        ' ' => Some((false, rdev::Key::Space)),
+        c => layout()
+            .key_for(c)
+            .map(|key| (false, key))
+            .or_else(|| layout().shifted_key_for(c).map(|key| (true, key))),
+    }
+}
+
+/// Removes a `#` comment from the end of a line of Python
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return &line[..i],
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits a line like `name(args)` into the name and the arguments
+fn call(line: &str) -> Option<(&str, &str)> {
+    let open = line.find('(')?;
+    let name = line[..open].trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some((name, line[open + 1..].strip_suffix(')')?))
+}
+
+/// Splits `text` at each `separator` that isn't inside quotes or brackets
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if q == c => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, c) if c == separator && depth == 0 => {
+                parts.push(text[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    if !text[start..].trim().is_empty() {
+        parts.push(text[start..].trim());
+    }
+    parts
+}
+
+/// The contents of a Python string literal, with its escapes replaced
+fn string_literal(text: &str) -> Option<String> {
+    let text = text.trim();
+    let (raw, text) = match text.strip_prefix(['r', 'R']) {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix(['u', 'U']).unwrap_or(text)),
+    };
+    let quote = text.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+    let inner = text.strip_prefix(quote)?.strip_suffix(quote)?;
+    if raw {
+        return Some(inner.to_string());
+    }
+    let mut string = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c) => string.push(c),
+                None => string.push('\\'),
+            },
+            c => string.push(c),
+        }
+    }
+    Some(string)
+}
+
+/// The file name of the image a SikuliX argument refers to, either directly or through a `Pattern`
+fn image(arg: &str) -> Option<String> {
+    let arg = arg.trim();
+    if let Some(pattern) = arg.strip_prefix("Pattern(") {
+        let end = pattern.find(')')?;
+        return string_literal(&pattern[..end]);
+    }
+    string_literal(arg).filter(|name| {
+        let name = name.to_lowercase();
+        name.ends_with(".png") || name.ends_with(".jpg") || name.ends_with(".jpeg")
+    })
+}
+
+/// The position of a SikuliX `Location(x, y)` argument
+fn location(arg: &str) -> Option<Vec2> {
+    let coordinates = arg.trim().strip_prefix("Location(")?.strip_suffix(')')?;
+    let (x, y) = coordinates.split_once(',')?;
+    Some(Vec2::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Imports a SikuliX script with the given lines from a folder of its own, next to a "button.png" target
+    fn import_sikulix(script: &str) -> (Imported, String) {
+        let directory = std::env::temp_dir().join(format!("{}.sikuli", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("button.png"), b"png").unwrap();
+        let path = directory.join("script.py");
+        fs::write(&path, script).unwrap();
+        let imported = sikulix(&path);
+        fs::remove_dir_all(&directory).unwrap();
+        (
+            imported.unwrap(),
+            path_string(&directory.join("button.png")),
+        )
+    }
+
+    /// Checks `keyframes` start with a while image loop around a short wait, repeating while the target is `present`,
+    /// that gives up after `checks` checks or without them waits forever
+    fn assert_wait_loop(keyframes: &[Keyframe], target: &str, present: bool, checks: Option<u32>) {
+        let [looped, rest @ ..] = keyframes else {
+            panic!("expected a loop, got {:?}", keyframes);
+        };
+        assert_eq!(looped.kind, 19);
+        assert!(matches!(
+            &looped.keyframe_type,
+            KeyframeType::WhileImage(path, p, None) if path == target && *p == present
+        ));
+        let end = looped.timestamp + looped.duration;
+        let within: Vec<&Keyframe> = rest.iter().take_while(|kf| kf.timestamp < end).collect();
+        // Everything within is inside the loop, so it plays on every iteration
+        for keyframe in &within {
+            assert!(keyframe.timestamp > looped.timestamp);
+            assert!(keyframe.timestamp + keyframe.duration < end);
+        }
+        let wait = match checks {
+            Some(checks) => {
+                let [jump, abort, label, wait] = within[..] else {
+                    panic!("expected a jump, abort, label and wait, got {:?}", within);
+                };
+                let KeyframeType::Label(name) = &label.keyframe_type else {
+                    panic!("expected a label, got {:?}", label);
+                };
+                assert!(matches!(
+                    &jump.keyframe_type,
+                    KeyframeType::Jump(to, max, 0) if to == name && *max == checks
+                ));
+                // The jump skips the abort until it runs out of jumps
+                assert_eq!(abort.kind, 12);
+                assert!(jump.timestamp < abort.timestamp && abort.timestamp < label.timestamp);
+                wait
+            }
+            None => {
+                let [wait] = within[..] else {
+                    panic!("expected a wait, got {:?}", within);
+                };
+                wait
+            }
+        };
+        assert_eq!(wait.kind, 4);
+        assert!(matches!(wait.keyframe_type, KeyframeType::Wait(secs) if secs == POLL_INTERVAL));
+    }
+
+    #[test]
+    fn imports_sikulix_wait_as_a_loop_until_the_image_appears() {
+        let (imported, target) = import_sikulix("wait(\"button.png\")\n");
+        assert!(imported.unsupported.is_empty());
+        let keyframes = &imported.snippet.keyframes;
+        assert_eq!(keyframes.len(), 5);
+        // SikuliX gives up after 3 seconds by default
+        assert_wait_loop(keyframes, &target, false, Some(6));
+        assert_eq!(
+            imported.snippet.targets.get(&keyframes[0].uid),
+            Some(&("button.png".to_string(), b"png".to_vec()))
+        );
+    }
+
+    #[test]
+    fn imports_sikulix_wait_vanish_as_a_loop_until_the_image_is_gone() {
+        let (imported, target) = import_sikulix("wait(1.5)\nwaitVanish(\"button.png\", 10)\n");
+        assert!(imported.unsupported.is_empty());
+        let keyframes = &imported.snippet.keyframes;
+        assert_eq!(keyframes.len(), 6);
+        assert!(matches!(keyframes[0].keyframe_type, KeyframeType::Wait(secs) if secs == 1.5));
+        // The loop starts after the wait before it
+        assert!(keyframes[1].timestamp > keyframes[0].timestamp + keyframes[0].duration);
+        assert_wait_loop(&keyframes[1..], &target, true, Some(20));
+    }
+
+    #[test]
+    fn imports_sikulix_wait_forever_as_a_loop_without_a_timeout() {
+        let (imported, target) = import_sikulix("wait(\"button.png\", FOREVER)\n");
+        assert!(imported.unsupported.is_empty());
+        let keyframes = &imported.snippet.keyframes;
+        assert_eq!(keyframes.len(), 2);
+        assert_wait_loop(keyframes, &target, false, None);
+    }
+}
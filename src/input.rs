@@ -0,0 +1,393 @@
+use std::{cell::RefCell, process::Command, sync::Mutex, thread, time::Duration};
+
+use enigo::{Axis, Coordinate, Direction, Enigo, Keyboard, Mouse};
+use rdev::EventType;
+use serde::{Deserialize, Serialize};
+
+use crate::layout::layout;
+
+/// Something that can simulate user input
+pub trait InputBackend {
+    fn simulate(&self, event: &EventType) -> Result<(), String>;
+}
+
+/// Which input backend playback simulates events with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum InputBackendKind {
+    #[default]
+    Rdev,
+    Enigo,
+    /// For Wayland, where neither of the others can simulate input
+    Ydotool,
+}
+
+impl InputBackendKind {
+    pub fn backend(self) -> &'static dyn InputBackend {
+        match self {
+            InputBackendKind::Rdev => &RdevBackend,
+            InputBackendKind::Enigo => &EnigoBackend,
+            InputBackendKind::Ydotool => &YdotoolBackend,
+        }
+    }
+}
+
+static BACKEND: Mutex<InputBackendKind> = Mutex::new(InputBackendKind::Rdev);
+
+/// Sets the backend used by [`simulate`] from now on
+pub fn set_backend(kind: InputBackendKind) {
+    *BACKEND.lock().unwrap() = kind;
+}
+
+/// How many more times a refused event is simulated before [`simulate`] gives up
+static RETRIES: Mutex<u32> = Mutex::new(0);
+/// Time between the attempts at simulating a refused event
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// Sets how many times [`simulate`] retries a refused event from now on
+pub fn set_retries(retries: u32) {
+    *RETRIES.lock().unwrap() = retries;
+}
+
+/// Simulates the event with the selected input backend
+///
+/// Events can be refused by anti-cheat or missing permissions, those are retried before returning the error
+pub fn simulate(event: &EventType) -> Result<(), String> {
+    let backend = BACKEND.lock().unwrap().backend();
+    let retries = *RETRIES.lock().unwrap();
+    let mut result = backend.simulate(event);
+    for attempt in 1..=retries {
+        let Err(err) = &result else {
+            break;
+        };
+        log::warn!("Retrying {:?} ({}/{}) - {}", event, attempt, retries, err);
+        thread::sleep(RETRY_DELAY);
+        result = backend.simulate(event);
+    }
+    result
+}
+
+/// Simulates input with rdev, the same library used for recording
+pub struct RdevBackend;
+
+impl InputBackend for RdevBackend {
+    fn simulate(&self, event: &EventType) -> Result<(), String> {
+        rdev::simulate(event).map_err(|_| format!("rdev refused to simulate {:?}", event))
+    }
+}
+
+thread_local! {
+    // Each thread that simulates input keeps its own connection, as it isn't shared between threads on every platform
+    static ENIGO: RefCell<Option<Enigo>> = const { RefCell::new(None) };
+}
+
+/// Simulates input with enigo, for applications that ignore rdev's events
+pub struct EnigoBackend;
+
+impl InputBackend for EnigoBackend {
+    fn simulate(&self, event: &EventType) -> Result<(), String> {
+        ENIGO.with(|connection| {
+            let mut connection = connection.borrow_mut();
+            if connection.is_none() {
+                let settings = enigo::Settings::default();
+                *connection = Some(Enigo::new(&settings).map_err(|e| e.to_string())?);
+            }
+            let enigo = connection.as_mut().unwrap();
+            let result = match event {
+                EventType::KeyPress(key) => enigo.key(enigo_key(key)?, Direction::Press),
+                EventType::KeyRelease(key) => enigo.key(enigo_key(key)?, Direction::Release),
+                EventType::ButtonPress(button) => {
+                    enigo.button(enigo_button(button)?, Direction::Press)
+                }
+                EventType::ButtonRelease(button) => {
+                    enigo.button(enigo_button(button)?, Direction::Release)
+                }
+                EventType::MouseMove { x, y } => {
+                    enigo.move_mouse(*x as i32, *y as i32, Coordinate::Abs)
+                }
+                // enigo scrolls down for positive amounts, rdev scrolls up
+                EventType::Wheel { delta_x, delta_y } => enigo
+                    .scroll(-*delta_y as i32, Axis::Vertical)
+                    .and_then(|_| enigo.scroll(*delta_x as i32, Axis::Horizontal)),
+            };
+            result.map_err(|e| e.to_string())
+        })
+    }
+}
+
+/// Simulates input by running `ydotool`, which goes through the kernel's uinput so it works on Wayland
+///
+/// Needs `ydotool` installed and its daemon `ydotoold` running
+pub struct YdotoolBackend;
+
+impl InputBackend for YdotoolBackend {
+    fn simulate(&self, event: &EventType) -> Result<(), String> {
+        let args = match event {
+            EventType::KeyPress(key) => vec!["key".to_string(), format!("{}:1", evdev_key(key)?)],
+            EventType::KeyRelease(key) => vec!["key".to_string(), format!("{}:0", evdev_key(key)?)],
+            // The high bits say whether to press (0x40) or release (0x80) the button
+            EventType::ButtonPress(button) => {
+                vec![
+                    "click".to_string(),
+                    format!("{:#x}", 0x40 | ydotool_button(button)?),
+                ]
+            }
+            EventType::ButtonRelease(button) => {
+                vec![
+                    "click".to_string(),
+                    format!("{:#x}", 0x80 | ydotool_button(button)?),
+                ]
+            }
+            EventType::MouseMove { x, y } => vec![
+                "mousemove".to_string(),
+                "--absolute".to_string(),
+                "-x".to_string(),
+                (*x as i32).to_string(),
+                "-y".to_string(),
+                (*y as i32).to_string(),
+            ],
+            EventType::Wheel { delta_x, delta_y } => vec![
+                "mousemove".to_string(),
+                "--wheel".to_string(),
+                "-x".to_string(),
+                delta_x.to_string(),
+                "-y".to_string(),
+                delta_y.to_string(),
+            ],
+        };
+        let status = Command::new("ydotool")
+            .args(&args)
+            .status()
+            .map_err(|e| format!("Failed to run ydotool - {}", e))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(format!("ydotool {} failed with {}", args.join(" "), status)),
+        }
+    }
+}
+
+fn ydotool_button(button: &rdev::Button) -> Result<u8, String> {
+    match button {
+        rdev::Button::Left => Ok(0x00),
+        rdev::Button::Right => Ok(0x01),
+        rdev::Button::Middle => Ok(0x02),
+        rdev::Button::Unknown(_) => Err(format!("ydotool can't simulate {:?}", button)),
+    }
+}
+
+/// Linux input event code of the key, which ydotool takes instead of names
+fn evdev_key(key: &rdev::Key) -> Result<u16, String> {
+    use rdev::Key;
+    let code = match key {
+        Key::Escape => 1,
+        Key::Num1 => 2,
+        Key::Num2 => 3,
+        Key::Num3 => 4,
+        Key::Num4 => 5,
+        Key::Num5 => 6,
+        Key::Num6 => 7,
+        Key::Num7 => 8,
+        Key::Num8 => 9,
+        Key::Num9 => 10,
+        Key::Num0 => 11,
+        Key::Minus => 12,
+        Key::Equal => 13,
+        Key::Backspace => 14,
+        Key::Tab => 15,
+        Key::KeyQ => 16,
+        Key::KeyW => 17,
+        Key::KeyE => 18,
+        Key::KeyR => 19,
+        Key::KeyT => 20,
+        Key::KeyY => 21,
+        Key::KeyU => 22,
+        Key::KeyI => 23,
+        Key::KeyO => 24,
+        Key::KeyP => 25,
+        Key::LeftBracket => 26,
+        Key::RightBracket => 27,
+        Key::Return => 28,
+        Key::ControlLeft => 29,
+        Key::KeyA => 30,
+        Key::KeyS => 31,
+        Key::KeyD => 32,
+        Key::KeyF => 33,
+        Key::KeyG => 34,
+        Key::KeyH => 35,
+        Key::KeyJ => 36,
+        Key::KeyK => 37,
+        Key::KeyL => 38,
+        Key::SemiColon => 39,
+        Key::Quote => 40,
+        Key::BackQuote => 41,
+        Key::ShiftLeft => 42,
+        Key::BackSlash => 43,
+        Key::KeyZ => 44,
+        Key::KeyX => 45,
+        Key::KeyC => 46,
+        Key::KeyV => 47,
+        Key::KeyB => 48,
+        Key::KeyN => 49,
+        Key::KeyM => 50,
+        Key::Comma => 51,
+        Key::Dot => 52,
+        Key::Slash => 53,
+        Key::ShiftRight => 54,
+        Key::KpMultiply => 55,
+        Key::Alt => 56,
+        Key::Space => 57,
+        Key::CapsLock => 58,
+        Key::F1 => 59,
+        Key::F2 => 60,
+        Key::F3 => 61,
+        Key::F4 => 62,
+        Key::F5 => 63,
+        Key::F6 => 64,
+        Key::F7 => 65,
+        Key::F8 => 66,
+        Key::F9 => 67,
+        Key::F10 => 68,
+        Key::NumLock => 69,
+        Key::ScrollLock => 70,
+        Key::Kp7 => 71,
+        Key::Kp8 => 72,
+        Key::Kp9 => 73,
+        Key::KpMinus => 74,
+        Key::Kp4 => 75,
+        Key::Kp5 => 76,
+        Key::Kp6 => 77,
+        Key::KpPlus => 78,
+        Key::Kp1 => 79,
+        Key::Kp2 => 80,
+        Key::Kp3 => 81,
+        Key::Kp0 => 82,
+        Key::KpDelete => 83,
+        Key::IntlBackslash => 86,
+        Key::F11 => 87,
+        Key::F12 => 88,
+        Key::KpReturn => 96,
+        Key::ControlRight => 97,
+        Key::KpDivide => 98,
+        Key::PrintScreen => 99,
+        Key::AltGr => 100,
+        Key::Home => 102,
+        Key::UpArrow => 103,
+        Key::PageUp => 104,
+        Key::LeftArrow => 105,
+        Key::RightArrow => 106,
+        Key::End => 107,
+        Key::DownArrow => 108,
+        Key::PageDown => 109,
+        Key::Insert => 110,
+        Key::Delete => 111,
+        Key::Pause => 119,
+        Key::MetaLeft => 125,
+        Key::MetaRight => 126,
+        _ => return Err(format!("ydotool can't simulate {:?}", key)),
+    };
+    Ok(code)
+}
+
+fn enigo_button(button: &rdev::Button) -> Result<enigo::Button, String> {
+    match button {
+        rdev::Button::Left => Ok(enigo::Button::Left),
+        rdev::Button::Right => Ok(enigo::Button::Right),
+        rdev::Button::Middle => Ok(enigo::Button::Middle),
+        rdev::Button::Unknown(_) => Err(format!("enigo can't simulate {:?}", button)),
+    }
+}
+
+fn enigo_key(key: &rdev::Key) -> Result<enigo::Key, String> {
+    use enigo::Key;
+    // enigo types characters rather than physical keys, so character keys follow the keyboard layout
+    if let Some(character) = layout().char_for(key) {
+        return Ok(Key::Unicode(character));
+    }
+    let character = match key {
+        rdev::Key::KeyA => 'a',
+        rdev::Key::KeyB => 'b',
+        rdev::Key::KeyC => 'c',
+        rdev::Key::KeyD => 'd',
+        rdev::Key::KeyE => 'e',
+        rdev::Key::KeyF => 'f',
+        rdev::Key::KeyG => 'g',
+        rdev::Key::KeyH => 'h',
+        rdev::Key::KeyI => 'i',
+        rdev::Key::KeyJ => 'j',
+        rdev::Key::KeyK => 'k',
+        rdev::Key::KeyL => 'l',
+        rdev::Key::KeyM => 'm',
+        rdev::Key::KeyN => 'n',
+        rdev::Key::KeyO => 'o',
+        rdev::Key::KeyP => 'p',
+        rdev::Key::KeyQ => 'q',
+        rdev::Key::KeyR => 'r',
+        rdev::Key::KeyS => 's',
+        rdev::Key::KeyT => 't',
+        rdev::Key::KeyU => 'u',
+        rdev::Key::KeyV => 'v',
+        rdev::Key::KeyW => 'w',
+        rdev::Key::KeyX => 'x',
+        rdev::Key::KeyY => 'y',
+        rdev::Key::KeyZ => 'z',
+        rdev::Key::Num1 | rdev::Key::Kp1 => '1',
+        rdev::Key::Num2 | rdev::Key::Kp2 => '2',
+        rdev::Key::Num3 | rdev::Key::Kp3 => '3',
+        rdev::Key::Num4 | rdev::Key::Kp4 => '4',
+        rdev::Key::Num5 | rdev::Key::Kp5 => '5',
+        rdev::Key::Num6 | rdev::Key::Kp6 => '6',
+        rdev::Key::Num7 | rdev::Key::Kp7 => '7',
+        rdev::Key::Num8 | rdev::Key::Kp8 => '8',
+        rdev::Key::Num9 | rdev::Key::Kp9 => '9',
+        rdev::Key::Num0 | rdev::Key::Kp0 => '0',
+        rdev::Key::BackQuote => '`',
+        rdev::Key::Minus | rdev::Key::KpMinus => '-',
+        rdev::Key::Equal => '=',
+        rdev::Key::KpPlus => '+',
+        rdev::Key::KpMultiply => '*',
+        rdev::Key::KpDivide | rdev::Key::Slash => '/',
+        rdev::Key::LeftBracket => '[',
+        rdev::Key::RightBracket => ']',
+        rdev::Key::SemiColon => ';',
+        rdev::Key::Quote => '\'',
+        rdev::Key::BackSlash | rdev::Key::IntlBackslash => '\\',
+        rdev::Key::Comma => ',',
+        rdev::Key::Dot => '.',
+        _ => {
+            return match key {
+                rdev::Key::Alt | rdev::Key::AltGr => Ok(Key::Alt),
+                rdev::Key::Backspace => Ok(Key::Backspace),
+                rdev::Key::CapsLock => Ok(Key::CapsLock),
+                rdev::Key::ControlLeft | rdev::Key::ControlRight => Ok(Key::Control),
+                rdev::Key::Delete | rdev::Key::KpDelete => Ok(Key::Delete),
+                rdev::Key::DownArrow => Ok(Key::DownArrow),
+                rdev::Key::End => Ok(Key::End),
+                rdev::Key::Escape => Ok(Key::Escape),
+                rdev::Key::F1 => Ok(Key::F1),
+                rdev::Key::F2 => Ok(Key::F2),
+                rdev::Key::F3 => Ok(Key::F3),
+                rdev::Key::F4 => Ok(Key::F4),
+                rdev::Key::F5 => Ok(Key::F5),
+                rdev::Key::F6 => Ok(Key::F6),
+                rdev::Key::F7 => Ok(Key::F7),
+                rdev::Key::F8 => Ok(Key::F8),
+                rdev::Key::F9 => Ok(Key::F9),
+                rdev::Key::F10 => Ok(Key::F10),
+                rdev::Key::F11 => Ok(Key::F11),
+                rdev::Key::F12 => Ok(Key::F12),
+                rdev::Key::Home => Ok(Key::Home),
+                rdev::Key::LeftArrow => Ok(Key::LeftArrow),
+                rdev::Key::MetaLeft | rdev::Key::MetaRight => Ok(Key::Meta),
+                rdev::Key::PageDown => Ok(Key::PageDown),
+                rdev::Key::PageUp => Ok(Key::PageUp),
+                rdev::Key::Return | rdev::Key::KpReturn => Ok(Key::Return),
+                rdev::Key::RightArrow => Ok(Key::RightArrow),
+                rdev::Key::ShiftLeft | rdev::Key::ShiftRight => Ok(Key::Shift),
+                rdev::Key::Space => Ok(Key::Space),
+                rdev::Key::Tab => Ok(Key::Tab),
+                rdev::Key::UpArrow => Ok(Key::UpArrow),
+                _ => Err(format!("enigo can't simulate {:?}", key)),
+            }
+        }
+    };
+    Ok(Key::Unicode(character))
+}
@@ -1,7 +1,12 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
 use egui::Vec2;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
 use serde::{Deserialize, Serialize};
 use uuid::{Bytes, Uuid};
 
+use crate::util::{button_to_char, find_monitor, key_to_char, monitor_at};
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum KeyframeType {
     /// Simulates a key press
@@ -20,6 +25,88 @@ pub enum KeyframeType {
     MagicMove(String), // 6
     /// Loop the keyframes within this keyframes timeframe
     Loop(u32, u32), // 7
+    /// Types out text, with `{expression}` replaced by its value during playback
+    Text(String), // 8
+    /// Plays one of the equally sized branches within this keyframes timeframe, picked at random by weight each time
+    ///
+    /// Also holds the branch picked for the current pass, if any
+    Random(Vec<f32>, Option<usize>), // 9
+    /// Marks a point in time that jump keyframes can send the playhead to
+    Label(String), // 10
+    /// Sends the playhead to the label with the given name, at most the given number of times per playback
+    ///
+    /// Also holds how many times it has jumped so far
+    Jump(String, u32, u32), // 11
+    /// Stops playback and shows the message, with `{expression}` replaced by its value
+    Abort(String), // 12
+    /// Shows a desktop notification with the text, with `{expression}` replaced by its value
+    Notify(String), // 13
+    /// Plays the sound file at the path, or a short tone if there is no path
+    Beep(String), // 14
+    /// Appends a line to the run log
+    Log(String), // 15
+    /// Switches to the virtual desktop with this number, counting from 1
+    Desktop(u32), // 16
+    /// Holds the key down for the whole keyframe, repeating it like the OS does for a held key
+    Hold(rdev::Key), // 17
+    /// Changes the counter with the given name, which keyframes can refer to like any other variable
+    Counter(String, CounterAction), // 18
+    /// Repeats the keyframes within this keyframes timeframe for as long as the target image is on screen,
+    /// or for as long as it isn't when `false`, checked at the start of each iteration
+    ///
+    /// Also holds how many iterations it has started, while it is repeating
+    WhileImage(String, bool, Option<u32>), // 19
+    /// Plays the first half of the keyframes within this keyframes timeframe if the target image is on screen,
+    /// or if it isn't when `false`, and the second half otherwise
+    ///
+    /// Also holds the branch picked for the current pass, `0` for then and `1` for else
+    If(String, bool, Option<usize>), // 20
+    /// Names the keyframes within this keyframes timeframe, which only play when a call keyframe calls them
+    Subroutine(String), // 21
+    /// Plays the subroutine with the given name, then carries on from the end of this keyframe
+    Call(String), // 22
+    /// Runs the WASM plugin at the path in a sandbox, passing it the argument, see [`crate::plugin`]
+    Plugin(String, String), // 23
+}
+
+impl KeyframeType {
+    /// Path of the image the keyframe looks for on screen, for magic moves, while image loops and ifs
+    pub fn target(&self) -> Option<&String> {
+        match self {
+            KeyframeType::MagicMove(path)
+            | KeyframeType::WhileImage(path, _, _)
+            | KeyframeType::If(path, _, _) => Some(path),
+            _ => None,
+        }
+    }
+    /// Mutable [`KeyframeType::target`], for relinking and relocating target images
+    pub fn target_mut(&mut self) -> Option<&mut String> {
+        match self {
+            KeyframeType::MagicMove(path)
+            | KeyframeType::WhileImage(path, _, _)
+            | KeyframeType::If(path, _, _) => Some(path),
+            _ => None,
+        }
+    }
+}
+
+/// What a counter keyframe does to its counter, counters start at 0 each time playback starts
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CounterAction {
+    /// Adds the step to the counter
+    Increment(i64),
+    /// Sets the counter back to the value
+    Reset(i64),
+}
+
+impl CounterAction {
+    /// Changes the `counter` as the keyframe would
+    pub fn apply(&self, counter: &mut i64) {
+        match self {
+            CounterAction::Increment(step) => *counter += step,
+            CounterAction::Reset(value) => *counter = *value,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -30,7 +117,79 @@ pub struct Keyframe {
     pub kind: u8,
     pub enabled: bool,
     pub uid: Bytes,
+    /// Expressions evaluated during playback in place of recorded fields, by field name, see [`crate::expression`]
+    #[serde(default)]
+    pub expressions: HashMap<String, String>,
+    /// Range in seconds of the random delay before the keyframe is played, if it has one
+    #[serde(default, deserialize_with = "deserialize_delay")]
+    pub delay: Option<(f32, f32)>,
+    /// Seconds between the keys of key strokes and text, `None` uses [`DEFAULT_KEY_DELAY`]
+    #[serde(default)]
+    pub key_delay: Option<f32>,
+    /// Monitor the position of a mouse move was recorded on, see [`MonitorAnchor`]
+    #[serde(default)]
+    pub monitor: Option<MonitorAnchor>,
+    /// Keyframe this one starts after, which decides its timestamp, see [`resolve_dependencies`]
+    #[serde(default)]
+    pub after: Option<Dependency>,
+    /// Annotation shown when reviewing the sequence, so a recording can double as a walkthrough of the task
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Makes a keyframe start once another has finished instead of at a fixed time,
+/// so moving or resizing earlier keyframes carries the later ones along
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dependency {
+    /// UID of the keyframe that has to finish first
+    pub uid: Bytes,
+    /// Seconds between the end of that keyframe and the start of this one
+    pub delay: f32,
+}
+
+/// Monitor a position was recorded on, so the position can follow the monitor when the displays are rearranged
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonitorAnchor {
+    pub name: String,
+    /// Top left corner of the monitor when the position was recorded
+    pub origin: Vec2,
+}
+
+impl MonitorAnchor {
+    /// Anchors `pos` to the monitor it is on, if it is on one
+    pub fn at(pos: Vec2) -> Option<Self> {
+        monitor_at(pos).map(|(name, bounds)| Self {
+            name,
+            origin: bounds.min.to_vec2(),
+        })
+    }
+    /// Moves `pos` by as much as the monitor has moved since it was recorded, or leaves it if the monitor is gone
+    pub fn follow(&self, pos: Vec2) -> Vec2 {
+        match find_monitor(&self.name) {
+            Some(bounds) => pos - self.origin + bounds.min.to_vec2(),
+            None => pos,
+        }
+    }
+}
+
+/// Seconds between typed keys, as many applications drop keys that arrive all at once
+pub const DEFAULT_KEY_DELAY: f32 = 0.005;
+/// Longest random delay in seconds, the most the delay range can be set to
+pub const MAX_DELAY: f32 = 60.;
+
+/// Reads a random delay range, clamped to `0..=MAX_DELAY` as files edited by hand can hold any number
+fn deserialize_delay<'de, D>(deserializer: D) -> Result<Option<(f32, f32)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let clamp = |secs: f32| match secs.is_nan() {
+        true => 0.,
+        false => secs.clamp(0., MAX_DELAY),
+    };
+    let delay = Option::<(f32, f32)>::deserialize(deserializer)?;
+    Ok(delay.map(|(min, max)| (clamp(min), clamp(max))))
 }
+
 impl Keyframe {
     pub fn mouse_move(timestamp: f32, pos: Vec2) -> Self {
         Self {
@@ -40,6 +199,12 @@ impl Keyframe {
             kind: 1,
             enabled: true,
             uid: Uuid::new_v4().to_bytes_le(),
+            expressions: HashMap::new(),
+            delay: None,
+            key_delay: None,
+            monitor: None,
+            after: None,
+            note: String::new(),
         }
     }
     pub fn mouse_button(timestamp: f32, duration: f32, btn: rdev::Button) -> Self {
@@ -50,6 +215,12 @@ impl Keyframe {
             kind: 2,
             enabled: true,
             uid: Uuid::new_v4().to_bytes_le(),
+            expressions: HashMap::new(),
+            delay: None,
+            key_delay: None,
+            monitor: None,
+            after: None,
+            note: String::new(),
         }
     }
     pub fn key_btn(timestamp: f32, duration: f32, key: rdev::Key) -> Self {
@@ -60,6 +231,12 @@ impl Keyframe {
             kind: 0,
             enabled: true,
             uid: Uuid::new_v4().to_bytes_le(),
+            expressions: HashMap::new(),
+            delay: None,
+            key_delay: None,
+            monitor: None,
+            after: None,
+            note: String::new(),
         }
     }
     pub fn scroll(timestamp: f32, delta: Vec2) -> Self {
@@ -70,14 +247,382 @@ impl Keyframe {
             kind: 3,
             enabled: true,
             uid: Uuid::new_v4().to_bytes_le(),
+            expressions: HashMap::new(),
+            delay: None,
+            key_delay: None,
+            monitor: None,
+            after: None,
+            note: String::new(),
+        }
+    }
+    /// Picks how long to wait before playing the keyframe from its delay range
+    ///
+    /// Ranges that aren't finite don't wait at all, rather than panicking
+    pub fn random_delay(&self) -> Duration {
+        let secs = match self.delay {
+            Some((min, max)) if !min.is_finite() || !max.is_finite() => return Duration::ZERO,
+            Some((min, max)) if max > min => rand::thread_rng().gen_range(min..max),
+            Some((min, _)) => min,
+            None => return Duration::ZERO,
+        };
+        Duration::try_from_secs_f32(secs.min(MAX_DELAY)).unwrap_or(Duration::ZERO)
+    }
+    /// How long to wait between typed keys
+    pub fn key_delay(&self) -> Duration {
+        Duration::from_secs_f32(self.key_delay.unwrap_or(DEFAULT_KEY_DELAY).max(0.))
+    }
+    /// Whether the keyframe spans the keyframes within its timeframe, like a loop
+    pub fn is_group(&self) -> bool {
+        matches!(self.kind, 7 | 9 | 19 | 20 | 21)
+    }
+    /// The branch picked for the current pass of a random or if keyframe, along with how many branches it has
+    pub fn picked_branch(&self) -> Option<(usize, usize)> {
+        match &self.keyframe_type {
+            KeyframeType::Random(weights, picked) => picked.map(|branch| (branch, weights.len())),
+            KeyframeType::If(_, _, picked) => picked.map(|branch| (branch, 2)),
+            _ => None,
         }
     }
+    /// Forgets the branch picked for the current pass of a random or if keyframe, once it ends
+    pub fn clear_branch(&mut self) {
+        if let KeyframeType::Random(_, picked) | KeyframeType::If(_, _, picked) =
+            &mut self.keyframe_type
+        {
+            *picked = None;
+        }
+    }
+    /// Earliest time the playhead is past the end of the keyframe, where it has ended
+    pub fn after_end(&self) -> f32 {
+        self.timestamp + self.duration + SEQUENTIAL_STEP
+    }
+    /// Start and end time of the `branch`th of `branches` equally sized branches of a random keyframe
+    pub fn branch_range(&self, branch: usize, branches: usize) -> (f32, f32) {
+        let width = self.duration / branches.max(1) as f32;
+        let start = self.timestamp + width * branch as f32;
+        (start, start + width)
+    }
     pub fn calculate_duration(&mut self, dt: f32) -> &mut Self {
         self.duration = dt - self.timestamp;
         self
     }
 }
 
+/// Picks a branch of a random keyframe, where each branch is picked in proportion to its weight
+///
+/// Falls back to the first branch if no branch has a positive weight
+pub fn pick_branch(weights: &[f32]) -> usize {
+    WeightedIndex::new(weights)
+        .map(|index| index.sample(&mut rand::thread_rng()))
+        .unwrap_or(0)
+}
+
+/// Timestamp of the label keyframe with the given name
+pub fn label_time(keyframes: &[Keyframe], name: &str) -> Option<f32> {
+    keyframes
+        .iter()
+        .find_map(|keyframe| match &keyframe.keyframe_type {
+            KeyframeType::Label(label) if label == name && keyframe.enabled => {
+                Some(keyframe.timestamp)
+            }
+            _ => None,
+        })
+}
+
+/// Index of the subroutine keyframe with the given name
+pub fn subroutine_index(keyframes: &[Keyframe], name: &str) -> Option<usize> {
+    keyframes
+        .iter()
+        .position(|keyframe| match &keyframe.keyframe_type {
+            KeyframeType::Subroutine(subroutine) => subroutine == name && keyframe.enabled,
+            _ => false,
+        })
+}
+
+/// How far into or past a keyframe sequential playback steps to
+///
+/// Stepping just inside the start means keyframes starting where a loop or jump sent the playhead are still played.
+const SEQUENTIAL_STEP: f32 = 0.001;
+
+/// The next time after `time` that an enabled keyframe starts or ends, for sequential playback
+///
+/// Rather than waiting for the playhead to get there, sequential playback jumps straight to it once the
+/// keyframes playing now are done.
+pub fn next_event(keyframes: &[Keyframe], time: f32) -> Option<f32> {
+    keyframes
+        .iter()
+        .filter(|keyframe| keyframe.enabled)
+        .flat_map(|keyframe| {
+            [
+                keyframe.timestamp + (keyframe.duration / 2.).min(SEQUENTIAL_STEP),
+                keyframe.after_end(),
+            ]
+        })
+        .filter(|t| *t > time)
+        .reduce(f32::min)
+}
+
+/// Resets how many times each jump keyframe has jumped and while image loops have repeated, at the start of playback
+pub fn reset_jumps(keyframes: &mut [Keyframe]) {
+    for keyframe in keyframes {
+        match &mut keyframe.keyframe_type {
+            KeyframeType::Jump(_, _, jumps) => *jumps = 0,
+            KeyframeType::WhileImage(_, _, iterations) => *iterations = None,
+            _ => {}
+        }
+    }
+}
+
+/// How bad a problem found by [`validate`] is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    /// Playback still works, but probably not as intended
+    Warning,
+    /// Playback would fail part way through, so it doesn't start
+    Error,
+}
+
+/// Something wrong with a keyframe, found before playback starts
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub uid: Bytes,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// An input only one keyframe can have pressed at a time
+#[derive(PartialEq)]
+enum Pressed {
+    Key(rdev::Key),
+    Button(rdev::Button),
+}
+
+impl Pressed {
+    fn of(keyframe: &Keyframe) -> Option<Self> {
+        match keyframe.keyframe_type {
+            KeyframeType::KeyBtn(key) | KeyframeType::Hold(key) => Some(Pressed::Key(key)),
+            KeyframeType::MouseBtn(button) => Some(Pressed::Button(button)),
+            _ => None,
+        }
+    }
+    fn name(&self) -> String {
+        match self {
+            Pressed::Key(key) => key_to_char(key),
+            Pressed::Button(button) => button_to_char(button),
+        }
+    }
+}
+
+/// Checks the enabled keyframes for anything that would make playback fail or misbehave
+///
+/// Keyframes taking longer than `max_duration` seconds are flagged, as they hold up playback and waits freeze the editor
+pub fn validate(keyframes: &[Keyframe], max_duration: f32) -> Vec<Problem> {
+    let mut problems = vec![];
+    let mut report = |keyframe: &Keyframe, severity, message| {
+        problems.push(Problem {
+            uid: keyframe.uid,
+            severity,
+            message,
+        })
+    };
+    let by_uid: HashMap<Bytes, &Keyframe> = keyframes
+        .iter()
+        .map(|keyframe| (keyframe.uid, keyframe))
+        .collect();
+    let mut presses = vec![];
+    for keyframe in keyframes.iter().filter(|keyframe| keyframe.enabled) {
+        if let Some(after) = keyframe.after {
+            // Follow the chain back, which leads back here if it is a loop
+            let mut next = by_uid.get(&after.uid).copied();
+            let mut steps = 0;
+            while let Some(current) = next.filter(|current| current.uid != keyframe.uid) {
+                next = current
+                    .after
+                    .and_then(|after| by_uid.get(&after.uid).copied());
+                steps += 1;
+                if steps > keyframes.len() {
+                    break;
+                }
+            }
+            if by_uid.get(&after.uid).is_none() {
+                report(
+                    keyframe,
+                    Severity::Warning,
+                    "Starts after a keyframe that no longer exists".to_string(),
+                );
+            } else if next.is_some() {
+                report(
+                    keyframe,
+                    Severity::Warning,
+                    "Starts after itself through a loop of keyframes starting after each other"
+                        .to_string(),
+                );
+            }
+        }
+        if !keyframe.timestamp.is_finite()
+            || !keyframe.duration.is_finite()
+            || keyframe.duration < 0.
+        {
+            report(
+                keyframe,
+                Severity::Error,
+                "Has no valid timing, it may have been recorded without being released".to_string(),
+            );
+            continue;
+        }
+        let length = match keyframe.keyframe_type {
+            KeyframeType::Wait(secs) => secs.max(keyframe.duration),
+            _ => keyframe.duration,
+        };
+        if length > max_duration {
+            report(
+                keyframe,
+                Severity::Warning,
+                format!("Takes {:.0}s, over the {:.0}s limit", length, max_duration),
+            );
+        }
+        if let KeyframeType::Counter(name, _) = &keyframe.keyframe_type {
+            if name.trim().is_empty() {
+                report(
+                    keyframe,
+                    Severity::Error,
+                    "Has no counter name to change".to_string(),
+                );
+            }
+        }
+        if let KeyframeType::Call(name) = &keyframe.keyframe_type {
+            if subroutine_index(keyframes, name).is_none() {
+                report(
+                    keyframe,
+                    Severity::Error,
+                    format!("Calls a subroutine named {:?} that doesn't exist", name),
+                );
+            }
+        }
+        if let KeyframeType::Plugin(path, _) = &keyframe.keyframe_type {
+            if !Path::new(path).exists() {
+                report(
+                    keyframe,
+                    Severity::Error,
+                    format!("Plugin {:?} doesn't exist", path),
+                );
+            }
+        }
+        if let Some(path) = keyframe.keyframe_type.target() {
+            if !Path::new(path).exists() {
+                report(
+                    keyframe,
+                    Severity::Error,
+                    format!("Target image {:?} doesn't exist", path),
+                );
+            }
+        }
+        if let Some(pressed) = Pressed::of(keyframe) {
+            presses.push((pressed, keyframe));
+        }
+    }
+    // Pressing what is already pressed means the first release lets go of both
+    presses.sort_by(|(_, a), (_, b)| a.timestamp.total_cmp(&b.timestamp));
+    let mut held: Vec<(&Pressed, f32)> = vec![];
+    for (pressed, keyframe) in &presses {
+        held.retain(|(_, end)| *end > keyframe.timestamp);
+        if held.iter().any(|(other, _)| *other == pressed) {
+            report(
+                *keyframe,
+                Severity::Warning,
+                format!(
+                    "Presses {} while an earlier keyframe holds it",
+                    pressed.name()
+                ),
+            );
+        }
+        held.push((pressed, keyframe.timestamp + keyframe.duration));
+    }
+    problems
+}
+
+/// Moves each keyframe that starts after another to the end of it plus its delay, following chains of them
+///
+/// Dependencies on keyframes that no longer exist are ignored, as are loops of them, which [`validate`] reports.
+/// Returns whether any keyframe moved.
+pub fn resolve_dependencies(keyframes: &mut [Keyframe]) -> bool {
+    if keyframes.iter().all(|keyframe| keyframe.after.is_none()) {
+        return false;
+    }
+    let indices: HashMap<Bytes, usize> = keyframes
+        .iter()
+        .enumerate()
+        .map(|(i, keyframe)| (keyframe.uid, i))
+        .collect();
+    let mut moved = false;
+    // Each pass settles at least one more link of every chain, so a chain can't take more passes than there are keyframes
+    for _ in 0..keyframes.len() {
+        let mut changed = false;
+        for i in 0..keyframes.len() {
+            let Some(after) = keyframes[i].after else {
+                continue;
+            };
+            let Some(&target) = indices.get(&after.uid) else {
+                continue;
+            };
+            let start = keyframes[target].timestamp + keyframes[target].duration + after.delay;
+            if start.is_finite() && (keyframes[i].timestamp - start).abs() > 1e-4 {
+                keyframes[i].timestamp = start.max(0.);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+        moved = true;
+    }
+    moved
+}
+
+/// Points the dependencies of copied keyframes at the copies of the keyframes they start after, using the old to new UIDs
+///
+/// Dependencies on keyframes that weren't copied are dropped, otherwise the copy would land on top of the original
+pub fn remap_dependencies(keyframes: &mut [Keyframe], new_uids: &HashMap<Bytes, Bytes>) {
+    for keyframe in keyframes {
+        keyframe.after = keyframe.after.and_then(|after| {
+            new_uids.get(&after.uid).map(|uid| Dependency {
+                uid: *uid,
+                delay: after.delay,
+            })
+        });
+    }
+}
+
+/// Name of the type of keyframe with the given kind, for showing to the user
+pub fn kind_name(kind: u8) -> &'static str {
+    match kind {
+        0 => "Key",
+        1 => "Mouse Move",
+        2 => "Mouse Button",
+        3 => "Scroll",
+        4 => "Wait",
+        5 => "Key Strokes",
+        6 => "Magic Move",
+        7 => "Loop",
+        8 => "Text",
+        9 => "Random",
+        10 => "Label",
+        11 => "Jump",
+        12 => "Abort",
+        13 => "Notify",
+        14 => "Beep",
+        15 => "Log",
+        16 => "Switch Desktop",
+        17 => "Hold Key",
+        18 => "Counter",
+        19 => "While Image",
+        20 => "If Image",
+        21 => "Subroutine",
+        22 => "Call",
+        23 => "Plugin",
+        _ => "Unknown",
+    }
+}
+
 impl Default for Keyframe {
     fn default() -> Self {
         Self {
@@ -87,6 +632,12 @@ impl Default for Keyframe {
             kind: 0,
             enabled: true,
             uid: Uuid::new_v4().to_bytes_le(),
+            expressions: HashMap::new(),
+            delay: None,
+            key_delay: None,
+            monitor: None,
+            after: None,
+            note: String::new(),
         }
     }
 }
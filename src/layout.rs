@@ -0,0 +1,126 @@
+use std::sync::Mutex;
+
+use rdev::Key;
+use serde::{Deserialize, Serialize};
+
+/// Physical keys of the number row and the three letter rows, left to right and top to bottom,
+/// named after their US QWERTY characters
+const CHARACTER_KEYS: [Key; 46] = [
+    Key::BackQuote,
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+    Key::Num0,
+    Key::Minus,
+    Key::Equal,
+    Key::KeyQ,
+    Key::KeyW,
+    Key::KeyE,
+    Key::KeyR,
+    Key::KeyT,
+    Key::KeyY,
+    Key::KeyU,
+    Key::KeyI,
+    Key::KeyO,
+    Key::KeyP,
+    Key::LeftBracket,
+    Key::RightBracket,
+    Key::KeyA,
+    Key::KeyS,
+    Key::KeyD,
+    Key::KeyF,
+    Key::KeyG,
+    Key::KeyH,
+    Key::KeyJ,
+    Key::KeyK,
+    Key::KeyL,
+    Key::SemiColon,
+    Key::Quote,
+    Key::KeyZ,
+    Key::KeyX,
+    Key::KeyC,
+    Key::KeyV,
+    Key::KeyB,
+    Key::KeyN,
+    Key::KeyM,
+    Key::Comma,
+    Key::Dot,
+    Key::Slash,
+];
+
+/// Keyboard layout of the system, which decides the character each physical key types
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum KeyboardLayout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+}
+
+impl KeyboardLayout {
+    /// Characters typed by each of the [`CHARACTER_KEYS`] without any modifiers
+    fn characters(self) -> &'static str {
+        match self {
+            KeyboardLayout::Qwerty => "`1234567890-=qwertyuiop[]asdfghjkl;'zxcvbnm,./",
+            KeyboardLayout::Azerty => "²&é\"'(-è_çà)=azertyuiop^$qsdfghjklmùwxcvbn,;:!",
+            KeyboardLayout::Qwertz => "^1234567890ß´qwertzuiopü+asdfghjklöäyxcvbnm,.-",
+            KeyboardLayout::Dvorak => "`1234567890[]',.pyfgcrl/=aoeuidhtns-;qjkxbmwvz",
+        }
+    }
+    /// Characters typed by each of the [`CHARACTER_KEYS`] while shift is held
+    fn shifted_characters(self) -> &'static str {
+        match self {
+            KeyboardLayout::Qwerty => "~!@#$%^&*()_+QWERTYUIOP{}ASDFGHJKL:\"ZXCVBNM<>?",
+            KeyboardLayout::Azerty => "²1234567890°+AZERTYUIOP¨£QSDFGHJKLM%WXCVBN?./§",
+            KeyboardLayout::Qwertz => "°!\"§$%&/()=?`QWERTZUIOPÜ*ASDFGHJKLÖÄYXCVBNM;:_",
+            KeyboardLayout::Dvorak => "~!@#$%^&*(){}\"<>PYFGCRL?+AOEUIDHTNS_:QJKXBMWVZ",
+        }
+    }
+    /// The physical key that types `c` on this layout without any modifiers
+    pub fn key_for(self, c: char) -> Option<Key> {
+        self.characters()
+            .chars()
+            .position(|x| x == c)
+            .map(|i| CHARACTER_KEYS[i])
+    }
+    /// The physical key that types `c` on this layout while shift is held
+    pub fn shifted_key_for(self, c: char) -> Option<Key> {
+        self.shifted_characters()
+            .chars()
+            .position(|x| x == c)
+            .map(|i| CHARACTER_KEYS[i])
+    }
+    /// The character `key` types on this layout without any modifiers
+    pub fn char_for(self, key: &Key) -> Option<char> {
+        CHARACTER_KEYS
+            .iter()
+            .position(|x| x == key)
+            .and_then(|i| self.characters().chars().nth(i))
+    }
+    /// The character `key` types on this layout while shift is held
+    pub fn shifted_char_for(self, key: &Key) -> Option<char> {
+        CHARACTER_KEYS
+            .iter()
+            .position(|x| x == key)
+            .and_then(|i| self.shifted_characters().chars().nth(i))
+    }
+}
+
+static LAYOUT: Mutex<KeyboardLayout> = Mutex::new(KeyboardLayout::Qwerty);
+
+/// Sets the layout used to convert between keys and characters from now on
+pub fn set_layout(layout: KeyboardLayout) {
+    *LAYOUT.lock().unwrap() = layout;
+}
+
+/// The layout used to convert between keys and characters
+pub fn layout() -> KeyboardLayout {
+    *LAYOUT.lock().unwrap()
+}
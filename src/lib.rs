@@ -1,8 +1,23 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
+mod console;
+mod engine;
+mod expression;
+mod file;
+mod ghost;
+mod guide;
+mod import;
+mod input;
 mod sequencer;
 mod keyframe;
+mod layout;
+mod player;
+mod plugin;
+mod remote;
 mod settings;
+mod theme;
 mod util;
-pub use app::App;
+pub use app::{App, LaunchOptions};
+pub use player::run_embedded;
+pub use remote::serve_stdio;
@@ -1,12 +1,27 @@
 #![warn(clippy::all, rust_2018_idioms)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+use std::sync::{atomic::Ordering, Arc};
+
 fn main() -> eframe::Result<()> {
     // setup logger to log automate events
     env_logger::builder()
         .filter_module("automate", log::LevelFilter::Info)
         .init();
 
+    // Runnables exported from the editor play their sequence and exit
+    if automate::run_embedded() {
+        return Ok(());
+    }
+
+    // e.g. "automate path/to/file.auto --play"
+    let launch = automate::LaunchOptions::from_args(std::env::args().skip(1));
+    let exit_code = Arc::clone(&launch.exit_code);
+    if launch.serve_stdio {
+        automate::serve_stdio();
+        return Ok(());
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_min_inner_size([740.0, 407.0])
@@ -22,6 +37,13 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "Automate",
         native_options,
-        Box::new(|cc| Ok(Box::new(automate::App::new(cc)))),
-    )
+        Box::new(|cc| Ok(Box::new(automate::App::new(cc, launch)))),
+    )?;
+
+    // Only set when playing on launch, so scripts can tell how playback ended
+    let exit_code = exit_code.load(Ordering::Relaxed);
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(())
 }
@@ -0,0 +1,527 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Once,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{Pos2, Rect, Vec2};
+use uuid::Bytes;
+
+use crate::{
+    engine::{self, Engine, Host, Progress, Stop},
+    expression, file, input,
+    keyframe::{Keyframe, KeyframeType},
+    plugin,
+    sequencer::{PlaybackEvent, SequencerState},
+    settings::{MonitorEdge, PlaybackSettings},
+    util::{
+        image_dif_opencv, is_modifier, monitor_bounds, play_sound, refresh_monitors, screenshot,
+        simulate_move, switch_desktop, template_match_opencv, text_to_keys,
+    },
+};
+
+/// How long a held key waits before repeating, like the OS auto-repeat
+const HOLD_REPEAT_DELAY: Duration = Duration::from_millis(500);
+/// Time between the repeats of a held key
+const HOLD_REPEAT_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Key that stops playback without the editor, the editor's default panic key since there are no settings to change it
+const PANIC_KEY: rdev::Key = rdev::Key::Pause;
+
+/// Set by the input listener when the panic key is pressed or the fail safe is hit, stopping playback like `stop`
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Fail safe edge of the sequence being played and the bounds of the primary monitor it is on
+static FAILSAFE: Mutex<Option<(MonitorEdge, Rect)>> = Mutex::new(None);
+
+/// Keys held down by hold keyframes, with the flag that keeps their repeat thread going
+static HELD_KEYS: Mutex<Vec<(rdev::Key, Arc<AtomicBool>, JoinHandle<()>)>> = Mutex::new(Vec::new());
+/// Releases owed for keys and buttons pressed by key and mouse button keyframes that haven't ended yet
+static PENDING_RELEASES: Mutex<Vec<rdev::EventType>> = Mutex::new(Vec::new());
+
+/// Simulates a key or button press and remembers to release it if playback stops before `release` is pressed
+fn press(event: rdev::EventType, release: rdev::EventType) -> Result<(), String> {
+    input::simulate(&event)?;
+    PENDING_RELEASES.lock().unwrap().push(release);
+    Ok(())
+}
+
+/// Simulates a key or button release pressed by [`press`]
+fn release(event: rdev::EventType) -> Result<(), String> {
+    PENDING_RELEASES
+        .lock()
+        .unwrap()
+        .retain(|pending| *pending != event);
+    input::simulate(&event)
+}
+
+/// Presses the key and keeps pressing it again on a thread until [`release_key`] is called
+fn hold_key(key: rdev::Key) -> Result<(), String> {
+    input::simulate(&rdev::EventType::KeyPress(key))?;
+    let holding = Arc::new(AtomicBool::new(true));
+    let shared_holding = Arc::clone(&holding);
+    let handle = thread::Builder::new()
+        .name("Hold Thread".to_owned())
+        .spawn(move || {
+            let mut next_repeat = Instant::now() + HOLD_REPEAT_DELAY;
+            while shared_holding.load(Ordering::Relaxed) {
+                if Instant::now() >= next_repeat {
+                    let _ = input::simulate(&rdev::EventType::KeyPress(key));
+                    next_repeat += HOLD_REPEAT_INTERVAL;
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+        })
+        .map_err(|err| format!("Failed to spawn hold thread - {}", err))?;
+    HELD_KEYS.lock().unwrap().push((key, holding, handle));
+    Ok(())
+}
+
+/// Stops repeating the key held by [`hold_key`] and releases it
+fn release_key(key: rdev::Key) -> Result<(), String> {
+    let released: Vec<_> = {
+        let mut held_keys = HELD_KEYS.lock().unwrap();
+        let (released, kept) = held_keys.drain(..).partition(|(held, _, _)| *held == key);
+        *held_keys = kept;
+        released
+    };
+    // Waits for the repeats to stop so none arrive after the release
+    for (_, holding, handle) in released {
+        holding.store(false, Ordering::Relaxed);
+        let _ = handle.join();
+    }
+    input::simulate(&rdev::EventType::KeyRelease(key))
+}
+
+/// Releases every key and button still pressed by playback, for when it stops part way through a keyframe
+pub fn release_held_inputs() {
+    let mut releases: Vec<_> = PENDING_RELEASES.lock().unwrap().drain(..).collect();
+    let held: Vec<_> = HELD_KEYS.lock().unwrap().drain(..).collect();
+    for (key, holding, handle) in held {
+        holding.store(false, Ordering::Relaxed);
+        let _ = handle.join();
+        releases.push(rdev::EventType::KeyRelease(key));
+    }
+    for event in releases {
+        if let Err(err) = input::simulate(&event) {
+            log::error!("Failed to release held input - {}", err);
+        }
+    }
+}
+
+/// Opens the target image of a magic move or while image loop
+fn open_target(path: &str) -> Result<image::DynamicImage, String> {
+    image::ImageReader::open(path)
+        .map_err(|err| err.to_string())
+        .and_then(|reader| reader.decode().map_err(|err| err.to_string()))
+        .map_err(|err| format!("Failed to open target image {:?} - {}", path, err))
+}
+
+/// Whether the target image at `path` is on screen right now, for while image loops
+pub fn target_found(path: &str) -> Result<bool, String> {
+    Ok(template_match_opencv(open_target(path)?).is_some())
+}
+
+/// Simulates the given keyframe
+///
+/// `start` decides whether to treat this as the start or end of a keyframe.
+/// Fails if the system refuses the simulated input even after retrying, which playback should stop for
+pub fn play_keyframe(keyframe: &Keyframe, start: bool, offset: &Vec2) -> Result<(), String> {
+    match &keyframe.keyframe_type {
+        KeyframeType::KeyBtn(key) => {
+            if start {
+                press(
+                    rdev::EventType::KeyPress(*key),
+                    rdev::EventType::KeyRelease(*key),
+                )?;
+            } else {
+                release(rdev::EventType::KeyRelease(*key))?;
+            }
+        }
+        KeyframeType::MouseBtn(btn) => {
+            if start {
+                press(
+                    rdev::EventType::ButtonPress(*btn),
+                    rdev::EventType::ButtonRelease(*btn),
+                )?;
+            } else {
+                release(rdev::EventType::ButtonRelease(*btn))?;
+            }
+        }
+        KeyframeType::MouseMove(pos) => {
+            if start {
+                let pos = match &keyframe.monitor {
+                    Some(monitor) => monitor.follow(*pos),
+                    None => *pos,
+                };
+                simulate_move(&pos, &offset)?;
+            }
+        }
+        KeyframeType::Scroll(delta) => {
+            if start {
+                input::simulate(&rdev::EventType::Wheel {
+                    delta_x: delta.x as i64,
+                    delta_y: delta.y as i64,
+                })?;
+            }
+        }
+        KeyframeType::Wait(secs) => {
+            if start {
+                // Todo(addis): multiply dt so that it takes *secs* seconds to traverse 1 second of sequencer time
+                // This will remove the need to block the thread and freeze the application, and keep the playhead moving in a slow but satisfying way
+                let duration = Duration::try_from_secs_f32(*secs)
+                    .map_err(|_| format!("Can't wait for {} seconds", secs))?;
+                thread::sleep(duration);
+            }
+        }
+        KeyframeType::KeyStrokes(keys) => {
+            if start {
+                type_keys(keys, keyframe.key_delay())?;
+            }
+        }
+        KeyframeType::MagicMove(path) => {
+            if start {
+                let target = open_target(path)?;
+                let now = Instant::now();
+                let target_center = template_match_opencv(target)
+                    .ok_or_else(|| format!("Target image {:?} isn't on screen", path))?;
+                log::info!("Magic found target in {:?}", now.elapsed());
+                simulate_move(&target_center, offset)?;
+            }
+        }
+        KeyframeType::Notify(text) => {
+            if start {
+                if let Err(err) = notify_rust::Notification::new()
+                    .summary("Automate")
+                    .body(text)
+                    .show()
+                {
+                    log::error!("Failed to show notification - {}", err);
+                }
+            }
+        }
+        KeyframeType::Beep(path) => {
+            if start {
+                play_sound(path);
+            }
+        }
+        KeyframeType::Hold(key) => {
+            if start {
+                hold_key(*key)?;
+            } else {
+                release_key(*key)?;
+            }
+        }
+        KeyframeType::Desktop(desktop) => {
+            if start {
+                switch_desktop(*desktop)
+                    .map_err(|err| format!("Failed to switch to desktop {} - {}", desktop, err))?;
+            }
+        }
+        // Control flow is handled outside of this function
+        KeyframeType::Loop(_, _)
+        | KeyframeType::Random(_, _)
+        | KeyframeType::Label(_)
+        | KeyframeType::Jump(_, _, _)
+        | KeyframeType::Abort(_)
+        | KeyframeType::Counter(_, _)
+        | KeyframeType::WhileImage(_, _, _)
+        | KeyframeType::If(_, _, _)
+        | KeyframeType::Subroutine(_)
+        | KeyframeType::Call(_) => {}
+        // Plugins run on their own thread, started by the engine
+        KeyframeType::Plugin(_, _) => {}
+        // Logged by whoever is playing, as it is part of the run's log
+        KeyframeType::Log(_) => {}
+        KeyframeType::Text(text) => {
+            if start {
+                type_keys(&text_to_keys(text), keyframe.key_delay())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Presses and releases each key in turn, holding modifiers down until the key after them has been released
+///
+/// So shift followed by a is typed as A. Waits `delay` before each key after the first
+fn type_keys(keys: &[rdev::Key], delay: Duration) -> Result<(), String> {
+    let mut held = vec![];
+    for (i, key) in keys.iter().enumerate() {
+        if i > 0 && !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        input::simulate(&rdev::EventType::KeyPress(*key))?;
+        if is_modifier(key) {
+            held.push(*key);
+            continue;
+        }
+        input::simulate(&rdev::EventType::KeyRelease(*key))?;
+        for modifier in held.drain(..).rev() {
+            input::simulate(&rdev::EventType::KeyRelease(modifier))?;
+        }
+    }
+    for modifier in held.into_iter().rev() {
+        input::simulate(&rdev::EventType::KeyRelease(modifier))?;
+    }
+    Ok(())
+}
+
+/// Plays a sequence from start to finish without the editor, blocking until it is done or `stop` is set
+///
+/// Follows the same rules as playback in the sequencer: keyframes start when the playhead enters them,
+/// end when it leaves them and loop keyframes send the playhead back to their start.
+/// Sequential sequences step the playhead from one keyframe to the next instead of following the clock.
+/// With a data source the sequence is played once per row on each repeat.
+/// Nobody is there to ask for the values of inputs, so they keep the values they were saved with.
+/// The playback settings saved with the file apply like in the editor, otherwise `offset` is used
+/// and fail detection is off. Fail detection compares the screen with the keyframe's screenshot in `images`.
+/// Returns whether playback reached the end.
+pub fn play(
+    state: SequencerState,
+    images: &HashMap<Bytes, Vec<u8>>,
+    offset: Vec2,
+    stop: &AtomicBool,
+    on_event: &mut dyn FnMut(PlaybackEvent),
+) -> bool {
+    refresh_monitors();
+    // Like the editor, the fail safe defaults to the right edge of the primary monitor
+    let edge = state
+        .playback
+        .map_or(MonitorEdge::Right, |playback| playback.failsafe_edge);
+    *FAILSAFE.lock().unwrap() = monitor_bounds(None).map(|bounds| (edge, bounds));
+    INTERRUPTED.store(false, Ordering::Relaxed);
+    listen_for_interrupts();
+    let finished = play_sequence(state, images, offset, stop, on_event);
+    *FAILSAFE.lock().unwrap() = None;
+    // Playback can stop part way through a key or button keyframe
+    release_held_inputs();
+    finished
+}
+
+/// Starts listening for the panic key and the fail safe on its own thread, the first time it is called
+fn listen_for_interrupts() {
+    static STARTED: Once = Once::new();
+    STARTED.call_once(|| {
+        let spawned = thread::Builder::new()
+            .name("Interrupt Listener Thread".to_owned())
+            .spawn(|| {
+                let listened = rdev::listen(|event| {
+                    let interrupted = match event.event_type {
+                        rdev::EventType::KeyPress(key) => key == PANIC_KEY,
+                        rdev::EventType::MouseMove { x, y } => {
+                            FAILSAFE.lock().unwrap().is_some_and(|(edge, bounds)| {
+                                edge.hit(bounds, Pos2::new(x as f32, y as f32))
+                            })
+                        }
+                        _ => false,
+                    };
+                    if interrupted {
+                        INTERRUPTED.store(true, Ordering::Relaxed);
+                    }
+                });
+                if let Err(err) = listened {
+                    log::error!(
+                        "Failed to listen for the panic key and fail safe - {:?}",
+                        err
+                    );
+                }
+            });
+        if let Err(err) = spawned {
+            log::error!("Failed to start the interrupt listener - {}", err);
+        }
+    });
+}
+
+/// The headless player, playing a sequence on the thread that called [`play`]
+struct Player<'a> {
+    /// Variables for each row of the data source, or a single run without one
+    runs: Vec<expression::Variables>,
+    sequential: bool,
+    /// Playback settings saved with the file, if it overrides the global ones
+    playback: Option<PlaybackSettings>,
+    offset: Vec2,
+    images: &'a HashMap<Bytes, Vec<u8>>,
+    stop: &'a AtomicBool,
+    on_event: &'a mut dyn FnMut(PlaybackEvent),
+    /// Time spent in random delays this step, which the playhead should not move for
+    delayed: Duration,
+}
+
+impl Player<'_> {
+    /// Whether playback was stopped by the caller, the panic key or the fail safe
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || INTERRUPTED.load(Ordering::Relaxed)
+    }
+}
+
+impl Host for Player<'_> {
+    fn variables(&self, row: usize) -> expression::Variables {
+        self.runs[row].clone()
+    }
+    fn sequential(&self) -> bool {
+        self.sequential
+    }
+    fn entering(&mut self, keyframes: &[Keyframe], index: usize, _time: f32) -> Result<bool, Stop> {
+        // Like the editor, check the screen still looks like the keyframe's screenshot
+        let Some(playback) = self.playback.filter(|playback| playback.fail_detection) else {
+            return Ok(true);
+        };
+        let Some(expected) = self.images.get(&keyframes[index].uid) else {
+            return Ok(true);
+        };
+        let Some(current) = screenshot() else {
+            return Ok(true);
+        };
+        let percentage_err = image_dif_opencv(&current, expected);
+        if percentage_err > playback.max_fail_error as f32 {
+            log::warn!("Fail Detected: {:?}% err", percentage_err);
+            (self.on_event)(PlaybackEvent::FailDetected { percentage_err });
+            return Err(Stop::Halted);
+        }
+        Ok(true)
+    }
+    fn delay(&mut self, delay: Duration) -> Result<(), Stop> {
+        let slept = engine::sleep_unless_stopped(delay, || self.stopped());
+        self.delayed += slept;
+        if self.stopped() {
+            (self.on_event)(PlaybackEvent::Stopped);
+            return Err(Stop::Halted);
+        }
+        Ok(())
+    }
+    fn target_found(&mut self, path: &str) -> Result<bool, String> {
+        target_found(path)
+    }
+    fn play(&mut self, keyframe: &Keyframe, start: bool) -> Result<(), String> {
+        play_keyframe(keyframe, start, &self.offset)
+    }
+    fn run_plugin(&mut self, path: &str, argument: &str) -> Result<plugin::Running, String> {
+        plugin::spawn(path, argument, &self.offset)
+    }
+    fn event(&mut self, event: PlaybackEvent) {
+        (self.on_event)(event);
+    }
+}
+
+fn play_sequence(
+    state: SequencerState,
+    images: &HashMap<Bytes, Vec<u8>>,
+    offset: Vec2,
+    stop: &AtomicBool,
+    on_event: &mut dyn FnMut(PlaybackEvent),
+) -> bool {
+    let mut keyframes = state.keyframes;
+    let runs = match &state.data_source {
+        Some(path) => match file::read_table(path) {
+            Ok(rows) => rows
+                .iter()
+                .enumerate()
+                .map(|row| expression::base_variables(&state.variables, &state.inputs, Some(row)))
+                .collect(),
+            Err(err) => {
+                log::error!("Failed to read data source {:?} - {}", path, err);
+                return false;
+            }
+        },
+        None => vec![expression::base_variables(
+            &state.variables,
+            &state.inputs,
+            None,
+        )],
+    };
+    let rows = runs.len();
+    let mut player = Player {
+        runs,
+        sequential: state.sequential,
+        playback: state.playback,
+        offset: state.playback.map_or(offset, |playback| playback.offset),
+        images,
+        stop,
+        on_event,
+        delayed: Duration::ZERO,
+    };
+    let end = engine::end(&keyframes);
+    let mut engine = Engine::default();
+    engine.start(&mut keyframes);
+    (player.on_event)(PlaybackEvent::Started);
+
+    let mut time = 0.;
+    let mut last_instant = Instant::now();
+    loop {
+        if player.stopped() {
+            (player.on_event)(PlaybackEvent::Stopped);
+            return false;
+        }
+        // Looping forever repeats until `stop` is set
+        if time >= end
+            && !engine.next_pass(
+                &mut keyframes,
+                &mut time,
+                rows,
+                state.repeats.max(1) as u32,
+                state.loop_forever,
+            )
+        {
+            break;
+        }
+        if !state.sequential {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let now = Instant::now();
+        let elapsed = (now - last_instant).as_secs_f32() * state.speed;
+        engine.advance(&keyframes, &mut time, elapsed, state.sequential);
+        match engine.step(&mut keyframes, &mut time, &mut player) {
+            Ok(Progress::Played) => {}
+            // Waiting for the target of a magic move, which the engine checks for every so often
+            Ok(Progress::Held) => thread::sleep(Duration::from_millis(10)),
+            Err(Stop::Aborted(message)) => {
+                log::warn!("Aborted playback - {}", message);
+                (player.on_event)(PlaybackEvent::Aborted { message });
+                return false;
+            }
+            Err(Stop::SimulationFailed(i, message)) => {
+                log::error!("Stopped playback, keyframe {} - {}", i, message);
+                (player.on_event)(PlaybackEvent::SimulationFailed { message });
+                return false;
+            }
+            Err(Stop::Failed(_, message)) => {
+                log::error!("{}", message);
+                (player.on_event)(PlaybackEvent::Failed { message });
+                return false;
+            }
+            // The player has already said why, e.g. when a fail was detected
+            Err(Stop::Halted) => return false,
+        }
+        last_instant = now + std::mem::take(&mut player.delayed);
+    }
+    (player.on_event)(PlaybackEvent::Finished);
+    true
+}
+
+/// Plays the sequence embedded in this executable by "Export Runnable", if there is one
+///
+/// Returns `false` if there is no embedded sequence, i.e. this is the editor
+pub fn run_embedded() -> bool {
+    let Some(loaded) = file::embedded_sequence() else {
+        return false;
+    };
+    log::info!(
+        "Playing embedded sequence of {} keyframes",
+        loaded.state.keyframes.len()
+    );
+    // Calibration is specific to the machine it was made on, so runnables play uncalibrated,
+    // and they are stopped by the panic key or fail safe since there is nothing else to stop them
+    play(
+        loaded.state,
+        &loaded.images,
+        Vec2::ZERO,
+        &AtomicBool::new(false),
+        &mut |_| {},
+    );
+    let _ = std::fs::remove_dir_all(file::runnable_assets_directory());
+    true
+}
@@ -0,0 +1,343 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::Vec2;
+use wasmi::{core::Trap, Caller, Config, Engine, Extern, Linker, Module, Store};
+
+use crate::{
+    keyframe::{Keyframe, KeyframeType},
+    player::play_keyframe,
+    util::string_to_keys,
+};
+
+/// Module plugins import the simulation API from
+const API: &str = "automate";
+/// Instructions a plugin may run each time it is played, a few seconds at most,
+/// so a plugin stuck in a loop without calling the API still comes to an end
+const FUEL: u64 = 300_000_000;
+/// Longest a plugin may sleep for in total each time it is played
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// Time between checks for playback being stopped while a plugin sleeps
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+/// Longest string a plugin may pass to the API, in bytes
+const MAX_STRING: usize = 64 * 1024;
+
+/// What a plugin can see while it runs, nothing but its argument and where playback is calibrated to
+struct Sandbox {
+    name: String,
+    argument: String,
+    offset: Vec2,
+    /// The first input the system refused to simulate, which stops playback once the plugin returns
+    error: Option<String>,
+    /// Set once playback stops, which stops the plugin at its next call to the API
+    cancelled: Arc<AtomicBool>,
+    /// Time spent sleeping so far, see [`MAX_SLEEP`]
+    slept: Duration,
+}
+
+/// A plugin installed in the plugin directory
+pub struct Installed {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The plugin registry, every WASM module in `directory` sorted by name
+pub fn registry(directory: &Path) -> Vec<Installed> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return vec![];
+    };
+    let mut installed: Vec<Installed> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "wasm")
+        })
+        .map(|path| Installed {
+            name: path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            path,
+        })
+        .collect();
+    installed.sort_by(|a, b| a.name.cmp(&b.name));
+    installed
+}
+
+/// Where plugins are installed unless the settings say otherwise
+pub fn default_directory() -> PathBuf {
+    eframe::storage_dir("Automate")
+        .unwrap_or_else(std::env::temp_dir)
+        .join("plugins")
+}
+
+/// A plugin running on its own thread, which is stopped once this is dropped
+pub struct Running {
+    result: Receiver<Result<(), String>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Running {
+    /// How the plugin ended, or `None` while it is still running
+    pub fn finished(&self) -> Option<Result<(), String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err("The plugin thread stopped unexpectedly".to_string()))
+            }
+        }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        Engine::new(&config)
+    })
+}
+
+/// Starts the WASM plugin at `path` on its own thread as the action of a plugin keyframe, passing it `argument`
+///
+/// Playback holds the playhead until [`Running::finished`] returns the outcome, and stops the plugin by dropping it.
+/// Plugins run in a sandbox, they can't reach the file system, the network or raw input,
+/// only the functions of the simulation API below, imported from the "automate" module:
+/// * `argument(ptr, len) -> i32` copies up to `len` bytes of the argument to `ptr`, returning its full length
+/// * `mouse_move(x: f32, y: f32) -> i32`
+/// * `mouse_click(button: i32) -> i32`, with 0 for left, 1 for right and 2 for middle
+/// * `scroll(dx: f32, dy: f32) -> i32`
+/// * `key_press(ptr, len) -> i32` presses and releases the key named by the UTF-8 string at `ptr`
+/// * `type_text(ptr, len) -> i32` types out the UTF-8 string at `ptr`
+/// * `sleep(ms: i32)`, for up to [`MAX_SLEEP`] in total
+/// * `log(ptr, len)` logs the UTF-8 string at `ptr`
+///
+/// Functions returning `i32` return 0 on success and -1 on failure. The plugin exports its `memory`
+/// and a `run() -> i32` function, which returns 0 once the action is done.
+pub fn spawn(path: &str, argument: &str, offset: &Vec2) -> Result<Running, String> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, result) = mpsc::channel();
+    let (path, argument, offset) = (path.to_string(), argument.to_string(), *offset);
+    let shared_cancelled = Arc::clone(&cancelled);
+    thread::Builder::new()
+        .name("Plugin Thread".to_owned())
+        .spawn(move || {
+            let _ = sender.send(run(&path, &argument, offset, shared_cancelled));
+        })
+        .map_err(|err| format!("Failed to spawn plugin thread - {}", err))?;
+    Ok(Running { result, cancelled })
+}
+
+/// Runs the plugin at `path` on the calling thread until it returns or is `cancelled`
+fn run(path: &str, argument: &str, offset: Vec2, cancelled: Arc<AtomicBool>) -> Result<(), String> {
+    let name = Path::new(path)
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let wasm = fs::read(path).map_err(|e| format!("Failed to read plugin {:?}: {}", path, e))?;
+    let module = Module::new(engine(), &wasm[..])
+        .map_err(|e| format!("{:?} isn't a valid plugin: {}", path, e))?;
+    let mut store = Store::new(
+        engine(),
+        Sandbox {
+            name: name.clone(),
+            argument: argument.to_string(),
+            offset,
+            error: None,
+            cancelled,
+            slept: Duration::ZERO,
+        },
+    );
+    store.add_fuel(FUEL).map_err(|e| e.to_string())?;
+    let instance = linker()
+        .map_err(|e| e.to_string())?
+        .instantiate(&mut store, &module)
+        .map_err(|e| format!("Failed to load plugin {}: {}", name, e))?
+        .start(&mut store)
+        .map_err(|e| format!("Failed to start plugin {}: {}", name, e))?;
+    let run = instance
+        .get_typed_func::<(), i32>(&store, "run")
+        .map_err(|_| format!("Plugin {} doesn't export a run function", name))?;
+    let result = run.call(&mut store, ());
+    if let Some(err) = store.data_mut().error.take() {
+        return Err(format!("Plugin {} - {}", name, err));
+    }
+    match result {
+        Ok(0) => Ok(()),
+        Ok(code) => Err(format!("Plugin {} failed with code {}", name, code)),
+        // Traps, including running out of fuel
+        Err(err) => Err(format!("Plugin {} stopped - {}", name, err)),
+    }
+}
+
+/// The simulation API, the only functions a plugin can import
+fn linker() -> Result<Linker<Sandbox>, wasmi::Error> {
+    let mut linker = Linker::new(engine());
+    linker.func_wrap(
+        API,
+        "argument",
+        |mut caller: Caller<'_, Sandbox>, ptr: i32, len: i32| -> i32 {
+            let argument = caller.data().argument.clone();
+            let (Ok(ptr), Ok(len)) = (usize::try_from(ptr), usize::try_from(len)) else {
+                return -1;
+            };
+            let Some(memory) = caller.get_export("memory").and_then(Extern::into_memory) else {
+                return -1;
+            };
+            let bytes = &argument.as_bytes()[..argument.len().min(len)];
+            match memory.write(&mut caller, ptr, bytes) {
+                Ok(()) => argument.len() as i32,
+                Err(_) => -1,
+            }
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "mouse_move",
+        |mut caller: Caller<'_, Sandbox>, x: f32, y: f32| -> Result<i32, Trap> {
+            simulate(&mut caller, KeyframeType::MouseMove(Vec2::new(x, y)), false)
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "mouse_click",
+        |mut caller: Caller<'_, Sandbox>, button: i32| -> Result<i32, Trap> {
+            let button = match button {
+                0 => rdev::Button::Left,
+                1 => rdev::Button::Right,
+                2 => rdev::Button::Middle,
+                _ => return Ok(-1),
+            };
+            simulate(&mut caller, KeyframeType::MouseBtn(button), true)
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "scroll",
+        |mut caller: Caller<'_, Sandbox>, dx: f32, dy: f32| -> Result<i32, Trap> {
+            simulate(&mut caller, KeyframeType::Scroll(Vec2::new(dx, dy)), false)
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "key_press",
+        |mut caller: Caller<'_, Sandbox>, ptr: i32, len: i32| -> Result<i32, Trap> {
+            let key = read_string(&caller, ptr, len)
+                .and_then(|name| string_to_keys(&name.to_lowercase()));
+            match key {
+                Some(key) => simulate(&mut caller, KeyframeType::KeyBtn(key), true),
+                None => Ok(-1),
+            }
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "type_text",
+        |mut caller: Caller<'_, Sandbox>, ptr: i32, len: i32| -> Result<i32, Trap> {
+            match read_string(&caller, ptr, len) {
+                Some(text) => simulate(&mut caller, KeyframeType::Text(text), false),
+                None => Ok(-1),
+            }
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "sleep",
+        |mut caller: Caller<'_, Sandbox>, ms: i32| -> Result<(), Trap> {
+            let sleep = Duration::from_millis(ms.max(0) as u64);
+            let sandbox = caller.data_mut();
+            sandbox.slept += sleep;
+            if sandbox.slept > MAX_SLEEP {
+                return Err(Trap::new(format!(
+                    "slept for longer than {} seconds",
+                    MAX_SLEEP.as_secs()
+                )));
+            }
+            // Sleeps in slices so stopping playback doesn't wait for the plugin to wake up
+            let end = Instant::now() + sleep;
+            loop {
+                check_cancelled(&caller)?;
+                let now = Instant::now();
+                if now >= end {
+                    return Ok(());
+                }
+                thread::sleep((end - now).min(SLEEP_SLICE));
+            }
+        },
+    )?;
+    linker.func_wrap(
+        API,
+        "log",
+        |caller: Caller<'_, Sandbox>, ptr: i32, len: i32| {
+            if let Some(message) = read_string(&caller, ptr, len) {
+                log::info!("Plugin {}: {}", caller.data().name, message);
+            }
+        },
+    )?;
+    Ok(linker)
+}
+
+/// Stops the plugin with a trap once playback has stopped
+fn check_cancelled(caller: &Caller<'_, Sandbox>) -> Result<(), Trap> {
+    match caller.data().cancelled.load(Ordering::Relaxed) {
+        true => Err(Trap::new("playback stopped")),
+        false => Ok(()),
+    }
+}
+
+/// Simulates a keyframe of the given type for a plugin, releasing it straight away when `release`
+///
+/// Nothing is simulated once playback has stopped, the plugin is stopped instead
+fn simulate(
+    caller: &mut Caller<'_, Sandbox>,
+    keyframe_type: KeyframeType,
+    release: bool,
+) -> Result<i32, Trap> {
+    check_cancelled(caller)?;
+    let keyframe = Keyframe {
+        keyframe_type,
+        ..Default::default()
+    };
+    let offset = caller.data().offset;
+    let result = play_keyframe(&keyframe, true, &offset).and_then(|()| match release {
+        true => play_keyframe(&keyframe, false, &offset),
+        false => Ok(()),
+    });
+    match result {
+        Ok(()) => Ok(0),
+        Err(err) => {
+            caller.data_mut().error.get_or_insert(err);
+            Ok(-1)
+        }
+    }
+}
+
+/// The UTF-8 string a plugin passed at `ptr` in its memory
+fn read_string(caller: &Caller<'_, Sandbox>, ptr: i32, len: i32) -> Option<String> {
+    let (ptr, len) = (usize::try_from(ptr).ok()?, usize::try_from(len).ok()?);
+    if len > MAX_STRING {
+        return None;
+    }
+    let memory = caller.get_export("memory").and_then(Extern::into_memory)?;
+    let mut bytes = vec![0; len];
+    memory.read(caller, ptr, &mut bytes).ok()?;
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,512 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Cursor, Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use eframe::egui::Vec2;
+
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Response};
+use tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response as HandshakeResponse},
+    http::StatusCode,
+};
+use uuid::Bytes;
+
+use crate::{
+    file, player,
+    sequencer::{PlaybackEvent, SequencerState},
+};
+
+/// How long to wait for the app to handle a command before giving up
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Header that remote control requests carry the session token in
+pub const TOKEN_HEADER: &str = "X-Automate-Token";
+
+/// Commands other programs can send to a running Automate
+pub enum RemoteCommand {
+    Load(PathBuf),
+    Play,
+    Pause,
+    /// Pauses and resets the playhead
+    Stop,
+    Status,
+    /// The screen as it was when fail detection last stopped playback
+    FailureScreenshot,
+}
+
+/// The app's answer to a [`RemoteCommand`]
+pub enum RemoteReply {
+    Done,
+    Status(RemoteStatus),
+    /// Raw RGBA screenshot
+    Screenshot(Vec<u8>),
+    Error(String),
+}
+
+#[derive(Serialize)]
+pub struct RemoteStatus {
+    pub file: String,
+    pub loading: bool,
+    pub playing: bool,
+    pub recording: bool,
+    pub time: f32,
+    /// How the last playback ended, "finished", "fail_detected", "abort_keyframe", "simulation_failed", "failed" or "invalid"
+    pub outcome: Option<&'static str>,
+    /// Percentage error that stopped the last playback
+    pub fail_error: Option<f32>,
+    /// Message of the abort keyframe that stopped the last playback
+    pub abort_message: Option<String>,
+    /// Why the input of the last playback could not be simulated
+    pub simulation_error: Option<String>,
+    /// Why the last playback couldn't go on, e.g. a jump to a missing label
+    pub failure: Option<String>,
+}
+
+/// A command waiting to be handled by the app on its next frame
+pub struct RemoteRequest {
+    pub command: RemoteCommand,
+    pub reply: Sender<RemoteReply>,
+}
+
+/// Sends commands to the app from other threads
+#[derive(Clone)]
+pub struct RemoteHandle {
+    sender: Sender<RemoteRequest>,
+    ctx: egui::Context,
+}
+
+impl RemoteHandle {
+    pub fn new(sender: Sender<RemoteRequest>, ctx: egui::Context) -> Self {
+        Self { sender, ctx }
+    }
+    /// Sends a command to the app and waits for it to be handled
+    pub fn send(&self, command: RemoteCommand) -> RemoteReply {
+        let (reply, receiver) = mpsc::channel();
+        if self.sender.send(RemoteRequest { command, reply }).is_err() {
+            return RemoteReply::Error("Automate is closing".to_string());
+        }
+        // Commands are handled in update, so make sure there is a frame to handle it in
+        self.ctx.request_repaint();
+        receiver
+            .recv_timeout(REPLY_TIMEOUT)
+            .unwrap_or_else(|_| RemoteReply::Error("Automate did not respond".to_string()))
+    }
+}
+
+/// Passes playback events on to everyone listening for them, as JSON
+#[derive(Clone, Default)]
+pub struct EventHub {
+    listeners: Arc<Mutex<Vec<Sender<String>>>>,
+}
+
+impl EventHub {
+    /// Returns a receiver for all events broadcast from now on
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.listeners.lock().unwrap().push(sender);
+        receiver
+    }
+    /// Sends an event to every listener, forgetting those that have gone away
+    pub fn broadcast(&self, event: &PlaybackEvent) {
+        let mut listeners = self.listeners.lock().unwrap();
+        if listeners.is_empty() {
+            return;
+        }
+        let Ok(json) = serde_json::to_string(event) else {
+            return;
+        };
+        listeners.retain(|listener| listener.send(json.clone()).is_ok());
+    }
+}
+
+/// Starts a WebSocket server on localhost that streams every playback event to its clients
+///
+/// Like [`start_http_server`], the handshake has to carry `token` in [`TOKEN_HEADER`] and web pages are refused.
+pub fn start_event_server(port: u16, token: String, hub: EventHub) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+    thread::Builder::new()
+        .name("Event Thread".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let token = token.clone();
+                let hub = hub.clone();
+                // Each client waits on its own events, so a slow client can't hold up the others
+                let _ = thread::Builder::new()
+                    .name("Event Client Thread".to_owned())
+                    .spawn(move || {
+                        let check = |request: &Request, response: HandshakeResponse| {
+                            let headers = request.headers();
+                            let refuse = |status: StatusCode, reason: String| {
+                                let mut response = ErrorResponse::new(Some(reason));
+                                *response.status_mut() = status;
+                                response
+                            };
+                            // Browsers always say which page opened the WebSocket, other programs don't
+                            if headers.contains_key("Origin") {
+                                return Err(refuse(
+                                    StatusCode::FORBIDDEN,
+                                    "WebSockets from web pages aren't allowed".to_string(),
+                                ));
+                            }
+                            match headers.get(TOKEN_HEADER).map(|value| value.as_bytes()) {
+                                Some(sent) if sent == token.as_bytes() => Ok(response),
+                                _ => Err(refuse(
+                                    StatusCode::UNAUTHORIZED,
+                                    format!("Missing or wrong {} header", TOKEN_HEADER),
+                                )),
+                            }
+                        };
+                        let Ok(mut websocket) = tungstenite::accept_hdr(stream, check) else {
+                            return;
+                        };
+                        // Only clients let in hear about events
+                        let events = hub.subscribe();
+                        for event in events {
+                            if websocket.send(tungstenite::Message::Text(event)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    log::info!("Streaming playback events on ws://127.0.0.1:{}", port);
+    Ok(())
+}
+
+/// Where the token of the running session is written for scripts to read
+///
+/// Always in a directory belonging to the current user, the runtime directory where there is one or the app's data
+/// directory otherwise, never a shared one like the temp directory where others could plant or read the file.
+pub fn token_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|directory| PathBuf::from(directory).join("automate"))
+        .or_else(|| eframe::storage_dir("Automate"))
+        .map(|directory| directory.join("automate.token"))
+}
+
+/// Makes a random token for this session, which remote control requests have to carry in [`TOKEN_HEADER`]
+///
+/// Being bound to localhost isn't enough, as any web page open in a browser can send requests there too.
+/// The token is written to [`token_path`], readable only by the current user where that's possible.
+pub fn session_token() -> String {
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    if let Some(path) = token_path() {
+        if let Err(err) = write_token(&path, &token) {
+            log::error!(
+                "Failed to write the remote control token to {:?} - {}",
+                path,
+                err
+            );
+        }
+    }
+    token
+}
+
+/// Writes the token to a new file at `path`, so its permissions are always the ones set here
+fn write_token(path: &Path, token: &str) -> std::io::Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    // The token of the last session is replaced rather than written over
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())
+}
+
+/// Starts an HTTP server on localhost for controlling Automate
+///
+/// `POST /load` (with the path as the body), `POST /play`, `POST /pause`, `POST /stop`,
+/// `GET /status` and `GET /failure-screenshot`.
+/// Requests have to carry `token` in [`TOKEN_HEADER`], and requests made by web pages are refused.
+pub fn start_http_server(port: u16, token: String, handle: RemoteHandle) -> Result<(), String> {
+    // Only local programs may control the app
+    let server = tiny_http::Server::http(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+    thread::Builder::new()
+        .name("HTTP Thread".to_owned())
+        .spawn(move || {
+            for mut request in server.incoming_requests() {
+                let header = |name: &'static str| {
+                    request
+                        .headers()
+                        .iter()
+                        .find(|header| header.field.equiv(name))
+                        .map(|header| header.value.as_str().to_string())
+                };
+                let (origin, sent_token) = (header("Origin"), header(TOKEN_HEADER));
+                // Browsers always say which page a cross-origin request came from, other programs don't
+                if origin.is_some() {
+                    let response = json_response(
+                        403,
+                        &json!({ "error": "Requests from web pages aren't allowed" }),
+                    );
+                    let _ = request.respond(response);
+                    continue;
+                }
+                if sent_token.as_deref() != Some(token.as_str()) {
+                    let response = json_response(
+                        401,
+                        &json!({ "error": format!("Missing or wrong {} header", TOKEN_HEADER) }),
+                    );
+                    let _ = request.respond(response);
+                    continue;
+                }
+                let command = match (request.method(), request.url()) {
+                    (Method::Post, "/load") => {
+                        let mut path = String::new();
+                        match request.as_reader().read_to_string(&mut path) {
+                            Ok(_) => Some(RemoteCommand::Load(PathBuf::from(path.trim()))),
+                            Err(_) => None,
+                        }
+                    }
+                    (Method::Post, "/play") => Some(RemoteCommand::Play),
+                    (Method::Post, "/pause") => Some(RemoteCommand::Pause),
+                    (Method::Post, "/stop") => Some(RemoteCommand::Stop),
+                    (Method::Get, "/status") => Some(RemoteCommand::Status),
+                    (Method::Get, "/failure-screenshot") => Some(RemoteCommand::FailureScreenshot),
+                    _ => None,
+                };
+                let response = match command {
+                    Some(command) => http_response(handle.send(command)),
+                    None => json_response(404, &serde_json::json!({ "error": "Not found" })),
+                };
+                let _ = request.respond(response);
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    log::info!("Remote control listening on http://127.0.0.1:{}", port);
+    Ok(())
+}
+
+/// Name of the local control socket, a named pipe on Windows and a Unix socket elsewhere
+fn local_socket_name() -> String {
+    if cfg!(windows) {
+        "@automate".to_string()
+    } else {
+        std::env::temp_dir()
+            .join("automate.sock")
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// Starts listening for commands from other processes on the same machine over a local socket
+///
+/// Commands are single lines: `play`, `pause`, `stop`, `status` or `load <path>`,
+/// each answered with a single line of `ok`, `error: <reason>` or the status as JSON
+pub fn start_local_server(handle: RemoteHandle) -> Result<(), String> {
+    let name = local_socket_name();
+    if LocalSocketStream::connect(name.as_str()).is_ok() {
+        return Err("Another instance of Automate is already listening".to_string());
+    }
+    // Nothing is listening, so a socket file left here is from an instance that didn't shut down cleanly
+    if cfg!(unix) {
+        let _ = std::fs::remove_file(&name);
+    }
+    let listener = LocalSocketListener::bind(name.as_str()).map_err(|e| e.to_string())?;
+    thread::Builder::new()
+        .name("Local Control Thread".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let handle = handle.clone();
+                let _ = thread::Builder::new()
+                    .name("Local Client Thread".to_owned())
+                    .spawn(move || handle_local_client(stream, handle));
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    log::info!("Local control listening on {}", name);
+    Ok(())
+}
+
+/// Answers commands from a local client until it disconnects
+fn handle_local_client(stream: LocalSocketStream, handle: RemoteHandle) {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line).is_ok_and(|read| read > 0) {
+        let reply = match parse_local_command(line.trim()) {
+            Some(command) => match handle.send(command) {
+                RemoteReply::Done => "ok".to_string(),
+                RemoteReply::Status(status) => serde_json::to_string(&status).unwrap_or_default(),
+                RemoteReply::Screenshot(_) => {
+                    "error: screenshots are only available over HTTP".to_string()
+                }
+                RemoteReply::Error(err) => format!("error: {}", err),
+            },
+            None => format!("error: unknown command {:?}", line.trim()),
+        };
+        if writeln!(reader.get_mut(), "{}", reply).is_err() {
+            break;
+        }
+        line.clear();
+    }
+}
+
+fn parse_local_command(line: &str) -> Option<RemoteCommand> {
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    match command {
+        "play" => Some(RemoteCommand::Play),
+        "pause" => Some(RemoteCommand::Pause),
+        "stop" => Some(RemoteCommand::Stop),
+        "status" => Some(RemoteCommand::Status),
+        "load" if !argument.trim().is_empty() => {
+            Some(RemoteCommand::Load(PathBuf::from(argument.trim())))
+        }
+        _ => None,
+    }
+}
+
+/// Serves JSON-RPC 2.0 over stdin and stdout without opening the editor, until stdin is closed
+///
+/// Requests and responses are single lines. The methods are `load` (with a `path` param), `play`, `stop` and `status`,
+/// and playback events are sent as `event` notifications. Playback is uncalibrated.
+pub fn serve_stdio() {
+    let mut loaded: Option<(String, SequencerState, Arc<HashMap<Bytes, Vec<u8>>>)> = None;
+    let mut playback: Option<(JoinHandle<bool>, Arc<AtomicBool>)> = None;
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                send_rpc(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": -32700, "message": err.to_string() },
+                }));
+                continue;
+            }
+        };
+        // Playback that reached the end on its own doesn't need stopping
+        if playback
+            .as_ref()
+            .is_some_and(|(thread, _)| thread.is_finished())
+        {
+            playback = None;
+        }
+
+        let params = &request["params"];
+        let result = match request["method"].as_str() {
+            Some("load") => match params["path"].as_str() {
+                Some(_) if playback.is_some() => {
+                    Err((-32000, "Cannot load while playing".to_string()))
+                }
+                Some(path) => match file::load(Path::new(path), &file::Progress::default()) {
+                    Ok(file) => {
+                        let keyframes = file.state.keyframes.len();
+                        loaded = Some((path.to_string(), file.state, Arc::new(file.images)));
+                        Ok(json!({ "keyframes": keyframes }))
+                    }
+                    Err(err) => Err((-32000, err)),
+                },
+                None => Err((-32602, "Missing path".to_string())),
+            },
+            Some("play") => {
+                match &loaded {
+                    _ if playback.is_some() => Err((-32000, "Already playing".to_string())),
+                    None => Err((-32000, "No file is loaded".to_string())),
+                    Some((_, state, images)) => {
+                        let (state, images) = (state.clone(), Arc::clone(images));
+                        let stop = Arc::new(AtomicBool::new(false));
+                        let shared_stop = Arc::clone(&stop);
+                        let thread = thread::Builder::new()
+                        .name("Playback Thread".to_owned())
+                        .spawn(move || {
+                            player::play(state, &images, Vec2::ZERO, &shared_stop, &mut |event| {
+                                send_rpc(json!({ "jsonrpc": "2.0", "method": "event", "params": event }));
+                            })
+                        });
+                        match thread {
+                            Ok(thread) => {
+                                playback = Some((thread, stop));
+                                Ok(json!(true))
+                            }
+                            Err(err) => Err((-32000, err.to_string())),
+                        }
+                    }
+                }
+            }
+            Some("stop") => {
+                if let Some((thread, stop)) = playback.take() {
+                    stop.store(true, Ordering::Relaxed);
+                    let _ = thread.join();
+                }
+                Ok(json!(true))
+            }
+            Some("status") => Ok(json!({
+                "file": loaded.as_ref().map(|(path, _, _)| path),
+                "playing": playback.is_some(),
+            })),
+            _ => Err((-32601, "Method not found".to_string())),
+        };
+        // Notifications don't get a response
+        let id = &request["id"];
+        if id.is_null() {
+            continue;
+        }
+        send_rpc(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        });
+    }
+    if let Some((thread, stop)) = playback {
+        stop.store(true, Ordering::Relaxed);
+        let _ = thread.join();
+    }
+}
+
+/// Writes a JSON-RPC message to stdout as a single line
+fn send_rpc(message: Value) {
+    // println locks stdout for the whole line, so messages from the playback thread don't interleave
+    println!("{}", message);
+}
+
+/// Turns the app's reply into an HTTP response
+fn http_response(reply: RemoteReply) -> Response<Cursor<Vec<u8>>> {
+    match reply {
+        RemoteReply::Done => json_response(200, &serde_json::json!({ "ok": true })),
+        RemoteReply::Status(status) => json_response(200, &status),
+        RemoteReply::Screenshot(image) => match file::encode_png(&image) {
+            Ok(png) => Response::from_data(png).with_header(content_type("image/png")),
+            Err(err) => json_response(500, &serde_json::json!({ "error": err })),
+        },
+        // The command could not be carried out in the app's current state
+        RemoteReply::Error(err) => json_response(409, &serde_json::json!({ "error": err })),
+    }
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> Response<Cursor<Vec<u8>>> {
+    Response::from_data(serde_json::to_vec(body).unwrap_or_default())
+        .with_status_code(status)
+        .with_header(content_type("application/json"))
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).unwrap()
+}
@@ -1,25 +1,112 @@
 use core::f32;
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
 use std::{thread, time::Instant};
 
-use crate::keyframe::{Keyframe, KeyframeType};
-use crate::settings::{MonitorEdge, Settings};
+use crate::engine::{self, Host, Stop};
+use crate::expression::{base_variables, expression_fields, Input, InputKind, Variables};
+use crate::file::{self, read_table};
+use crate::ghost::Ghost;
+use crate::input;
+use crate::keyframe::{
+    kind_name, remap_dependencies, resolve_dependencies, validate, CounterAction, Dependency,
+    Keyframe, KeyframeType, MonitorAnchor, Problem, Severity, DEFAULT_KEY_DELAY, MAX_DELAY,
+};
+use crate::player::{play_keyframe, release_held_inputs, target_found};
+use crate::plugin;
+use crate::settings::{
+    KeybindType, MonitorCalibration, MonitorEdge, PlaybackSettings, Settings, Track,
+};
+use crate::theme::theme;
 use crate::util::*;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use eframe::egui::{self, pos2, Ui, Vec2};
 use egui::{vec2, Align2, ColorImage, FontId, TextureHandle};
 use egui::{Pos2, Rect};
 use serde::{Deserialize, Serialize};
 use uuid::{Bytes, Uuid};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SequencerState {
     pub repeats: i32,
+    /// Repeat until playback is stopped, ignoring `repeats`
+    #[serde(default)]
+    pub loop_forever: bool,
+    /// Play each keyframe as soon as the one before it is done instead of at its timestamp
+    #[serde(default)]
+    pub sequential: bool,
     pub speed: f32,
     pub keyframes: Vec<Keyframe>,
+    /// Named values that keyframe expressions can refer to
+    #[serde(default)]
+    pub variables: Vec<(String, String)>,
+    /// Variables whose values are asked for when playback starts
+    #[serde(default)]
+    pub inputs: Vec<Input>,
+    /// CSV or TSV file whose rows are each played through, with the columns as variables
+    #[serde(default)]
+    pub data_source: Option<PathBuf>,
+    /// Where the user left off in the timeline when the file was saved, if it was saved with one
+    #[serde(default)]
+    pub view: Option<ViewState>,
+    /// Used instead of the global playback settings while the file is open
+    #[serde(default)]
+    pub playback: Option<PlaybackSettings>,
+}
+
+impl Default for SequencerState {
+    fn default() -> Self {
+        Self {
+            repeats: 1,
+            loop_forever: false,
+            sequential: false,
+            speed: 1.,
+            keyframes: vec![],
+            variables: vec![],
+            inputs: vec![],
+            data_source: None,
+            view: None,
+            playback: None,
+        }
+    }
+}
+
+/// Zoom, scroll, playhead and selection of the timeline, restored when a file is opened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ViewState {
+    pub scale: f32,
+    pub scroll: f32,
+    pub time: f32,
+    pub selected_keyframes: Vec<Bytes>,
+    pub show_console: bool,
+}
+
+/// Size of the playback overlay window
+const HUD_SIZE: Vec2 = vec2(260., 84.);
+/// Size of the on screen display of pressed keys
+const OSD_SIZE: Vec2 = vec2(640., 96.);
+/// How long a pressed key stays on the on screen display
+const OSD_DURATION: Duration = Duration::from_millis(1500);
+/// Most keys shown on the on screen display at once
+const OSD_MAX_INPUTS: usize = 6;
+
+/// Width and height of the target image cropped when converting clicks to magic moves
+const MAGIC_MOVE_TARGET_SIZE: u32 = 64;
+
+/// Prefix used to recognise keyframes copied to the system clipboard by Automate
+const CLIPBOARD_PREFIX: &str = "automate/keyframes;base64,";
+/// How long whether the system clipboard holds keyframes is trusted for before it is read again
+const CLIPBOARD_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Keyframes and their screenshots as stored on the system clipboard
+#[derive(Serialize, Deserialize)]
+pub struct ClipboardData {
+    pub keyframes: Vec<Keyframe>,
+    /// Screenshots encoded as PNGs, which are far smaller than the raw images
+    pub images: Vec<(Bytes, Vec<u8>)>,
 }
 
 /// Represents any possible change made to the sequencer, used for undo/redo
@@ -30,7 +117,16 @@ pub enum ChangeData {
     EditDuration(f32),
     EditMagicFindPath(String, String),
     EditMouseButton(rdev::Button, rdev::Button),
+    /// Replaces the whole keyframe type, for edits that don't have a more specific change
+    EditKeyframeType(KeyframeType, KeyframeType),
+    EditDelay(Option<(f32, f32)>, Option<(f32, f32)>),
+    EditKeyDelay(Option<f32>, Option<f32>),
+    EditNote(String, String),
+    /// Field name, old and new expression, where an empty expression means the recorded value is used
+    EditExpression(String, String, String),
     EnableKeyframes(bool),
+    /// UID, old and new dependency of each keyframe whose dependency changed
+    EditDependencies(Vec<(Bytes, Option<Dependency>, Option<Dependency>)>),
 }
 
 /// Represents any possible change made to the sequencer, used for undo/redo
@@ -39,6 +135,270 @@ pub struct Change {
     pub data: Vec<ChangeData>,
 }
 
+/// How a keyframe is highlighted in the timeline, derived from the selection and the playhead
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyframeState {
+    Idle,
+    /// The playhead is inside of the keyframe, which takes priority over it being selected
+    Playing,
+    Selected,
+}
+
+/// Popup opened by double clicking an empty spot of a track, which adds a keyframe of the track's type there
+struct QuickAdd {
+    track: Track,
+    time: f32,
+    /// Where on screen the track was double clicked
+    pos: egui::Pos2,
+    /// Key or text typed in for the keyboard track
+    text: String,
+    /// Position to move to for the movement track
+    target: Vec2,
+    /// Whether it was shown before this frame, as the double click that opened it is outside of it
+    shown: bool,
+}
+
+/// Timings shown in the debug panel, to find out what makes big projects slow
+#[derive(Default)]
+struct Profile {
+    last_frame: Option<Instant>,
+    /// Seconds between frames, averaged over the last few frames
+    frame_time: f32,
+    /// Seconds spent in `render_keyframes`, averaged over the last few frames
+    render_time: f32,
+    /// Seconds the last keyframe started after its timestamp, in real time
+    jitter: f32,
+    /// Latest a keyframe has started since playback began
+    max_jitter: f32,
+}
+
+impl Profile {
+    /// Smooths out single slow frames so the numbers are readable
+    fn average(average: &mut f32, sample: Duration) {
+        *average = *average * 0.9 + sample.as_secs_f32() * 0.1;
+    }
+    fn frame(&mut self, now: Instant) {
+        if let Some(last) = self.last_frame {
+            Self::average(&mut self.frame_time, now - last);
+        }
+        self.last_frame = Some(now);
+    }
+    fn keyframe_started(&mut self, late: f32) {
+        self.jitter = late;
+        self.max_jitter = self.max_jitter.max(late);
+    }
+}
+
+/// Input seen by the record thread, sent to [`Sequencer::update`] to be turned into keyframes
+///
+/// Times are in seconds since recording started, taken when the input happened rather than when it was received
+enum RecordEvent {
+    /// Also has the UID its screenshot is stored under, as the screenshot is taken straight away
+    KeyPress {
+        key: rdev::Key,
+        time: f32,
+        uid: Bytes,
+    },
+    KeyRelease {
+        key: rdev::Key,
+        time: f32,
+    },
+    ButtonPress {
+        button: rdev::Button,
+        time: f32,
+        uid: Bytes,
+    },
+    ButtonRelease {
+        button: rdev::Button,
+        time: f32,
+    },
+    MouseMove {
+        pos: Vec2,
+        time: f32,
+    },
+    /// A mouse move at the last recorded position, regardless of the record resolution
+    ManualMouseMove {
+        time: f32,
+    },
+    Scroll {
+        delta: Vec2,
+        time: f32,
+    },
+    /// Where the cursor was reported while calibrating
+    Calibration(Vec2),
+}
+
+/// Keys of the keybinds heard by the record thread, which only look at the key and not the modifiers held with it
+#[derive(Clone, Copy)]
+struct GlobalKeys {
+    toggle_recording: Option<rdev::Key>,
+    stop: Option<rdev::Key>,
+    add_keyframe: Option<rdev::Key>,
+    confirm_step: Option<rdev::Key>,
+}
+
+impl Default for GlobalKeys {
+    fn default() -> Self {
+        Self {
+            toggle_recording: Some(rdev::Key::F8),
+            stop: Some(rdev::Key::Escape),
+            add_keyframe: Some(rdev::Key::F9),
+            confirm_step: Some(rdev::Key::F7),
+        }
+    }
+}
+
+/// Everything the record thread shares with the sequencer, kept so the thread can be restarted
+#[derive(Clone)]
+struct RecordShared {
+    recording: Arc<AtomicBool>,
+    play: Arc<AtomicBool>,
+    recording_instant: Arc<Mutex<Instant>>,
+    calibrate: Arc<AtomicBool>,
+    failsafe_edge: Arc<Mutex<MonitorEdge>>,
+    failsafe_edge_override: Arc<Mutex<Option<MonitorEdge>>>,
+    failsafe_bounds: Arc<Mutex<Rect>>,
+    panic_key: Arc<Mutex<rdev::Key>>,
+    global_keys: Arc<Mutex<GlobalKeys>>,
+    panicked: Arc<AtomicBool>,
+    step_confirmed: Arc<AtomicBool>,
+    recent_inputs: Arc<Mutex<Vec<(String, u32, Instant)>>>,
+    mouse_pos: Arc<Mutex<Vec2>>,
+    record_sender: mpsc::Sender<RecordEvent>,
+    screenshot_sender: mpsc::Sender<Bytes>,
+}
+
+/// The thread listening to global input, which recording and the keybinds that work without focus depend on
+///
+/// The listener can fail to start or stop with an error, which would otherwise go unnoticed
+struct RecordThread {
+    shared: RecordShared,
+    handle: Option<thread::JoinHandle<()>>,
+    /// Why the listener stopped, set by the thread as it exits
+    error: Arc<Mutex<Option<String>>>,
+    /// Whether the user has been told the thread stopped
+    reported: bool,
+}
+
+impl RecordThread {
+    fn spawn(shared: RecordShared) -> Self {
+        let error = Arc::new(Mutex::new(None));
+        let thread_shared = shared.clone();
+        let thread_error = Arc::clone(&error);
+        let handle = thread::Builder::new()
+            .name("Record Thread".to_owned())
+            .spawn(move || {
+                log::info!("Created Recording Thread");
+                let message =
+                    match rdev::listen(move |event| handle_record_event(&thread_shared, event)) {
+                        Ok(()) => "The input listener stopped".to_string(),
+                        Err(error) => format!("{:?}", error),
+                    };
+                log::error!("Record thread stopped - {}", message);
+                *thread_error.lock().unwrap() = Some(message);
+            });
+        let handle = match handle {
+            Ok(handle) => Some(handle),
+            Err(err) => {
+                *error.lock().unwrap() = Some(err.to_string());
+                None
+            }
+        };
+        Self {
+            shared,
+            handle,
+            error,
+            reported: false,
+        }
+    }
+    /// Keeps `shared` without listening, for a sequencer built without its threads
+    fn idle(shared: RecordShared) -> Self {
+        Self {
+            shared,
+            handle: None,
+            error: Arc::new(Mutex::new(Some(
+                "The record thread was not started".to_string(),
+            ))),
+            reported: false,
+        }
+    }
+    /// Why the thread isn't running, `None` while it is
+    fn error(&self) -> Option<String> {
+        match &self.handle {
+            Some(handle) if !handle.is_finished() => None,
+            // A panic in the listener ends the thread without setting the error
+            _ => Some(
+                self.error
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .unwrap_or("The record thread panicked".to_string()),
+            ),
+        }
+    }
+    /// Starts listening again, with the same state as before
+    fn restart(&mut self) {
+        log::info!("Restarting record thread");
+        *self = Self::spawn(self.shared.clone());
+    }
+}
+
+/// How the last playback came to an end
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackOutcome {
+    /// Played through to the end
+    Finished,
+    /// Stopped by fail detection, with the percentage error that stopped it
+    FailDetected(f32),
+    /// Stopped by an abort keyframe, with its message
+    Aborted(String),
+    /// Stopped because the system refused simulated input, with why
+    SimulationFailed(String),
+    /// Stopped because playback couldn't go on, e.g. a jump to a missing label, with why
+    Failed(String),
+    /// Refused to start because of problems with the sequence, with how many
+    Invalid(usize),
+}
+
+/// Something that happened during playback, streamed to remote control
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PlaybackEvent {
+    Started,
+    KeyframeStarted {
+        index: usize,
+        uid: String,
+        kind: u8,
+    },
+    KeyframeFinished {
+        index: usize,
+        uid: String,
+        kind: u8,
+    },
+    FailDetected {
+        percentage_err: f32,
+    },
+    /// A line added to the run log by a log keyframe
+    Log {
+        message: String,
+    },
+    /// Stopped by an abort keyframe
+    Aborted {
+        message: String,
+    },
+    /// Stopped because the system refused simulated input
+    SimulationFailed {
+        message: String,
+    },
+    /// Stopped because playback couldn't go on, e.g. a jump to a missing label
+    Failed {
+        message: String,
+    },
+    Finished,
+    /// Stopped before the end by the user
+    Stopped,
+}
+
 /// We derive Deserialize/Serialize, so we can persist app state on shutdown.
 #[derive(Deserialize, Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
@@ -48,15 +408,38 @@ pub struct Sequencer {
     #[serde(skip)]
     repeats: i32,
     #[serde(skip)]
+    loop_forever: bool,
+    #[serde(skip)]
+    sequential: bool,
+    #[serde(skip)]
     speed: f32,
     #[serde(skip)]
     pub keyframes: Vec<Keyframe>,
     #[serde(skip)]
+    pub variables: Vec<(String, String)>,
+    #[serde(skip)]
+    pub inputs: Vec<Input>,
+    #[serde(skip)]
+    // Values being entered for the inputs before playback starts, while they are being asked for
+    input_prompt: Option<Vec<String>>,
+    #[serde(skip)]
+    // Set once the inputs have values for the next playback, so they aren't asked for again
+    inputs_confirmed: bool,
+    #[serde(skip)]
+    pub data_source: Option<PathBuf>,
+    #[serde(skip)]
+    // Rows of the data source, read when playback starts
+    data_rows: Vec<Variables>,
+    #[serde(skip)]
     pub selected_keyframes: Vec<Bytes>,
+    /// Playback state, including the keyframes the playhead is currently inside of
     #[serde(skip)]
-    pub keyframe_state: Vec<usize>,
+    engine: engine::Engine,
     #[serde(skip)]
     pub images: Arc<Mutex<HashMap<Bytes, Vec<u8>>>>,
+    /// Screenshots that have not changed since the file was last saved or loaded
+    #[serde(skip)]
+    pub saved_images: HashSet<Bytes>,
     #[serde(skip)]
     pub changed: Arc<AtomicBool>,
     #[serde(skip)]
@@ -66,6 +449,9 @@ pub struct Sequencer {
     #[serde(skip)]
     pub clip_board: Vec<Keyframe>,
     #[serde(skip)]
+    // When the system clipboard was last checked for keyframes, and whether it had any
+    clipboard_check: Option<(Instant, bool)>,
+    #[serde(skip)]
     pub calibrate: Arc<AtomicBool>,
     #[serde(skip)]
     should_sort: bool,
@@ -101,16 +487,131 @@ pub struct Sequencer {
     #[serde(skip)]
     current_image_uid: Bytes,
     #[serde(skip)]
-    texture_handles: Vec<TextureHandle>,
+    // Index of each keyframe by UID, updated whenever keyframes are added, removed, sorted or replaced
+    uid_index: HashMap<Bytes, usize>,
+    #[serde(skip)]
+    // Textures of the screenshots that have been shown, freed once their keyframe or screenshot is gone
+    texture_handles: HashMap<Bytes, TextureHandle>,
+    #[serde(skip)]
+    profile: Profile,
+    #[serde(skip)]
+    // Width of the visible timeline in points, as of the last frame
+    timeline_width: f32,
+    #[serde(skip)]
+    // Screen position of 0s on the timeline before scrolling, as of the last frame
+    timeline_left: f32,
+    #[serde(skip)]
+    // Whether the last scrolling came from a touchpad, which pans instead of zooming when scrolled sideways
+    touchpad: bool,
+    /// Scroll the timeline to keep the playhead visible during playback
+    pub follow_playhead: bool,
+    #[serde(skip)]
+    /// Where the Add Keyframe window inserts keyframes, marked on the timeline while it is open
+    pub insert_marker: Option<f32>,
+    #[serde(skip)]
+    quick_add: Option<QuickAdd>,
+    /// Show a small window above everything else while playing
+    pub show_hud: bool,
+    /// Draw the mouse path of the visible part of the timeline over the screenshot
+    pub show_mouse_trail: bool,
+    /// Also draw the mouse path over the screen during playback
+    pub show_trail_overlay: bool,
+    /// Show the keys and buttons being pressed in large text while recording and playing
+    pub show_key_osd: bool,
+    #[serde(skip)]
+    // Keys and buttons pressed recently, how many times in a row and when they were last pressed, shared with the record thread
+    recent_inputs: Arc<Mutex<Vec<(String, u32, Instant)>>>,
+    #[serde(skip)]
+    // Bounds of the primary monitor, where the playback overlays are shown
+    primary_monitor: Option<Rect>,
+    #[serde(skip)]
+    // When the countdown before playback ends, while there is one
+    countdown: Option<Instant>,
+    #[serde(skip)]
+    /// Play without simulating any input, showing what would happen over the screenshots instead
+    pub dry_run: bool,
+    #[serde(skip)]
+    ghost: Ghost,
+    #[serde(skip)]
+    // Found by the last check of the sequence, before playback or when asked for
+    problems: Vec<Problem>,
+    #[serde(skip)]
+    pub show_problems: bool,
+    #[serde(skip)]
+    // Target images of magic moves that didn't exist when the file was loaded, until they are relinked
+    pub missing_assets: Vec<String>,
     pub failsafe_edge: Arc<Mutex<MonitorEdge>>,
     #[serde(skip)]
+    /// Playback settings of the open file, used instead of the global settings
+    pub playback_overrides: Option<PlaybackSettings>,
+    #[serde(skip)]
+    // Fail safe edge of the open file, shared with the record thread
+    failsafe_edge_override: Arc<Mutex<Option<MonitorEdge>>>,
+    /// Name of the monitor whose edge is the fail safe, `None` for the primary monitor
+    pub failsafe_monitor: Option<String>,
+    #[serde(skip)]
+    // Bounds of the fail safe monitor, shared with the record thread
+    failsafe_bounds: Arc<Mutex<Rect>>,
+    #[serde(skip)]
+    // Key that stops playback from anywhere, shared with the record thread
+    panic_key: Arc<Mutex<rdev::Key>>,
+    #[serde(skip)]
+    // Keys of the keybinds that work without focus, shared with the record thread
+    global_keys: Arc<Mutex<GlobalKeys>>,
+    #[serde(skip)]
+    // Set by the record thread when the panic key stops playback, so the editor can take focus
+    panicked: Arc<AtomicBool>,
+    #[serde(skip)]
+    /// Wait for the confirm step key before playing each keyframe
+    pub confirm_steps: bool,
+    #[serde(skip)]
+    // Set by the record thread when the confirm step key is pressed
+    step_confirmed: Arc<AtomicBool>,
+    #[serde(skip)]
+    // The keyframe holding the playhead until its step is confirmed
+    awaiting_step: Option<Bytes>,
+    #[serde(skip)]
+    // The keyframe whose step was just confirmed, so it plays instead of waiting again
+    confirmed_step: Option<Bytes>,
+    #[serde(skip)]
     //            Undo       , Redo
     pub changes: (Vec<Change>, Vec<Change>),
     #[serde(skip)]
-    pub recording_keyframes: Arc<Mutex<Vec<Keyframe>>>,
+    // Keyframes of the recording in progress, moved to `keyframes` once it stops
+    pub recording_keyframes: Vec<Keyframe>,
+    #[serde(skip)]
+    record_thread: RecordThread,
+    #[serde(skip)]
+    // Input from the record thread, received each frame
+    record_events: mpsc::Receiver<RecordEvent>,
+    #[serde(skip)]
+    // Mouse moves left to skip before the next one is recorded, see `mouse_movement_record_resolution`
+    mouse_move_count: i32,
+    #[serde(skip)]
+    previous_mouse_position: Vec2,
     #[serde(skip)]
     mouse_pos: Arc<Mutex<Vec2>>,
     pub modal: (bool, String, String),
+    #[serde(skip)]
+    // None while playing and when playback was stopped by the user
+    pub outcome: Option<PlaybackOutcome>,
+    #[serde(skip)]
+    // What was on screen when fail detection last stopped playback
+    pub failure_screenshot: Option<Vec<u8>>,
+    #[serde(skip)]
+    // Events since the last frame, drained by the app
+    pub events: Vec<PlaybackEvent>,
+    #[serde(skip)]
+    // Lines added by log keyframes since playback last started
+    pub run_log: Vec<String>,
+    #[serde(skip)]
+    was_playing: bool,
+    #[serde(skip)]
+    // Text being edited in the selected panel, before the edit
+    text_edit_start: String,
+    #[serde(skip)]
+    // Keyframe type being edited in the selected panel, before the edit
+    type_edit_start: Option<KeyframeType>,
 }
 
 impl Sequencer {
@@ -118,7 +619,12 @@ impl Sequencer {
     ///
     /// Also manages creating the keystrokes recording thread
     pub fn new() -> Self {
-        let recording_keyframes: Arc<Mutex<Vec<Keyframe>>> = Arc::new(Mutex::new(vec![]));
+        Self::with_threads(true)
+    }
+    /// Builds the sequencer, only starting the screenshot and record threads if `threads` is set
+    ///
+    /// Tests build it without them, so they don't hook global input
+    fn with_threads(threads: bool) -> Self {
         let recording = Arc::new(AtomicBool::new(false));
         let play = Arc::new(AtomicBool::new(false));
         let mouse_movement_record_resolution = Arc::new(AtomicI32::new(20));
@@ -127,196 +633,74 @@ impl Sequencer {
         let calibrate = Arc::new(AtomicBool::new(false));
         let images = Arc::new(Mutex::new(HashMap::new()));
         let failsafe_edge = Arc::new(Mutex::new(MonitorEdge::Right));
+        let failsafe_bounds = Arc::new(Mutex::new(
+            monitor_bounds(None).unwrap_or(Rect::from_min_size(Pos2::ZERO, vec2(1920., 1080.))),
+        ));
         let mouse_pos = Arc::new(Mutex::new(Vec2::ZERO));
+        let panic_key = Arc::new(Mutex::new(rdev::Key::Pause));
+        let global_keys = Arc::new(Mutex::new(GlobalKeys::default()));
+        let panicked = Arc::new(AtomicBool::new(false));
+        let step_confirmed = Arc::new(AtomicBool::new(false));
+        let recent_inputs = Arc::new(Mutex::new(Vec::new()));
 
-        let shared_kfs = Arc::clone(&recording_keyframes);
-        let shared_rec = Arc::clone(&recording);
-        let shared_play = Arc::clone(&play);
-        let shared_count = Arc::clone(&mouse_movement_record_resolution);
-        let shared_instant = Arc::clone(&recording_instant);
-        let shared_changed = Arc::clone(&changed);
-        let shared_calibrate = Arc::clone(&calibrate);
-        let shared_images = Arc::clone(&images);
-        let shared_edge = Arc::clone(&failsafe_edge);
-        let shared_mouse_pos = Arc::clone(&mouse_pos);
+        let failsafe_edge_override = Arc::new(Mutex::new(None));
 
-        let mut previous_mouse_position = Vec2::ZERO;
-        // this needs to get reset every time recording starts
-        let mut mouse_move_count = 100;
+        let (record_sender, record_events) = mpsc::channel::<RecordEvent>();
 
-        // Spawn the recording thread
-        let _ = thread::Builder::new()
-            .name("Record Thread".to_owned())
-            .spawn(move || {
-                log::info!("Created Recording Thread");
-                if let Err(error) = rdev::listen(move |event: rdev::Event| {
-                    let mut rec_keyframes = shared_kfs.lock().unwrap();
-
-                    let is_recording = shared_rec.load(Ordering::Relaxed);
-                    let mut tmp_keyframe = None;
-                    let dt = Instant::now().duration_since(*shared_instant.lock().unwrap());
-                    // Handle global keybindings without focus
-                    match &event.event_type {
-                        rdev::EventType::KeyRelease(key) => {
-                            match key {
-                                // Keybind(F8): Toggle recording
-                                rdev::Key::F8 => {
-                                    println!("{:?}", !is_recording);
-                                    if is_recording {
-                                        shared_rec.swap(false, Ordering::Relaxed);
-                                    } else {
-                                        shared_rec.swap(true, Ordering::Relaxed);
-                                        mouse_move_count = 20;
-                                        previous_mouse_position = Vec2::ZERO;
-                                    }
-                                }
-                                // Keybind(esc): Toggle play execution
-                                rdev::Key::Escape => {
-                                    shared_play.swap(false, Ordering::Relaxed);
-                                }
-                                // Keybind(F9): Manually add a mouse move keyframe (can be used for filling in missed movements due to record resolution)
-                                rdev::Key::F9 => {
-                                    tmp_keyframe = Some(Keyframe::mouse_move(
-                                        dt.as_secs_f32(),
-                                        previous_mouse_position,
-                                    ))
-                                }
-                                _ => {}
-                            }
-                        }
-                        rdev::EventType::MouseMove { x, y } => {
-                            // Update the current mouse position
-                            *shared_mouse_pos.lock().unwrap() = Vec2::new(*x as f32, *y as f32);
-                            // Offset Calibration
-                            if shared_calibrate.load(Ordering::Relaxed) {
-                                rec_keyframes.push(Keyframe {
-                                    timestamp: f32::NAN,
-                                    duration: f32::NAN,
-                                    keyframe_type: KeyframeType::MouseMove(Vec2::new(
-                                        *x as f32, *y as f32,
-                                    )),
-                                    kind: u8::MAX, // This is code to say the keyframe is for calibration only and must be deleted after use
-                                    enabled: false,
-                                    uid: Uuid::nil().to_bytes_le(),
-                                });
-                            }
-                            // Handle monitor edge fail safe
-                            match *shared_edge.lock().unwrap() {
-                                MonitorEdge::Left => {
-                                    if *x < 0. {
-                                        shared_play.swap(false, Ordering::Relaxed);
-                                    }
-                                }
-                                MonitorEdge::Right => {
-                                    if *x > 1920. {
-                                        shared_play.swap(false, Ordering::Relaxed);
-                                    }
-                                }
-                                MonitorEdge::Bottom => {
-                                    if *y > 1080. {
-                                        shared_play.swap(false, Ordering::Relaxed);
-                                    }
-                                }
-                                MonitorEdge::Top => {
-                                    if *y < 0. {
-                                        shared_play.swap(false, Ordering::Relaxed);
-                                    }
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                    if is_recording && tmp_keyframe.is_none() {
-                        // Checks if there are no keyframes (Would only be the case if a new recording has started and there is no start screenshot)
-                        tmp_keyframe = match &event.event_type {
-                            // Button & Key Press events just push info
-                            rdev::EventType::ButtonPress(btn) => {
-                                let keyframe =
-                                    Keyframe::mouse_button(dt.as_secs_f32(), f32::NAN, btn.clone());
-                                if let Some(screenshot) = screenshot() {
-                                    shared_images
-                                        .lock()
-                                        .unwrap()
-                                        .insert(keyframe.uid, screenshot);
-                                }
-                                Some(keyframe)
-                            }
-                            rdev::EventType::KeyPress(key) => {
-                                let keyframe =
-                                    Keyframe::key_btn(dt.as_secs_f32(), f32::NAN, key.clone());
-                                if let Some(screenshot) = screenshot() {
-                                    shared_images
-                                        .lock()
-                                        .unwrap()
-                                        .insert(keyframe.uid, screenshot);
-                                }
-                                Some(keyframe)
-                            }
-                            // Button & Key Release events search for the matching keypress event to create a full keyframe
-                            rdev::EventType::ButtonRelease(btn) => {
-                                if let Some(keyframe) = rec_keyframes.iter_mut().rev().find(|kf| {
-                                    if let KeyframeType::MouseBtn(b) = kf.keyframe_type {
-                                        b == *btn
-                                    } else {
-                                        false
-                                    }
-                                }) {
-                                    keyframe.calculate_duration(dt.as_secs_f32());
-                                }
-                                None
-                            }
-                            rdev::EventType::KeyRelease(key) => {
-                                if let Some(keyframe) = rec_keyframes.iter_mut().rev().find(|kf| {
-                                    if let KeyframeType::KeyBtn(k) = kf.keyframe_type {
-                                        k == *key
-                                    } else {
-                                        false
-                                    }
-                                }) {
-                                    keyframe.calculate_duration(dt.as_secs_f32());
-                                }
-                                None
-                            }
-                            rdev::EventType::MouseMove { x, y } => {
-                                let pos = Vec2::new(*x as f32, *y as f32);
-                                mouse_move_count -= 1;
-
-                                match previous_mouse_position == pos {
-                                    false => match mouse_move_count <= 0 {
-                                        true => {
-                                            previous_mouse_position = pos;
-                                            mouse_move_count =
-                                                100 - shared_count.load(Ordering::Relaxed);
-                                            Some(Keyframe::mouse_move(dt.as_secs_f32(), pos))
-                                        }
-                                        false => None,
-                                    },
-                                    true => None,
-                                }
-                            }
-                            rdev::EventType::Wheel { delta_x, delta_y } => {
-                                match *delta_x == 0 && *delta_y == 0 {
-                                    true => None,
-                                    false => Some(Keyframe::scroll(
-                                        dt.as_secs_f32(),
-                                        Vec2::new(*delta_x as f32, *delta_y as f32),
-                                    )),
-                                }
-                            }
-                        };
-                        // If a keyframe was created push the necessary data to sequencer
-                        if let Some(keyframe) = tmp_keyframe {
-                            rec_keyframes.push(keyframe);
-                            shared_changed.swap(true, Ordering::Relaxed);
+        // Screenshots are taken on their own thread so capturing the screen doesn't hold up the hook and drop events,
+        // the record thread sends the UID of the keyframe each screenshot belongs to
+        let (screenshot_sender, screenshot_requests) = mpsc::channel::<Bytes>();
+        let shared_images = Arc::clone(&images);
+        if threads {
+            let _ = thread::Builder::new()
+                .name("Screenshot Thread".to_owned())
+                .spawn(move || {
+                    for uid in screenshot_requests {
+                        if let Some(screenshot) = screenshot() {
+                            let screenshot =
+                                resize_screenshot(&screenshot, screenshot_quality().scale);
+                            shared_images.lock().unwrap().insert(uid, screenshot);
                         }
                     }
-                }) {
-                    log::error!("Error: {:?}", error)
-                }
-            });
+                });
+        }
+
+        let record_shared = RecordShared {
+            recording: Arc::clone(&recording),
+            play: Arc::clone(&play),
+            recording_instant: Arc::clone(&recording_instant),
+            calibrate: Arc::clone(&calibrate),
+            failsafe_edge: Arc::clone(&failsafe_edge),
+            failsafe_edge_override: Arc::clone(&failsafe_edge_override),
+            failsafe_bounds: Arc::clone(&failsafe_bounds),
+            panic_key: Arc::clone(&panic_key),
+            global_keys: Arc::clone(&global_keys),
+            panicked: Arc::clone(&panicked),
+            step_confirmed: Arc::clone(&step_confirmed),
+            recent_inputs: Arc::clone(&recent_inputs),
+            mouse_pos: Arc::clone(&mouse_pos),
+            record_sender,
+            screenshot_sender,
+        };
+        let record_thread = match threads {
+            true => RecordThread::spawn(record_shared),
+            false => RecordThread::idle(record_shared),
+        };
         Self {
             keyframes: Vec::new(),
-            recording_keyframes,
+            variables: Vec::new(),
+            inputs: Vec::new(),
+            input_prompt: None,
+            inputs_confirmed: false,
+            data_source: None,
+            data_rows: Vec::new(),
+            text_edit_start: String::new(),
+            type_edit_start: None,
+            recording_keyframes: Vec::new(),
+            record_events,
+            record_thread,
+            mouse_move_count: 100,
+            previous_mouse_position: Vec2::ZERO,
             changed,
             should_sort: false,
             drag_start: pos2(0., 0.),
@@ -327,6 +711,8 @@ impl Sequencer {
             resizing: false,
             scale: 0.01,
             repeats: 1,
+            loop_forever: false,
+            sequential: false,
             speed: 1.0,
             scroll: 0.0,
             time: 0.0,
@@ -334,23 +720,61 @@ impl Sequencer {
             play,
             mouse_movement_record_resolution,
             selected_keyframes: vec![],
-            keyframe_state: Vec::new(),
+            engine: engine::Engine::default(),
             recording,
             clear_before_recording: true,
             was_recording: false,
             recording_instant,
             loaded_file: "".to_string(),
             clip_board: vec![],
+            clipboard_check: None,
             once_bool: false,
             calibrate,
             current_image: None,
             current_image_uid: Uuid::nil().to_bytes_le(),
             images,
-            texture_handles: Vec::new(),
+            saved_images: HashSet::new(),
+            uid_index: HashMap::new(),
+            texture_handles: HashMap::new(),
+            profile: Profile::default(),
+            timeline_width: 800.,
+            timeline_left: 78.,
+            touchpad: false,
+            follow_playhead: true,
+            insert_marker: None,
+            quick_add: None,
+            show_hud: true,
+            show_mouse_trail: true,
+            show_trail_overlay: false,
+            primary_monitor: None,
+            countdown: None,
+            dry_run: false,
+            ghost: Ghost::default(),
+            problems: Vec::new(),
+            show_problems: false,
+            missing_assets: Vec::new(),
             failsafe_edge,
+            playback_overrides: None,
+            failsafe_edge_override,
+            failsafe_monitor: None,
+            failsafe_bounds,
+            panic_key,
+            global_keys,
+            panicked,
+            confirm_steps: false,
+            step_confirmed,
+            awaiting_step: None,
+            confirmed_step: None,
+            show_key_osd: false,
+            recent_inputs,
             changes: (Vec::new(), Vec::new()),
             mouse_pos,
             modal: (false, "".to_string(), "".to_string()),
+            outcome: None,
+            failure_screenshot: None,
+            events: Vec::new(),
+            run_log: Vec::new(),
+            was_playing: false,
         }
     }
     // Handles cleanup after changes were made
@@ -367,23 +791,346 @@ impl Sequencer {
     pub fn get_time(&self) -> f32 {
         self.time
     }
+    /// Returns when the last of the selected keyframes ends, if any are selected
+    pub fn selection_end(&self) -> Option<f32> {
+        self.selected_keyframes
+            .iter()
+            .filter_map(|uid| self.index_of(uid))
+            .map(|i| self.keyframes[i].timestamp + self.keyframes[i].duration)
+            .filter(|end| end.is_finite())
+            .reduce(f32::max)
+    }
     /// Saves the current state of the sequencer to `SequencerState`
     pub fn save_to_state(&self) -> SequencerState {
         SequencerState {
             repeats: self.repeats,
+            loop_forever: self.loop_forever,
+            sequential: self.sequential,
             speed: self.speed,
             keyframes: self.keyframes.clone(),
+            variables: self.variables.clone(),
+            inputs: self.inputs.clone(),
+            data_source: self.data_source.clone(),
+            view: Some(ViewState {
+                scale: self.scale,
+                scroll: self.scroll,
+                time: self.time,
+                selected_keyframes: self.selected_keyframes.clone(),
+                // Set by the app, which owns the console
+                show_console: false,
+            }),
+            playback: self.playback_overrides,
+        }
+    }
+    /// Playback settings of the open file, or the global settings if it doesn't override them
+    pub fn playback_settings(&self, settings: &Settings) -> PlaybackSettings {
+        self.playback_overrides.unwrap_or(PlaybackSettings {
+            fail_detection: settings.fail_detection,
+            max_fail_error: settings.max_fail_error,
+            offset: settings.offset,
+            failsafe_edge: *self.failsafe_edge.lock().unwrap(),
+        })
+    }
+    /// Sets the playback settings of the open file, `None` to use the global settings
+    pub fn set_playback_overrides(&mut self, overrides: Option<PlaybackSettings>) {
+        *self.failsafe_edge_override.lock().unwrap() = overrides.map(|o| o.failsafe_edge);
+        self.playback_overrides = overrides;
+    }
+    /// Sets which monitor's edge is the fail safe and looks up its bounds, `None` for the primary monitor
+    pub fn set_failsafe_monitor(&mut self, name: Option<String>) {
+        if let Some(bounds) = monitor_bounds(name.as_deref()) {
+            *self.failsafe_bounds.lock().unwrap() = bounds;
+        }
+        self.failsafe_monitor = name;
+    }
+    /// Moves the mouse over several points on each monitor and fits how the positions the mouse hook reports
+    /// map to the positions that were simulated
+    ///
+    /// Unlike moving to (0,0) and inverting the reported position, this still works with display scaling.
+    /// Monitors where the hook didn't report enough of the movements are left out
+    pub fn calibrate_monitors(&mut self) -> Result<Vec<MonitorCalibration>, String> {
+        // Where on each monitor to move to, as fractions of its size
+        const POINTS: [(f32, f32); 5] =
+            [(0.1, 0.1), (0.9, 0.1), (0.5, 0.5), (0.1, 0.9), (0.9, 0.9)];
+        self.calibrate.swap(true, Ordering::Relaxed);
+        let mut calibrations = vec![];
+        for (_, monitor) in monitor_rects() {
+            let mut samples = vec![];
+            for (x, y) in POINTS {
+                let target = monitor.min.to_vec2() + monitor.size() * vec2(x, y);
+                // Forget positions reported before this movement
+                self.receive_recorded_events();
+                if let Err(err) = input::simulate(&rdev::EventType::MouseMove {
+                    x: target.x as f64,
+                    y: target.y as f64,
+                }) {
+                    log::warn!("Failed to move to calibration point {:?}: {}", target, err);
+                    continue;
+                }
+                // Give the hook time to report the movement
+                thread::sleep(Duration::from_millis(50));
+                if let Some(reported) = self.receive_recorded_events() {
+                    samples.push((target, reported));
+                }
+            }
+            if let Some(calibration) = MonitorCalibration::fit(&samples, monitor) {
+                calibrations.push(calibration);
+            }
+        }
+        self.calibrate.swap(false, Ordering::Relaxed);
+        match calibrations.is_empty() {
+            true => Err("The mouse hook didn't report the calibration movements".to_string()),
+            false => Ok(calibrations),
         }
     }
+    /// Sets the key that stops playback from anywhere
+    pub fn set_panic_key(&self, key: rdev::Key) {
+        *self.panic_key.lock().unwrap() = key;
+    }
+    /// Updates the keys of the keybinds that work without focus from the shortcuts in `settings`
+    pub fn set_global_keys(&self, settings: &Settings) {
+        let key = |kind| {
+            settings
+                .shortcut(kind)
+                .and_then(|shortcut| egui_to_rdev_key(shortcut.logical_key))
+        };
+        *self.global_keys.lock().unwrap() = GlobalKeys {
+            toggle_recording: key(KeybindType::ToggleRecording),
+            stop: key(KeybindType::ToggleExecution),
+            add_keyframe: key(KeybindType::AddKeyframe),
+            confirm_step: key(KeybindType::ConfirmStep),
+        };
+    }
     /// Loads the sequencer with the `SequencerState`
     pub fn load_from_state(&mut self, state: SequencerState) {
+        // Textures belong to the keyframes of the previous file
+        self.texture_handles.clear();
+        self.current_image = None;
+        self.current_image_uid = Uuid::nil().to_bytes_le();
         self.keyframes.clear();
         self.keyframes.extend(state.keyframes.into_iter());
-        self.keyframe_state.clear();
-        self.keyframe_state
-            .extend(vec![0; self.keyframes.len()].into_iter());
+        self.engine.playing.clear();
+        self.reindex();
+        self.missing_assets = self.find_missing_assets();
+        if !self.missing_assets.is_empty() {
+            log::warn!("Missing target images: {:?}", self.missing_assets);
+        }
         self.speed = state.speed;
         self.repeats = state.repeats;
+        self.loop_forever = state.loop_forever;
+        self.sequential = state.sequential;
+        self.variables = state.variables;
+        self.inputs = state.inputs;
+        self.input_prompt = None;
+        self.data_source = state.data_source;
+        self.set_playback_overrides(state.playback);
+        if let Some(view) = state.view {
+            self.scale = view.scale.clamp(0.01, 10.0);
+            self.scroll = view.scroll.max(0.);
+            self.time = view.time.max(0.);
+            // Keyframes that no longer exist can't stay selected
+            self.selected_keyframes = view
+                .selected_keyframes
+                .into_iter()
+                .filter(|uid| self.index_of(uid).is_some())
+                .collect();
+            self.selected_keyframes.sort();
+        }
+    }
+    /// Target images of magic moves and while image loops that don't exist, each listed once
+    fn find_missing_assets(&self) -> Vec<String> {
+        let mut missing: Vec<String> = self
+            .keyframes
+            .iter()
+            .filter_map(|keyframe| keyframe.keyframe_type.target())
+            .filter(|path| !Path::new(path).exists())
+            .cloned()
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+    /// Points every keyframe with the `old` target image at `new`, as a single undo step
+    pub fn relink(&mut self, old: &str, new: String) {
+        let mut uids = vec![];
+        for keyframe in &mut self.keyframes {
+            if let Some(path) = keyframe.keyframe_type.target_mut() {
+                if path == old {
+                    *path = new.clone();
+                    uids.push(keyframe.uid);
+                }
+            }
+        }
+        log::info!(
+            "Relinked {} magic moves from {:?} to {:?}",
+            uids.len(),
+            old,
+            new
+        );
+        self.changes.0.push(Change {
+            uids,
+            data: vec![ChangeData::EditMagicFindPath(old.to_string(), new)],
+        });
+        self.missing_assets.retain(|path| path != old);
+        self.changed.swap(true, Ordering::Relaxed);
+    }
+    /// Plays with the values the inputs already have, without asking for them
+    ///
+    /// For playback started from the command line or remote control, where nobody is there to answer
+    pub fn skip_input_prompt(&mut self) {
+        self.inputs_confirmed = true;
+    }
+    /// Asks for the values of the inputs before playback starts, offering the values given last time
+    pub fn input_prompt_window(&mut self, ctx: &egui::Context) {
+        let Some(values) = &mut self.input_prompt else {
+            return;
+        };
+        let mut open = true;
+        let mut play = false;
+        egui::Window::new("Inputs")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("inputs_grid")
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        for (input, value) in self.inputs.iter().zip(values.iter_mut()) {
+                            ui.label(&input.name);
+                            ui.horizontal(|ui| {
+                                let valid = input.accepts(value);
+                                let mut edit =
+                                    egui::TextEdit::singleline(value).desired_width(180.);
+                                if !valid {
+                                    edit = edit.text_color(ui.visuals().error_fg_color);
+                                }
+                                let resp = ui.add(edit);
+                                if !valid {
+                                    resp.on_hover_text("Not a number");
+                                }
+                                if input.kind == InputKind::File && ui.button("Browse").clicked() {
+                                    if let Some(path) = rfd::FileDialog::new().pick_file() {
+                                        *value = path.to_string_lossy().to_string();
+                                    }
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+                let valid = self
+                    .inputs
+                    .iter()
+                    .zip(values.iter())
+                    .all(|(input, value)| input.accepts(value));
+                play = ui.add_enabled(valid, egui::Button::new("⏵ Play")).clicked();
+            });
+        if play {
+            for (input, value) in self.inputs.iter_mut().zip(values.drain(..)) {
+                if input.value != value {
+                    input.value = value;
+                    // Remembered with the file so they are offered again next time
+                    self.changed.swap(true, Ordering::Relaxed);
+                }
+            }
+            self.input_prompt = None;
+            self.inputs_confirmed = true;
+            self.toggle_play();
+        } else if !open {
+            self.input_prompt = None;
+        }
+    }
+    /// Lists the missing target images of the loaded file, letting each be pointed at a new location
+    ///
+    /// A folder can be picked to relink every missing image with the same file name in it at once
+    pub fn relink_window(&mut self, ctx: &egui::Context) {
+        if self.missing_assets.is_empty() {
+            return;
+        }
+        let mut open = true;
+        let mut relinks = vec![];
+        egui::Window::new("Missing Images")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(360.)
+            .show(ctx, |ui| {
+                ui.label("These target images of magic moves no longer exist, locate them to play the sequence.");
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(300.)
+                    .show(ui, |ui| {
+                        for missing in &self.missing_assets {
+                            ui.horizontal(|ui| {
+                                let name = Path::new(missing)
+                                    .file_name()
+                                    .map(|name| name.to_string_lossy().to_string())
+                                    .unwrap_or(missing.clone());
+                                ui.label(name).on_hover_text(missing);
+                                if ui.button("Locate").clicked() {
+                                    if let Some(path) = rfd::FileDialog::new()
+                                        .add_filter("Images", &["png"])
+                                        .pick_file()
+                                    {
+                                        relinks.push((
+                                            missing.clone(),
+                                            path.to_string_lossy().to_string(),
+                                        ));
+                                    }
+                                }
+                            });
+                        }
+                    });
+                ui.separator();
+                if ui
+                    .button("Relink Folder")
+                    .on_hover_text("Finds the images with the same file names in a folder")
+                    .clicked()
+                {
+                    if let Some(folder) = rfd::FileDialog::new().pick_folder() {
+                        for missing in &self.missing_assets {
+                            if let Some(name) = Path::new(missing).file_name() {
+                                let path = folder.join(name);
+                                if path.exists() {
+                                    relinks.push((
+                                        missing.clone(),
+                                        path.to_string_lossy().to_string(),
+                                    ));
+                                }
+                            }
+                        }
+                        if relinks.is_empty() {
+                            log::warn!("None of the missing images are in {:?}", folder);
+                        }
+                    }
+                }
+            });
+        for (old, new) in relinks {
+            self.relink(&old, new);
+        }
+        // Closing the window leaves the rest missing, to be caught before playback
+        if !open {
+            self.missing_assets.clear();
+        }
+    }
+    /// Reads the rows of the data source, if there is one, and starts from the first row
+    fn load_data_rows(&mut self) -> Result<(), String> {
+        self.data_rows = match &self.data_source {
+            Some(path) => read_table(path)?,
+            None => vec![],
+        };
+        Ok(())
+    }
+    /// Variables shared by every keyframe while playing the given row of the data source, without the counters
+    fn row_variables(&self, row: usize) -> Variables {
+        base_variables(
+            &self.variables,
+            &self.inputs,
+            self.data_rows.get(row).map(|values| (row, values)),
+        )
+    }
+    /// Returns whether the sequencer is playing
+    pub fn is_playing(&self) -> bool {
+        self.play.load(Ordering::Relaxed)
     }
     /// Toggles whether the sequencer is playing or not
     pub fn toggle_play(&mut self) {
@@ -391,6 +1138,7 @@ impl Sequencer {
         // If its not already playing, it will so we need to empty the selected keyframes
         if !is_playing {
             self.selected_keyframes.clear();
+            self.outcome = None;
         }
         self.play.swap(!is_playing, Ordering::Relaxed);
     }
@@ -398,27 +1146,138 @@ impl Sequencer {
     pub fn reset_time(&mut self) {
         self.time = 0.;
     }
+    /// Moves the playhead to the start of the sequence
+    pub fn jump_to_start(&mut self) {
+        self.reset_time();
+        self.scroll_into_view(self.time);
+    }
+    /// Moves the playhead to the end of the last keyframe
+    pub fn jump_to_end(&mut self) {
+        self.time = self
+            .keyframes
+            .iter()
+            .map(|k| k.timestamp + k.duration)
+            .fold(0., f32::max);
+        self.scroll_into_view(self.time);
+    }
+    /// Scrolls the timeline by a whole view, forwards for positive `pages` and backwards for negative
+    pub fn page(&mut self, pages: f32) {
+        self.scroll = (self.scroll + self.visible_duration() * pages).max(0.);
+    }
+    /// Pages the timeline so `time` is in view, if it isn't already
+    fn scroll_into_view(&mut self, time: f32) {
+        let visible = self.visible_duration();
+        if time < self.scroll || time > self.scroll + visible * 0.9 {
+            self.scroll = (time - visible * 0.1).max(0.);
+        }
+    }
     /// Increase the current time by 0.1 seconds
     pub fn step_time(&mut self) {
         self.time += 0.1;
     }
     /// Increases the scale of the keyframes to zoom in
-    pub fn zoom(&mut self, delta: f32) {
+    ///
+    /// The time under `anchor`, the x position of the pointer, stays where it is so the content under it doesn't drift away
+    pub fn zoom(&mut self, delta: f32, anchor: Option<f32>) {
+        if delta == 0. {
+            return;
+        }
         let multiplier = 1.0 / 100.0;
+        // Seconds from the left of the timeline to the anchor, before and after zooming
+        let offset = |scale: f32| {
+            anchor
+                .map(|x| {
+                    (x - self.timeline_left).clamp(0., self.timeline_width) / (20. + 40. * scale)
+                })
+                .unwrap_or(0.)
+        };
+        let before = offset(self.scale);
         self.scale = (self.scale + delta * multiplier).clamp(0.01, 10.0);
+        self.scroll = (self.scroll + before - offset(self.scale)).max(0.);
+    }
+    /// Zooms and scrolls the timeline from the mouse wheel and touch gestures
+    ///
+    /// Pinching zooms around the pointer and two fingers pan the timeline along with them,
+    /// while a mouse wheel scrolls and zooms when turned sideways.
+    pub fn navigate(&mut self, i: &egui::InputState) {
+        let anchor = i.pointer.hover_pos().map(|pos| pos.x);
+        // Pinching a touchpad or touch screen, or the mouse wheel with ctrl held
+        let zoom = i.zoom_delta();
+        if zoom != 1. {
+            let scale = ((20. + 40. * self.scale) * zoom - 20.) / 40.;
+            self.zoom((scale - self.scale) * 100., anchor);
+        }
+        // Touchpads scroll in points and mouse wheels in lines, the scrolling carries on smoothly after the event
+        for event in &i.events {
+            if let egui::Event::MouseWheel { unit, .. } = event {
+                self.touchpad = *unit == egui::MouseWheelUnit::Point;
+            }
+        }
+        let mut pan = i
+            .multi_touch()
+            .map(|touch| touch.translation_delta.x)
+            .unwrap_or(0.);
+        if self.touchpad {
+            pan += i.smooth_scroll_delta.x;
+        } else {
+            self.zoom(i.smooth_scroll_delta.x, anchor);
+        }
+        self.scroll(i.smooth_scroll_delta.y);
+        if pan != 0. {
+            self.scroll = (self.scroll - pan / (20. + 40. * self.scale)).max(0.);
+        }
+    }
+    /// Zooms and scrolls so the whole sequence fills the visible timeline
+    pub fn zoom_to_fit(&mut self) {
+        let start = self.keyframes.iter().map(|k| k.timestamp).reduce(f32::min);
+        let end = self
+            .keyframes
+            .iter()
+            .map(|k| k.timestamp + k.duration)
+            .reduce(f32::max);
+        if let (Some(start), Some(end)) = (start, end) {
+            self.zoom_to_range(start, end);
+        }
+    }
+    /// Zooms and scrolls so the selected keyframes fill the visible timeline, or the whole sequence if none are selected
+    pub fn zoom_to_selection(&mut self) {
+        let selected = self
+            .keyframes
+            .iter()
+            .filter(|k| self.selected_keyframes.binary_search(&k.uid).is_ok());
+        let (start, end) = selected.fold((f32::INFINITY, f32::NEG_INFINITY), |(start, end), k| {
+            (start.min(k.timestamp), end.max(k.timestamp + k.duration))
+        });
+        if start <= end {
+            self.zoom_to_range(start, end);
+        } else {
+            self.zoom_to_fit();
+        }
+    }
+    /// Sets the zoom and scroll so the time from `start` to `end` seconds fills the visible timeline, with a little margin
+    fn zoom_to_range(&mut self, start: f32, end: f32) {
+        let span = (end - start).max(0.1) * 1.1;
+        // A second takes up 20 + 40 * scale points, see util::scale
+        let points_per_second = self.timeline_width / span;
+        self.scale = ((points_per_second - 20.) / 40.).clamp(0.01, 10.0);
+        self.scroll = ((start + end) / 2. - self.visible_duration() / 2.).max(0.);
+    }
+    /// Number of seconds that fit in the visible timeline at the current zoom
+    fn visible_duration(&self) -> f32 {
+        self.timeline_width / (20. + 40. * self.scale)
+    }
+    /// Scrolls through the keyframes
+    pub fn scroll(&mut self, delta: f32) {
+        let multiplier = 0.0125;
+        self.scroll = (self.scroll + (delta * multiplier) / self.scale.clamp(0.5, f32::INFINITY))
+            .clamp(0., f32::INFINITY);
     }
-    /// Scrolls through the keyframes
-    pub fn scroll(&mut self, delta: f32) {
-        let multiplier = 0.0125;
-        self.scroll = (self.scroll + (delta * multiplier) / self.scale.clamp(0.5, f32::INFINITY))
-            .clamp(0., f32::INFINITY);
-    }
     /// Enable all the selected keyframes
     pub fn enable_keyframes(&mut self, enabled: bool) {
         if !self.selected_keyframes.is_empty() {
-            // Find all selected keyframes (state of 2 == selected)
-            for i in 0..self.keyframe_state.len() {
-                if self.keyframe_state[i] == 2 {
+            // Find all selected keyframes
+            for i in 0..self.keyframes.len() {
+                if self.is_selected(i) {
                     self.keyframes[i].enabled = enabled;
                 }
             }
@@ -434,12 +1293,13 @@ impl Sequencer {
         if !self.selected_keyframes.is_empty() {
             self.clip_board.clear();
             let now = Instant::now();
-            // Find all selected keyframes (state of 2 == selected)
-            for i in 0..self.keyframe_state.len() {
-                if self.keyframe_state[i] == 2 {
+            // Find all selected keyframes
+            for i in 0..self.keyframes.len() {
+                if self.is_selected(i) {
                     self.clip_board.push(self.keyframes[i].clone());
                 }
             }
+            self.copy_to_os_clipboard();
             log::info!(
                 "Copied {} keyframes in {:?}",
                 self.clip_board.len(),
@@ -447,18 +1307,88 @@ impl Sequencer {
             );
         }
     }
+    /// Writes the clipboard and its screenshots to the system clipboard so other instances can paste them
+    fn copy_to_os_clipboard(&self) {
+        let images = self.images.lock().unwrap();
+        let data = ClipboardData {
+            keyframes: self.clip_board.clone(),
+            images: self
+                .clip_board
+                .iter()
+                .filter_map(|kf| {
+                    let png = file::encode_png(images.get(&kf.uid)?).ok()?;
+                    Some((kf.uid, png))
+                })
+                .collect(),
+        };
+        match bincode::serialize(&data) {
+            Ok(bytes) => {
+                let text = format!("{}{}", CLIPBOARD_PREFIX, STANDARD.encode(bytes));
+                if let Err(err) =
+                    arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text))
+                {
+                    log::warn!(
+                        "Failed to copy keyframes to the system clipboard: {:?}",
+                        err
+                    );
+                }
+            }
+            Err(err) => log::error!("Failed to serialize copied keyframes: {:?}", err),
+        }
+    }
+    /// Reads keyframes copied by any instance of Automate from the system clipboard
+    fn paste_from_os_clipboard(&self) -> Option<ClipboardData> {
+        let text = arboard::Clipboard::new()
+            .and_then(|mut clipboard| clipboard.get_text())
+            .ok()?;
+        let bytes = STANDARD.decode(text.strip_prefix(CLIPBOARD_PREFIX)?).ok()?;
+        bincode::deserialize(&bytes).ok()
+    }
+    /// Whether there are keyframes to paste, on the system clipboard or the clipboard of this instance
+    ///
+    /// Reading the system clipboard is slow, so its answer is kept for [`CLIPBOARD_CHECK_INTERVAL`]
+    pub fn can_paste(&mut self) -> bool {
+        if !self.clip_board.is_empty() {
+            return true;
+        }
+        match self.clipboard_check {
+            Some((checked, has_keyframes)) if checked.elapsed() < CLIPBOARD_CHECK_INTERVAL => {
+                has_keyframes
+            }
+            // Only the prefix is checked, decoding the keyframes is left for pasting them
+            _ => {
+                let has_keyframes = arboard::Clipboard::new()
+                    .and_then(|mut clipboard| clipboard.get_text())
+                    .is_ok_and(|text| text.starts_with(CLIPBOARD_PREFIX));
+                self.clipboard_check = Some((Instant::now(), has_keyframes));
+                has_keyframes
+            }
+        }
+    }
     ///Paste the clipboard
+    ///
+    /// Prefers keyframes on the system clipboard, falling back to the clipboard of this instance
     pub fn paste(&mut self) {
-        if !self.clip_board.is_empty() {
+        let (clip_board, clip_images): (Vec<Keyframe>, HashMap<Bytes, Vec<u8>>) =
+            match self.paste_from_os_clipboard() {
+                Some(data) => (
+                    data.keyframes,
+                    data.images
+                        .into_iter()
+                        .filter_map(|(uid, png)| Some((uid, file::decode_png(&png).ok()?)))
+                        .collect(),
+                ),
+                None => (self.clip_board.clone(), HashMap::new()),
+            };
+        if !clip_board.is_empty() {
             let mut images = self.images.lock().unwrap();
 
             // Selected keyframes will be reset and then filled with the new keyframes
             self.selected_keyframes.clear();
             // Used to update the state for new keyframes
 
-            let mut clip_board: Vec<Keyframe> = self
-                .clip_board
-                .clone()
+            let mut new_uids = HashMap::new();
+            let mut clip_board: Vec<Keyframe> = clip_board
                 .into_iter()
                 .map(|mut kf| {
                     // Shift them all forward slightly so its clear what has been copied
@@ -466,41 +1396,206 @@ impl Sequencer {
                     // Change the UIDs for the copied keyframes
                     let new_uid = Uuid::new_v4().to_bytes_le();
                     // Check if the keyframe had an image, clone it with the new UID if so
-                    if let Some(image) = images.get(&kf.uid).cloned() {
+                    if let Some(image) = clip_images
+                        .get(&kf.uid)
+                        .or_else(|| images.get(&kf.uid))
+                        .cloned()
+                    {
                         images.insert(new_uid, image);
                     }
                     // Update the UID so there are no duplicates
+                    new_uids.insert(kf.uid, new_uid);
                     kf.uid = new_uid;
 
                     // Use the new UUIDs as the currently selected keyframes
                     self.selected_keyframes.push(new_uid);
-                    self.keyframe_state.push(0);
                     kf
                 })
                 .collect();
+            remap_dependencies(&mut clip_board, &new_uids);
+            self.selected_keyframes.sort();
 
             self.changes.0.push(Change {
                 uids: vec![],
                 data: vec![ChangeData::AddKeyframes(clip_board.clone())],
             });
-            self.keyframes.append(&mut clip_board);
+            self.append_keyframes(&mut clip_board);
             // since the keyframes array has changed, it should be resorted
             self.should_sort = true;
         }
     }
+    /// Duplicate the selected keyframes directly after the end of the selection
+    ///
+    /// Screenshots are cloned along with the keyframes and the whole operation is a single undo step
+    pub fn duplicate(&mut self) {
+        let now = Instant::now();
+        // Find all selected keyframes
+        let selected: Vec<Keyframe> = (0..self.keyframes.len())
+            .filter(|i| self.is_selected(*i))
+            .map(|i| self.keyframes[i].clone())
+            .collect();
+        if selected.is_empty() {
+            return;
+        }
+        // Offset the duplicates by the length of the selection so they start where it ends
+        let start = selected
+            .iter()
+            .map(|kf| kf.timestamp)
+            .fold(f32::INFINITY, f32::min);
+        let end = selected
+            .iter()
+            .map(|kf| kf.timestamp + kf.duration)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let offset = end - start;
+
+        let mut images = self.images.lock().unwrap();
+        // Select the duplicates instead of the originals
+        self.selected_keyframes.clear();
+        let mut new_uids = HashMap::new();
+        let mut duplicates: Vec<Keyframe> = selected
+            .into_iter()
+            .map(|mut kf| {
+                kf.timestamp += offset;
+                let new_uid = Uuid::new_v4().to_bytes_le();
+                // Check if the keyframe had an image, clone it with the new UID if so
+                if let Some(image) = images.get(&kf.uid).cloned() {
+                    images.insert(new_uid, image);
+                }
+                new_uids.insert(kf.uid, new_uid);
+                kf.uid = new_uid;
+                self.selected_keyframes.push(new_uid);
+                kf
+            })
+            .collect();
+        remap_dependencies(&mut duplicates, &new_uids);
+        drop(images);
+        self.selected_keyframes.sort();
+
+        log::info!(
+            "Duplicated {} keyframes in {:?}",
+            duplicates.len(),
+            now.elapsed()
+        );
+        self.changes.0.push(Change {
+            uids: vec![],
+            data: vec![ChangeData::AddKeyframes(duplicates.clone())],
+        });
+        self.append_keyframes(&mut duplicates);
+        self.changed();
+    }
+    /// Collects the selected keyframes along with their screenshots and magic move targets, moved to start at 0
+    ///
+    /// Targets whose path uses environment variables or doesn't exist are left out, keeping their path as is
+    pub fn selection_snippet(&self) -> file::Snippet {
+        let mut snippet = file::Snippet::default();
+        let images = self.images.lock().unwrap();
+        for i in 0..self.keyframes.len() {
+            if !self.is_selected(i) {
+                continue;
+            }
+            let keyframe = &self.keyframes[i];
+            if let Some(image) = images.get(&keyframe.uid) {
+                snippet.images.insert(keyframe.uid, image.clone());
+            }
+            if let Some(path) = keyframe.keyframe_type.target() {
+                let target = Path::new(path);
+                match (target.file_name(), std::fs::read(target)) {
+                    (Some(name), Ok(image)) if !path.contains('$') => {
+                        let name = name.to_string_lossy().to_string();
+                        snippet.targets.insert(keyframe.uid, (name, image));
+                    }
+                    _ => log::warn!("Not including magic move target {:?} in the snippet", path),
+                }
+            }
+            snippet.keyframes.push(keyframe.clone());
+        }
+        let start = snippet
+            .keyframes
+            .iter()
+            .map(|kf| kf.timestamp)
+            .fold(f32::INFINITY, f32::min);
+        for keyframe in &mut snippet.keyframes {
+            keyframe.timestamp -= start;
+        }
+        snippet
+    }
+    /// Inserts the keyframes of a snippet at the playhead as a single undo step, selecting them
+    ///
+    /// Magic move targets are written to the targets folder of the loaded file, reusing identical images already there
+    pub fn insert_snippet(&mut self, snippet: file::Snippet) -> Result<(), String> {
+        if snippet.keyframes.is_empty() {
+            return Err("The snippet has no keyframes".to_string());
+        }
+        // Snippets without targets can go into a file that hasn't been saved yet
+        let directory = match snippet.targets.is_empty() {
+            true => None,
+            false => {
+                let directory = self.targets_directory()?;
+                std::fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+                Some(directory)
+            }
+        };
+        let time = self.get_time();
+        let mut images = self.images.lock().unwrap();
+        self.selected_keyframes.clear();
+        let mut inserted = Vec::with_capacity(snippet.keyframes.len());
+        let mut new_uids = HashMap::new();
+        for mut kf in snippet.keyframes {
+            let new_uid = Uuid::new_v4().to_bytes_le();
+            if let (Some(directory), Some((name, image))) =
+                (&directory, snippet.targets.get(&kf.uid))
+            {
+                // Only the file name is used so a snippet can't write outside the targets folder
+                let name = Path::new(name)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| format!("{}.png", Uuid::from_bytes_le(new_uid)));
+                let mut path = directory.join(&name);
+                if std::fs::read(&path).is_ok_and(|existing| existing != *image) {
+                    path = directory.join(format!("{}_{}", Uuid::from_bytes_le(new_uid), name));
+                }
+                if !path.exists() {
+                    std::fs::write(&path, image).map_err(|e| e.to_string())?;
+                }
+                if let Some(target) = kf.keyframe_type.target_mut() {
+                    *target = path.to_string_lossy().to_string();
+                }
+            }
+            if let Some(image) = snippet.images.get(&kf.uid) {
+                images.insert(new_uid, image.clone());
+            }
+            kf.timestamp += time;
+            new_uids.insert(kf.uid, new_uid);
+            kf.uid = new_uid;
+            self.selected_keyframes.push(new_uid);
+            inserted.push(kf);
+        }
+        remap_dependencies(&mut inserted, &new_uids);
+        drop(images);
+        self.selected_keyframes.sort();
+
+        log::info!("Inserted a snippet of {} keyframes", inserted.len());
+        self.changes.0.push(Change {
+            uids: vec![],
+            data: vec![ChangeData::AddKeyframes(inserted.clone())],
+        });
+        self.append_keyframes(&mut inserted);
+        self.changed();
+        Ok(())
+    }
     /// Copy the selected keyframes to clipboard and delete them from the keyframes vec
     pub fn cut(&mut self) {
         self.clip_board.clear();
         let now = Instant::now();
-        let mut undo_vec = Vec::new();
-        // Find all selected keyframes (state of 2 == selected)
-        for i in (0..self.keyframe_state.len()).rev() {
-            if self.keyframe_state[i] == 2 {
-                self.clip_board.push(self.keyframes[i].clone());
-                undo_vec.push(self.keyframes.remove(i));
-                self.keyframe_state.remove(i);
-            }
+        // Find all selected keyframes
+        let selected: Vec<usize> = (0..self.keyframes.len())
+            .rev()
+            .filter(|&i| self.is_selected(i))
+            .collect();
+        for &i in &selected {
+            self.clip_board.push(self.keyframes[i].clone());
         }
+        let undo_vec = self.remove_keyframes(&selected);
         log::info!(
             "Cut {} keyframes in {:?}",
             self.clip_board.len(),
@@ -508,6 +1603,7 @@ impl Sequencer {
         );
         // Since the clipboard starts empty, if it isn't now that means keyframes were copied and then removed
         if !self.clip_board.is_empty() {
+            self.copy_to_os_clipboard();
             self.changes.0.push(Change {
                 uids: vec![],
                 data: vec![ChangeData::RemoveKeyframes(undo_vec)],
@@ -522,7 +1618,7 @@ impl Sequencer {
         if !self.selected_keyframes.is_empty() {
             let now = Instant::now();
             let number_of_selected_keyframes = self.selected_keyframes.len();
-            let number_of_keyframes = self.keyframe_state.len();
+            let number_of_keyframes = self.keyframes.len();
             // Sort the selected list from least the greatest index
             self.selected_keyframes.sort();
             // self.selected_keyframes.reverse();
@@ -530,25 +1626,22 @@ impl Sequencer {
             let mut undo_vec = Vec::new();
             if number_of_keyframes == number_of_selected_keyframes {
                 undo_vec = self.keyframes.to_vec();
-                self.keyframes.clear();
-                self.keyframe_state.clear();
+                self.clear_keyframes();
                 self.selected_keyframes.clear();
             } else {
-                // Otherwise loop through keyframes and remove from last to first (avoids index out of bounds)
-                let mut last_index = 0;
-                for uid in &self.selected_keyframes {
-                    let mut index = 0;
-                    for i in 0..self.keyframes.len() {
-                        if self.keyframes[i].uid == *uid {
-                            index = i;
-                            break;
-                        }
-                    }
-                    undo_vec.push(self.keyframes.remove(index));
-                    self.keyframe_state.remove(index);
-                    self.images.lock().unwrap().remove(uid);
-                    last_index = index;
+                // Otherwise remove the selected keyframes together, so the rest are only reindexed once
+                let selected = std::mem::take(&mut self.selected_keyframes);
+                let indices: Vec<usize> = selected
+                    .iter()
+                    .filter_map(|uid| self.index_of(uid))
+                    .collect();
+                let mut last_index = indices.iter().copied().min().unwrap_or(0);
+                undo_vec = self.remove_keyframes(&indices);
+                let mut images = self.images.lock().unwrap();
+                for uid in &selected {
+                    images.remove(uid);
                 }
+                drop(images);
                 // If there are still keyframes left, we want to select the last one before the selection
                 if !self.keyframes.is_empty() {
                     // If the last keyframe before selection was the very last keyframe then we get the second last
@@ -580,72 +1673,95 @@ impl Sequencer {
                 match change {
                     // Perform the inverse of the operation since we are "undo"ing it
                     ChangeData::AddKeyframes(kfs) => {
-                        for kf in kfs {
-                            'outer: for i in (0..self.keyframes.len()).rev() {
-                                if kf.uid == self.keyframes[i].uid {
-                                    self.keyframes.remove(i);
-                                    self.keyframe_state.remove(i);
-                                    break 'outer;
-                                }
-                            }
-                        }
+                        let indices: Vec<usize> =
+                            kfs.iter().filter_map(|kf| self.index_of(&kf.uid)).collect();
+                        self.remove_keyframes(&indices);
                     }
                     ChangeData::RemoveKeyframes(kfs) => {
                         for kf in kfs {
-                            self.keyframes.push(kf.clone());
-                            self.keyframe_state.push(0);
+                            self.push_keyframe(kf.clone());
                         }
                     }
                     ChangeData::EditTimestamp(delta) => {
                         for uid in &changes.uids {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    self.keyframes[i].timestamp -= *delta;
-                                }
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].timestamp -= *delta;
                             }
                         }
                     }
                     ChangeData::EditDuration(delta) => {
                         for uid in &changes.uids {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    self.keyframes[i].duration -= *delta;
-                                }
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].duration -= *delta;
                             }
                         }
                     }
                     ChangeData::EditMagicFindPath(old, _new) => {
-                        if let Some(uid) = changes.uids.first() {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    if let KeyframeType::MagicMove(path) =
-                                        &mut self.keyframes[i].keyframe_type
-                                    {
-                                        *path = old.clone();
-                                    }
+                        for uid in &changes.uids {
+                            if let Some(i) = self.index_of(uid) {
+                                if let Some(path) = self.keyframes[i].keyframe_type.target_mut() {
+                                    *path = old.clone();
                                 }
                             }
                         }
                     }
+                    ChangeData::EditKeyframeType(old, _new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].keyframe_type = old.clone();
+                            }
+                        }
+                    }
+                    ChangeData::EditDependencies(edits) => {
+                        for (uid, old, _new) in edits {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].after = *old;
+                            }
+                        }
+                    }
+                    ChangeData::EditDelay(old, _new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].delay = *old;
+                            }
+                        }
+                    }
+                    ChangeData::EditKeyDelay(old, _new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].key_delay = *old;
+                            }
+                        }
+                    }
+                    ChangeData::EditNote(old, _new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].note = old.clone();
+                            }
+                        }
+                    }
+                    ChangeData::EditExpression(field, old, _new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                set_expression(&mut self.keyframes[i], field, old);
+                            }
+                        }
+                    }
                     ChangeData::EditMouseButton(old, _new) => {
                         if let Some(uid) = changes.uids.first() {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    if let KeyframeType::MouseBtn(btn) =
-                                        &mut self.keyframes[i].keyframe_type
-                                    {
-                                        *btn = old.clone();
-                                    }
+                            if let Some(i) = self.index_of(uid) {
+                                if let KeyframeType::MouseBtn(btn) =
+                                    &mut self.keyframes[i].keyframe_type
+                                {
+                                    *btn = old.clone();
                                 }
                             }
                         }
                     }
                     ChangeData::EnableKeyframes(enabled) => {
                         for uid in &changes.uids {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    self.keyframes[i].enabled = !*enabled;
-                                }
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].enabled = !*enabled;
                             }
                         }
                     }
@@ -670,71 +1786,94 @@ impl Sequencer {
                     // Perform the operation since we are "redo"ing it
                     ChangeData::AddKeyframes(kfs) => {
                         for kf in kfs {
-                            self.keyframes.push(kf.clone());
-                            self.keyframe_state.push(0);
+                            self.push_keyframe(kf.clone());
                         }
                     }
                     ChangeData::RemoveKeyframes(kfs) => {
-                        for kf in kfs {
-                            'outer: for i in (0..self.keyframes.len()).rev() {
-                                if kf.uid == self.keyframes[i].uid {
-                                    self.keyframes.remove(i);
-                                    self.keyframe_state.remove(i);
-                                    break 'outer;
-                                }
-                            }
-                        }
+                        let indices: Vec<usize> =
+                            kfs.iter().filter_map(|kf| self.index_of(&kf.uid)).collect();
+                        self.remove_keyframes(&indices);
                     }
                     ChangeData::EditTimestamp(delta) => {
                         for uid in &changes.uids {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    self.keyframes[i].timestamp += *delta;
-                                }
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].timestamp += *delta;
                             }
                         }
                     }
                     ChangeData::EditDuration(delta) => {
                         for uid in &changes.uids {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    self.keyframes[i].duration += *delta;
-                                }
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].duration += *delta;
                             }
                         }
                     }
                     ChangeData::EditMagicFindPath(_old, new) => {
-                        if let Some(uid) = changes.uids.first() {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    if let KeyframeType::MagicMove(path) =
-                                        &mut self.keyframes[i].keyframe_type
-                                    {
-                                        *path = new.clone();
-                                    }
+                        for uid in &changes.uids {
+                            if let Some(i) = self.index_of(uid) {
+                                if let Some(path) = self.keyframes[i].keyframe_type.target_mut() {
+                                    *path = new.clone();
                                 }
                             }
                         }
                     }
+                    ChangeData::EditKeyframeType(_old, new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].keyframe_type = new.clone();
+                            }
+                        }
+                    }
+                    ChangeData::EditDependencies(edits) => {
+                        for (uid, _old, new) in edits {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].after = *new;
+                            }
+                        }
+                    }
+                    ChangeData::EditDelay(_old, new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].delay = *new;
+                            }
+                        }
+                    }
+                    ChangeData::EditKeyDelay(_old, new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].key_delay = *new;
+                            }
+                        }
+                    }
+                    ChangeData::EditNote(_old, new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].note = new.clone();
+                            }
+                        }
+                    }
+                    ChangeData::EditExpression(field, _old, new) => {
+                        if let Some(uid) = changes.uids.first() {
+                            if let Some(i) = self.index_of(uid) {
+                                set_expression(&mut self.keyframes[i], field, new);
+                            }
+                        }
+                    }
                     ChangeData::EditMouseButton(_old, new) => {
                         if let Some(uid) = changes.uids.first() {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    if let KeyframeType::MouseBtn(btn) =
-                                        &mut self.keyframes[i].keyframe_type
-                                    {
-                                        *btn = new.clone();
-                                    }
+                            if let Some(i) = self.index_of(uid) {
+                                if let KeyframeType::MouseBtn(btn) =
+                                    &mut self.keyframes[i].keyframe_type
+                                {
+                                    *btn = new.clone();
                                 }
                             }
                         }
                     }
                     ChangeData::EnableKeyframes(enabled) => {
                         for uid in &changes.uids {
-                            for i in 0..self.keyframes.len() {
-                                if *uid == self.keyframes[i].uid {
-                                    self.keyframes[i].enabled = *enabled;
-                                }
+                            if let Some(i) = self.index_of(uid) {
+                                self.keyframes[i].enabled = *enabled;
                             }
                         }
                     }
@@ -757,6 +1896,105 @@ impl Sequencer {
         });
         self.selected_keyframes.sort();
     }
+    /// Selects every keyframe of the given kind, replacing the current selection
+    pub fn select_kind(&mut self, kind: u8) {
+        self.selected_keyframes = self
+            .keyframes
+            .iter()
+            .filter(|kf| kf.kind == kind)
+            .map(|kf| kf.uid)
+            .collect();
+        self.selected_keyframes.sort();
+    }
+    /// Selects every keyframe that isn't selected, and deselects the rest
+    pub fn invert_selection(&mut self) {
+        self.selected_keyframes = self
+            .keyframes
+            .iter()
+            .filter(|kf| self.selected_keyframes.binary_search(&kf.uid).is_err())
+            .map(|kf| kf.uid)
+            .collect();
+        self.selected_keyframes.sort();
+    }
+    /// Why the thread listening to global input isn't running, `None` while it is
+    pub fn record_thread_error(&self) -> Option<String> {
+        self.record_thread.error()
+    }
+    /// Starts listening to global input again after the record thread stopped
+    pub fn restart_record_thread(&mut self) {
+        self.record_thread.restart();
+    }
+    /// Turns the input sent by the record thread since the last call into keyframes of the recording
+    ///
+    /// Returns where the cursor was last reported while calibrating, if it was
+    pub fn receive_recorded_events(&mut self) -> Option<Vec2> {
+        let mut calibration = None;
+        while let Ok(event) = self.record_events.try_recv() {
+            let keyframe =
+                match event {
+                    RecordEvent::KeyPress { key, time, uid } => {
+                        let mut keyframe = Keyframe::key_btn(time, f32::NAN, key);
+                        keyframe.uid = uid;
+                        Some(keyframe)
+                    }
+                    RecordEvent::ButtonPress { button, time, uid } => {
+                        let mut keyframe = Keyframe::mouse_button(time, f32::NAN, button);
+                        keyframe.uid = uid;
+                        Some(keyframe)
+                    }
+                    // Releases complete the keyframe of the matching press
+                    RecordEvent::KeyRelease { key, time } => {
+                        if let Some(keyframe) = self.recording_keyframes.iter_mut().rev().find(
+                            |kf| matches!(kf.keyframe_type, KeyframeType::KeyBtn(k) if k == key),
+                        ) {
+                            keyframe.calculate_duration(time);
+                        }
+                        None
+                    }
+                    RecordEvent::ButtonRelease { button, time } => {
+                        if let Some(keyframe) = self.recording_keyframes.iter_mut().rev().find(
+                        |kf| matches!(kf.keyframe_type, KeyframeType::MouseBtn(b) if b == button),
+                    ) {
+                        keyframe.calculate_duration(time);
+                    }
+                        None
+                    }
+                    // Only every so many movements are kept, depending on the record resolution
+                    RecordEvent::MouseMove { pos, time } => {
+                        self.mouse_move_count -= 1;
+                        match self.previous_mouse_position != pos && self.mouse_move_count <= 0 {
+                            true => {
+                                self.previous_mouse_position = pos;
+                                self.mouse_move_count = 100
+                                    - self
+                                        .mouse_movement_record_resolution
+                                        .load(Ordering::Relaxed);
+                                let mut keyframe = Keyframe::mouse_move(time, pos);
+                                keyframe.monitor = MonitorAnchor::at(pos);
+                                Some(keyframe)
+                            }
+                            false => None,
+                        }
+                    }
+                    RecordEvent::ManualMouseMove { time } => {
+                        Some(Keyframe::mouse_move(time, self.previous_mouse_position))
+                    }
+                    RecordEvent::Scroll { delta, time } => match delta == Vec2::ZERO {
+                        true => None,
+                        false => Some(Keyframe::scroll(time, delta)),
+                    },
+                    RecordEvent::Calibration(pos) => {
+                        calibration = Some(pos);
+                        None
+                    }
+                };
+            if let Some(keyframe) = keyframe {
+                self.recording_keyframes.push(keyframe);
+                self.changed.swap(true, Ordering::Relaxed);
+            }
+        }
+        calibration
+    }
     /// Toggle whether the sequencer is recording keystrokes or not
     ///
     /// * When starting recording: If `clear_before_recording` is `true`, reset the sequencer and record from 0 seconds
@@ -769,12 +2007,15 @@ impl Sequencer {
 
         // Start recording
         if self.recording.load(Ordering::Relaxed) {
+            // Input from before recording started is left out
+            self.receive_recorded_events();
+            self.recording_keyframes.clear();
+            self.mouse_move_count = 20;
+            self.previous_mouse_position = Vec2::ZERO;
             let mut rec_instant = self.recording_instant.lock().unwrap();
-            self.recording_keyframes.lock().unwrap().clear();
             if self.clear_before_recording {
                 self.time = 0.;
-                self.keyframes.clear();
-                self.keyframe_state.clear();
+                self.clear_keyframes();
                 let _ = std::mem::replace(&mut *rec_instant, Instant::now());
             } else {
                 // Save the keframes before recording so we can revert to this later with undo
@@ -784,15 +2025,19 @@ impl Sequencer {
                 );
             }
             self.was_recording = true;
+            refresh_monitors();
             log::info!("Start Recording");
         // Stop Recording
         } else {
-            let mut recording_keyframes = self.recording_keyframes.lock().unwrap();
+            // Input sent before recording stopped still belongs to the recording
+            self.receive_recorded_events();
+            let mut recording_keyframes = std::mem::take(&mut self.recording_keyframes);
             let last = recording_keyframes.last();
             if let Some(last) = last {
                 if (last.timestamp + last.duration - self.time).abs() <= 0.04 {
+                    let toggle_recording = self.global_keys.lock().unwrap().toggle_recording;
                     let is_record_stop_keyframe = match last.keyframe_type {
-                        KeyframeType::KeyBtn(rdev::Key::F8) => true,
+                        KeyframeType::KeyBtn(key) => Some(key) == toggle_recording,
                         KeyframeType::MouseBtn(rdev::Button::Left) => true,
                         _ => false,
                     };
@@ -806,10 +2051,7 @@ impl Sequencer {
                     data: vec![ChangeData::AddKeyframes(recording_keyframes.clone())],
                 });
                 // Move the recorded keyframes to the main vec
-                self.keyframe_state
-                    .append(&mut vec![0; recording_keyframes.len()]);
-                self.keyframes.append(&mut recording_keyframes);
-                drop(recording_keyframes);
+                self.append_keyframes(&mut recording_keyframes);
             }
             if self.clear_before_recording {
                 self.time = 0.;
@@ -821,30 +2063,41 @@ impl Sequencer {
     /// Loops through all the sequencer's keyframes and renders them accordingly
     ///
     /// Also handles deleting keyframes due to convenience
-    fn render_keyframes(&mut self, ui: &mut Ui, max_rect: &Rect) {
+    fn render_keyframes(&mut self, ui: &mut Ui, max_rect: &Rect, tracks: &[Track]) {
+        let theme = theme();
         let mut keyframes = [
             self.keyframes.as_slice(),
-            self.recording_keyframes.lock().unwrap().to_vec().as_slice(),
+            self.recording_keyframes.as_slice(),
         ]
         .concat();
+        // Where each drawn keyframe ended up, to link keyframes that start after others
+        let mut rects: HashMap<Bytes, Rect> = HashMap::new();
 
         for i in 0..keyframes.len() {
-            let mut state = -1;
-            if let Some(s) = self.keyframe_state.get(i) {
-                state = *s as i32;
-            }
+            // Keyframes still being recorded come after the sequencer's own and are never highlighted
+            let state = match i < self.keyframes.len() {
+                true => self.state_of(i),
+                false => KeyframeState::Idle,
+            };
             let offset_y = ui.spacing().item_spacing.y;
-            // Determine which row to draw the keyframe on depending on its type
-            let y = match keyframes[i].kind {
-                1 => ROW_HEIGHT * 2. + 9.,       // Mouse move
-                6 => ROW_HEIGHT * 2. + 9.,       // Mouse move
-                2 => ROW_HEIGHT + offset_y * 2., // Mouse buttons
-                3 => ROW_HEIGHT + offset_y * 2., // Scroll
-                _ => offset_y,                   // 0,4,5 (keypress, wait, keystrokes)
+            // Determine which row to draw the keyframe on depending on its type, skipping hidden tracks
+            let row = match keyframes[i].is_group() {
+                true => 0,
+                false => match tracks
+                    .iter()
+                    .position(|t| *t == Track::of(keyframes[i].kind))
+                {
+                    Some(row) => row,
+                    None => continue,
+                },
             };
+            let y = ROW_HEIGHT * row as f32 + offset_y * (row + 1) as f32;
             // Determins the spacing for normal keyframes and also makes loop keyframes full height
-            let spacing = if keyframes[i].kind == 7 {
-                vec2(ui.spacing().item_spacing.x, ROW_HEIGHT * -1.)
+            let spacing = if keyframes[i].is_group() {
+                vec2(
+                    ui.spacing().item_spacing.x,
+                    ROW_HEIGHT * (tracks.len() as f32 - 1.) * -0.5,
+                )
             } else {
                 ui.spacing().item_spacing
             };
@@ -894,23 +2147,13 @@ impl Sequencer {
                 }
 
                 let color = if keyframes[i].enabled {
-                    match keyframes[i].kind {
-                        0 => egui::Color32::LIGHT_RED,               //Keyboard
-                        1 => egui::Color32::from_rgb(95, 186, 213),  //Mouse move
-                        2 => egui::Color32::LIGHT_GREEN,             //Button Click
-                        3 => egui::Color32::from_rgb(255, 234, 127), //Scroll
-                        4 => egui::Color32::BLACK,                   //Wait
-                        5 => egui::Color32::LIGHT_RED,               //Keyboard
-                        6 => egui::Color32::from_rgb(214, 180, 252), //Mouse move
-                        7 => egui::Color32::TRANSPARENT,             //Loop
-                        _ => egui::Color32::LIGHT_GRAY,
-                    }
+                    theme.keyframe_color(keyframes[i].kind)
                 } else {
                     egui::Color32::from_rgba_premultiplied(60, 60, 60, 80)
                 };
 
                 let stroke = match state {
-                    1 => {
+                    KeyframeState::Playing => {
                         if keyframes[i].enabled {
                             egui::Stroke::new(1.5, egui::Color32::from_rgb(255, 128, 128))
                         // Red
@@ -918,10 +2161,10 @@ impl Sequencer {
                             egui::Stroke::NONE
                         }
                     } //Playing
-                    2 => egui::Stroke::new(1.5, egui::Color32::from_rgb(233, 181, 125)), //Selected
-                    // Handle edge case for loop keyframes which should be transparent with white text and border
-                    _ => match keyframes[i].kind == 7 {
-                        true => egui::Stroke::new(1., egui::Color32::WHITE),
+                    KeyframeState::Selected => egui::Stroke::new(1.5, theme.selection_color()),
+                    // Handle edge case for loop keyframes which should be transparent with a border and text in the text color
+                    _ => match keyframes[i].is_group() {
+                        true => egui::Stroke::new(1., ui.visuals().strong_text_color()),
                         false => egui::Stroke::new(
                             0.4,
                             egui::Color32::from_rgba_premultiplied(15, 37, 42, 180),
@@ -929,14 +2172,44 @@ impl Sequencer {
                     }, //Not selected
                 };
 
-                if keyframes[i].kind == 7 {
-                    rect = rect.expand(2.);
+                if keyframes[i].is_group() {
+                    // Groups within other groups are drawn inset, so it is clear which group they belong to
+                    let (start, end) = (
+                        keyframes[i].timestamp,
+                        keyframes[i].timestamp + keyframes[i].duration,
+                    );
+                    let depth = keyframes
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, other)| {
+                            *j != i
+                                && other.is_group()
+                                && other.timestamp <= start
+                                && end <= other.timestamp + other.duration
+                        })
+                        .count();
+                    let inset = (4. * depth as f32).min(rect.height() * 0.25);
+                    rect = rect.expand(2.).shrink2(vec2(0., inset));
                 }
-                let keyframe = ui
-                    .allocate_rect(rect, egui::Sense::click_and_drag())
-                    .on_hover_text(format!("{:?}", keyframes[i].keyframe_type));
+                let keyframe = ui.allocate_rect(rect, egui::Sense::click_and_drag());
+                // Show the keyframe's screenshot alongside its type, to find the right moment without selecting it
+                let preview = match keyframe.hovered() {
+                    true => self.screenshot_texture(ui.ctx(), keyframes[i].uid),
+                    false => None,
+                };
+                let keyframe = keyframe.on_hover_ui(|ui| {
+                    ui.label(format!("{:?}", keyframes[i].keyframe_type));
+                    if let Some(texture) = &preview {
+                        ui.image((texture.id(), vec2(192., 108.)));
+                    }
+                    if !keyframes[i].note.is_empty() {
+                        ui.separator();
+                        render_note(ui, &keyframes[i].note);
+                    }
+                });
                 ui.painter()
                     .rect(rect, egui::Rounding::same(2.0), color, stroke);
+                rects.insert(keyframes[i].uid, rect);
 
                 // Checks if it is worth displaying a label for the keyframe based of its width
                 let label = format!(
@@ -950,21 +2223,95 @@ impl Sequencer {
                         KeyframeType::KeyStrokes(keys) => keys_to_string(keys),
                         KeyframeType::MagicMove(_path) => "🔮".to_string(),
                         KeyframeType::Loop(repeats, i) => format!("{i}/{repeats}"),
+                        KeyframeType::Text(text) => text.clone(),
+                        KeyframeType::Random(weights, picked) => match picked {
+                            Some(branch) => format!("🎲 {}/{}", branch + 1, weights.len()),
+                            None => format!("🎲 {}", weights.len()),
+                        },
+                        KeyframeType::Label(name) => format!("🏷 {}", name),
+                        KeyframeType::Jump(label, _, _) => format!("↪ {}", label),
+                        KeyframeType::Subroutine(name) => format!("ƒ {}", name),
+                        KeyframeType::Call(name) => format!("📞 {}", name),
+                        KeyframeType::Plugin(path, _) => format!(
+                            "🧩 {}",
+                            Path::new(path)
+                                .file_stem()
+                                .unwrap_or_default()
+                                .to_string_lossy()
+                        ),
+                        KeyframeType::Abort(_) => "⛔".to_string(),
+                        KeyframeType::Notify(text) => format!("🔔 {}", text),
+                        KeyframeType::Beep(_) => "🔊".to_string(),
+                        KeyframeType::Log(text) => format!("📝 {}", text),
+                        KeyframeType::Desktop(desktop) => format!("🖵 {}", desktop),
+                        KeyframeType::Hold(key) => format!("⏬ {}", key_to_char(key)),
+                        KeyframeType::Counter(name, CounterAction::Increment(step)) => {
+                            format!("#{} {:+}", name, step)
+                        }
+                        KeyframeType::Counter(name, CounterAction::Reset(value)) => {
+                            format!("#{} = {}", name, value)
+                        }
+                        KeyframeType::WhileImage(_, present, iterations) => format!(
+                            "⟳ {} 🔮 {}",
+                            if *present { "while" } else { "until" },
+                            iterations.map(|n| n.to_string()).unwrap_or_default()
+                        ),
+                        KeyframeType::If(_, present, _) => {
+                            format!("❓ if {}🔮", if *present { "" } else { "no " })
+                        }
                     }
                 );
+                // Divide random keyframes into their branches
+                if let KeyframeType::Random(weights, _) = &keyframes[i].keyframe_type {
+                    for branch in 1..weights.len() {
+                        let x = rect.min.x + rect.width() * branch as f32 / weights.len() as f32;
+                        ui.painter().vline(
+                            x,
+                            rect.y_range(),
+                            egui::Stroke::new(1., egui::Color32::from_white_alpha(80)),
+                        );
+                    }
+                }
+                // And if keyframes into their then and else halves
+                if let KeyframeType::If(_, _, _) = &keyframes[i].keyframe_type {
+                    ui.painter().vline(
+                        rect.center().x,
+                        rect.y_range(),
+                        egui::Stroke::new(1., egui::Color32::from_white_alpha(80)),
+                    );
+                    for (x, text) in [(rect.min.x, "then"), (rect.center().x, "else")] {
+                        ui.painter().text(
+                            pos2(x + 4., rect.min.y + 2.),
+                            Align2::LEFT_TOP,
+                            text,
+                            FontId::proportional(10.),
+                            ui.visuals().weak_text_color(),
+                        );
+                    }
+                }
                 if rect.width() > label.len() as f32 * 10. {
                     ui.painter().text(
                         rect.center(),
                         Align2::CENTER_CENTER,
                         format!("{}", label),
                         FontId::default(),
-                        // Handle edge case for loop keyframes which should be transparent with white text and border
-                        match keyframes[i].kind == 7 {
-                            true => egui::Color32::WHITE,
+                        // Handle edge case for loop keyframes which should be transparent with a border and text in the text color
+                        match keyframes[i].is_group() {
+                            true => ui.visuals().strong_text_color(),
                             false => egui::Color32::BLACK,
                         },
                     );
                 }
+                // Keyframes with a note are marked, the note is shown when hovering them
+                if !keyframes[i].note.is_empty() && rect.width() > 12. {
+                    ui.painter().text(
+                        rect.right_top() + vec2(-2., 1.),
+                        Align2::RIGHT_TOP,
+                        "📝",
+                        FontId::proportional(9.),
+                        ui.visuals().strong_text_color(),
+                    );
+                }
                 // Handles the user clicking a keyframe
                 if keyframe.clicked() {
                     // Check whether there was more than one keyframe selected before clearing the vec, (used for edge cases)
@@ -1039,9 +2386,9 @@ impl Sequencer {
                             (end.x - self.drag_start.x) * (1.0 / scale(ui, 1.0, self.scale));
                         let t = keyframes[i].timestamp + drag_delta;
                         if t > 0.0 {
-                            // Find all selected keyframes (state of 2 == selected)
-                            for j in 0..self.keyframe_state.len() {
-                                if self.keyframe_state[j] == 2 {
+                            // Find all selected keyframes
+                            for j in 0..self.keyframes.len() {
+                                if self.is_selected(j) {
                                     self.keyframes[j].timestamp += drag_delta;
                                 }
                             }
@@ -1065,6 +2412,10 @@ impl Sequencer {
                     // we need to update the keyframes vec to match the new order
                     self.changed();
                 }
+                // Handles on the edges of the keyframe for changing when it starts and how long it lasts
+                if i < self.keyframes.len() && rect.width() > 12. {
+                    self.render_resize_handles(ui, i, rect);
+                }
 
                 ui.input_mut(|input| {
                     if input.consume_key(egui::Modifiers::NONE, egui::Key::Delete) {
@@ -1089,9 +2440,118 @@ impl Sequencer {
                 });
             }
         }
+        // Link the end of each keyframe to the start of the keyframes waiting for it
+        for keyframe in &keyframes {
+            let Some(after) = keyframe.after else {
+                continue;
+            };
+            if let (Some(from), Some(to)) = (rects.get(&after.uid), rects.get(&keyframe.uid)) {
+                let (start, end) = (from.right_center(), to.left_center());
+                let stroke = egui::Stroke::new(1., theme.selection_color());
+                ui.painter().line_segment([start, end], stroke);
+                ui.painter().circle_filled(end, 2., stroke.color);
+            }
+        }
+    }
+    /// Renders and handles dragging the handles on the start and end edges of the keyframe at `i`
+    ///
+    /// Edges snap to the playhead and the edges of other keyframes, unless alt is held.
+    /// Wait keyframes wait for as long as they last
+    fn render_resize_handles(&mut self, ui: &mut Ui, i: usize, rect: Rect) {
+        const HANDLE_WIDTH: f32 = 4.;
+        // Distance in points within which an edge snaps
+        const SNAP_DISTANCE: f32 = 6.;
+        const MIN_DURATION: f32 = 0.01;
+        let uid = self.keyframes[i].uid;
+        for start_edge in [true, false] {
+            let handle_rect = if start_edge {
+                Rect::from_min_max(rect.min, pos2(rect.min.x + HANDLE_WIDTH, rect.max.y))
+            } else {
+                Rect::from_min_max(pos2(rect.max.x - HANDLE_WIDTH, rect.min.y), rect.max)
+            };
+            let handle = ui.interact(
+                handle_rect,
+                ui.id().with(("resize", uid, start_edge)),
+                egui::Sense::drag(),
+            );
+            if handle.hovered() || handle.dragged() {
+                ui.output_mut(|o| o.cursor_icon = egui::CursorIcon::ResizeHorizontal);
+            }
+            if handle.drag_started() {
+                if let Some(start) = handle.interact_pointer_pos() {
+                    self.resizing = true;
+                    self.drag_start = start;
+                    // Using total_drag_start to remember the keyframe's timestamp and duration before resizing
+                    self.total_drag_start =
+                        pos2(self.keyframes[i].timestamp, self.keyframes[i].duration);
+                }
+            }
+            if !self.resizing {
+                continue;
+            }
+            let (start, duration) = (self.total_drag_start.x, self.total_drag_start.y);
+            if handle.dragged() {
+                if let Some(pos) = handle.interact_pointer_pos() {
+                    let points_per_second = scale(ui, 1.0, self.scale);
+                    let delta = (pos.x - self.drag_start.x) / points_per_second;
+                    let edge = if start_edge {
+                        start + delta
+                    } else {
+                        start + duration + delta
+                    };
+                    let edge = match ui.input(|i| i.modifiers.alt) {
+                        true => edge,
+                        false => self.snap_time(edge, i, SNAP_DISTANCE / points_per_second),
+                    };
+                    let keyframe = &mut self.keyframes[i];
+                    if start_edge {
+                        keyframe.timestamp = edge.clamp(0., start + duration - MIN_DURATION);
+                        keyframe.duration = start + duration - keyframe.timestamp;
+                    } else {
+                        keyframe.duration = (edge - start).max(MIN_DURATION);
+                    }
+                    self.changed.swap(true, Ordering::Relaxed);
+                }
+            }
+            if handle.drag_stopped() {
+                self.resizing = false;
+                let keyframe = &mut self.keyframes[i];
+                let mut data = vec![
+                    ChangeData::EditTimestamp(keyframe.timestamp - start),
+                    ChangeData::EditDuration(keyframe.duration - duration),
+                ];
+                if let KeyframeType::Wait(secs) = &mut keyframe.keyframe_type {
+                    let old_secs = *secs;
+                    *secs = keyframe.duration;
+                    data.push(ChangeData::EditKeyframeType(
+                        KeyframeType::Wait(old_secs),
+                        KeyframeType::Wait(*secs),
+                    ));
+                }
+                self.changes.0.push(Change {
+                    uids: vec![uid],
+                    data,
+                });
+                self.changed();
+            }
+        }
+    }
+    /// Returns the playhead time or the start or end of a keyframe other than the one at `except`,
+    /// whichever is closest to `time` and within `distance` seconds of it, otherwise `time`
+    fn snap_time(&self, time: f32, except: usize, distance: f32) -> f32 {
+        self.keyframes
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != except)
+            .flat_map(|(_, keyframe)| [keyframe.timestamp, keyframe.timestamp + keyframe.duration])
+            .chain([self.time])
+            .filter(|t| (t - time).abs() <= distance)
+            .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()))
+            .unwrap_or(time)
     }
     /// Handles rendering the control bar
-    fn render_control_bar(&mut self, ui: &mut Ui) {
+    fn render_control_bar(&mut self, ui: &mut Ui, settings: &Settings) {
+        let record_shortcut = settings.shortcut_text(ui.ctx(), KeybindType::ToggleRecording);
         if ui.button("⏪").on_hover_text("Restart").clicked() {
             self.reset_time();
         }
@@ -1138,14 +2598,23 @@ impl Sequencer {
         )
         .on_hover_text("Time");
 
-        let (r, s) = (self.repeats, self.speed);
-        ui.add(
+        let (r, l, q, s) = (self.repeats, self.loop_forever, self.sequential, self.speed);
+        ui.add_enabled(
+            !self.loop_forever,
             egui::DragValue::new(&mut self.repeats)
                 .speed(1)
                 .range(1..=10000),
         )
         .on_hover_text("Number of repeats");
-        ui.add(
+        ui.toggle_value(&mut self.loop_forever, "∞").on_hover_text(
+            "Loop forever, until stopped with the panic key, Escape or the fail safe",
+        );
+        ui.toggle_value(&mut self.sequential, "⏭").on_hover_text(
+            "Sequential: play each keyframe as soon as the one before it is done instead of at its timestamp, \
+            waiting for the targets of magic moves to appear",
+        );
+        ui.add_enabled(
+            !self.sequential,
             egui::DragValue::new(&mut self.speed)
                 .speed(1)
                 .suffix("x")
@@ -1153,20 +2622,38 @@ impl Sequencer {
         )
         .on_hover_text("Playback Speed");
         // Check if repeats or speed has changed,
-        if (r, s) != (self.repeats, self.speed) {
+        if (r, l, q, s) != (self.repeats, self.loop_forever, self.sequential, self.speed) {
             // This is important as if a change is not detected, they cannot be saved properly leading to
             // frustrating inconsistences as both are saved per file
             self.changed.swap(true, Ordering::Relaxed);
         }
 
+        ui.toggle_value(&mut self.follow_playhead, "⇥")
+            .on_hover_text("Follow the playhead during playback");
+        ui.toggle_value(&mut self.confirm_steps, "👣")
+            .on_hover_text(format!(
+                "Step Confirm: wait for {} before playing each keyframe",
+                settings.shortcut_text(ui.ctx(), KeybindType::ConfirmStep)
+            ));
+        if self.awaiting_step.is_some() && ui.button("✔").on_hover_text("Confirm Step").clicked()
+        {
+            self.step_confirmed.store(true, Ordering::Relaxed);
+        }
+        ui.toggle_value(&mut self.dry_run, "👻")
+            .on_hover_text("Dry Run: play without simulating any input, showing the cursor and keys over the screenshots instead");
+
         if self.recording.load(Ordering::Relaxed) {
-            if ui.button("⏹").on_hover_text("Stop Recording: F8").clicked() {
+            if ui
+                .button("⏹")
+                .on_hover_text(format!("Stop Recording: {}", record_shortcut))
+                .clicked()
+            {
                 self.toggle_recording();
             }
         } else {
             if ui
                 .button(egui::RichText::new("⏺").color(egui::Color32::LIGHT_RED))
-                .on_hover_text("Start Recording: F8")
+                .on_hover_text(format!("Start Recording: {}", record_shortcut))
                 .clicked()
             {
                 self.toggle_recording();
@@ -1273,22 +2760,54 @@ impl Sequencer {
         );
         painter.line_segment([p1, p2], egui::Stroke::new(1.0, egui::Color32::LIGHT_RED));
     }
-    /// Render the whole sequencer ui
-    ///
-    /// Handles the controlbar, timeline, playhead and keyframes
-    pub fn show(&mut self, ctx: &egui::Context) {
-        egui::TopBottomPanel::bottom("Sequencer").show(ctx, |ui| {
-            use egui_extras::{Column, TableBuilder};
-
-            let mut keyframe_clip_rect = ui
+    /// Render a dashed line where the Add Keyframe window will insert keyframes
+    fn render_insert_marker(&self, ui: &Ui, time: f32, rows: i32, rect: Rect) {
+        let Some(point) = time_to_rect(
+            scale(ui, time - self.scroll, self.scale) + 3.,
+            0.0,
+            0.0,
+            ui.spacing().item_spacing,
+            rect,
+        ) else {
+            return;
+        };
+        let p1 = pos2(point.min.x + 1., point.min.y - 2.);
+        let p2 = pos2(p1.x, p1.y + ROW_HEIGHT * rows as f32 + (3 * rows) as f32);
+        let painter = ui.painter().with_clip_rect(rect.expand2(vec2(0., 4.0)));
+        painter.text(
+            p1 - vec2(0.0, 3.0),
+            Align2::CENTER_TOP,
+            "+",
+            FontId::monospace(10.0),
+            egui::Color32::LIGHT_GREEN,
+        );
+        painter.add(egui::Shape::dashed_line(
+            &[p1, p2],
+            egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN),
+            4.,
+            3.,
+        ));
+    }
+    /// Render the whole sequencer ui
+    ///
+    /// Handles the controlbar, timeline, playhead and keyframes
+    pub fn show(&mut self, ctx: &egui::Context, settings: &Settings) {
+        let tracks = settings.visible_tracks();
+        egui::TopBottomPanel::bottom("Sequencer").show(ctx, |ui| {
+            use egui_extras::{Column, TableBuilder};
+
+            let mut keyframe_clip_rect = ui
                 .max_rect()
                 .translate(vec2(6.5, 0.))
                 .translate(vec2(0., (ROW_HEIGHT + ui.spacing().item_spacing.y) * 2.));
 
-            keyframe_clip_rect.max.y =
-                keyframe_clip_rect.min.y + (ROW_HEIGHT) * 3. + ui.spacing().item_spacing.y;
+            keyframe_clip_rect.max.y = keyframe_clip_rect.min.y
+                + ROW_HEIGHT * tracks.len() as f32
+                + ui.spacing().item_spacing.y;
             // Shift the clipping rect over to exclude the first column
             keyframe_clip_rect.min.x += 60.;
+            self.timeline_width = keyframe_clip_rect.width();
+            self.timeline_left = keyframe_clip_rect.min.x;
 
             TableBuilder::new(ui)
                 .striped(false)
@@ -1303,7 +2822,7 @@ impl Sequencer {
                         ui.strong("Inputs");
                     });
                     header.col(|ui| {
-                        self.render_control_bar(ui);
+                        self.render_control_bar(ui, settings);
                     });
                 })
                 .body(|mut body| {
@@ -1321,30 +2840,16 @@ impl Sequencer {
                             );
                         });
                     });
-                    body.row(ROW_HEIGHT, |mut row| {
-                        row.col(|ui| {
-                            ui.label("Keyboard").on_hover_text("id: 0");
-                        });
-                        row.col(|ui| {
-                            self.sense(ui);
-                        });
-                    });
-                    body.row(ROW_HEIGHT, |mut row| {
-                        row.col(|ui| {
-                            ui.label("Mouse").on_hover_text("id: 2,3");
-                        });
-                        row.col(|ui| {
-                            self.sense(ui);
-                        });
-                    });
-                    body.row(ROW_HEIGHT, |mut row| {
-                        row.col(|ui| {
-                            ui.label("Movement").on_hover_text("id: 1");
-                        });
-                        row.col(|ui| {
-                            self.sense(ui);
+                    for track in &tracks {
+                        body.row(ROW_HEIGHT, |mut row| {
+                            row.col(|ui| {
+                                ui.label(track.name()).on_hover_text(track.hint());
+                            });
+                            row.col(|ui| {
+                                self.sense(ui, settings, *track);
+                            });
                         });
-                    });
+                    }
                     body.row(ROW_HEIGHT, |mut row| {
                         row.col(|_| {});
                         row.col(|ui| {
@@ -1371,7 +2876,9 @@ impl Sequencer {
                         });
                     });
                 });
-            self.render_keyframes(ui, &keyframe_clip_rect);
+            let render_start = Instant::now();
+            self.render_keyframes(ui, &keyframe_clip_rect, &tracks);
+            Profile::average(&mut self.profile.render_time, render_start.elapsed());
             if self.selecting {
                 ui.painter().rect(
                     self.compute_selection_rect(&keyframe_clip_rect),
@@ -1381,8 +2888,106 @@ impl Sequencer {
                 );
             }
 
-            self.render_playhead(ui, 3, keyframe_clip_rect);
+            if let Some(time) = self.insert_marker {
+                self.render_insert_marker(ui, time, tracks.len() as i32, keyframe_clip_rect);
+            }
+            self.render_playhead(ui, tracks.len() as i32, keyframe_clip_rect);
         });
+        self.render_quick_add(ctx);
+    }
+    /// Render the popup opened by double clicking a track, closing it once a keyframe is added or it is clicked away from
+    fn render_quick_add(&mut self, ctx: &egui::Context) {
+        let Some(quick_add) = &mut self.quick_add else {
+            return;
+        };
+        let time = quick_add.time;
+        let mut added = None;
+        let popup = egui::Area::new(egui::Id::new("Quick Add"))
+            .order(egui::Order::Foreground)
+            .fixed_pos(quick_add.pos)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.strong(format!("Add at {:.2}s", time));
+                    match quick_add.track {
+                        Track::Keyboard => {
+                            let resp = ui.add(
+                                egui::TextEdit::singleline(&mut quick_add.text)
+                                    .hint_text("Key or text")
+                                    .desired_width(100.),
+                            );
+                            if !quick_add.shown {
+                                resp.request_focus();
+                            }
+                            let key = string_to_keys(&quick_add.text);
+                            let text = quick_add.text.clone();
+                            let enter =
+                                resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .add_enabled(key.is_some(), egui::Button::new("Key"))
+                                    .clicked()
+                                    || (enter && key.is_some())
+                                {
+                                    added = key.map(|key| Keyframe::key_btn(time, 0.1, key));
+                                } else if ui
+                                    .add_enabled(!text.is_empty(), egui::Button::new("Text"))
+                                    .clicked()
+                                    || (enter && !text.is_empty())
+                                {
+                                    added = Some(Keyframe {
+                                        timestamp: time,
+                                        duration: 0.1,
+                                        keyframe_type: KeyframeType::Text(text),
+                                        kind: 8,
+                                        ..Default::default()
+                                    });
+                                }
+                                if ui.button("Wait").clicked() {
+                                    added = Some(Keyframe {
+                                        timestamp: time,
+                                        duration: 1.,
+                                        keyframe_type: KeyframeType::Wait(1.),
+                                        kind: 4,
+                                        ..Default::default()
+                                    });
+                                }
+                            });
+                        }
+                        Track::Mouse => {
+                            ui.horizontal(|ui| {
+                                for (button, name) in [
+                                    (rdev::Button::Left, "Left"),
+                                    (rdev::Button::Right, "Right"),
+                                    (rdev::Button::Middle, "Middle"),
+                                ] {
+                                    if ui.button(name).clicked() {
+                                        added = Some(Keyframe::mouse_button(time, 0.1, button));
+                                    }
+                                }
+                            });
+                        }
+                        Track::Movement => {
+                            ui.horizontal(|ui| {
+                                ui.add(egui::DragValue::new(&mut quick_add.target.x).prefix("x: "));
+                                ui.add(egui::DragValue::new(&mut quick_add.target.y).prefix("y: "));
+                                if ui.button("Move").clicked() {
+                                    added = Some(Keyframe::mouse_move(time, quick_add.target));
+                                }
+                            });
+                        }
+                    }
+                });
+            })
+            .response;
+        let dismissed = (quick_add.shown && popup.clicked_elsewhere())
+            || ctx.input(|i| i.key_pressed(egui::Key::Escape));
+        quick_add.shown = true;
+        if let Some(keyframe) = added {
+            self.quick_add = None;
+            self.add_keyframe(&keyframe);
+        } else if dismissed {
+            self.quick_add = None;
+        }
     }
     /// Render the scroll bar
     /// Gives a view of how scrolled in the sequencer is
@@ -1421,24 +3026,262 @@ impl Sequencer {
                 ));
                 ui.checkbox(&mut self.clear_before_recording, "Overwrite Recording");
                 ui.checkbox(&mut settings.retake_screenshots, "Retake screenshots");
+                ui.horizontal(|ui| match self.record_thread.error() {
+                    None => {
+                        ui.label("Record Thread: Running");
+                    }
+                    Some(err) => {
+                        ui.label("Record Thread: Stopped").on_hover_text(err);
+                        if ui.small_button("Restart").clicked() {
+                            self.record_thread.restart();
+                        }
+                    }
+                });
+                ui.separator();
+                ui.strong("Profiling");
+                ui.label(format!(
+                    "Frame Time: {:.1}ms",
+                    self.profile.frame_time * 1000.
+                ));
+                ui.label(format!(
+                    "Keyframes: {:.2}ms",
+                    self.profile.render_time * 1000.
+                ))
+                .on_hover_text("Time spent drawing the keyframes of the timeline");
+                ui.label(format!(
+                    "Jitter: {:.1}ms (max {:.1}ms)",
+                    self.profile.jitter * 1000.,
+                    self.profile.max_jitter * 1000.
+                ))
+                .on_hover_text("How late keyframes started playing after their timestamp");
+                let screenshot_bytes: usize =
+                    self.images.lock().unwrap().values().map(Vec::len).sum();
+                ui.label(format!(
+                    "Screenshots: {:.1}MB",
+                    screenshot_bytes as f32 / 1_000_000.
+                ));
+                ui.label(format!("Textures: {}", self.texture_handles.len()));
+                ui.separator();
+                // Variables that keyframe expressions can refer to, saved with the file
+                ui.strong("Variables")
+                    .on_hover_text("Used by keyframe expressions, along with the loop counter i");
+                let mut remove = None;
+                let mut edited = false;
+                for (index, (name, value)) in self.variables.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        edited |= ui
+                            .add(
+                                egui::TextEdit::singleline(name)
+                                    .hint_text("name")
+                                    .desired_width(60.),
+                            )
+                            .changed();
+                        edited |= ui
+                            .add(
+                                egui::TextEdit::singleline(value)
+                                    .hint_text("value")
+                                    .desired_width(80.),
+                            )
+                            .changed();
+                        if ui.small_button("🗑").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = remove {
+                    self.variables.remove(index);
+                    edited = true;
+                }
+                if ui.button("Add Variable").clicked() {
+                    self.variables.push((String::new(), String::new()));
+                }
+                // Variables asked for each time playback starts
+                ui.strong("Inputs")
+                    .on_hover_text("Variables whose values are asked for when playback starts");
+                let mut remove = None;
+                for (index, input) in self.inputs.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        edited |= ui
+                            .add(
+                                egui::TextEdit::singleline(&mut input.name)
+                                    .hint_text("name")
+                                    .desired_width(60.),
+                            )
+                            .changed();
+                        egui::ComboBox::from_id_source(("input_kind", index))
+                            .width(70.)
+                            .selected_text(input.kind.name())
+                            .show_ui(ui, |ui| {
+                                for kind in InputKind::ALL {
+                                    edited |= ui
+                                        .selectable_value(&mut input.kind, kind, kind.name())
+                                        .changed();
+                                }
+                            });
+                        if ui.small_button("🗑").clicked() {
+                            remove = Some(index);
+                        }
+                    });
+                }
+                if let Some(index) = remove {
+                    self.inputs.remove(index);
+                    edited = true;
+                }
+                if ui.button("Add Input").clicked() {
+                    self.inputs.push(Input {
+                        name: String::new(),
+                        kind: InputKind::Text,
+                        value: String::new(),
+                    });
+                    edited = true;
+                }
+                // Plays the sequence once per row, with the columns of the row as variables
+                ui.strong("Data Source").on_hover_text(
+                    "Plays once per row of a CSV or TSV file, using its columns as variables",
+                );
+                ui.horizontal(|ui| {
+                    match &self.data_source {
+                        Some(path) => {
+                            ui.label(path.file_name().unwrap_or_default().to_string_lossy())
+                                .on_hover_text(path.to_string_lossy());
+                            if ui.small_button("🗑").clicked() {
+                                self.data_source = None;
+                                edited = true;
+                            }
+                        }
+                        None => {
+                            ui.label("None");
+                        }
+                    }
+                    if ui.button("Open").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Data", &["csv", "tsv"])
+                            .pick_file()
+                        {
+                            self.data_source = Some(path);
+                            edited = true;
+                        }
+                    }
+                });
+                if self.is_playing() && !self.data_rows.is_empty() {
+                    ui.label(format!(
+                        "Row {}/{}",
+                        self.engine.row + 1,
+                        self.data_rows.len()
+                    ));
+                }
+                // Settings that depend on the machine the sequence was made for
+                ui.strong("Playback Settings")
+                    .on_hover_text("Saved with the file and used instead of the global settings");
+                let mut overrides = self.playback_overrides;
+                let mut overriding = overrides.is_some();
+                if ui
+                    .checkbox(&mut overriding, "Override for this file")
+                    .changed()
+                {
+                    overrides = match overriding {
+                        true => Some(self.playback_settings(settings)),
+                        false => None,
+                    };
+                }
+                if let Some(playback) = &mut overrides {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut playback.fail_detection, "Fail detection");
+                        ui.add(
+                            egui::DragValue::new(&mut playback.max_fail_error)
+                                .custom_formatter(|n, _| format!("{}%", n))
+                                .speed(1)
+                                .range(0..=100),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Offset");
+                        ui.add(egui::DragValue::new(&mut playback.offset.x).speed(1))
+                            .on_hover_text("X");
+                        ui.add(egui::DragValue::new(&mut playback.offset.y).speed(1))
+                            .on_hover_text("Y");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Fail safe");
+                        egui::ComboBox::from_id_source("file_failsafe_edge")
+                            .selected_text(playback.failsafe_edge.name())
+                            .show_ui(ui, |ui| {
+                                for edge in MonitorEdge::ALL {
+                                    ui.selectable_value(
+                                        &mut playback.failsafe_edge,
+                                        edge,
+                                        edge.name(),
+                                    );
+                                }
+                            });
+                    });
+                }
+                if overrides != self.playback_overrides {
+                    self.set_playback_overrides(overrides);
+                    edited = true;
+                }
+                if edited {
+                    self.changed.swap(true, Ordering::Relaxed);
+                }
+                if !self.engine.counters.is_empty() {
+                    ui.separator();
+                    ui.strong("Counters");
+                    let mut counters: Vec<_> = self.engine.counters.iter().collect();
+                    counters.sort();
+                    for (name, value) in counters {
+                        ui.monospace(format!("{} = {}", name, value));
+                    }
+                }
+                if !self.run_log.is_empty() {
+                    ui.separator();
+                    ui.strong("Run Log");
+                    egui::ScrollArea::vertical()
+                        .id_source("run_log")
+                        .max_height(120.)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for line in &self.run_log {
+                                ui.monospace(line);
+                            }
+                        });
+                }
             });
     }
     /// Renders the editable data of the selected keyframe
     pub fn selected_panel(&mut self, ctx: &egui::Context, settings: &Settings) {
+        let offset = self.playback_settings(settings).offset;
         egui::SidePanel::left("Selected Keyframe")
             .min_width(155.0)
             .max_width(155.0)
             .resizable(false)
             .show(ctx, |ui| {
                 if let Some(uid) = self.selected_keyframes.last() {
-                    let mut index = 0;
-                    for i in 0..self.keyframes.len() {
-                        if self.keyframes[i].uid == *uid {
-                            index = i;
-                            break;
-                        }
-                    }
+                    let index = self.index_of(uid).unwrap_or(0);
+                    // Names of the labels that jump keyframes can jump to
+                    let labels: Vec<String> = self
+                        .keyframes
+                        .iter()
+                        .filter_map(|keyframe| match &keyframe.keyframe_type {
+                            KeyframeType::Label(name) => Some(name.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    // Names of the subroutines that call keyframes can call
+                    let subroutines: Vec<String> = self
+                        .keyframes
+                        .iter()
+                        .filter_map(|keyframe| match &keyframe.keyframe_type {
+                            KeyframeType::Subroutine(name) => Some(name.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    // The keyframe this one starts after, as it is shown in the problems window
+                    let after_target = self.keyframes[index]
+                        .after
+                        .and_then(|after| self.index_of(&after.uid))
+                        .map(|i| format!("{} #{}", kind_name(self.keyframes[i].kind), i));
                     let keyframe = &mut self.keyframes[index];
+                    let text_field = text_field(&keyframe.keyframe_type);
                     let mut changed = false;
 
                     match &mut keyframe.keyframe_type {
@@ -1470,8 +3313,13 @@ impl Sequencer {
                         KeyframeType::MouseMove(pos) => {
                             ui.strong("Mouse move");
                             ui.label(format!("position: {:?}", pos));
+                            if let Some(monitor) = &keyframe.monitor {
+                                ui.label(format!("monitor: {}", monitor.name));
+                            }
                             if ui.button("Go").clicked() {
-                                simulate_move(pos, &settings.offset);
+                                if let Err(err) = simulate_move(pos, &offset) {
+                                    log::error!("Failed to move to {:?} - {}", pos, err);
+                                }
                             }
                         }
                         KeyframeType::Scroll(delta) => {
@@ -1486,6 +3334,11 @@ impl Sequencer {
                             ui.strong("Key Strokes");
                             ui.label(keys_to_string(keys));
                         }
+                        KeyframeType::Hold(key) => {
+                            ui.strong("Hold Key");
+                            ui.label(format!("{:?}", key));
+                            ui.small("Held down for the whole keyframe");
+                        }
                         KeyframeType::MagicMove(path) => {
                             ui.strong("Magic!!");
                             // ui.text_edit_singleline(path);
@@ -1516,51 +3369,551 @@ impl Sequencer {
                                 }
                             });
                         }
+                        KeyframeType::WhileImage(path, present, iterations) => {
+                            ui.strong("While Image");
+                            ui.horizontal(|ui| {
+                                ui.set_max_width(100.);
+                                ui.label(
+                                    Path::new(path)
+                                        .file_name()
+                                        .map(|name| name.to_string_lossy().to_string())
+                                        .unwrap_or_default(),
+                                )
+                                .on_hover_text(path.as_str());
+                                if ui.button("Open").clicked() {
+                                    if let Some(p) = rfd::FileDialog::new()
+                                        .add_filter("Images", &["png"])
+                                        .pick_file()
+                                    {
+                                        let p = p.to_string_lossy().to_string();
+                                        self.changes.0.push(Change {
+                                            uids: vec![keyframe.uid],
+                                            data: vec![ChangeData::EditMagicFindPath(
+                                                path.clone(),
+                                                p.clone(),
+                                            )],
+                                        });
+                                        changed = true;
+                                        *path = p;
+                                    }
+                                }
+                            });
+                            let old_present = *present;
+                            ui.horizontal(|ui| {
+                                ui.selectable_value(present, true, "While found");
+                                ui.selectable_value(present, false, "Until found");
+                            })
+                            .response
+                            .on_hover_text("Checked at the start of each iteration");
+                            if *present != old_present {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::WhileImage(path.clone(), old_present, None),
+                                        KeyframeType::WhileImage(path.clone(), *present, None),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                            if let Some(iterations) = iterations {
+                                ui.label(format!("i: {iterations}"));
+                            }
+                        }
+                        KeyframeType::If(path, present, picked) => {
+                            ui.strong("If Image");
+                            ui.horizontal(|ui| {
+                                ui.set_max_width(100.);
+                                ui.label(
+                                    Path::new(path)
+                                        .file_name()
+                                        .map(|name| name.to_string_lossy().to_string())
+                                        .unwrap_or_default(),
+                                )
+                                .on_hover_text(path.as_str());
+                                if ui.button("Open").clicked() {
+                                    if let Some(p) = rfd::FileDialog::new()
+                                        .add_filter("Images", &["png"])
+                                        .pick_file()
+                                    {
+                                        let p = p.to_string_lossy().to_string();
+                                        self.changes.0.push(Change {
+                                            uids: vec![keyframe.uid],
+                                            data: vec![ChangeData::EditMagicFindPath(
+                                                path.clone(),
+                                                p.clone(),
+                                            )],
+                                        });
+                                        changed = true;
+                                        *path = p;
+                                    }
+                                }
+                            });
+                            let old_present = *present;
+                            ui.horizontal(|ui| {
+                                ui.selectable_value(present, true, "If found");
+                                ui.selectable_value(present, false, "If not found");
+                            })
+                            .response
+                            .on_hover_text("The first half plays when this holds, the second half when it doesn't");
+                            if *present != old_present {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::If(path.clone(), old_present, None),
+                                        KeyframeType::If(path.clone(), *present, None),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                            if let Some(branch) = picked {
+                                ui.label(if *branch == 0 { "Playing: then" } else { "Playing: else" });
+                            }
+                        }
+                        KeyframeType::Beep(path) => {
+                            ui.strong("Beep");
+                            ui.horizontal(|ui| {
+                                ui.set_max_width(100.);
+                                ui.label(match Path::new(path).file_name() {
+                                    Some(name) => name.to_string_lossy().to_string(),
+                                    None => "Tone".to_string(),
+                                });
+                                if ui.button("Open").clicked() {
+                                    if let Some(p) = rfd::FileDialog::new()
+                                        .add_filter("Sounds", &["wav", "mp3", "ogg", "flac"])
+                                        .pick_file()
+                                    {
+                                        let p = p.to_string_lossy().to_string();
+                                        self.changes.0.push(Change {
+                                            uids: vec![keyframe.uid],
+                                            data: vec![ChangeData::EditKeyframeType(
+                                                KeyframeType::Beep(path.clone()),
+                                                KeyframeType::Beep(p.clone()),
+                                            )],
+                                        });
+                                        changed = true;
+                                        *path = p;
+                                    }
+                                }
+                            });
+                            if !path.is_empty() && ui.button("Use Tone").clicked() {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::Beep(std::mem::take(path)),
+                                        KeyframeType::Beep(String::new()),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                            if ui.button("Test").clicked() {
+                                play_sound(path);
+                            }
+                        }
+                        KeyframeType::Plugin(path, argument) => {
+                            ui.strong("Plugin");
+                            ui.horizontal(|ui| {
+                                ui.set_max_width(100.);
+                                ui.label(
+                                    Path::new(path)
+                                        .file_name()
+                                        .unwrap_or_default()
+                                        .to_string_lossy(),
+                                )
+                                .on_hover_text(path.as_str());
+                                if ui.button("Open").clicked() {
+                                    if let Some(p) = rfd::FileDialog::new()
+                                        .add_filter("WASM Plugins", &["wasm"])
+                                        .pick_file()
+                                    {
+                                        let p = p.to_string_lossy().to_string();
+                                        self.changes.0.push(Change {
+                                            uids: vec![keyframe.uid],
+                                            data: vec![ChangeData::EditKeyframeType(
+                                                KeyframeType::Plugin(path.clone(), argument.clone()),
+                                                KeyframeType::Plugin(p.clone(), argument.clone()),
+                                            )],
+                                        });
+                                        changed = true;
+                                        *path = p;
+                                    }
+                                }
+                            });
+                            ui.label("Argument");
+                            let old_argument = argument.clone();
+                            let resp = ui
+                                .text_edit_singleline(argument)
+                                .on_hover_text("Passed to the plugin, {expression} is replaced by its value");
+                            if resp.gained_focus() {
+                                self.text_edit_start = old_argument;
+                            }
+                            // Record a single change per edit rather than one per character
+                            if resp.lost_focus() && self.text_edit_start != *argument {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::Plugin(
+                                            path.clone(),
+                                            std::mem::take(&mut self.text_edit_start),
+                                        ),
+                                        KeyframeType::Plugin(path.clone(), argument.clone()),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        }
+                        KeyframeType::Desktop(desktop) => {
+                            ui.strong("Switch Desktop");
+                            let old_desktop = *desktop;
+                            let resp = ui
+                                .horizontal(|ui| {
+                                    ui.label("Desktop");
+                                    ui.add(egui::DragValue::new(desktop).range(1..=20))
+                                })
+                                .inner;
+                            if resp.drag_started() || resp.gained_focus() {
+                                self.type_edit_start = Some(KeyframeType::Desktop(old_desktop));
+                            }
+                            if resp.drag_stopped() || resp.lost_focus() {
+                                if let Some(old_type) = self.type_edit_start.take() {
+                                    if !matches!(old_type, KeyframeType::Desktop(d) if d == *desktop) {
+                                        self.changes.0.push(Change {
+                                            uids: vec![keyframe.uid],
+                                            data: vec![ChangeData::EditKeyframeType(
+                                                old_type,
+                                                KeyframeType::Desktop(*desktop),
+                                            )],
+                                        });
+                                        changed = true;
+                                    }
+                                }
+                            }
+                            if ui.button("Go").clicked() {
+                                if let Err(err) = switch_desktop(*desktop) {
+                                    log::error!("Failed to switch to desktop {} - {}", desktop, err);
+                                }
+                            }
+                        }
                         KeyframeType::Loop(repeats, i) => {
                             ui.strong("Loop");
                             ui.label("Repeats");
                             ui.add(egui::DragValue::new(repeats).speed(1).range(0..=100));
                             ui.label(format!("i: {i}"));
                         }
-                    }
-                    // Used later to check if the keyframe was edited
-                    let (tmpx, tmpy) = (keyframe.timestamp, keyframe.duration);
-
-                    // Edit keyframe.timestamp
-                    ui.horizontal(|ui| {
-                        ui.label("Timestamp");
-                        let resp = ui.add(
-                            egui::DragValue::new(&mut keyframe.timestamp)
-                                .speed(0.2)
-                                .range(0.0..=3600.0),
-                        );
-                        if resp.drag_started() {
-                            // Using total_drag_start since the y is never needed anywhere else, and since
-                            // there is only one mouse to drag things, it is only in use for one thing at a time.
-                            self.total_drag_start.y = keyframe.timestamp;
-                        }
-                        if resp.drag_stopped() {
-                            self.changes.0.push(Change {
-                                uids: vec![keyframe.uid],
-                                data: vec![ChangeData::EditTimestamp(
-                                    keyframe.timestamp - self.total_drag_start.y,
-                                )],
+                        KeyframeType::Random(weights, _) => {
+                            ui.strong("Random");
+                            ui.label("Branch weights").on_hover_text(
+                                "Each pass plays one branch, picked in proportion to its weight",
+                            );
+                            let old_type = KeyframeType::Random(weights.clone(), None);
+                            let mut edited = false;
+                            for (branch, weight) in weights.iter_mut().enumerate() {
+                                ui.horizontal(|ui| {
+                                    ui.label(format!("{}", branch + 1));
+                                    let resp = ui.add(
+                                        egui::DragValue::new(weight).speed(0.1).range(0.0..=100.0),
+                                    );
+                                    if resp.drag_started() {
+                                        self.type_edit_start = Some(old_type.clone());
+                                    }
+                                    if resp.drag_stopped() {
+                                        edited = true;
+                                    }
+                                });
+                            }
+                            ui.horizontal(|ui| {
+                                if ui.button("+").on_hover_text("Add a branch").clicked() {
+                                    self.type_edit_start = Some(old_type.clone());
+                                    weights.push(1.);
+                                    edited = true;
+                                }
+                                if weights.len() > 1
+                                    && ui.button("-").on_hover_text("Remove the last branch").clicked()
+                                {
+                                    self.type_edit_start = Some(old_type.clone());
+                                    weights.pop();
+                                    edited = true;
+                                }
                             });
+                            if edited {
+                                if let Some(old_type) = self.type_edit_start.take() {
+                                    self.changes.0.push(Change {
+                                        uids: vec![keyframe.uid],
+                                        data: vec![ChangeData::EditKeyframeType(
+                                            old_type,
+                                            KeyframeType::Random(weights.clone(), None),
+                                        )],
+                                    });
+                                    changed = true;
+                                }
+                            }
                         }
-                    });
-
-                    // Edit keyframe.duration
-                    ui.horizontal(|ui| {
-                        ui.label("Duration");
-                        let resp = ui.add(
-                            egui::DragValue::new(&mut keyframe.duration)
-                                .speed(0.1)
-                                .range(0.00001..=100.0),
-                        );
-                        if resp.drag_started() {
-                            // Using total_drag_start since the y is never needed anywhere else, and since
-                            // there is only one mouse to drag things, it is only in use for one thing at a time.
-                            self.total_drag_start.y = keyframe.duration;
+                        KeyframeType::Label(name) => {
+                            ui.strong("Label");
+                            let old_name = name.clone();
+                            let resp = ui.text_edit_singleline(name);
+                            if resp.gained_focus() {
+                                self.text_edit_start = old_name;
+                            }
+                            // Record a single change per edit rather than one per character
+                            if resp.lost_focus() && self.text_edit_start != *name {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::Label(std::mem::take(
+                                            &mut self.text_edit_start,
+                                        )),
+                                        KeyframeType::Label(name.clone()),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        }
+                        KeyframeType::Subroutine(name) => {
+                            ui.strong("Subroutine");
+                            let old_name = name.clone();
+                            let resp = ui.text_edit_singleline(name);
+                            if resp.gained_focus() {
+                                self.text_edit_start = old_name;
+                            }
+                            // Record a single change per edit rather than one per character
+                            if resp.lost_focus() && self.text_edit_start != *name {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::Subroutine(std::mem::take(
+                                            &mut self.text_edit_start,
+                                        )),
+                                        KeyframeType::Subroutine(name.clone()),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        }
+                        KeyframeType::Call(name) => {
+                            ui.strong("Call");
+                            let old_name = name.clone();
+                            egui::ComboBox::from_label("")
+                                .selected_text(name.as_str())
+                                .show_ui(ui, |ui| {
+                                    for subroutine in &subroutines {
+                                        ui.selectable_value(name, subroutine.clone(), subroutine);
+                                    }
+                                });
+                            if *name != old_name {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        KeyframeType::Call(old_name),
+                                        KeyframeType::Call(name.clone()),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        }
+                        KeyframeType::Jump(label, max_jumps, jumps) => {
+                            ui.strong("Jump");
+                            let (old_label, old_max_jumps) = (label.clone(), *max_jumps);
+                            egui::ComboBox::from_label("")
+                                .selected_text(label.as_str())
+                                .show_ui(ui, |ui| {
+                                    for name in &labels {
+                                        ui.selectable_value(label, name.clone(), name);
+                                    }
+                                });
+                            let resp = ui
+                                .horizontal(|ui| {
+                                    ui.label("Max jumps");
+                                    ui.add(egui::DragValue::new(max_jumps).range(0..=10000))
+                                })
+                                .inner
+                                .on_hover_text("Stops jumping after this many jumps, so it can't loop forever");
+                            ui.label(format!("jumps: {jumps}"));
+                            if resp.drag_started() {
+                                self.type_edit_start =
+                                    Some(KeyframeType::Jump(old_label.clone(), old_max_jumps, 0));
+                            }
+                            let old_type = if *label != old_label {
+                                Some(KeyframeType::Jump(old_label, old_max_jumps, 0))
+                            } else if resp.drag_stopped() {
+                                self.type_edit_start.take()
+                            } else {
+                                None
+                            };
+                            if let Some(old_type) = old_type {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        old_type,
+                                        KeyframeType::Jump(label.clone(), *max_jumps, 0),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        }
+                        KeyframeType::Counter(name, action) => {
+                            ui.strong("Counter");
+                            let old_type = KeyframeType::Counter(name.clone(), *action);
+                            let name_resp = ui
+                                .horizontal(|ui| {
+                                    ui.label("Name");
+                                    ui.text_edit_singleline(name)
+                                })
+                                .inner
+                                .on_hover_text(
+                                    "Used like a variable in expressions and text, e.g. report {name}.pdf",
+                                );
+                            let (mut reset, mut value) = match *action {
+                                CounterAction::Increment(step) => (false, step),
+                                CounterAction::Reset(value) => (true, value),
+                            };
+                            let mode_changed = ui
+                                .horizontal(|ui| {
+                                    ui.selectable_value(&mut reset, false, "Increment").changed()
+                                        | ui.selectable_value(&mut reset, true, "Reset").changed()
+                                })
+                                .inner;
+                            let value_resp = ui
+                                .horizontal(|ui| {
+                                    ui.label(if reset { "Value" } else { "Step" });
+                                    ui.add(egui::DragValue::new(&mut value).speed(0.1))
+                                })
+                                .inner;
+                            *action = match reset {
+                                true => CounterAction::Reset(value),
+                                false => CounterAction::Increment(value),
+                            };
+                            if name_resp.gained_focus()
+                                || value_resp.drag_started()
+                                || value_resp.gained_focus()
+                            {
+                                self.type_edit_start = Some(old_type.clone());
+                            }
+                            let old_type = if mode_changed {
+                                Some(old_type)
+                            } else if name_resp.lost_focus()
+                                || value_resp.drag_stopped()
+                                || value_resp.lost_focus()
+                            {
+                                self.type_edit_start.take()
+                            } else {
+                                None
+                            };
+                            if let Some(old_type) = old_type {
+                                // Record a single change per edit rather than one per character or step
+                                if !matches!(&old_type, KeyframeType::Counter(n, a) if n == name && a == action)
+                                {
+                                    self.changes.0.push(Change {
+                                        uids: vec![keyframe.uid],
+                                        data: vec![ChangeData::EditKeyframeType(
+                                            old_type,
+                                            KeyframeType::Counter(name.clone(), *action),
+                                        )],
+                                    });
+                                    changed = true;
+                                }
+                            }
+                        }
+                        KeyframeType::Text(text)
+                        | KeyframeType::Abort(text)
+                        | KeyframeType::Notify(text)
+                        | KeyframeType::Log(text) => {
+                            let (title, hint, variant) = text_field.unwrap();
+                            ui.strong(title);
+                            let old_text = text.clone();
+                            let resp = ui
+                                .add(egui::TextEdit::multiline(text).desired_rows(2))
+                                .on_hover_text(hint);
+                            if resp.gained_focus() {
+                                self.text_edit_start = old_text;
+                            }
+                            // Record a single change per edit rather than one per character
+                            if resp.lost_focus() && self.text_edit_start != *text {
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditKeyframeType(
+                                        variant(std::mem::take(&mut self.text_edit_start)),
+                                        variant(text.clone()),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        }
+                    }
+                    // Expressions evaluated in place of the recorded values during playback
+                    let fields = expression_fields(&keyframe.keyframe_type);
+                    if !fields.is_empty() {
+                        ui.label("Expressions").on_hover_text(
+                            "Evaluated during playback instead of the recorded value, e.g. 300 + i * 24",
+                        );
+                        for field in fields {
+                            ui.horizontal(|ui| {
+                                ui.label(*field);
+                                let mut expression =
+                                    keyframe.expressions.get(*field).cloned().unwrap_or_default();
+                                let resp = ui.add(
+                                    egui::TextEdit::singleline(&mut expression)
+                                        .hint_text("recorded")
+                                        .desired_width(f32::INFINITY),
+                                );
+                                if resp.gained_focus() {
+                                    self.text_edit_start = expression.clone();
+                                }
+                                if resp.changed() {
+                                    set_expression(keyframe, field, &expression);
+                                }
+                                // Record a single change per edit rather than one per character
+                                if resp.lost_focus() && self.text_edit_start != expression {
+                                    self.changes.0.push(Change {
+                                        uids: vec![keyframe.uid],
+                                        data: vec![ChangeData::EditExpression(
+                                            field.to_string(),
+                                            std::mem::take(&mut self.text_edit_start),
+                                            expression,
+                                        )],
+                                    });
+                                    changed = true;
+                                }
+                            });
+                        }
+                    }
+                    // Used later to check if the keyframe was edited
+                    let (tmpx, tmpy) = (keyframe.timestamp, keyframe.duration);
+
+                    // Edit keyframe.timestamp
+                    ui.horizontal(|ui| {
+                        ui.label("Timestamp");
+                        let resp = ui.add(
+                            egui::DragValue::new(&mut keyframe.timestamp)
+                                .speed(0.2)
+                                .range(0.0..=3600.0),
+                        );
+                        if resp.drag_started() {
+                            // Using total_drag_start since the y is never needed anywhere else, and since
+                            // there is only one mouse to drag things, it is only in use for one thing at a time.
+                            self.total_drag_start.y = keyframe.timestamp;
+                        }
+                        if resp.drag_stopped() {
+                            self.changes.0.push(Change {
+                                uids: vec![keyframe.uid],
+                                data: vec![ChangeData::EditTimestamp(
+                                    keyframe.timestamp - self.total_drag_start.y,
+                                )],
+                            });
+                        }
+                    });
+
+                    // Edit keyframe.duration
+                    ui.horizontal(|ui| {
+                        ui.label("Duration");
+                        let resp = ui.add(
+                            egui::DragValue::new(&mut keyframe.duration)
+                                .speed(0.1)
+                                .range(0.00001..=100.0),
+                        );
+                        if resp.drag_started() {
+                            // Using total_drag_start since the y is never needed anywhere else, and since
+                            // there is only one mouse to drag things, it is only in use for one thing at a time.
+                            self.total_drag_start.y = keyframe.duration;
                         }
                         if resp.drag_stopped() {
                             self.changes.0.push(Change {
@@ -1571,6 +3924,174 @@ impl Sequencer {
                             });
                         }
                     });
+                    // Edit keyframe.key_delay, the wait between typed keys
+                    if matches!(
+                        keyframe.keyframe_type,
+                        KeyframeType::KeyStrokes(_) | KeyframeType::Text(_)
+                    ) {
+                        let mut key_delay =
+                            keyframe.key_delay.unwrap_or(DEFAULT_KEY_DELAY) * 1000.;
+                        let resp = ui
+                            .horizontal(|ui| {
+                                ui.label("Key delay");
+                                ui.add(
+                                    egui::DragValue::new(&mut key_delay)
+                                        .speed(0.5)
+                                        .range(0.0..=1000.0)
+                                        .suffix("ms"),
+                                )
+                            })
+                            .inner
+                            .on_hover_text("Time between each key, for applications that drop keys typed too quickly");
+                        if resp.drag_started() || resp.gained_focus() {
+                            self.total_drag_start.x =
+                                keyframe.key_delay.unwrap_or(DEFAULT_KEY_DELAY);
+                        }
+                        if resp.changed() {
+                            keyframe.key_delay = Some(key_delay / 1000.);
+                        }
+                        if (resp.drag_stopped() || resp.lost_focus())
+                            && keyframe.key_delay != Some(self.total_drag_start.x)
+                        {
+                            self.changes.0.push(Change {
+                                uids: vec![keyframe.uid],
+                                data: vec![ChangeData::EditKeyDelay(
+                                    Some(self.total_drag_start.x),
+                                    keyframe.key_delay,
+                                )],
+                            });
+                            changed = true;
+                        }
+                    }
+                    // Edit keyframe.delay, a random wait before the keyframe plays
+                    let old_delay = keyframe.delay;
+                    let mut random_delay = keyframe.delay.is_some();
+                    if ui
+                        .checkbox(&mut random_delay, "Random delay")
+                        .on_hover_text("Wait a random time in a range before playing the keyframe")
+                        .changed()
+                    {
+                        keyframe.delay = random_delay.then_some((0., 1.));
+                        self.changes.0.push(Change {
+                            uids: vec![keyframe.uid],
+                            data: vec![ChangeData::EditDelay(old_delay, keyframe.delay)],
+                        });
+                        changed = true;
+                    }
+                    if let Some((min, max)) = &mut keyframe.delay {
+                        ui.horizontal(|ui| {
+                            let min_resp = ui.add(
+                                egui::DragValue::new(min)
+                                    .speed(0.05)
+                                    .range(0.0..=MAX_DELAY)
+                                    .suffix("s"),
+                            );
+                            ui.label("to");
+                            let max_resp = ui.add(
+                                egui::DragValue::new(max)
+                                    .speed(0.05)
+                                    .range(0.0..=MAX_DELAY)
+                                    .suffix("s"),
+                            );
+                            if min_resp.drag_started() || max_resp.drag_started() {
+                                // Using total_drag_start since there is only one mouse to drag things
+                                self.total_drag_start = pos2(*min, *max);
+                            }
+                            if min_resp.drag_stopped() || max_resp.drag_stopped() {
+                                *max = max.max(*min);
+                                self.changes.0.push(Change {
+                                    uids: vec![keyframe.uid],
+                                    data: vec![ChangeData::EditDelay(
+                                        Some((self.total_drag_start.x, self.total_drag_start.y)),
+                                        Some((*min, *max)),
+                                    )],
+                                });
+                                changed = true;
+                            }
+                        });
+                    }
+                    // Edit keyframe.after, the keyframe this one starts after
+                    if let Some(after) = keyframe.after {
+                        let mut delay = after.delay;
+                        let mut unchain = false;
+                        ui.horizontal(|ui| {
+                            ui.label("After").on_hover_text(match &after_target {
+                                Some(target) => format!("Starts this long after {} ends", target),
+                                None => "The keyframe it starts after no longer exists".to_string(),
+                            });
+                            let delay_resp = ui.add(
+                                egui::DragValue::new(&mut delay)
+                                    .speed(0.05)
+                                    .range(-60.0..=3600.0)
+                                    .suffix("s"),
+                            );
+                            if delay_resp.drag_started() {
+                                self.total_drag_start.x = after.delay;
+                            }
+                            if delay_resp.changed() {
+                                // Move along with the delay while it is being dragged
+                                self.should_sort = true;
+                            }
+                            if delay_resp.drag_stopped() {
+                                let old = Dependency {
+                                    delay: self.total_drag_start.x,
+                                    ..after
+                                };
+                                self.changes.0.push(Change {
+                                    uids: vec![],
+                                    data: vec![ChangeData::EditDependencies(vec![(
+                                        keyframe.uid,
+                                        Some(old),
+                                        Some(Dependency { delay, ..after }),
+                                    )])],
+                                });
+                                changed = true;
+                            }
+                            unchain = ui
+                                .small_button("✖")
+                                .on_hover_text("Start at a fixed time again")
+                                .clicked();
+                        });
+                        keyframe.after = Some(Dependency { delay, ..after });
+                        if unchain {
+                            keyframe.after = None;
+                            self.changes.0.push(Change {
+                                uids: vec![],
+                                data: vec![ChangeData::EditDependencies(vec![(
+                                    keyframe.uid,
+                                    Some(after),
+                                    None,
+                                )])],
+                            });
+                            changed = true;
+                        }
+                    }
+                    // Edit keyframe.note, recording a single change per edit rather than one per character
+                    let note_resp = ui
+                        .collapsing("Note", |ui| {
+                            ui.add(
+                                egui::TextEdit::multiline(&mut keyframe.note)
+                                    .hint_text("# Heading\n- **bold** point")
+                                    .desired_rows(3)
+                                    .desired_width(f32::INFINITY),
+                            )
+                        })
+                        .body_returned;
+                    if let Some(resp) = note_resp {
+                        if resp.gained_focus() {
+                            self.text_edit_start = keyframe.note.clone();
+                        }
+                        if resp.lost_focus() && self.text_edit_start != keyframe.note {
+                            self.changes.0.push(Change {
+                                uids: vec![keyframe.uid],
+                                data: vec![ChangeData::EditNote(
+                                    std::mem::take(&mut self.text_edit_start),
+                                    keyframe.note.clone(),
+                                )],
+                            });
+                            changed = true;
+                        }
+                    }
                     // Keyframe.uid
                     ui.small(format!(
                         "UID: {}",
@@ -1591,14 +4112,188 @@ impl Sequencer {
     pub fn central_panel(&self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
             egui_extras::install_image_loaders(ctx);
+            // Notes of the keyframes being played, or of the selected keyframe, to review the sequence step by step
+            let mut notes: Vec<&Keyframe> = self
+                .keyframes
+                .iter()
+                .filter(|keyframe| self.engine.playing.contains(&keyframe.uid))
+                .collect();
+            if notes.is_empty() {
+                notes.extend(
+                    self.selected_keyframes
+                        .last()
+                        .and_then(|uid| self.index_of(uid))
+                        .map(|i| &self.keyframes[i]),
+                );
+            }
+            notes.retain(|keyframe| !keyframe.note.is_empty());
+            let mut notes_pos = ui.max_rect().left_bottom();
             ui.vertical_centered_justified(|ui| {
                 if let Some(texture) = &self.current_image {
                     let size = Vec2::new(ui.available_height() * (16. / 9.), ui.available_height());
-                    ui.image((texture.id(), size));
+                    let image = ui.image((texture.id(), size));
+                    if self.show_mouse_trail {
+                        let points: Vec<Pos2> = self
+                            .mouse_trail()
+                            .into_iter()
+                            .map(|pos| screen_to_rect(pos, image.rect))
+                            .collect();
+                        draw_trail(&ui.painter_at(image.rect), &points);
+                    }
+                    if self.dry_run {
+                        self.ghost.draw(ui, image.rect);
+                    }
+                    notes_pos = image.rect.left_bottom();
                 }
             });
+            if !notes.is_empty() {
+                egui::Area::new(egui::Id::new("Keyframe Notes"))
+                    .order(egui::Order::Foreground)
+                    .fixed_pos(notes_pos + vec2(8., -8.))
+                    .pivot(Align2::LEFT_BOTTOM)
+                    .interactable(false)
+                    .show(ctx, |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.set_max_width(320.);
+                            for (j, keyframe) in notes.into_iter().enumerate() {
+                                if j > 0 {
+                                    ui.separator();
+                                }
+                                render_note(ui, &keyframe.note);
+                            }
+                        });
+                    });
+            }
         });
     }
+    /// Returns the texture of the keyframe's screenshot, loading it if it hasn't been yet
+    ///
+    /// `None` if the keyframe has no screenshot
+    fn screenshot_texture(&mut self, ctx: &egui::Context, uid: Bytes) -> Option<TextureHandle> {
+        // Check if the texture already exists
+        if let Some(texture_handle) = self.texture_handles.get(&uid) {
+            return Some(texture_handle.clone());
+        }
+        // Otherwise load it
+        let name = Uuid::from_bytes_le(uid).to_string();
+        let images = self.images.lock().unwrap();
+        let screenshot = images.get(&uid)?;
+        let image = ColorImage::from_rgba_unmultiplied(
+            screenshot_dimensions(screenshot),
+            screenshot.as_slice(),
+        );
+        let texture_handle = ctx.load_texture(name, image, Default::default());
+        self.texture_handles.insert(uid, texture_handle.clone());
+        Some(texture_handle)
+    }
+    /// Rebuilds the UID lookup used by [`Sequencer::index_of`]
+    fn reindex(&mut self) {
+        self.uid_index = self
+            .keyframes
+            .iter()
+            .enumerate()
+            .map(|(i, kf)| (kf.uid, i))
+            .collect();
+    }
+    /// Updates the index of the keyframes from `start` on, after they have moved
+    fn reindex_from(&mut self, start: usize) {
+        for (i, kf) in self.keyframes.iter().enumerate().skip(start) {
+            self.uid_index.insert(kf.uid, i);
+        }
+    }
+    /// Adds a keyframe to the end of the keyframes
+    pub fn push_keyframe(&mut self, keyframe: Keyframe) {
+        self.uid_index.insert(keyframe.uid, self.keyframes.len());
+        self.keyframes.push(keyframe);
+    }
+    /// Moves the given keyframes to the end of the keyframes, leaving `keyframes` empty
+    fn append_keyframes(&mut self, keyframes: &mut Vec<Keyframe>) {
+        let start = self.keyframes.len();
+        self.keyframes.append(keyframes);
+        self.reindex_from(start);
+    }
+    /// Inserts a keyframe at `index`, shifting the ones after it along
+    fn insert_keyframe(&mut self, index: usize, keyframe: Keyframe) {
+        self.keyframes.insert(index, keyframe);
+        self.reindex_from(index);
+    }
+    /// Removes the keyframes at `indices`, shifting the ones after them back, and returns them from last to first
+    ///
+    /// Removing them together means the rest are only reindexed once however many are removed
+    fn remove_keyframes(&mut self, indices: &[usize]) -> Vec<Keyframe> {
+        let mut remove = vec![false; self.keyframes.len()];
+        for &i in indices {
+            remove[i] = true;
+        }
+        let Some(first) = remove.iter().position(|&remove| remove) else {
+            return Vec::new();
+        };
+        let mut removed = Vec::with_capacity(indices.len());
+        for (i, keyframe) in std::mem::take(&mut self.keyframes).into_iter().enumerate() {
+            if remove[i] {
+                self.uid_index.remove(&keyframe.uid);
+                removed.push(keyframe);
+            } else {
+                self.keyframes.push(keyframe);
+            }
+        }
+        self.reindex_from(first);
+        removed.reverse();
+        removed
+    }
+    /// Puts `keyframe` in place of the keyframe at `index`, returning the one it replaced
+    fn replace_keyframe(&mut self, index: usize, keyframe: Keyframe) -> Keyframe {
+        self.uid_index.insert(keyframe.uid, index);
+        let replaced = std::mem::replace(&mut self.keyframes[index], keyframe);
+        if replaced.uid != self.keyframes[index].uid {
+            self.uid_index.remove(&replaced.uid);
+        }
+        replaced
+    }
+    /// Removes every keyframe
+    pub fn clear_keyframes(&mut self) {
+        self.keyframes.clear();
+        self.uid_index.clear();
+    }
+    /// Index of the keyframe with the given UID
+    ///
+    /// The index is kept up to date as keyframes are added, removed and sorted, so it is never searched for
+    pub fn index_of(&self, uid: &Bytes) -> Option<usize> {
+        let index = self.uid_index.get(uid).copied();
+        if let Some(i) = index {
+            let found = self.keyframes.get(i).map(|kf| kf.uid);
+            debug_assert_eq!(found, Some(*uid), "the UID index is out of date");
+        }
+        index
+    }
+    /// Whether the keyframe at `index` is selected
+    pub fn is_selected(&self, index: usize) -> bool {
+        self.selected_keyframes
+            .binary_search(&self.keyframes[index].uid)
+            .is_ok()
+    }
+    /// How the keyframe at `index` should be highlighted
+    pub fn state_of(&self, index: usize) -> KeyframeState {
+        let uid = &self.keyframes[index].uid;
+        if self.engine.playing.contains(uid) {
+            KeyframeState::Playing
+        } else if self.selected_keyframes.binary_search(uid).is_ok() {
+            KeyframeState::Selected
+        } else {
+            KeyframeState::Idle
+        }
+    }
+    /// Frees the textures of screenshots whose keyframe or screenshot no longer exists
+    pub fn evict_textures(&mut self) {
+        let images = self.images.lock().unwrap();
+        let keyframes: HashSet<Bytes> = self.keyframes.iter().map(|kf| kf.uid).collect();
+        self.texture_handles
+            .retain(|uid, _| keyframes.contains(uid) && images.contains_key(uid));
+        if !self.texture_handles.contains_key(&self.current_image_uid) {
+            self.current_image = None;
+            self.current_image_uid = Uuid::nil().to_bytes_le();
+        }
+    }
     /// Calculates the `Rect` created by mouse selection
     ///
     /// Manipulates the rect to draw properly with min being top left and max being bottom right
@@ -1622,17 +4317,51 @@ impl Sequencer {
         }
     }
     /// Handles sensing input relevant to the sequencer
-    fn sense(&mut self, ui: &mut Ui) {
+    fn sense(&mut self, ui: &mut Ui, settings: &Settings, track: Track) {
         let sequencer = ui.allocate_response(
             ui.available_size_before_wrap(),
             egui::Sense::click_and_drag(),
         );
 
+        // Home, End and the page keys belong to whichever text field is being edited
+        let typing = ui.ctx().wants_keyboard_input();
         ui.input_mut(|i| {
             // Keybind(ctrl+a): Select all keyframes when focused in the sequencer timeline
-            if i.consume_key(egui::Modifiers::CTRL, egui::Key::A) {
+            if settings.consume(i, KeybindType::SelectAll) {
                 self.select_all();
             }
+            // Keybind(ctrl+i): Invert the selection
+            if settings.consume(i, KeybindType::InvertSelection) {
+                self.invert_selection();
+            }
+            // Keybind(ctrl+d): Duplicate the selected keyframes in place
+            if settings.consume(i, KeybindType::Duplicate) {
+                self.duplicate();
+            }
+            // Keybind(ctrl+shift+0): Zoom to fit the selected keyframes, checked first as ctrl+0 ignores shift
+            if settings.consume(i, KeybindType::ZoomToSelection) {
+                self.zoom_to_selection();
+            }
+            // Keybind(ctrl+0): Zoom to fit the whole sequence
+            if settings.consume(i, KeybindType::ZoomToFit) {
+                self.zoom_to_fit();
+            }
+            // Keybind(home): Jump the playhead to the start of the sequence
+            if !typing && settings.consume(i, KeybindType::JumpToStart) {
+                self.jump_to_start();
+            }
+            // Keybind(end): Jump the playhead to the end of the sequence
+            if !typing && settings.consume(i, KeybindType::JumpToEnd) {
+                self.jump_to_end();
+            }
+            // Keybind(page up): Scroll the timeline back by a whole view
+            if !typing && settings.consume(i, KeybindType::PageBackward) {
+                self.page(-1.);
+            }
+            // Keybind(page down): Scroll the timeline forward by a whole view
+            if !typing && settings.consume(i, KeybindType::PageForward) {
+                self.page(1.);
+            }
             // Egui handles ctrl+[c,v,x] weirdly and results in multiple events for each press, once_bool avoids this
             if !self.once_bool {
                 self.once_bool = i.events.iter().any(|e| match e {
@@ -1672,6 +4401,33 @@ impl Sequencer {
                 }
             });
         }
+        // Double clicking an empty spot of a track opens a popup to add a keyframe there
+        if sequencer.double_clicked() {
+            if let Some(pos) = sequencer.interact_pointer_pos() {
+                let time = ((pos.x - self.timeline_left) * (1.0 / scale(ui, 1.0, self.scale))
+                    + self.scroll)
+                    .max(0.);
+                // Movements start from where the sequence last moved the mouse to before then
+                let target = self
+                    .keyframes
+                    .iter()
+                    .filter(|keyframe| keyframe.timestamp <= time)
+                    .filter_map(|keyframe| match keyframe.keyframe_type {
+                        KeyframeType::MouseMove(pos) => Some((keyframe.timestamp, pos)),
+                        _ => None,
+                    })
+                    .max_by(|a, b| a.0.total_cmp(&b.0))
+                    .map_or(Vec2::ZERO, |(_, pos)| pos);
+                self.quick_add = Some(QuickAdd {
+                    track,
+                    time,
+                    pos,
+                    text: String::new(),
+                    target,
+                    shown: false,
+                });
+            }
+        }
         if self.selecting {
             self.selection.max += sequencer.drag_delta();
         }
@@ -1685,6 +4441,24 @@ impl Sequencer {
     }
     /// Handles keeping state, and replaying keystrokes when playing
     pub fn update(&mut self, last_instant: &mut Instant, ctx: &egui::Context, settings: &Settings) {
+        let playback = self.playback_settings(settings);
+        self.receive_recorded_events();
+        // Recording and the keybinds that work without focus depend on the record thread, so its death is surfaced once
+        if !self.record_thread.reported {
+            if let Some(err) = self.record_thread.error() {
+                self.record_thread.reported = true;
+                // Recording can't continue without input, the recorded keyframes are kept below
+                self.recording.swap(false, Ordering::Relaxed);
+                self.modal = (
+                    true,
+                    "Input Listener Stopped".to_string(),
+                    format!(
+                        "Recording and global keybinds won't work until it is restarted from the Record menu.\n{}",
+                        err
+                    ),
+                );
+            }
+        }
         // Handle focus of the window when recording and when not
         // Since toggle_recording cant be called from the recording thread, it gets called here with "was_recording" as a safety check
         if self.was_recording != self.recording.load(Ordering::Relaxed) {
@@ -1694,89 +4468,50 @@ impl Sequencer {
                 ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
             }
         }
-
-        // make sure that the keyframes and their respective state are synced correctly (probably are)
-        if self.recording.load(Ordering::Relaxed) {
-            if self.keyframes.len() != self.keyframe_state.len() {
-                panic!("playing vec is out of sync")
-            }
+        if self.panicked.swap(false, Ordering::Relaxed) {
+            log::warn!("Playback stopped with the panic key");
+            ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
         }
 
+        // Keyframes that start after others follow them, even while they are being dragged
+        if (self.should_sort || self.dragging) && resolve_dependencies(&mut self.keyframes) {
+            self.should_sort = true;
+        }
         // Sorts keyframes in chronologicall order with an exeption for loop keyframes
         if self.should_sort {
             self.keyframes.sort_by(|a, b| {
                 // These checks keep loop keyframes at the start of the array so they are rendered first
-                if a.kind == 7 && b.kind == 7 {
+                if a.is_group() && b.is_group() {
                     return std::cmp::Ordering::Equal;
                 }
-                if a.kind == 7 && b.kind != 7 {
+                if a.is_group() && !b.is_group() {
                     return std::cmp::Ordering::Less;
                 }
-                if a.kind != 7 && b.kind == 7 {
+                if !a.is_group() && b.is_group() {
                     return std::cmp::Ordering::Greater;
                 }
                 a.timestamp.partial_cmp(&b.timestamp).unwrap()
             });
             self.should_sort = false;
+            self.reindex();
+            // Keyframes may have been removed along with their screenshots
+            self.evict_textures();
         }
 
-        // Reset the selected keyframes to be recomputed below
-        self.keyframe_state.iter_mut().for_each(|state| {
-            if state == &2 {
-                *state = 0;
-            }
-        });
-
-        // Compute keyframe state from the selected keyframes
-        for uid in &self.selected_keyframes {
-            let mut index = 0;
-            for i in 0..self.keyframes.len() {
-                if self.keyframes[i].uid == *uid {
-                    index = i;
-                    break;
-                }
-            }
-
-            // Prevent "z-fighting" where it wants to be highlighted and playing at the same time
-            if self.keyframe_state[index] != 1 {
-                self.keyframe_state[index] = 2; // 2 == selected/highlighted (orange)
-            }
-        }
         // Handle selecting the correct keyframe screenshot
         if self.selected_keyframes.is_empty() {
             // Get the first keyframe with an image and show that
         } else {
             // Code to get the mose recently selected keyframe and display its image if possible, otherwise show start/end image
-            let mut tmp = self.keyframe_state.clone();
-            tmp.reverse();
-            // Finds the first selected keyframe state in the list (effectively the last keyframe)
-            let x = tmp.iter().position(|&state| state == 2);
+            // Finds the last selected keyframe in the list
+            let x = (0..self.keyframes.len())
+                .rev()
+                .find(|i| self.state_of(*i) == KeyframeState::Selected);
             if let Some(index) = x {
-                // Since the tmp vec is reversed we need to invert it below
-                let uid = self.keyframes[self.keyframes.len() - index - 1].uid;
+                let uid = self.keyframes[index].uid;
                 if self.current_image_uid != uid {
-                    if let Some(screenshot) = &self.images.lock().unwrap().get(&uid) {
-                        // Check if the texture already exists
-                        if let Some(texture_handle) = self
-                            .texture_handles
-                            .iter()
-                            .find(|h| h.name() == Uuid::from_bytes_le(uid).to_string())
-                        {
-                            self.current_image = Some(texture_handle.clone());
-                        } else {
-                            // Otherwise load it
-                            let x = ColorImage::from_rgba_unmultiplied(
-                                [1920, 1080],
-                                &screenshot.as_slice(),
-                            );
-                            let texture_handle = ctx.load_texture(
-                                Uuid::from_bytes_le(uid).to_string(),
-                                x,
-                                Default::default(),
-                            );
-                            self.texture_handles.push(texture_handle.clone());
-                            self.current_image = Some(texture_handle);
-                        }
+                    if let Some(texture_handle) = self.screenshot_texture(ctx, uid) {
+                        self.current_image = Some(texture_handle);
                         self.current_image_uid = uid;
                     }
                 }
@@ -1784,178 +4519,261 @@ impl Sequencer {
         }
 
         let now = Instant::now();
+        self.profile.frame(now);
         let dt = now - *last_instant;
-        let play = self.play.load(Ordering::Relaxed);
-        // Step in time
-        if play || self.recording.load(Ordering::Relaxed) {
-            self.time += dt.as_secs_f32() * self.speed;
-        }
-
-        // Stop playing when it reaches the end of the keyframes
-        // there is a loop keyframe at the end
-        // or the second last keyframe ends after the last keyframe,
-        // Todo(addis): solve this issue, its very annoying
-        if play {
-            if let Some(last) = self.keyframes.last() {
-                if self.time >= last.timestamp + last.duration {
-                    if self.repeats > 1 {
-                        // Repeat the automation
-                        self.time = 0.0;
-                        self.repeats -= 1;
-                    } else {
-                        self.toggle_play();
-                        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        // Time spent in random delays this frame, which the playhead should not move for
+        let mut delayed = Duration::ZERO;
+        let mut play = self.play.load(Ordering::Relaxed);
+        // Playback can also be stopped from the recording thread, so changes are detected here
+        if play != self.was_playing {
+            if play {
+                let confirmed = std::mem::take(&mut self.inputs_confirmed);
+                let errors = self.check_problems(settings);
+                if errors > 0 {
+                    self.play.swap(false, Ordering::Relaxed);
+                    play = false;
+                    self.outcome = Some(PlaybackOutcome::Invalid(errors));
+                    self.show_problems = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                    log::warn!("Refused to play, the sequence has {} errors", errors);
+                } else if let Err(err) = self.load_data_rows() {
+                    // The data source is read each time playback starts so edits to it are picked up
+                    self.play.swap(false, Ordering::Relaxed);
+                    play = false;
+                    self.modal = (true, "Failed to read data source".to_string(), err.clone());
+                    log::error!("Failed to read data source - {}", err);
+                } else if !self.inputs.is_empty() && !confirmed {
+                    // Playback starts once values are given for the inputs
+                    self.play.swap(false, Ordering::Relaxed);
+                    play = false;
+                    self.input_prompt = Some(self.inputs.iter().map(|i| i.value.clone()).collect());
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                } else {
+                    self.engine.start(&mut self.keyframes);
+                    self.run_log.clear();
+                    self.profile.max_jitter = 0.;
+                    self.ghost.clear();
+                    refresh_monitors();
+                    self.events.push(PlaybackEvent::Started);
+                    if settings.minimize_on_play && !self.dry_run {
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(true));
+                    }
+                    // Gives the user time to focus the window being automated
+                    if settings.start_delay > 0. && !self.dry_run {
+                        self.countdown = Some(now + Duration::from_secs_f32(settings.start_delay));
                     }
                 }
-            }
-        }
-        // check if the time has changed
-        if self.prev_time != self.time {
-            //The playhead has moved if the current time is not equal to the previous time
-            // Todo(addis): create a slice of keyframes to come (without already played keyframes), to skip checking needlessly when playing
-            // Todo(addis): or create a current and next keyframe tuple and only check those, then update it if one is handled
-            // Idea: v <-- change the 0 to a most recently finished keyframe index variable
-            for i in 0..self.keyframes.len() {
-                // Skip this keyframe if it is disabled
-                if !self.keyframes[i].enabled {
-                    continue;
+            } else {
+                // Playback can stop part way through a key or button keyframe, however it was stopped
+                self.engine.stop();
+                release_held_inputs();
+                self.countdown = None;
+                self.awaiting_step = None;
+                self.confirmed_step = None;
+                if settings.minimize_on_play && !self.dry_run {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
                 }
-                let current_keyframe_state = self.keyframe_state[i]; //1 if playing, 0 if not
-                                                                     // checks if the playhead is entering or exiting the current keyframe, (far left or far right of keyframe in terms of time)
-                let timestamp = self.keyframes[i].timestamp;
-                let duration = self.keyframes[i].duration;
-
-                if self.time >= timestamp && self.time <= timestamp + duration {
-                    self.keyframe_state[i] = 1; //change keyframe state to playing, highlight
-
-                    // Set the current image when playing if it's not already set to the current image
-                    let uid = self.keyframes[i].uid;
-                    if self.current_image_uid != uid {
-                        if let Some(screenshot) = &self.images.lock().unwrap().get(&uid) {
-                            if let Some(texture_handle) = self
-                                .texture_handles
-                                .iter()
-                                .find(|h| h.name() == Uuid::from_bytes_le(uid).to_string())
-                            {
-                                self.current_image = Some(texture_handle.clone());
-                            } else {
-                                // Otherwise load it
-                                let image = ColorImage::from_rgba_unmultiplied(
-                                    [1920, 1080],
-                                    &screenshot.as_slice(),
-                                );
-                                let texture_handle = ctx.load_texture(
-                                    Uuid::from_bytes_le(uid).to_string(),
-                                    image,
-                                    Default::default(),
-                                );
-                                self.texture_handles.push(texture_handle.clone());
-                                self.current_image = Some(texture_handle);
-                            }
-                            self.current_image_uid = uid;
-                        }
-                    }
-                    // Checks if the keyframe has changed since the playhead moved
-                    if current_keyframe_state != self.keyframe_state[i] {
-                        // If so and the sequencer is playing
-                        if play {
-                            // When fail detection is enabled check if the keyframe has a screenshot
-                            if settings.fail_detection || settings.retake_screenshots {
-                                if let Some(src1) = screenshot() {
-                                    let mut images = self.images.lock().unwrap();
-                                    if settings.retake_screenshots {
-                                        // Replace the current screenshot with a new one
-                                        images.remove(&uid);
-                                        images.insert(uid, src1);
-                                    } else {
-                                        if let Some(src2) = images.get(&uid) {
-                                            let percentage_err = image_dif_opencv(&src1, src2);
-                                            if percentage_err > settings.max_fail_error as f32 {
-                                                self.play.swap(false, Ordering::Relaxed);
-                                                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
-                                                self.modal = (
-                                                    true,
-                                                    format!("Fail Detected: {:?}%", percentage_err)
-                                                        .to_string(),
-                                                    "Paused playback as a result.".to_string(),
-                                                );
-                                                log::warn!(
-                                                    "Fail Detected: {:?}% err",
-                                                    percentage_err
-                                                );
-                                                break;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            self.handle_playing_keyframe(
-                                &self.keyframes[i],
-                                true,
-                                &settings.offset,
-                            );
-                        }
-                    }
-                } else {
-                    // Unhighlight an already highlighted keyframe making sure to avoid selected keyframes
-                    if self.keyframe_state[i] == 1 {
-                        self.keyframe_state[i] = 0; //change keyframe state to not playing, no highlight
-                    }
-                    // Checks if the keyframe has changed since the playhead moved
-                    if current_keyframe_state != self.keyframe_state[i] {
-                        // If so and the sequencer is playing
-                        if play {
-                            self.handle_playing_keyframe(
-                                &self.keyframes[i],
-                                false,
-                                &settings.offset,
-                            );
-                            if let KeyframeType::Loop(repeats, j) = self.keyframes[i].keyframe_type
-                            {
-                                if j < repeats {
-                                    self.keyframes[i].keyframe_type =
-                                        KeyframeType::Loop(repeats, j + 1);
-                                    self.time = self.keyframes[i].timestamp;
-                                } else {
-                                    self.keyframes[i].keyframe_type =
-                                        KeyframeType::Loop(repeats, 1);
-                                }
-                            }
-                        }
-                    }
+                if self.outcome.is_none() {
+                    self.events.push(PlaybackEvent::Stopped);
                 }
             }
+            self.was_playing = play;
         }
-        self.once_bool = false;
-        //update previous time to keep track of when time changes
-        self.prev_time = self.time;
-        *last_instant = now;
-    }
-    /// Deletes all movement keyframes determined to be redundant.
-    pub fn cull_minor_movement_keyframes(&mut self) {
-        let mut previous_move_keyframe: Option<usize> = None;
-        let mut keyframes_to_remove: Vec<usize> = Vec::new();
-        for i in 0..self.keyframes.len() {
-            // If the current keyframe is a movement
-            if self.keyframes[i].kind == 1 {
-                // And the previous was a movement, remove the previous
-                if let Some(index) = previous_move_keyframe {
-                    // Remove the previous as it essentially does nothing (its minor)
-                    keyframes_to_remove.push(index);
-                }
-                // Update the previous move keyframe to be the current move keyframe
-                previous_move_keyframe = Some(i);
-            } else {
-                // If its not a move keyframe
-                previous_move_keyframe = None;
+        if self.countdown.is_some_and(|end| now >= end) {
+            self.countdown = None;
+        }
+        // Once confirmed, the keyframe waiting for its step is played at the time it was stopped at
+        let mut confirmed = false;
+        if let Some(uid) = self.awaiting_step {
+            // Turning step confirm off part way lets the waiting keyframe play too
+            if self.step_confirmed.swap(false, Ordering::Relaxed) || !self.confirm_steps {
+                self.awaiting_step = None;
+                self.confirmed_step = Some(uid);
+                confirmed = true;
             }
         }
-
-        let mut undo_vec = Vec::new();
-        for i in keyframes_to_remove.iter().rev() {
-            undo_vec.push(self.keyframes.remove(*i));
-            self.keyframe_state.remove(*i);
+        // Step in time, the playhead waits for the countdown and for steps to be confirmed
+        let stepping =
+            play && self.countdown.is_none() && self.awaiting_step.is_none() && !confirmed;
+        if stepping {
+            // Sequential playback goes straight to the next keyframe, a magic move waiting for its target holds it there
+            let elapsed = dt.as_secs_f32() * self.speed;
+            self.engine
+                .advance(&self.keyframes, &mut self.time, elapsed, self.sequential);
+        } else if self.recording.load(Ordering::Relaxed) {
+            self.time += dt.as_secs_f32() * self.speed;
+        }
+        // Page the timeline along with the playhead once it nears the edge of the view
+        if play && self.follow_playhead {
+            self.scroll_into_view(self.time);
+        }
+
+        // check if the time has changed, or if a magic move waiting for its target should look again
+        if self.prev_time != self.time || confirmed || (play && self.engine.is_waiting()) {
+            if play {
+                // The engine gets the keyframes while the editor plays them, so they are put back right after
+                let mut keyframes = std::mem::take(&mut self.keyframes);
+                let mut engine = std::mem::take(&mut self.engine);
+                let mut time = self.time;
+                let mut editor = Editor {
+                    sequencer: self,
+                    ctx,
+                    settings,
+                    playback: &playback,
+                    delayed: Duration::ZERO,
+                };
+                let result = engine.step(&mut keyframes, &mut time, &mut editor);
+                delayed = editor.delayed;
+                self.keyframes = keyframes;
+                self.engine = engine;
+                self.time = time;
+                if let Err(stop) = result {
+                    self.playback_stopped(ctx, stop);
+                }
+            } else {
+                // Highlight the keyframes the playhead is inside of
+                for keyframe in self.keyframes.iter().filter(|keyframe| keyframe.enabled) {
+                    let inside = self.time >= keyframe.timestamp
+                        && self.time <= keyframe.timestamp + keyframe.duration;
+                    match inside {
+                        true => self.engine.playing.insert(keyframe.uid),
+                        false => self.engine.playing.remove(&keyframe.uid),
+                    };
+                }
+            }
+            // Show the screenshot of the latest keyframe the playhead is inside of
+            let uid = self
+                .keyframes
+                .iter()
+                .rev()
+                .find(|keyframe| self.engine.playing.contains(&keyframe.uid))
+                .map(|keyframe| keyframe.uid);
+            if let Some(uid) = uid.filter(|uid| *uid != self.current_image_uid) {
+                if let Some(texture_handle) = self.screenshot_texture(ctx, uid) {
+                    self.current_image = Some(texture_handle);
+                    self.current_image_uid = uid;
+                }
+            }
+        }
+        // Once the playhead passes the end of the keyframes, play the next row of the data source,
+        // repeat or finish
+        if self.is_playing() && self.time >= engine::end(&self.keyframes) {
+            let rows = self.data_rows.len().max(1);
+            let repeats = self.repeats.max(1) as u32;
+            if !self.engine.next_pass(
+                &mut self.keyframes,
+                &mut self.time,
+                rows,
+                repeats,
+                self.loop_forever,
+            ) {
+                self.outcome = Some(PlaybackOutcome::Finished);
+                self.events.push(PlaybackEvent::Finished);
+                self.toggle_play();
+                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            }
+        }
+        self.once_bool = false;
+        //update previous time to keep track of when time changes
+        self.prev_time = self.time;
+        *last_instant = now + delayed;
+    }
+    /// Shows why playback stopped before the end
+    fn playback_stopped(&mut self, ctx: &egui::Context, stop: Stop) {
+        match stop {
+            // Abort keyframes stop playback on purpose, as a guard
+            Stop::Aborted(message) => {
+                self.play.swap(false, Ordering::Relaxed);
+                self.outcome = Some(PlaybackOutcome::Aborted(message.clone()));
+                self.events.push(PlaybackEvent::Aborted {
+                    message: message.clone(),
+                });
+                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                log::warn!("Aborted playback - {}", message);
+                self.modal = (true, "Playback Aborted".to_string(), message);
+            }
+            Stop::SimulationFailed(index, err) => self.simulation_failed(ctx, index, err),
+            Stop::Failed(title, message) => {
+                self.play.swap(false, Ordering::Relaxed);
+                self.outcome = Some(PlaybackOutcome::Failed(message.clone()));
+                self.events.push(PlaybackEvent::Failed {
+                    message: message.clone(),
+                });
+                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                log::error!("{}", message);
+                self.modal = (true, title.to_string(), message);
+            }
+            Stop::Halted => {
+                self.play.swap(false, Ordering::Relaxed);
+            }
+        }
+    }
+    /// Stops playback because the system refused the input simulated for the keyframe at `index`
+    fn simulation_failed(&mut self, ctx: &egui::Context, index: usize, err: String) {
+        self.play.swap(false, Ordering::Relaxed);
+        self.outcome = Some(PlaybackOutcome::SimulationFailed(err.clone()));
+        self.events.push(PlaybackEvent::SimulationFailed {
+            message: err.clone(),
+        });
+        ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+        self.modal = (
+            true,
+            "Input Refused".to_string(),
+            format!(
+                "Stopped playback at keyframe {}, the input could not be simulated: {}",
+                index, err
+            ),
+        );
+        log::error!("Failed to simulate keyframe {} - {}", index, err);
+    }
+    /// Deletes all movement keyframes determined to be redundant.
+    ///
+    /// A movement followed by another movement is kept if the cursor rests there for at least `cull_min_pause`,
+    /// or if it is far enough from the last kept movement and changes direction enough to be a meaningful waypoint
+    pub fn cull_minor_movement_keyframes(&mut self, settings: &Settings) {
+        let mut previous_kept: Option<Vec2> = None;
+        let mut keyframes_to_remove: Vec<usize> = Vec::new();
+        for i in 0..self.keyframes.len() {
+            // If the current keyframe is not a movement, it breaks up the run of movements
+            let pos = match self.keyframes[i].keyframe_type {
+                KeyframeType::MouseMove(pos) => pos,
+                _ => {
+                    previous_kept = None;
+                    continue;
+                }
+            };
+            // The last movement before any other keyframe is always kept
+            let (next_pos, next_timestamp) = match self.keyframes.get(i + 1) {
+                Some(Keyframe {
+                    keyframe_type: KeyframeType::MouseMove(next_pos),
+                    timestamp,
+                    ..
+                }) => (*next_pos, *timestamp),
+                _ => {
+                    previous_kept = Some(pos);
+                    continue;
+                }
+            };
+            // Hovering in place is often needed for menus that open on hover
+            let paused = next_timestamp - self.keyframes[i].timestamp >= settings.cull_min_pause;
+            let waypoint = match previous_kept {
+                Some(previous) => {
+                    (pos - previous).length() >= settings.cull_min_distance
+                        && direction_change(pos - previous, next_pos - pos)
+                            >= settings.cull_min_angle
+                }
+                None => false,
+            };
+            if paused || waypoint {
+                previous_kept = Some(pos);
+            } else {
+                // Remove it as it essentially does nothing (its minor)
+                keyframes_to_remove.push(i);
+            }
         }
+
+        let undo_vec = self.remove_keyframes(&keyframes_to_remove);
         if !undo_vec.is_empty() {
             self.changes.0.push(Change {
                 uids: vec![],
@@ -1964,12 +4782,267 @@ impl Sequencer {
             self.changed();
         }
     }
+    /// Reduces every run of consecutive movement keyframes to the fewest waypoints that keep the path within `tolerance` pixels
+    pub fn simplify_movement_keyframes(&mut self, tolerance: f32) {
+        let mut runs: Vec<Vec<usize>> = Vec::new();
+        let mut run: Vec<usize> = Vec::new();
+        for i in 0..self.keyframes.len() {
+            if self.keyframes[i].kind == 1 {
+                run.push(i);
+            } else if !run.is_empty() {
+                runs.push(std::mem::take(&mut run));
+            }
+        }
+        runs.push(run);
+
+        let mut keyframes_to_remove: Vec<usize> = Vec::new();
+        for run in runs.iter().filter(|run| run.len() > 2) {
+            let points: Vec<Vec2> = run
+                .iter()
+                .map(|i| match self.keyframes[*i].keyframe_type {
+                    KeyframeType::MouseMove(pos) => pos,
+                    _ => Vec2::ZERO,
+                })
+                .collect();
+            let mut kept = douglas_peucker(&points, tolerance).into_iter().peekable();
+            for (j, index) in run.iter().enumerate() {
+                if kept.peek() == Some(&j) {
+                    kept.next();
+                } else {
+                    keyframes_to_remove.push(*index);
+                }
+            }
+        }
+
+        let undo_vec = self.remove_keyframes(&keyframes_to_remove);
+        if !undo_vec.is_empty() {
+            log::info!("Simplified paths by removing {} keyframes", undo_vec.len());
+            self.selected_keyframes.clear();
+            self.changes.0.push(Change {
+                uids: vec![],
+                data: vec![ChangeData::RemoveKeyframes(undo_vec)],
+            });
+            self.changed();
+        }
+    }
+    /// Converts the movement before each selected mouse button keyframe into a magic move
+    ///
+    /// The target image is cropped around the click position from the click's screenshot and saved next to the loaded file,
+    /// so the click still lands on the same element if the window moves
+    pub fn convert_to_magic_move(&mut self) {
+        // Find all selected mouse button keyframes
+        let clicks: Vec<usize> = (0..self.keyframes.len())
+            .filter(|i| self.is_selected(*i) && self.keyframes[*i].kind == 2)
+            .collect();
+        if clicks.is_empty() {
+            return;
+        }
+        let directory = match self.targets_directory().and_then(|directory| {
+            std::fs::create_dir_all(&directory)
+                .map(|()| directory)
+                .map_err(|err| err.to_string())
+        }) {
+            Ok(directory) => directory,
+            Err(err) => {
+                log::error!("Failed to create the magic move targets folder: {}", err);
+                self.modal = (true, "Failed to convert to Magic Move".to_string(), err);
+                return;
+            }
+        };
+
+        let mut undo_vec = Vec::new();
+        let mut magic_keyframes = Vec::new();
+        let mut failed = 0;
+        for click in clicks {
+            // The click happens wherever the last movement before it left the mouse
+            let movement = (0..click)
+                .rev()
+                .find(|i| self.keyframes[*i].kind == 1 || self.keyframes[*i].kind == 6);
+            // Skip clicks whose movement was already converted by a previous click
+            if movement.is_some_and(|i| self.keyframes[i].kind == 6) {
+                continue;
+            }
+            let (Some(movement), Some(screenshot)) = (
+                movement,
+                self.images
+                    .lock()
+                    .unwrap()
+                    .get(&self.keyframes[click].uid)
+                    .cloned(),
+            ) else {
+                failed += 1;
+                continue;
+            };
+            let KeyframeType::MouseMove(pos) = self.keyframes[movement].keyframe_type else {
+                continue;
+            };
+            let Some(target) = crop_screenshot(&screenshot, pos, MAGIC_MOVE_TARGET_SIZE) else {
+                failed += 1;
+                continue;
+            };
+            let path = directory.join(format!(
+                "{}.png",
+                Uuid::from_bytes_le(self.keyframes[click].uid)
+            ));
+            if let Err(err) = target.save(&path) {
+                log::error!("Failed to save magic move target {:?}: {:?}", path, err);
+                failed += 1;
+                continue;
+            }
+            let magic_keyframe = Keyframe {
+                keyframe_type: KeyframeType::MagicMove(path.to_string_lossy().to_string()),
+                kind: 6,
+                uid: Uuid::new_v4().to_bytes_le(),
+                ..self.keyframes[movement].clone()
+            };
+            undo_vec.push(self.replace_keyframe(movement, magic_keyframe.clone()));
+            magic_keyframes.push(magic_keyframe);
+        }
+        if failed > 0 {
+            self.modal = (
+                true,
+                "Failed to convert to Magic Move".to_string(),
+                format!(
+                    "{} click(s) had no screenshot or no movement before them.",
+                    failed
+                ),
+            );
+        }
+        if !magic_keyframes.is_empty() {
+            log::info!("Converted {} clicks to magic moves", magic_keyframes.len());
+            self.changes.0.push(Change {
+                uids: vec![],
+                data: vec![
+                    ChangeData::RemoveKeyframes(undo_vec),
+                    ChangeData::AddKeyframes(magic_keyframes),
+                ],
+            });
+            self.changed();
+        }
+    }
+    /// Folder next to the loaded file that magic move targets are stored in, e.g. "file_targets/"
+    ///
+    /// Fails if the file hasn't been saved yet, as there is nowhere next to it to store them
+    fn targets_directory(&self) -> Result<PathBuf, String> {
+        if self.loaded_file.is_empty() || self.loaded_file == "untitled.auto" {
+            return Err(
+                "Save the file first, magic move targets are stored next to it.".to_string(),
+            );
+        }
+        let file = Path::new(&self.loaded_file);
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+        Ok(file.with_file_name(format!("{}_targets", stem)))
+    }
+    /// Makes each selected keyframe start after the selected keyframe before it, keeping the gaps between them as delays
+    pub fn chain_selected(&mut self) {
+        let selected: Vec<usize> = (0..self.keyframes.len())
+            .filter(|i| self.is_selected(*i) && !self.keyframes[*i].is_group())
+            .collect();
+        let edits = selected
+            .windows(2)
+            .map(|pair| {
+                let (previous, keyframe) = (&self.keyframes[pair[0]], &self.keyframes[pair[1]]);
+                let after = Dependency {
+                    uid: previous.uid,
+                    delay: keyframe.timestamp - (previous.timestamp + previous.duration),
+                };
+                (keyframe.uid, keyframe.after, Some(after))
+            })
+            .collect();
+        self.edit_dependencies(edits);
+    }
+    /// Lets the selected keyframes start at a fixed time again
+    pub fn unchain_selected(&mut self) {
+        let edits = (0..self.keyframes.len())
+            .filter(|i| self.is_selected(*i))
+            .filter_map(|i| {
+                let keyframe = &self.keyframes[i];
+                keyframe
+                    .after
+                    .map(|after| (keyframe.uid, Some(after), None))
+            })
+            .collect();
+        self.edit_dependencies(edits);
+    }
+    /// Applies the new dependency of each keyframe as a single undo step
+    fn edit_dependencies(&mut self, edits: Vec<(Bytes, Option<Dependency>, Option<Dependency>)>) {
+        if edits.is_empty() {
+            return;
+        }
+        for (uid, _old, new) in &edits {
+            if let Some(i) = self.index_of(uid) {
+                self.keyframes[i].after = *new;
+            }
+        }
+        self.changes.0.push(Change {
+            uids: vec![],
+            data: vec![ChangeData::EditDependencies(edits)],
+        });
+        self.changed();
+    }
+    /// Merges each run of consecutive selected movement keyframes into a single move to the run's final position
+    ///
+    /// A run is broken by any keyframe that is not a selected mouse move
+    pub fn merge_movement_keyframes(&mut self) {
+        let mut runs: Vec<Vec<usize>> = Vec::new();
+        let mut run: Vec<usize> = Vec::new();
+        for i in 0..self.keyframes.len() {
+            // Loop keyframes are kept at the start of the vec and do not break up runs
+            if self.keyframes[i].is_group() {
+                continue;
+            }
+            // Add it to the current run if it is selected and is a mouse move
+            if self.is_selected(i) && self.keyframes[i].kind == 1 {
+                run.push(i);
+            } else if !run.is_empty() {
+                runs.push(std::mem::take(&mut run));
+            }
+        }
+        runs.push(run);
+
+        let mut undo_vec = Vec::new();
+        let mut merged_keyframes = Vec::new();
+        // Each merged keyframe takes the place of the first in its run, the rest are removed together afterwards
+        let mut merged_away = Vec::new();
+        for run in runs.into_iter().filter(|run| run.len() > 1) {
+            let first = &self.keyframes[run[0]];
+            let last = &self.keyframes[run[run.len() - 1]];
+            if let KeyframeType::MouseMove(pos) = last.keyframe_type {
+                let mut merged = Keyframe::mouse_move(first.timestamp, pos);
+                merged.monitor = last.monitor.clone();
+                merged.duration = last.timestamp + last.duration - first.timestamp;
+                undo_vec.push(self.replace_keyframe(run[0], merged.clone()));
+                merged_away.extend_from_slice(&run[1..]);
+                merged_keyframes.push(merged);
+            }
+        }
+        undo_vec.append(&mut self.remove_keyframes(&merged_away));
+
+        if !merged_keyframes.is_empty() {
+            log::info!(
+                "Merged {} movement keyframes into {}",
+                undo_vec.len(),
+                merged_keyframes.len()
+            );
+            // Select only the merged keyframes
+            self.selected_keyframes = merged_keyframes.iter().map(|kf| kf.uid).collect();
+            self.selected_keyframes.sort();
+            self.changes.0.push(Change {
+                uids: vec![],
+                data: vec![
+                    ChangeData::RemoveKeyframes(undo_vec),
+                    ChangeData::AddKeyframes(merged_keyframes),
+                ],
+            });
+            self.changed();
+        }
+    }
     /// Combine keybtn keyframes into a single keystroke
     fn combine_into_keystrokes(&mut self) {
         let mut selected_keyframes: Vec<usize> = Vec::new();
-        for i in (0..self.keyframe_state.len()).rev() {
+        for i in (0..self.keyframes.len()).rev() {
             // Add it to selected keyframes if it is selected and is a key press
-            if self.keyframe_state[i] == 2 {
+            if self.is_selected(i) {
                 if self.keyframes[i].kind == 0 {
                     selected_keyframes.push(i);
                 } else {
@@ -1981,16 +5054,16 @@ impl Sequencer {
         let mut keys: Vec<rdev::Key> = Vec::new();
         let mut last_index = 0;
         let mut last_timestamp = 0.;
-        let mut undo_vec = Vec::new();
+        let mut combined = Vec::new();
         for index in selected_keyframes {
             last_timestamp = self.keyframes[index].timestamp;
             if let KeyframeType::KeyBtn(key) = self.keyframes[index].keyframe_type {
                 keys.push(key);
-                undo_vec.push(self.keyframes.remove(index));
-                self.keyframe_state.remove(index);
+                combined.push(index);
                 last_index = index;
             }
         }
+        let undo_vec = self.remove_keyframes(&combined);
         keys.reverse();
         if !keys.is_empty() {
             let uid = Uuid::new_v4().to_bytes_le();
@@ -2001,12 +5074,17 @@ impl Sequencer {
                 kind: 5,
                 enabled: true,
                 uid,
+                expressions: HashMap::new(),
+                delay: None,
+                key_delay: None,
+                monitor: None,
+                after: None,
+                note: String::new(),
             };
-            self.keyframes.insert(last_index, combined_keyframe.clone());
+            self.insert_keyframe(last_index, combined_keyframe.clone());
             // Clear and select only the new keyframe
             self.selected_keyframes = vec![uid];
 
-            self.keyframe_state.insert(last_index, 0);
             self.changes.0.push(Change {
                 uids: vec![],
                 data: vec![
@@ -2017,75 +5095,6 @@ impl Sequencer {
             self.changed();
         }
     }
-    /// Simulates the given keyframe
-    ///
-    /// `start` decides whether to treat this as the start or end of a keyframe
-    fn handle_playing_keyframe(&self, keyframe: &Keyframe, start: bool, offset: &Vec2) {
-        match &keyframe.keyframe_type {
-            KeyframeType::KeyBtn(key) => {
-                if start {
-                    rdev::simulate(&rdev::EventType::KeyPress(*key))
-                        .expect("Failed to simulate keypress");
-                } else {
-                    rdev::simulate(&rdev::EventType::KeyRelease(*key))
-                        .expect("Failed to simulate keyrelease");
-                }
-            }
-            KeyframeType::MouseBtn(btn) => {
-                if start {
-                    rdev::simulate(&rdev::EventType::ButtonPress(*btn))
-                        .expect("Failed to simulate Button Release");
-                } else {
-                    rdev::simulate(&rdev::EventType::ButtonRelease(*btn))
-                        .expect("Failed to simulate Button Release");
-                }
-            }
-            KeyframeType::MouseMove(pos) => {
-                if start {
-                    simulate_move(pos, &offset);
-                }
-            }
-            KeyframeType::Scroll(delta) => {
-                if start {
-                    rdev::simulate(&rdev::EventType::Wheel {
-                        delta_x: delta.x as i64,
-                        delta_y: delta.y as i64,
-                    })
-                    .expect("Failed to simulate Mouse Scroll (Possibly due to anti-cheat)");
-                }
-            }
-            KeyframeType::Wait(secs) => {
-                if start {
-                    // Todo(addis): multiply dt so that it takes *secs* seconds to traverse 1 second of sequencer time
-                    // This will remove the need to block the thread and freeze the application, and keep the playhead moving in a slow but satisfying way
-                    thread::sleep(Duration::from_secs_f32(secs.clone()));
-                }
-            }
-            KeyframeType::KeyStrokes(keys) => {
-                if start {
-                    for key in keys {
-                        rdev::simulate(&rdev::EventType::KeyPress(*key))
-                            .expect("Failed to simulate keypress");
-                        // thread::sleep(Duration::from_secs(0.01));
-                        rdev::simulate(&rdev::EventType::KeyRelease(*key))
-                            .expect("Failed to simulate keypress");
-                    }
-                }
-            }
-            KeyframeType::MagicMove(path) => {
-                if start {
-                    let target = image::ImageReader::open(path).unwrap().decode().unwrap();
-                    let now = Instant::now();
-                    if let Some(target_center) = template_match_opencv(target.clone()) {
-                        log::info!("Magic found target in {:?}", now.elapsed());
-                        simulate_move(&target_center, offset);
-                    }
-                }
-            }
-            // Loop is handled outside of this function
-            KeyframeType::Loop(_, _) => {}
-        }
-    }
     pub fn context_menu(&mut self, ui: &mut Ui, keyframe: Option<&Keyframe>) {
         // Enable/Disable keyframe
         if let Some(keyframe) = keyframe {
@@ -2107,6 +5116,55 @@ impl Sequencer {
             self.combine_into_keystrokes();
             ui.close_menu();
         }
+        // Converts the movement before a click into a magic move targeting what was clicked
+        if let Some(Keyframe {
+            keyframe_type: KeyframeType::MouseBtn(_),
+            ..
+        }) = keyframe
+        {
+            if ui
+                .add(egui::Button::new("Convert to Magic Move"))
+                .on_hover_text("Find what was clicked using its screenshot instead of its position")
+                .clicked()
+            {
+                self.convert_to_magic_move();
+                ui.close_menu();
+            }
+        }
+        if ui
+            .add_enabled(
+                self.selected_keyframes.len() > 1,
+                egui::Button::new("Chain"),
+            )
+            .on_hover_text(
+                "Make each selected keyframe start after the one before it, so they move together",
+            )
+            .clicked()
+        {
+            self.chain_selected();
+            ui.close_menu();
+        }
+        if keyframe.is_some_and(|keyframe| keyframe.after.is_some()) {
+            if ui
+                .add(egui::Button::new("Unchain"))
+                .on_hover_text("Start the selected keyframes at a fixed time again")
+                .clicked()
+            {
+                self.unchain_selected();
+                ui.close_menu();
+            }
+        }
+        // Merges runs of selected movement keyframes into a single move
+        if ui
+            .add(egui::Button::new("Merge Moves"))
+            .on_hover_text(
+                "Merge consecutive selected mouse moves into one move to the final position",
+            )
+            .clicked()
+        {
+            self.merge_movement_keyframes();
+            ui.close_menu();
+        }
         ui.separator();
         if ui
             .add_enabled(
@@ -2128,9 +5186,10 @@ impl Sequencer {
             self.copy();
             ui.close_menu();
         }
+        let can_paste = self.can_paste();
         if ui
             .add_enabled(
-                !self.clip_board.is_empty(),
+                can_paste,
                 egui::Button::new("Paste").shortcut_text("Ctrl+V"),
             )
             .clicked()
@@ -2138,6 +5197,16 @@ impl Sequencer {
             self.paste();
             ui.close_menu();
         }
+        if ui
+            .add_enabled(
+                !self.selected_keyframes.is_empty(),
+                egui::Button::new("Duplicate").shortcut_text("Ctrl+D"),
+            )
+            .clicked()
+        {
+            self.duplicate();
+            ui.close_menu();
+        }
         ui.separator();
         if ui
             .add_enabled(
@@ -2150,6 +5219,340 @@ impl Sequencer {
             ui.close_menu();
         }
     }
+    /// Checks the sequence for problems, logging any warnings
+    ///
+    /// Returns how many of them are errors, which playback doesn't start with
+    pub fn check_problems(&mut self, settings: &Settings) -> usize {
+        self.problems = validate(&self.keyframes, settings.max_keyframe_duration);
+        for problem in &self.problems {
+            if problem.severity == Severity::Warning {
+                let index = self.index_of(&problem.uid).unwrap_or_default();
+                log::warn!("Keyframe {} - {}", index, problem.message);
+            }
+        }
+        self.problems
+            .iter()
+            .filter(|problem| problem.severity == Severity::Error)
+            .count()
+    }
+    /// Lists the problems found by the last check, each with a link that selects its keyframe
+    pub fn problems_window(&mut self, ctx: &egui::Context, settings: &Settings) {
+        if !self.show_problems {
+            return;
+        }
+        let mut open = true;
+        let mut reveal = None;
+        let mut recheck = false;
+        egui::Window::new("Problems")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(360.)
+            .show(ctx, |ui| {
+                if self.problems.is_empty() {
+                    ui.label("No problems found");
+                }
+                egui::ScrollArea::vertical()
+                    .max_height(300.)
+                    .show(ui, |ui| {
+                        for problem in &self.problems {
+                            ui.horizontal(|ui| {
+                                match problem.severity {
+                                    Severity::Error => ui
+                                        .colored_label(egui::Color32::LIGHT_RED, "⛔")
+                                        .on_hover_text("Stops playback from starting"),
+                                    Severity::Warning => {
+                                        ui.colored_label(egui::Color32::YELLOW, "⚠")
+                                    }
+                                };
+                                let name = match self.index_of(&problem.uid) {
+                                    Some(i) => {
+                                        format!("{} {}", kind_name(self.keyframes[i].kind), i)
+                                    }
+                                    None => "Removed".to_string(),
+                                };
+                                if ui.link(name).clicked() {
+                                    reveal = Some(problem.uid);
+                                }
+                                ui.label(&problem.message);
+                            });
+                        }
+                    });
+                ui.separator();
+                if ui.button("Check Again").clicked() {
+                    recheck = true;
+                }
+            });
+        if let Some(uid) = reveal {
+            if let Some(i) = self.index_of(&uid) {
+                self.selected_keyframes = vec![uid];
+                self.scroll_into_view(self.keyframes[i].timestamp);
+            }
+        }
+        if recheck {
+            self.check_problems(settings);
+        }
+        if !open {
+            self.show_problems = false;
+        }
+    }
+    /// Shows the progress of playback in a small window above everything else, with a button to stop it
+    ///
+    /// The editor is usually covered by what is being automated, so this is the only way to see how far along it is.
+    /// Not shown for dry runs, which are watched in the editor
+    pub fn hud(&mut self, ctx: &egui::Context) {
+        if !self.show_hud || self.dry_run || !self.play.load(Ordering::Relaxed) {
+            return;
+        }
+        let (end, progress) = self.progress();
+        let countdown = self.countdown.map(|end| end - Instant::now());
+        // The latest keyframe the playhead is inside of
+        let current = self
+            .keyframes
+            .iter()
+            .filter(|k| self.engine.playing.contains(&k.uid))
+            .last()
+            .map(|k| kind_name(k.kind))
+            .unwrap_or_default();
+        let remaining = (end - self.time).max(0.) / self.speed;
+        // The keyframe waiting for its step to be confirmed, and the key that confirms it
+        let awaiting = self
+            .awaiting_step
+            .and_then(|uid| self.index_of(&uid))
+            .map(|i| {
+                (
+                    format!("{} #{}", kind_name(self.keyframes[i].kind), i),
+                    self.global_keys.lock().unwrap().confirm_step,
+                )
+            });
+        let monitor = self.primary_monitor();
+        let position = pos2(monitor.max.x - HUD_SIZE.x - 20., monitor.min.y + 20.);
+        let viewport = egui::ViewportBuilder::default()
+            .with_title("Automate Playback")
+            .with_inner_size(HUD_SIZE)
+            .with_position(position)
+            .with_always_on_top()
+            .with_decorations(false)
+            .with_resizable(false)
+            // Taking focus would send the automation's input to the overlay
+            .with_active(false);
+        let mut stop = false;
+        let mut confirm = false;
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("playback_hud"),
+            viewport,
+            |ctx, _| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong(format!("{:.0}%", progress * 100.));
+                        ui.label(current);
+                    });
+                    ui.add(egui::ProgressBar::new(progress));
+                    ui.horizontal(|ui| {
+                        match (countdown, &awaiting) {
+                            (Some(left), _) => {
+                                ui.strong(format!("Starting in {}...", left.as_secs_f32().ceil()))
+                            }
+                            (None, Some((keyframe, Some(key)))) => {
+                                ui.strong(format!("Press {:?} to play {}", key, keyframe))
+                            }
+                            (None, Some((keyframe, None))) => {
+                                ui.strong(format!("Waiting to play {}", keyframe))
+                            }
+                            (None, None) => ui.label(format!(
+                                "{:.1}s elapsed, {:.1}s left",
+                                self.time, remaining
+                            )),
+                        };
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if ui.button("⏹").on_hover_text("Stop Playback").clicked() {
+                                stop = true;
+                            }
+                            if awaiting.is_some()
+                                && ui.button("✔").on_hover_text("Confirm Step").clicked()
+                            {
+                                confirm = true;
+                            }
+                        });
+                    });
+                });
+            },
+        );
+        if stop {
+            self.toggle_play();
+        }
+        if confirm {
+            self.step_confirmed.store(true, Ordering::Relaxed);
+        }
+    }
+    /// Shows only the transport controls and progress, for the mini player that replaces the editor
+    ///
+    /// Returns whether the user asked to go back to the editor
+    pub fn mini_player(&mut self, ctx: &egui::Context) -> bool {
+        let (end, progress) = self.progress();
+        let mut expand = false;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("⏪").on_hover_text("Restart").clicked() {
+                    self.reset_time();
+                }
+                if self.play.load(Ordering::Relaxed) {
+                    if ui.button("⏸").on_hover_text("Pause").clicked() {
+                        self.toggle_play();
+                    }
+                } else if ui.button("⏵").on_hover_text("Play").clicked() {
+                    self.toggle_play();
+                }
+                ui.label(format!("{:.1}s / {:.1}s", self.time, end));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    expand = ui.button("⛶").on_hover_text("Show Editor").clicked();
+                });
+            });
+            ui.add(egui::ProgressBar::new(progress).show_percentage());
+        });
+        expand
+    }
+    /// End of the last enabled keyframe, and how far the playhead is to it from `0.0` to `1.0`
+    fn progress(&self) -> (f32, f32) {
+        let end = self
+            .keyframes
+            .iter()
+            .filter(|k| k.enabled)
+            .map(|k| k.timestamp + k.duration)
+            .fold(0., f32::max);
+        let progress = match end > 0. {
+            true => (self.time / end).clamp(0., 1.),
+            false => 1.,
+        };
+        (end, progress)
+    }
+    /// Draws the mouse path over the screen during playback, in a window that clicks go through
+    pub fn trail_overlay(&mut self, ctx: &egui::Context, offset: &Vec2) {
+        if !(self.show_mouse_trail && self.show_trail_overlay)
+            || self.dry_run
+            || !self.play.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        let monitor = self.primary_monitor();
+        // Playback moves the mouse with the calibration offset, if there is one
+        let offset = match offset.any_nan() {
+            true => Vec2::ZERO,
+            false => *offset,
+        };
+        let points: Vec<Pos2> = self
+            .mouse_trail()
+            .into_iter()
+            .map(|pos| (pos + offset - monitor.min.to_vec2()).to_pos2())
+            .collect();
+        let viewport = egui::ViewportBuilder::default()
+            .with_title("Automate Mouse Trail")
+            .with_position(monitor.min)
+            .with_inner_size(monitor.size())
+            .with_always_on_top()
+            .with_decorations(false)
+            .with_transparent(true)
+            .with_mouse_passthrough(true)
+            .with_active(false);
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("mouse_trail_overlay"),
+            viewport,
+            |ctx, _| {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::none())
+                    .show(ctx, |ui| draw_trail(ui.painter(), &points));
+            },
+        );
+    }
+    /// Shows the keys and buttons pressed recently in large text at the bottom of the screen, like screencast tools do
+    ///
+    /// Shown while recording and during playback, in a window that clicks go through
+    pub fn key_osd(&mut self, ctx: &egui::Context) {
+        let active = self.recording.load(Ordering::Relaxed) || self.play.load(Ordering::Relaxed);
+        if !active {
+            self.recent_inputs.lock().unwrap().clear();
+            return;
+        }
+        if !self.show_key_osd {
+            return;
+        }
+        let inputs: Vec<(String, f32)> = {
+            let mut recent_inputs = self.recent_inputs.lock().unwrap();
+            recent_inputs.retain(|(_, _, at)| at.elapsed() < OSD_DURATION);
+            let skip = recent_inputs.len().saturating_sub(OSD_MAX_INPUTS);
+            recent_inputs
+                .iter()
+                .skip(skip)
+                .map(|(input, count, at)| {
+                    let text = match count {
+                        1 => input.clone(),
+                        _ => format!("{} ×{}", input, count),
+                    };
+                    // Fade out over the last third of the duration
+                    let age = at.elapsed().as_secs_f32() / OSD_DURATION.as_secs_f32();
+                    (text, ((1. - age) * 3.).min(1.))
+                })
+                .collect()
+        };
+        let monitor = self.primary_monitor();
+        let position = pos2(
+            monitor.center().x - OSD_SIZE.x / 2.,
+            monitor.max.y - OSD_SIZE.y - 80.,
+        );
+        let viewport = egui::ViewportBuilder::default()
+            .with_title("Automate Keys")
+            .with_position(position)
+            .with_inner_size(OSD_SIZE)
+            .with_always_on_top()
+            .with_decorations(false)
+            .with_transparent(true)
+            .with_mouse_passthrough(true)
+            .with_active(false);
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("key_osd"),
+            viewport,
+            |ctx, _| {
+                egui::CentralPanel::default()
+                    .frame(egui::Frame::none())
+                    .show(ctx, |ui| {
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            for (text, opacity) in inputs.iter().rev() {
+                                egui::Frame::none()
+                                    .fill(egui::Color32::from_black_alpha((180. * opacity) as u8))
+                                    .rounding(8.)
+                                    .inner_margin(egui::Margin::symmetric(16., 8.))
+                                    .show(ui, |ui| {
+                                        ui.label(
+                                            egui::RichText::new(text).size(36.).color(
+                                                egui::Color32::WHITE.gamma_multiply(*opacity),
+                                            ),
+                                        );
+                                    });
+                            }
+                        });
+                    });
+            },
+        );
+        // Keep fading out keys that aren't being pressed anymore
+        ctx.request_repaint();
+    }
+    /// Positions of the enabled mouse moves in the visible part of the timeline, in order
+    fn mouse_trail(&self) -> Vec<Vec2> {
+        let (start, end) = (self.scroll, self.scroll + self.visible_duration());
+        self.keyframes
+            .iter()
+            .filter(|k| k.enabled && k.timestamp >= start && k.timestamp <= end)
+            .filter_map(|k| match k.keyframe_type {
+                KeyframeType::MouseMove(pos) => Some(pos),
+                _ => None,
+            })
+            .collect()
+    }
+    /// Bounds of the primary monitor, looked up the first time they are needed
+    fn primary_monitor(&mut self) -> Rect {
+        *self.primary_monitor.get_or_insert_with(|| {
+            monitor_bounds(None).unwrap_or(Rect::from_min_size(Pos2::ZERO, vec2(1920., 1080.)))
+        })
+    }
     /// Renders a modal that can be used for displaying errors or other
     pub fn modal(&mut self, ctx: &egui::Context) {
         egui::Window::new(self.modal.1.clone())
@@ -2164,8 +5567,7 @@ impl Sequencer {
     /// Adds a keyframe to the stack list and handles other necessary operations
     pub fn add_keyframe(&mut self, keyframe: &Keyframe) {
         let kf = keyframe.clone();
-        self.keyframes.push(keyframe.clone());
-        self.keyframe_state.push(0);
+        self.push_keyframe(keyframe.clone());
         self.changes.0.push(Change {
             uids: vec![],
             data: vec![ChangeData::AddKeyframes(vec![kf])],
@@ -2174,8 +5576,390 @@ impl Sequencer {
     }
 }
 
+/// The editor as a playback host, playing a step of the sequence each frame
+struct Editor<'a> {
+    sequencer: &'a mut Sequencer,
+    ctx: &'a egui::Context,
+    settings: &'a Settings,
+    playback: &'a PlaybackSettings,
+    /// Time spent in random delays this frame, which the playhead should not move for
+    delayed: Duration,
+}
+
+impl Host for Editor<'_> {
+    fn variables(&self, row: usize) -> Variables {
+        self.sequencer.row_variables(row)
+    }
+    fn dry_run(&self) -> bool {
+        self.sequencer.dry_run
+    }
+    fn sequential(&self) -> bool {
+        self.sequencer.sequential
+    }
+    fn entering(&mut self, keyframes: &[Keyframe], index: usize, time: f32) -> Result<bool, Stop> {
+        let sequencer = &mut *self.sequencer;
+        let uid = keyframes[index].uid;
+        // Hold the playhead here until the step is confirmed
+        if sequencer.confirm_steps && sequencer.confirmed_step != Some(uid) {
+            sequencer.awaiting_step = Some(uid);
+            sequencer.step_confirmed.store(false, Ordering::Relaxed);
+            return Ok(false);
+        }
+        sequencer.confirmed_step = None;
+        sequencer
+            .profile
+            .keyframe_started((time - keyframes[index].timestamp).max(0.) / sequencer.speed);
+        // When fail detection is enabled check if the keyframe has a screenshot
+        // The screen doesn't change during a dry run, so there is nothing to compare
+        if sequencer.dry_run || !(self.playback.fail_detection || self.settings.retake_screenshots)
+        {
+            return Ok(true);
+        }
+        let Some(src1) = screenshot() else {
+            return Ok(true);
+        };
+        let mut images = sequencer.images.lock().unwrap();
+        if self.settings.retake_screenshots {
+            // Replace the current screenshot with a new one
+            images.remove(&uid);
+            // The old screenshot's texture would be shown otherwise
+            sequencer.texture_handles.remove(&uid);
+            if sequencer.current_image_uid == uid {
+                sequencer.current_image_uid = Uuid::nil().to_bytes_le();
+            }
+            images.insert(uid, resize_screenshot(&src1, screenshot_quality().scale));
+            sequencer.saved_images.remove(&uid);
+        } else if let Some(src2) = images.get(&uid) {
+            let percentage_err = image_dif_opencv(&src1, src2);
+            if percentage_err > self.playback.max_fail_error as f32 {
+                drop(images);
+                sequencer.play.swap(false, Ordering::Relaxed);
+                sequencer.outcome = Some(PlaybackOutcome::FailDetected(percentage_err));
+                sequencer.failure_screenshot = Some(src1);
+                sequencer
+                    .events
+                    .push(PlaybackEvent::FailDetected { percentage_err });
+                self.ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+                sequencer.modal = (
+                    true,
+                    format!("Fail Detected: {:?}%", percentage_err).to_string(),
+                    "Paused playback as a result.".to_string(),
+                );
+                log::warn!("Fail Detected: {:?}% err", percentage_err);
+                return Err(Stop::Halted);
+            }
+        }
+        Ok(true)
+    }
+    fn delay(&mut self, delay: Duration) -> Result<(), Stop> {
+        // Waits on the play flag, which the panic key and fail safe clear from the record thread
+        let play = &self.sequencer.play;
+        let slept = engine::sleep_unless_stopped(delay, || !play.load(Ordering::Relaxed));
+        self.delayed += slept;
+        match self.sequencer.play.load(Ordering::Relaxed) {
+            true => Ok(()),
+            false => Err(Stop::Halted),
+        }
+    }
+    fn target_found(&mut self, path: &str) -> Result<bool, String> {
+        target_found(path)
+    }
+    fn play(&mut self, keyframe: &Keyframe, start: bool) -> Result<(), String> {
+        match self.sequencer.dry_run {
+            true => {
+                self.sequencer.ghost.play_keyframe(keyframe, start);
+                Ok(())
+            }
+            false => play_keyframe(keyframe, start, &self.playback.offset),
+        }
+    }
+    fn run_plugin(&mut self, path: &str, argument: &str) -> Result<plugin::Running, String> {
+        plugin::spawn(path, argument, &self.playback.offset)
+    }
+    fn event(&mut self, event: PlaybackEvent) {
+        if let PlaybackEvent::Log { message } = &event {
+            self.sequencer.run_log.push(message.clone());
+        }
+        self.sequencer.events.push(event);
+    }
+}
+
+/// Handles a global input event on the record thread
+///
+/// Stops playback for the panic key and fail safe and toggles recording, then sends the event to the sequencer while recording
+fn handle_record_event(shared: &RecordShared, event: rdev::Event) {
+    let is_recording = shared.recording.load(Ordering::Relaxed);
+    let dt = Instant::now().duration_since(*shared.recording_instant.lock().unwrap());
+    // Keybind(panic key): Stop playback and let go of held keys, reacting to the press rather than the release
+    let is_panic_key = match &event.event_type {
+        rdev::EventType::KeyPress(key) | rdev::EventType::KeyRelease(key) => {
+            *key == *shared.panic_key.lock().unwrap()
+        }
+        _ => false,
+    };
+    if is_panic_key {
+        if let rdev::EventType::KeyPress(_) = event.event_type {
+            if shared.play.swap(false, Ordering::Relaxed) {
+                shared.panicked.swap(true, Ordering::Relaxed);
+            }
+        }
+        return;
+    }
+    // Remember presses for the on screen display, which also catches the input simulated by playback
+    if is_recording || shared.play.load(Ordering::Relaxed) {
+        let input = match &event.event_type {
+            rdev::EventType::KeyPress(key) => Some(key_to_char(key)),
+            rdev::EventType::ButtonPress(button) => Some(button_to_char(button)),
+            _ => None,
+        };
+        if let Some(input) = input {
+            let mut recent_inputs = shared.recent_inputs.lock().unwrap();
+            match recent_inputs.last_mut() {
+                // Repeats of a held key are counted rather than shown again
+                Some((last, count, at)) if *last == input => {
+                    *count += 1;
+                    *at = Instant::now();
+                }
+                _ => recent_inputs.push((input, 1, Instant::now())),
+            }
+        }
+    }
+    // Handle global keybindings without focus
+    match &event.event_type {
+        rdev::EventType::KeyRelease(key) => {
+            let global_keys = *shared.global_keys.lock().unwrap();
+            // Keybind(F8): Toggle recording
+            if Some(*key) == global_keys.toggle_recording {
+                shared.recording.swap(!is_recording, Ordering::Relaxed);
+            }
+            // Keybind(esc): Toggle play execution
+            else if Some(*key) == global_keys.stop {
+                shared.play.swap(false, Ordering::Relaxed);
+            }
+            // Keybind(F9): Manually add a mouse move keyframe (can be used for filling in missed movements due to record resolution)
+            else if Some(*key) == global_keys.add_keyframe && is_recording {
+                let _ = shared.record_sender.send(RecordEvent::ManualMouseMove {
+                    time: dt.as_secs_f32(),
+                });
+            }
+            // Keybind(F7): Play the keyframe waiting for its step to be confirmed
+            else if Some(*key) == global_keys.confirm_step && shared.play.load(Ordering::Relaxed)
+            {
+                shared.step_confirmed.store(true, Ordering::Relaxed);
+            }
+        }
+        rdev::EventType::MouseMove { x, y } => {
+            // Update the current mouse position
+            *shared.mouse_pos.lock().unwrap() = Vec2::new(*x as f32, *y as f32);
+            // Offset Calibration
+            if shared.calibrate.load(Ordering::Relaxed) {
+                let _ = shared
+                    .record_sender
+                    .send(RecordEvent::Calibration(Vec2::new(*x as f32, *y as f32)));
+            }
+            // Handle monitor edge fail safe
+            let bounds = *shared.failsafe_bounds.lock().unwrap();
+            let edge = shared
+                .failsafe_edge_override
+                .lock()
+                .unwrap()
+                .unwrap_or(*shared.failsafe_edge.lock().unwrap());
+            if edge.hit(bounds, Pos2::new(*x as f32, *y as f32)) {
+                shared.play.swap(false, Ordering::Relaxed);
+            }
+        }
+        _ => {}
+    }
+    if is_recording {
+        let time = dt.as_secs_f32();
+        // Presses are screenshot straight away, before the screen reacts to them
+        let recorded = match &event.event_type {
+            rdev::EventType::ButtonPress(button) => {
+                let uid = Uuid::new_v4().to_bytes_le();
+                let _ = shared.screenshot_sender.send(uid);
+                RecordEvent::ButtonPress {
+                    button: *button,
+                    time,
+                    uid,
+                }
+            }
+            rdev::EventType::KeyPress(key) => {
+                let uid = Uuid::new_v4().to_bytes_le();
+                let _ = shared.screenshot_sender.send(uid);
+                RecordEvent::KeyPress {
+                    key: *key,
+                    time,
+                    uid,
+                }
+            }
+            rdev::EventType::ButtonRelease(button) => RecordEvent::ButtonRelease {
+                button: *button,
+                time,
+            },
+            rdev::EventType::KeyRelease(key) => RecordEvent::KeyRelease { key: *key, time },
+            rdev::EventType::MouseMove { x, y } => RecordEvent::MouseMove {
+                pos: Vec2::new(*x as f32, *y as f32),
+                time,
+            },
+            rdev::EventType::Wheel { delta_x, delta_y } => RecordEvent::Scroll {
+                delta: Vec2::new(*delta_x as f32, *delta_y as f32),
+                time,
+            },
+        };
+        let _ = shared.record_sender.send(recorded);
+    }
+}
+
+/// Draws a mouse path as a line through its points, with a dot at each point
+fn draw_trail(painter: &egui::Painter, points: &[Pos2]) {
+    let color = theme().selection_color();
+    for pair in points.windows(2) {
+        painter.line_segment([pair[0], pair[1]], egui::Stroke::new(1.5, color));
+    }
+    for point in points {
+        painter.circle_filled(*point, 2., color);
+    }
+}
+
+/// Shows a keyframe's note, where lines starting with `#` are headings, lines starting with `- ` are bullet points
+/// and text between `**` is bold
+fn render_note(ui: &mut Ui, note: &str) {
+    let (text_color, strong_color) = (ui.visuals().text_color(), ui.visuals().strong_text_color());
+    let format = |color| egui::TextFormat {
+        font_id: FontId::default(),
+        color,
+        ..Default::default()
+    };
+    for line in note.lines() {
+        if let Some(heading) = line.strip_prefix('#') {
+            ui.strong(heading.trim_start_matches('#').trim());
+            continue;
+        }
+        let (bullet, text) = match line.trim_start().strip_prefix("- ") {
+            Some(text) => ("• ", text),
+            None => ("", line),
+        };
+        let mut job = egui::text::LayoutJob::default();
+        job.append(bullet, 0., format(text_color));
+        // Every other part between `**` is bold
+        for (j, part) in text.split("**").enumerate() {
+            job.append(
+                part,
+                0.,
+                format(if j % 2 == 1 { strong_color } else { text_color }),
+            );
+        }
+        ui.label(job);
+    }
+}
+
+/// Title, hover text and variant of keyframe types that are edited as a single block of text
+fn text_field(
+    keyframe_type: &KeyframeType,
+) -> Option<(&'static str, &'static str, fn(String) -> KeyframeType)> {
+    match keyframe_type {
+        KeyframeType::Text(_) => Some((
+            "Text",
+            "{expression} is replaced by its value, e.g. row {i + 1}",
+            KeyframeType::Text,
+        )),
+        KeyframeType::Abort(_) => Some((
+            "Abort",
+            "Shown when playback is stopped, {expression} is replaced by its value",
+            KeyframeType::Abort,
+        )),
+        KeyframeType::Notify(_) => Some((
+            "Notification",
+            "{expression} is replaced by its value, e.g. batch {i + 1}/{n} complete",
+            KeyframeType::Notify,
+        )),
+        KeyframeType::Log(_) => Some((
+            "Log",
+            "Added to the run log, {expression} is replaced by its value, e.g. logging in as {user}",
+            KeyframeType::Log,
+        )),
+        _ => None,
+    }
+}
+
+/// Sets the expression used for `field` of the keyframe, an empty expression uses the recorded value
+fn set_expression(keyframe: &mut Keyframe, field: &str, expression: &str) {
+    if expression.trim().is_empty() {
+        keyframe.expressions.remove(field);
+    } else {
+        keyframe
+            .expressions
+            .insert(field.to_string(), expression.to_string());
+    }
+}
+
 impl Default for Sequencer {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframes(count: usize) -> Vec<Keyframe> {
+        (0..count)
+            .map(|i| Keyframe {
+                timestamp: i as f32,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Checks every keyframe is found at its own index, and that nothing else is indexed
+    fn assert_indexed(sequencer: &Sequencer) {
+        for (i, keyframe) in sequencer.keyframes.iter().enumerate() {
+            assert_eq!(sequencer.index_of(&keyframe.uid), Some(i));
+        }
+        assert_eq!(sequencer.uid_index.len(), sequencer.keyframes.len());
+    }
+
+    #[test]
+    fn indexes_keyframes_as_they_are_inserted() {
+        let mut sequencer = Sequencer::with_threads(false);
+        sequencer.append_keyframes(&mut keyframes(3));
+        sequencer.push_keyframe(Keyframe::default());
+        assert_indexed(&sequencer);
+        let inserted = Keyframe::default();
+        let uid = inserted.uid;
+        sequencer.insert_keyframe(1, inserted);
+        assert_eq!(sequencer.index_of(&uid), Some(1));
+        assert_indexed(&sequencer);
+    }
+
+    #[test]
+    fn indexes_keyframes_as_they_are_removed() {
+        let mut sequencer = Sequencer::with_threads(false);
+        sequencer.append_keyframes(&mut keyframes(4));
+        let removed = sequencer.remove_keyframes(&[1]);
+        assert_eq!(sequencer.index_of(&removed[0].uid), None);
+        assert_indexed(&sequencer);
+        sequencer.clear_keyframes();
+        assert_indexed(&sequencer);
+    }
+
+    #[test]
+    fn indexes_keyframes_removed_together() {
+        let mut sequencer = Sequencer::with_threads(false);
+        let mut added = keyframes(6);
+        let uids: Vec<Bytes> = added.iter().map(|keyframe| keyframe.uid).collect();
+        sequencer.append_keyframes(&mut added);
+        // Indices in any order come back from last to first
+        let removed = sequencer.remove_keyframes(&[1, 4, 2]);
+        let removed: Vec<Bytes> = removed.iter().map(|keyframe| keyframe.uid).collect();
+        assert_eq!(removed, vec![uids[4], uids[2], uids[1]]);
+        let kept: Vec<Bytes> = sequencer
+            .keyframes
+            .iter()
+            .map(|keyframe| keyframe.uid)
+            .collect();
+        assert_eq!(kept, vec![uids[0], uids[3], uids[5]]);
+        assert_indexed(&sequencer);
+    }
+}
@@ -1,10 +1,18 @@
-use std::ops::Add;
+use std::{ops::Add, path::PathBuf};
 
-use egui::{KeyboardShortcut, Vec2};
-use rdev::Button;
+use egui::{KeyboardShortcut, Pos2, Rect, Vec2};
+use rdev::{Button, Key};
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize)]
+use crate::{
+    input::InputBackendKind,
+    keyframe::{Keyframe, KeyframeType},
+    layout::KeyboardLayout,
+    theme::Theme,
+    util::{set_calibration, CaptureBackendKind, ScreenshotQuality},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
 pub enum KeybindType {
     SaveFile,
     NewFile,
@@ -15,18 +23,204 @@ pub enum KeybindType {
     NextKeyframe,
     PreviousKeyframe,
     TogglePlay,
+    StepTime,
     ResetTime,
     ToggleRecording,
     ToggleExecution,
     AddKeyframe,
+    ConfirmStep,
     SelectAll,
+    InvertSelection,
+    Duplicate,
+    ZoomToFit,
+    ZoomToSelection,
+    JumpToStart,
+    JumpToEnd,
+    PageBackward,
+    PageForward,
 }
+/// Keys that can be chosen as the panic key, as they are rarely part of a recording
+pub const PANIC_KEYS: [Key; 6] = [
+    Key::Pause,
+    Key::ScrollLock,
+    Key::PrintScreen,
+    Key::Insert,
+    Key::F12,
+    Key::F10,
+];
+
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
 pub enum MonitorEdge {
     Left,
     Right,
     Bottom,
     Top,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl MonitorEdge {
+    pub const ALL: [MonitorEdge; 8] = [
+        MonitorEdge::Left,
+        MonitorEdge::Right,
+        MonitorEdge::Bottom,
+        MonitorEdge::Top,
+        MonitorEdge::TopLeft,
+        MonitorEdge::TopRight,
+        MonitorEdge::BottomLeft,
+        MonitorEdge::BottomRight,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            MonitorEdge::Left => "Left",
+            MonitorEdge::Right => "Right",
+            MonitorEdge::Bottom => "Bottom",
+            MonitorEdge::Top => "Top",
+            MonitorEdge::TopLeft => "Top Left",
+            MonitorEdge::TopRight => "Top Right",
+            MonitorEdge::BottomLeft => "Bottom Left",
+            MonitorEdge::BottomRight => "Bottom Right",
+        }
+    }
+    /// Whether `pos` is on this edge of a monitor with `bounds`,
+    /// only near the monitor so crossing onto other monitors doesn't count
+    pub fn hit(&self, bounds: Rect, pos: Pos2) -> bool {
+        let left = pos.x <= bounds.min.x;
+        let right = pos.x >= bounds.max.x - 1.;
+        let top = pos.y <= bounds.min.y;
+        let bottom = pos.y >= bounds.max.y - 1.;
+        let hit = match self {
+            MonitorEdge::Left => left,
+            MonitorEdge::Right => right,
+            MonitorEdge::Bottom => bottom,
+            MonitorEdge::Top => top,
+            MonitorEdge::TopLeft => top && left,
+            MonitorEdge::TopRight => top && right,
+            MonitorEdge::BottomLeft => bottom && left,
+            MonitorEdge::BottomRight => bottom && right,
+        };
+        hit && bounds.expand(1.).contains(pos)
+    }
+}
+
+/// Rows of the sequencer that keyframes are drawn on
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub enum Track {
+    Keyboard,
+    Mouse,
+    Movement,
+}
+
+impl Track {
+    pub const ALL: [Track; 3] = [Track::Keyboard, Track::Mouse, Track::Movement];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Track::Keyboard => "Keyboard",
+            Track::Mouse => "Mouse",
+            Track::Movement => "Movement",
+        }
+    }
+    /// Kinds of the keyframes on the track, shown when hovering its name
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Track::Keyboard => "id: 0",
+            Track::Mouse => "id: 2,3",
+            Track::Movement => "id: 1",
+        }
+    }
+    /// Track that keyframes of `kind` are drawn on, groups like loops span all of them instead
+    pub fn of(kind: u8) -> Self {
+        match kind {
+            1 | 6 => Track::Movement,
+            2 | 3 => Track::Mouse,
+            _ => Track::Keyboard,
+        }
+    }
+}
+
+/// Position and visibility of a track in the sequencer
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct TrackLayout {
+    pub track: Track,
+    pub visible: bool,
+}
+
+/// A monitor offset saved under a name, for each display setup the offset was calibrated on
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct OffsetProfile {
+    pub name: String,
+    pub offset: Vec2,
+    /// Multi-point calibration of the display setup, if it was calibrated with one
+    #[serde(default)]
+    pub monitors: Vec<MonitorCalibration>,
+}
+
+/// A keyframe saved under a name along with its settings, added with one click from the Add Keyframe window
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct KeyframeTemplate {
+    pub name: String,
+    pub keyframe: Keyframe,
+}
+
+/// How positions reported by the mouse hook on one monitor map to the positions that have to be simulated to reach them
+///
+/// Found by [`crate::sequencer::Sequencer::calibrate_monitors`], which unlike the single point offset also handles display scaling
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct MonitorCalibration {
+    /// Bounds of the monitor in reported positions
+    pub bounds: Rect,
+    pub scale: Vec2,
+    pub offset: Vec2,
+}
+
+impl MonitorCalibration {
+    /// Fits a scale and offset on each axis to pairs of simulated and reported positions with least squares
+    ///
+    /// `monitor` is the bounds of the monitor in simulated positions.
+    /// Returns `None` if the reported positions don't spread out enough on both axes to fit a scale
+    pub fn fit(samples: &[(Vec2, Vec2)], monitor: Rect) -> Option<Self> {
+        let n = samples.len() as f32;
+        let mean_simulated = samples.iter().fold(Vec2::ZERO, |sum, (s, _)| sum + *s) / n;
+        let mean_reported = samples.iter().fold(Vec2::ZERO, |sum, (_, r)| sum + *r) / n;
+        let mut covariance = Vec2::ZERO;
+        let mut variance = Vec2::ZERO;
+        for (simulated, reported) in samples {
+            let r = *reported - mean_reported;
+            covariance += r * (*simulated - mean_simulated);
+            variance += r * r;
+        }
+        if variance.x < 1. || variance.y < 1. {
+            return None;
+        }
+        let scale = covariance / variance;
+        let offset = mean_simulated - mean_reported * scale;
+        // Bring the corners of the monitor back into reported positions
+        let to_reported = |p: Vec2| ((p - offset) / scale).to_pos2();
+        let bounds = Rect::from_two_pos(
+            to_reported(monitor.min.to_vec2()),
+            to_reported(monitor.max.to_vec2()),
+        );
+        Some(Self {
+            bounds,
+            scale,
+            offset,
+        })
+    }
+    /// The position to simulate to reach the reported position `pos`
+    pub fn apply(&self, pos: Vec2) -> Vec2 {
+        pos * self.scale + self.offset
+    }
+}
+
+/// Playback settings that depend on the machine a sequence was made for, so a file can override the global ones
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct PlaybackSettings {
+    pub fail_detection: bool,
+    pub max_fail_error: u32,
+    pub offset: Vec2,
+    pub failsafe_edge: MonitorEdge,
 }
 
 pub enum SettingsPage {
@@ -39,7 +233,7 @@ impl Default for SettingsPage {
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Keybind {
     pub text: String,
     pub kind: KeybindType,
@@ -55,15 +249,204 @@ impl Keybind {
     }
 }
 
+/// Keybinds every action starts with, in the order they are listed on the Shortcuts page
+pub fn default_keybinds() -> Vec<Keybind> {
+    vec![
+        Keybind::new(
+            "Save File".to_string(),
+            KeybindType::SaveFile,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::S),
+        ),
+        Keybind::new(
+            "New File".to_string(),
+            KeybindType::NewFile,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::N),
+        ),
+        Keybind::new(
+            "Open File".to_string(),
+            KeybindType::OpenFile,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::O),
+        ),
+        Keybind::new(
+            "Undo".to_string(),
+            KeybindType::Undo,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Z),
+        ),
+        Keybind::new(
+            "Redo".to_string(),
+            KeybindType::Redo,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Y),
+        ),
+        Keybind::new(
+            "Toggle Settings".to_string(),
+            KeybindType::ToggleSettings,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Comma),
+        ),
+        Keybind::new(
+            "Next Keyframe".to_string(),
+            KeybindType::NextKeyframe,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::ArrowRight),
+        ),
+        Keybind::new(
+            "Previous Keyframe".to_string(),
+            KeybindType::PreviousKeyframe,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::ArrowLeft),
+        ),
+        Keybind::new(
+            "Toggle Play".to_string(),
+            KeybindType::TogglePlay,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Space),
+        ),
+        Keybind::new(
+            "Step Forward".to_string(),
+            KeybindType::StepTime,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::ArrowRight),
+        ),
+        Keybind::new(
+            "Reset Time".to_string(),
+            KeybindType::ResetTime,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::ArrowLeft),
+        ),
+        Keybind::new(
+            "Toggle Recording".to_string(),
+            KeybindType::ToggleRecording,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F8),
+        ),
+        Keybind::new(
+            "Toggle Execution".to_string(),
+            KeybindType::ToggleExecution,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Escape),
+        ),
+        Keybind::new(
+            "Add Keyframe".to_string(),
+            KeybindType::AddKeyframe,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F9),
+        ),
+        Keybind::new(
+            "Confirm Step".to_string(),
+            KeybindType::ConfirmStep,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F7),
+        ),
+        Keybind::new(
+            "Select All".to_string(),
+            KeybindType::SelectAll,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::A),
+        ),
+        Keybind::new(
+            "Invert Selection".to_string(),
+            KeybindType::InvertSelection,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::I),
+        ),
+        Keybind::new(
+            "Duplicate".to_string(),
+            KeybindType::Duplicate,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::D),
+        ),
+        Keybind::new(
+            "Zoom to Fit".to_string(),
+            KeybindType::ZoomToFit,
+            KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Num0),
+        ),
+        Keybind::new(
+            "Zoom to Selection".to_string(),
+            KeybindType::ZoomToSelection,
+            KeyboardShortcut::new(
+                egui::Modifiers::CTRL | egui::Modifiers::SHIFT,
+                egui::Key::Num0,
+            ),
+        ),
+        Keybind::new(
+            "Jump to Start".to_string(),
+            KeybindType::JumpToStart,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Home),
+        ),
+        Keybind::new(
+            "Jump to End".to_string(),
+            KeybindType::JumpToEnd,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::End),
+        ),
+        Keybind::new(
+            "Page Backward".to_string(),
+            KeybindType::PageBackward,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::PageUp),
+        ),
+        Keybind::new(
+            "Page Forward".to_string(),
+            KeybindType::PageForward,
+            KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::PageDown),
+        ),
+    ]
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct Settings {
     #[serde(skip)]
     pub keybind_search: String,
+    /// Index of the keybind waiting for its new shortcut to be pressed
+    #[serde(skip)]
+    pub capturing_keybind: Option<usize>,
+    /// Why the last captured shortcut was refused
+    #[serde(skip)]
+    pub keybind_conflict: Option<String>,
+    #[serde(deserialize_with = "deserialize_keybinds")]
     pub keybinds: Vec<Keybind>,
     pub fail_detection: bool,
     pub max_fail_error: u32,
     pub offset: Vec2,
+    /// Saved offsets to switch between when the display setup changes
+    pub offset_profiles: Vec<OffsetProfile>,
+    /// Multi-point calibration of each monitor, used instead of the offset for positions on them
+    pub monitor_calibration: Vec<MonitorCalibration>,
+    /// Name typed in for the next offset profile
+    #[serde(skip)]
+    pub offset_profile_name: String,
     pub retake_screenshots: bool,
+    /// Movements closer than this (in pixels) to the last kept movement are culled
+    pub cull_min_distance: f32,
+    /// Movements that change direction less than this (in degrees) are culled
+    pub cull_min_angle: f32,
+    /// Movements where the cursor rests for at least this long (in seconds) are never culled
+    pub cull_min_pause: f32,
+    /// Maximum distance (in pixels) a simplified path may stray from the recorded path
+    pub simplify_tolerance: f32,
+    /// Keyframes taking longer than this (in seconds) are flagged before playback
+    pub max_keyframe_duration: f32,
+    pub autosave: bool,
+    /// Minutes between autosaves of unsaved changes
+    pub autosave_interval: f32,
+    pub autosave_directory: PathBuf,
+    /// Number of previous versions kept when saving over a file
+    pub backup_count: u32,
+    /// Folder of the plugin registry, whose WASM plugins are offered when adding a plugin keyframe
+    pub plugin_directory: PathBuf,
+    /// Serve the HTTP remote control API on localhost
+    pub remote_control: bool,
+    pub remote_port: u16,
+    /// Port of the WebSocket streaming playback events
+    pub remote_event_port: u16,
+    /// Accept commands from other processes over a named pipe / Unix socket
+    pub local_control: bool,
+    /// Library used to simulate input during playback
+    pub input_backend: InputBackendKind,
+    /// Times a refused input event is simulated again before playback stops
+    pub simulation_retries: u32,
+    /// Program used to capture screenshots
+    pub capture_backend: CaptureBackendKind,
+    pub screenshot_quality: ScreenshotQuality,
+    /// Layout of the keyboard, for converting between keys and the characters they type
+    pub keyboard_layout: KeyboardLayout,
+    pub theme: Theme,
+    /// Global key that stops playback and releases held keys, see [`PANIC_KEYS`]
+    pub panic_key: Key,
+    /// Minimize the editor while playing so it doesn't cover the application being automated
+    pub minimize_on_play: bool,
+    /// Seconds between pressing play and the first keyframe being played
+    pub start_delay: f32,
+    /// Order of the tracks in the sequencer from top to bottom and whether they are shown
+    pub tracks: Vec<TrackLayout>,
+    /// Keyframes listed at the top of the Add Keyframe window
+    pub keyframe_templates: Vec<KeyframeTemplate>,
     #[serde(skip)]
     pub page: SettingsPage,
     #[serde(skip)]
@@ -75,82 +458,55 @@ impl Default for Settings {
     fn default() -> Self {
         Self {
             keybind_search: "".to_string(),
-            keybinds: vec![
-                Keybind::new(
-                    "Save File".to_string(),
-                    KeybindType::SaveFile,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::S),
-                ),
-                Keybind::new(
-                    "New File".to_string(),
-                    KeybindType::NewFile,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::N),
-                ),
-                Keybind::new(
-                    "Open File".to_string(),
-                    KeybindType::OpenFile,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::O),
-                ),
-                Keybind::new(
-                    "Undo".to_string(),
-                    KeybindType::Undo,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Z),
-                ),
-                Keybind::new(
-                    "Redo".to_string(),
-                    KeybindType::Redo,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Y),
-                ),
-                Keybind::new(
-                    "Toggle Settings".to_string(),
-                    KeybindType::ToggleSettings,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::Comma),
-                ),
-                Keybind::new(
-                    "Next Keyframe".to_string(),
-                    KeybindType::NextKeyframe,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::ArrowRight),
-                ),
-                Keybind::new(
-                    "Previous Keyframe".to_string(),
-                    KeybindType::PreviousKeyframe,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::ArrowLeft),
-                ),
-                Keybind::new(
-                    "Toggle Play".to_string(),
-                    KeybindType::TogglePlay,
-                    KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Space),
-                ),
-                Keybind::new(
-                    "Reset Time".to_string(),
-                    KeybindType::ResetTime,
-                    KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::ArrowLeft),
-                ),
-                Keybind::new(
-                    "Toggle Recording".to_string(),
-                    KeybindType::ToggleRecording,
-                    KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F8),
-                ),
-                Keybind::new(
-                    "Toggle Execution".to_string(),
-                    KeybindType::ToggleExecution,
-                    KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::Escape),
-                ),
-                Keybind::new(
-                    "Add Keyframe".to_string(),
-                    KeybindType::AddKeyframe,
-                    KeyboardShortcut::new(egui::Modifiers::NONE, egui::Key::F9),
-                ),
-                Keybind::new(
-                    "Select All".to_string(),
-                    KeybindType::SelectAll,
-                    KeyboardShortcut::new(egui::Modifiers::CTRL, egui::Key::A),
-                ),
-            ],
+            capturing_keybind: None,
+            keybind_conflict: None,
+            keybinds: default_keybinds(),
             fail_detection: true,
             max_fail_error: 20,
             offset: Vec2::NAN,
+            offset_profiles: vec![],
+            monitor_calibration: vec![],
+            offset_profile_name: String::new(),
             retake_screenshots: false,
+            cull_min_distance: 30.,
+            cull_min_angle: 30.,
+            cull_min_pause: 0.5,
+            simplify_tolerance: 4.,
+            max_keyframe_duration: 300.,
+            autosave: true,
+            autosave_interval: 5.,
+            autosave_directory: std::env::temp_dir().join("automate_autosaves"),
+            backup_count: 3,
+            plugin_directory: crate::plugin::default_directory(),
+            remote_control: false,
+            remote_port: 7878,
+            remote_event_port: 7879,
+            local_control: false,
+            input_backend: InputBackendKind::Rdev,
+            simulation_retries: 2,
+            capture_backend: CaptureBackendKind::Xcap,
+            screenshot_quality: ScreenshotQuality::default(),
+            keyboard_layout: KeyboardLayout::Qwerty,
+            theme: Theme::default(),
+            panic_key: Key::Pause,
+            minimize_on_play: false,
+            start_delay: 0.,
+            tracks: Track::ALL
+                .into_iter()
+                .map(|track| TrackLayout {
+                    track,
+                    visible: true,
+                })
+                .collect(),
+            keyframe_templates: vec![KeyframeTemplate {
+                name: "Wait 2s".to_string(),
+                keyframe: Keyframe {
+                    duration: 2.,
+                    keyframe_type: KeyframeType::Wait(2.),
+                    kind: 4,
+                    ..Default::default()
+                },
+            }],
             page: SettingsPage::Preferences,
             show: false,
             add_keyframe_data: AddKeyframeData {
@@ -161,11 +517,176 @@ impl Default for Settings {
                 wait: 0.0,
                 magic_move_path: String::new(),
                 loop_iterations: 1,
+                text: String::new(),
+                random_branches: 2,
+                label: String::new(),
+                max_jumps: 10,
+                abort_message: String::new(),
+                notification: String::new(),
+                sound_path: String::new(),
+                log_message: String::new(),
+                desktop: 1,
+                hold_key_str: String::new(),
+                hold_duration: 1.,
+                counter_name: String::new(),
+                counter_reset: false,
+                counter_value: 1,
+                while_path: String::new(),
+                while_present: true,
+                if_path: String::new(),
+                if_present: true,
+                subroutine: String::new(),
+                plugin_path: String::new(),
+                template_name: String::new(),
+                insert_at: InsertAt::Playhead,
+                insert_timestamp: 0.,
             },
         }
     }
 }
 
+/// Whether both shortcuts are pressed the same way, treating ctrl and command as the same modifier
+fn same_shortcut(a: &KeyboardShortcut, b: &KeyboardShortcut) -> bool {
+    a.logical_key == b.logical_key
+        && (a.modifiers.matches_exact(b.modifiers) || b.modifiers.matches_exact(a.modifiers))
+}
+
+/// Reads the saved keybinds on top of the defaults
+///
+/// Keybinds added since the settings were saved get their default shortcut and the names always come from the defaults
+fn deserialize_keybinds<'de, D>(deserializer: D) -> Result<Vec<Keybind>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let saved = Vec::<Keybind>::deserialize(deserializer)?;
+    let mut keybinds = default_keybinds();
+    for keybind in &mut keybinds {
+        if let Some(saved) = saved.iter().find(|saved| saved.kind == keybind.kind) {
+            keybind.keybind = saved.keybind;
+        }
+    }
+    Ok(keybinds)
+}
+
+impl Settings {
+    /// Shortcut bound to `kind`
+    pub fn shortcut(&self, kind: KeybindType) -> Option<KeyboardShortcut> {
+        self.keybinds
+            .iter()
+            .find(|keybind| keybind.kind == kind)
+            .map(|keybind| keybind.keybind)
+    }
+    /// Shortcut `kind` is bound to by default
+    pub fn default_shortcut(kind: KeybindType) -> Option<KeyboardShortcut> {
+        default_keybinds()
+            .into_iter()
+            .find(|keybind| keybind.kind == kind)
+            .map(|keybind| keybind.keybind)
+    }
+    /// Whether the shortcut bound to `kind` was pressed this frame, with exactly its modifiers
+    ///
+    /// Shift may also be held for the next and previous keyframe keybinds, as it extends the selection.
+    /// Nothing is pressed while a new shortcut is being captured.
+    pub fn pressed(&self, input: &egui::InputState, kind: KeybindType) -> bool {
+        let Some(shortcut) = self.shortcut(kind) else {
+            return false;
+        };
+        if self.capturing_keybind.is_some() {
+            return false;
+        }
+        let extends = matches!(
+            kind,
+            KeybindType::NextKeyframe | KeybindType::PreviousKeyframe
+        );
+        input.key_pressed(shortcut.logical_key)
+            && (input.modifiers.matches_exact(shortcut.modifiers)
+                || extends
+                    && input
+                        .modifiers
+                        .matches_exact(shortcut.modifiers | egui::Modifiers::SHIFT))
+    }
+    /// Like [`Settings::pressed`] but takes the key press away from other widgets
+    pub fn consume(&self, input: &mut egui::InputState, kind: KeybindType) -> bool {
+        self.capturing_keybind.is_none()
+            && self
+                .shortcut(kind)
+                .is_some_and(|shortcut| input.consume_shortcut(&shortcut))
+    }
+    /// Visible tracks from top to bottom, always at least one
+    pub fn visible_tracks(&self) -> Vec<Track> {
+        let tracks: Vec<Track> = self
+            .tracks
+            .iter()
+            .filter(|layout| layout.visible)
+            .map(|layout| layout.track)
+            .collect();
+        match tracks.is_empty() {
+            true => Track::ALL.to_vec(),
+            false => tracks,
+        }
+    }
+    /// Names of the actions other than the one at `index` that are bound to `shortcut`
+    pub fn conflicts(&self, index: usize, shortcut: &KeyboardShortcut) -> Vec<&str> {
+        self.keybinds
+            .iter()
+            .enumerate()
+            .filter(|(i, keybind)| *i != index && same_shortcut(&keybind.keybind, shortcut))
+            .map(|(_, keybind)| keybind.text.as_str())
+            .collect()
+    }
+    /// Common modifier combinations with `key` that no action is bound to yet
+    pub fn free_shortcuts(&self, key: egui::Key) -> Vec<KeyboardShortcut> {
+        [
+            egui::Modifiers::NONE,
+            egui::Modifiers::CTRL,
+            egui::Modifiers::SHIFT,
+            egui::Modifiers::ALT,
+            egui::Modifiers::CTRL | egui::Modifiers::SHIFT,
+            egui::Modifiers::CTRL | egui::Modifiers::ALT,
+        ]
+        .into_iter()
+        .map(|modifiers| KeyboardShortcut::new(modifiers, key))
+        .filter(|shortcut| {
+            !self
+                .keybinds
+                .iter()
+                .any(|keybind| same_shortcut(&keybind.keybind, shortcut))
+        })
+        .collect()
+    }
+    /// Text of the shortcut bound to `kind`, shown next to menu buttons
+    pub fn shortcut_text(&self, ctx: &egui::Context, kind: KeybindType) -> String {
+        self.shortcut(kind)
+            .map(|shortcut| ctx.format_shortcut(&shortcut))
+            .unwrap_or_default()
+    }
+    /// Index of the offset profile matching the current calibration, if there is one
+    pub fn offset_profile(&self) -> Option<usize> {
+        self.offset_profiles.iter().position(|profile| {
+            profile.offset == self.offset && profile.monitors == self.monitor_calibration
+        })
+    }
+    /// Switches to the calibration saved in the offset profile at `index`
+    pub fn use_offset_profile(&mut self, index: usize) {
+        if let Some(profile) = self.offset_profiles.get(index) {
+            self.offset = profile.offset;
+            self.monitor_calibration = profile.monitors.clone();
+            set_calibration(self.monitor_calibration.clone());
+        }
+    }
+}
+
+/// Where the Add Keyframe window inserts keyframes
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize, Serialize)]
+pub enum InsertAt {
+    #[default]
+    Playhead,
+    /// At the end of the selected keyframes
+    AfterSelection,
+    /// At [`AddKeyframeData::insert_timestamp`]
+    Timestamp,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct AddKeyframeData {
     #[serde(skip)]
@@ -176,4 +697,50 @@ pub struct AddKeyframeData {
     pub wait: f32,
     pub magic_move_path: String,
     pub loop_iterations: u32,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub random_branches: usize,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub max_jumps: u32,
+    #[serde(default)]
+    pub abort_message: String,
+    #[serde(default)]
+    pub notification: String,
+    #[serde(default)]
+    pub sound_path: String,
+    #[serde(default)]
+    pub log_message: String,
+    #[serde(default)]
+    pub desktop: u32,
+    #[serde(default)]
+    pub hold_key_str: String,
+    #[serde(default)]
+    pub hold_duration: f32,
+    #[serde(default)]
+    pub counter_name: String,
+    #[serde(default)]
+    pub counter_reset: bool,
+    #[serde(default)]
+    pub counter_value: i64,
+    #[serde(default)]
+    pub while_path: String,
+    #[serde(default)]
+    pub while_present: bool,
+    #[serde(default)]
+    pub if_path: String,
+    #[serde(default)]
+    pub if_present: bool,
+    #[serde(default)]
+    pub subroutine: String,
+    #[serde(default)]
+    pub plugin_path: String,
+    #[serde(default)]
+    pub template_name: String,
+    #[serde(default)]
+    pub insert_at: InsertAt,
+    #[serde(default)]
+    pub insert_timestamp: f32,
 }
@@ -0,0 +1,96 @@
+use std::sync::Mutex;
+
+use egui::{Color32, Visuals};
+use serde::{Deserialize, Serialize};
+
+/// Whether the app is drawn light on dark or dark on light
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum ThemeMode {
+    #[default]
+    Dark,
+    Light,
+}
+
+/// Look of the app, applied on top of egui's default visuals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Theme {
+    pub mode: ThemeMode,
+    /// Replaces egui's blue for selected text and widgets, and the orange outline of selected keyframes
+    pub accent: Option<Color32>,
+}
+
+impl Theme {
+    /// Outline of selected keyframes
+    pub fn selection_color(&self) -> Color32 {
+        self.accent.unwrap_or(Color32::from_rgb(233, 181, 125))
+    }
+    /// Fill of keyframes of the given kind, a little darker on light backgrounds so they stand out
+    pub fn keyframe_color(&self, kind: u8) -> Color32 {
+        let color = match kind {
+            0 => Color32::LIGHT_RED,                //Keyboard
+            1 => Color32::from_rgb(95, 186, 213),   //Mouse move
+            2 => Color32::LIGHT_GREEN,              //Button Click
+            3 => Color32::from_rgb(255, 234, 127),  //Scroll
+            4 => Color32::BLACK,                    //Wait
+            5 => Color32::LIGHT_RED,                //Keyboard
+            6 => Color32::from_rgb(214, 180, 252),  //Mouse move
+            7 => Color32::TRANSPARENT,              //Loop
+            8 => Color32::LIGHT_RED,                //Text
+            9 => Color32::TRANSPARENT,              //Random
+            10 => Color32::from_rgb(180, 220, 255), //Label
+            11 => Color32::from_rgb(255, 190, 120), //Jump
+            12 => Color32::from_rgb(230, 90, 90),   //Abort
+            13 => Color32::from_rgb(255, 234, 180), //Notify
+            14 => Color32::from_rgb(255, 234, 180), //Beep
+            15 => Color32::from_rgb(200, 200, 200), //Log
+            16 => Color32::from_rgb(180, 220, 255), //Desktop
+            17 => Color32::from_rgb(147, 190, 66),  //Hold
+            18 => Color32::from_rgb(200, 200, 200), //Counter
+            19 => Color32::TRANSPARENT,             //While Image
+            20 => Color32::TRANSPARENT,             //If Image
+            21 => Color32::TRANSPARENT,             //Subroutine
+            22 => Color32::from_rgb(255, 190, 120), //Call
+            23 => Color32::from_rgb(214, 180, 252), //Plugin
+            _ => Color32::LIGHT_GRAY,
+        };
+        match self.mode {
+            ThemeMode::Dark => color,
+            // Scaling the channels keeps transparent keyframes transparent, as the color is premultiplied
+            ThemeMode::Light => {
+                let [r, g, b, a] = color.to_array();
+                let darken = |c: u8| (c as f32 * 0.8) as u8;
+                Color32::from_rgba_premultiplied(darken(r), darken(g), darken(b), a)
+            }
+        }
+    }
+    /// Sets the visuals of the app to match the theme
+    pub fn apply(&self, ctx: &egui::Context) {
+        let mut visuals = match self.mode {
+            ThemeMode::Dark => Visuals::dark(),
+            ThemeMode::Light => Visuals::light(),
+        };
+        if let Some(accent) = self.accent {
+            visuals.selection.bg_fill = accent;
+            visuals.hyperlink_color = accent;
+            visuals.widgets.hovered.bg_stroke.color = accent;
+            // Keep selected text readable on bright accents
+            visuals.selection.stroke.color = match egui::Rgba::from(accent).intensity() > 0.6 {
+                true => Color32::BLACK,
+                false => Color32::WHITE,
+            };
+        }
+        ctx.set_visuals(visuals);
+        *THEME.lock().unwrap() = *self;
+    }
+}
+
+static THEME: Mutex<Theme> = Mutex::new(Theme {
+    mode: ThemeMode::Dark,
+    accent: None,
+});
+
+/// The theme the app was last drawn with
+pub fn theme() -> Theme {
+    *THEME.lock().unwrap()
+}
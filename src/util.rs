@@ -1,7 +1,12 @@
+use std::sync::Mutex;
+
 use egui::{emath::RectTransform, vec2, Pos2, Rect, Ui, Vec2};
 use image::{DynamicImage, ImageBuffer, Rgba};
+use serde::{Deserialize, Serialize};
 use xcap::Monitor;
 
+use crate::{input, layout::layout, settings::MonitorCalibration};
+
 pub const ROW_HEIGHT: f32 = 24.0;
 
 /// Converts a given `t` in seconds to a window space rect using `d` duration to determine the width
@@ -50,11 +55,38 @@ pub fn selection_contains_keyframe(selection: &Rect, keyframe: Rect) -> bool {
 
 pub fn keys_to_string(keys: &Vec<rdev::Key>) -> String {
     let mut string = String::new();
-    for key in keys {
+    let mut keys = keys.iter().peekable();
+    while let Some(key) = keys.next() {
+        // Shift followed by a character key is shown as the character it types
+        if is_shift(key) {
+            if let Some(c) = keys.peek().and_then(|next| layout().shifted_char_for(next)) {
+                string.push(c);
+                keys.next();
+                continue;
+            }
+        }
         string.push_str(key_to_char(key).as_str());
     }
     string
 }
+/// Returns whether the key is one of the shift keys
+pub fn is_shift(key: &rdev::Key) -> bool {
+    matches!(key, rdev::Key::ShiftLeft | rdev::Key::ShiftRight)
+}
+/// Returns whether the key is a modifier, which is held down until the next key has been pressed when typing keys
+pub fn is_modifier(key: &rdev::Key) -> bool {
+    matches!(
+        key,
+        rdev::Key::ShiftLeft
+            | rdev::Key::ShiftRight
+            | rdev::Key::ControlLeft
+            | rdev::Key::ControlRight
+            | rdev::Key::Alt
+            | rdev::Key::AltGr
+            | rdev::Key::MetaLeft
+            | rdev::Key::MetaRight
+    )
+}
 #[allow(unused)]
 pub fn strings_to_keys(string: &String) -> Vec<rdev::Key> {
     let mut keys = vec![];
@@ -65,18 +97,48 @@ pub fn strings_to_keys(string: &String) -> Vec<rdev::Key> {
             keys.push(key);
         } else {
             // if it failes then loop through the individual characters of the string too
-            for y in x.chars().into_iter() {
-                let key = string_to_keys(y.to_string().as_str());
-                if let Some(key) = key {
-                    keys.push(key);
+            for y in x.chars() {
+                if let Some(char_keys) = char_to_keys(y) {
+                    keys.extend(char_keys);
                 }
             }
         }
     }
     return keys;
 }
+/// Converts text into the keys that type it one character at a time, keeping spaces and new lines
+///
+/// Characters that need shift are preceded by [`rdev::Key::ShiftLeft`]
+pub fn text_to_keys(text: &str) -> Vec<rdev::Key> {
+    text.chars()
+        .filter_map(|c| match c {
+            ' ' => Some(vec![rdev::Key::Space]),
+            '\n' => Some(vec![rdev::Key::Return]),
+            '\t' => Some(vec![rdev::Key::Tab]),
+            c => char_to_keys(c),
+        })
+        .flatten()
+        .collect()
+}
+/// Converts a character into the keys that type it on the selected keyboard layout, with shift first if it is needed
+pub fn char_to_keys(c: char) -> Option<Vec<rdev::Key>> {
+    if let Some(key) = layout().key_for(c) {
+        return Some(vec![key]);
+    }
+    if let Some(key) = layout().shifted_key_for(c) {
+        return Some(vec![rdev::Key::ShiftLeft, key]);
+    }
+    string_to_keys(&c.to_string()).map(|key| vec![key])
+}
+/// Converts the name of a key, or a single character, into the key that types it on the selected keyboard layout
 #[allow(unused)]
 pub fn string_to_keys(c: &str) -> Option<rdev::Key> {
+    let mut chars = c.chars();
+    if let (Some(character), None) = (chars.next(), chars.next()) {
+        if let Some(key) = layout().key_for(character) {
+            return Some(key);
+        }
+    }
     match c {
         "a" => Some(rdev::Key::KeyA),
         "b" => Some(rdev::Key::KeyB),
@@ -186,7 +248,25 @@ pub fn string_to_keys(c: &str) -> Option<rdev::Key> {
         _ => None,
     }
 }
+/// Converts a key pressed in the editor into the key heard by the record thread, for keybinds that work without focus
+pub fn egui_to_rdev_key(key: egui::Key) -> Option<rdev::Key> {
+    match key {
+        egui::Key::Escape => Some(rdev::Key::Escape),
+        egui::Key::Enter => Some(rdev::Key::Return),
+        egui::Key::Backspace => Some(rdev::Key::Backspace),
+        egui::Key::Delete => Some(rdev::Key::Delete),
+        egui::Key::ArrowUp => Some(rdev::Key::UpArrow),
+        egui::Key::ArrowDown => Some(rdev::Key::DownArrow),
+        egui::Key::ArrowLeft => Some(rdev::Key::LeftArrow),
+        egui::Key::ArrowRight => Some(rdev::Key::RightArrow),
+        // The rest are named the same, e.g. "F8" and "PageUp"
+        _ => string_to_keys(&key.name().to_lowercase()),
+    }
+}
 pub fn key_to_char(k: &rdev::Key) -> String {
+    if let Some(character) = layout().char_for(k) {
+        return character.to_string();
+    }
     match k {
         rdev::Key::KeyA => "a",
         rdev::Key::KeyB => "b",
@@ -314,6 +394,57 @@ pub fn scroll_to_char(delta: &Vec2) -> String {
     .to_string();
 }
 
+/// Returns the change in direction between two movement vectors in degrees, from `0` to `180`
+pub fn direction_change(a: Vec2, b: Vec2) -> f32 {
+    if a == Vec2::ZERO || b == Vec2::ZERO {
+        return 0.;
+    }
+    let delta = (b.angle() - a.angle()).abs() % std::f32::consts::TAU;
+    delta.min(std::f32::consts::TAU - delta).to_degrees()
+}
+
+/// Simplifies a path using the Douglas–Peucker algorithm
+///
+/// Returns the indices of the points to keep so that no removed point is further than `tolerance` pixels from the simplified path.
+/// The first and last points are always kept.
+pub fn douglas_peucker(points: &[Vec2], tolerance: f32) -> Vec<usize> {
+    if points.len() < 3 {
+        return (0..points.len()).collect();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // Use a stack of (start, end) segments instead of recursion, long recordings can have thousands of points
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = stack.pop() {
+        let (a, b) = (points[start], points[end]);
+        let mut furthest = (0, 0.0);
+        for i in start + 1..end {
+            let distance = distance_to_segment(points[i], a, b);
+            if distance > furthest.1 {
+                furthest = (i, distance);
+            }
+        }
+        if furthest.1 > tolerance {
+            keep[furthest.0] = true;
+            stack.push((start, furthest.0));
+            stack.push((furthest.0, end));
+        }
+    }
+    (0..points.len()).filter(|i| keep[*i]).collect()
+}
+
+/// Returns the shortest distance between point `p` and the line segment from `a` to `b`
+pub fn distance_to_segment(p: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let length_sq = ab.length_sq();
+    if length_sq == 0. {
+        return (p - a).length();
+    }
+    let t = ((p - a).dot(ab) / length_sq).clamp(0., 1.);
+    (p - (a + ab * t)).length()
+}
+
 /// Correctly scales a given time `i` to screen position
 pub fn scale(ui: &Ui, i: f32, scale: f32) -> f32 {
     let width = ui.max_rect().size().x;
@@ -323,96 +454,544 @@ pub fn scale(ui: &Ui, i: f32, scale: f32) -> f32 {
     i * spacing
 }
 
+/// Names of the connected monitors, with the primary monitor first
+pub fn monitor_names() -> Vec<String> {
+    let mut monitors = Monitor::all().unwrap_or_default();
+    monitors.sort_by_key(|m| !m.is_primary());
+    monitors.iter().map(|m| m.name().to_string()).collect()
+}
+
+/// Bounds of the monitor with the given name in screen coordinates
+///
+/// Falls back to the primary monitor when there is no name or no monitor has it (e.g. it was unplugged)
+pub fn monitor_bounds(name: Option<&str>) -> Option<Rect> {
+    let monitors = Monitor::all().ok()?;
+    let monitor = name
+        .and_then(|name| monitors.iter().find(|m| m.name() == name))
+        .or_else(|| monitors.iter().find(|m| m.is_primary()))?;
+    Some(Rect::from_min_size(
+        Pos2::new(monitor.x() as f32, monitor.y() as f32),
+        vec2(monitor.width() as f32, monitor.height() as f32),
+    ))
+}
+
+/// Captures the screen with `grim`, which writes a PNG to stdout when given `-`
+fn grim_screenshot() -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+    let output = std::process::Command::new("grim")
+        .args(["-t", "png", "-"])
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).to_string());
+    }
+    let image = image::load_from_memory_with_format(&output.stdout, image::ImageFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(image.to_rgba8())
+}
+
+/// Captures the primary monitor with `xcap`
+fn xcap_screenshot() -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, String> {
+    let monitors = Monitor::all().map_err(|e| e.to_string())?;
+    let monitor = monitors
+        .iter()
+        .find(|m| m.is_primary())
+        .ok_or("There is no primary monitor")?;
+    let image = monitor.capture_image().map_err(|e| e.to_string())?;
+    ImageBuffer::from_raw(image.width(), image.height(), image.into_raw())
+        .ok_or("The screenshot has the wrong size for its dimensions".to_string())
+}
+
+/// Name and bounds of every monitor, in the positions used to simulate mouse moves
+pub fn monitor_rects() -> Vec<(String, Rect)> {
+    Monitor::all()
+        .unwrap_or_default()
+        .iter()
+        .map(|monitor| {
+            let bounds = Rect::from_min_size(
+                Pos2::new(monitor.x() as f32, monitor.y() as f32),
+                vec2(monitor.width() as f32, monitor.height() as f32),
+            );
+            (monitor.name().to_string(), bounds)
+        })
+        .collect()
+}
+
+// Looking up monitors is too slow to do for every recorded or played mouse move
+static MONITORS: Mutex<Vec<(String, Rect)>> = Mutex::new(Vec::new());
+
+// Bounds and scale factor of the primary monitor, which screenshots are taken of
+static PRIMARY_MONITOR: Mutex<Option<(Rect, f32)>> = Mutex::new(None);
+
+/// Looks up the monitors again, as they may have been rearranged since recording or playback last started
+pub fn refresh_monitors() {
+    *MONITORS.lock().unwrap() = monitor_rects();
+    let monitors = Monitor::all().unwrap_or_default();
+    *PRIMARY_MONITOR.lock().unwrap() = monitors.iter().find(|m| m.is_primary()).map(|monitor| {
+        let bounds = Rect::from_min_size(
+            Pos2::new(monitor.x() as f32, monitor.y() as f32),
+            vec2(monitor.width() as f32, monitor.height() as f32),
+        );
+        (bounds, monitor.scale_factor())
+    });
+}
+
+/// Scale factor of the primary monitor, e.g. 1.5 at 150% scaling
+pub fn display_scale() -> f32 {
+    PRIMARY_MONITOR
+        .lock()
+        .unwrap()
+        .map(|(_, scale)| scale)
+        .filter(|scale| *scale > 0.)
+        .unwrap_or(1.)
+}
+
+/// Size that every screenshot is stored at
+pub const SCREENSHOT_SIZE: Vec2 = vec2(1920., 1080.);
+
+/// Bounds of the primary monitor in pixels, the area screenshots cover
+fn primary_monitor_bounds() -> Rect {
+    PRIMARY_MONITOR
+        .lock()
+        .unwrap()
+        .map(|(bounds, _)| bounds)
+        .unwrap_or(Rect::from_min_size(Pos2::ZERO, SCREENSHOT_SIZE))
+}
+
+/// Maps a position in a screenshot to the position on the screen
+///
+/// Screenshots are captured in physical pixels while positions on the screen are scaled with the display,
+/// going through the bounds of the monitor keeps them lined up at any scale
+pub fn screenshot_to_screen(pos: Vec2) -> Vec2 {
+    let bounds = primary_monitor_bounds();
+    bounds.min.to_vec2() + pos / SCREENSHOT_SIZE * bounds.size()
+}
+
+/// Maps a position on the screen to the position in a screenshot, see [`screenshot_to_screen`]
+pub fn screen_to_screenshot(pos: Vec2) -> Vec2 {
+    let bounds = primary_monitor_bounds();
+    (pos - bounds.min.to_vec2()) / bounds.size() * SCREENSHOT_SIZE
+}
+
+/// Name and bounds of the monitor containing `pos`, as of the last [`refresh_monitors`]
+pub fn monitor_at(pos: Vec2) -> Option<(String, Rect)> {
+    MONITORS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(_, bounds)| bounds.contains(pos.to_pos2()))
+        .cloned()
+}
+
+/// Bounds of the monitor called `name`, as of the last [`refresh_monitors`]
+pub fn find_monitor(name: &str) -> Option<Rect> {
+    MONITORS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(monitor, _)| monitor == name)
+        .map(|(_, bounds)| *bounds)
+}
+
+/// Maps a position on the screen to where it is on a screenshot shown in `rect`
+pub fn screen_to_rect(pos: Vec2, rect: Rect) -> Pos2 {
+    rect.min + screen_to_screenshot(pos) / SCREENSHOT_SIZE * rect.size()
+}
+
+/// Which program screenshots are captured with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum CaptureBackendKind {
+    #[default]
+    Xcap,
+    /// Runs `grim`, for Wayland compositors where xcap can't capture the screen
+    Grim,
+}
+
+static CAPTURE_BACKEND: Mutex<CaptureBackendKind> = Mutex::new(CaptureBackendKind::Xcap);
+
+/// Sets the backend used by [`screenshot`] from now on
+pub fn set_capture_backend(kind: CaptureBackendKind) {
+    *CAPTURE_BACKEND.lock().unwrap() = kind;
+}
+
 /// Takes a screenshot of the primary monitor and returns it as a `Vec<u8>` in `Rgba` format
+///
+/// Screenshots are always resized to [`SCREENSHOT_SIZE`], whatever the resolution and scaling of the monitor
 pub fn screenshot() -> Option<Vec<u8>> {
-    let monitors = Monitor::all().unwrap();
-    let monitor = monitors.iter().find(|m| m.is_primary());
+    let kind = *CAPTURE_BACKEND.lock().unwrap();
+    let image = match kind {
+        CaptureBackendKind::Grim => grim_screenshot()
+            .map_err(|err| log::error!("Failed to take a screenshot with grim - {}", err))
+            .ok()?,
+        CaptureBackendKind::Xcap => xcap_screenshot()
+            .map_err(|err| log::error!("Failed to take a screenshot with xcap - {}", err))
+            .ok()?,
+    };
+    let (width, height) = (SCREENSHOT_SIZE.x as u32, SCREENSHOT_SIZE.y as u32);
+    match image.dimensions() == (width, height) {
+        true => Some(image.into_raw()),
+        false => Some(
+            image::imageops::resize(&image, width, height, image::imageops::FilterType::Triangle)
+                .into_raw(),
+        ),
+    }
+}
+
+/// How strongly screenshots are compressed when saved, stronger compression is slower
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+/// How recorded screenshots are stored, trading detail for memory and file size
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct ScreenshotQuality {
+    /// Fraction of [`SCREENSHOT_SIZE`] recorded screenshots are downscaled to, one of [`SCREENSHOT_SCALES`]
+    pub scale: f32,
+    pub compression: PngCompression,
+}
+
+impl Default for ScreenshotQuality {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            compression: PngCompression::Default,
+        }
+    }
+}
+
+/// Scales recorded screenshots can be stored at, all of which keep whole pixel sizes
+pub const SCREENSHOT_SCALES: [f32; 4] = [1., 0.75, 0.5, 0.25];
+
+static SCREENSHOT_QUALITY: Mutex<ScreenshotQuality> = Mutex::new(ScreenshotQuality {
+    scale: 1.,
+    compression: PngCompression::Default,
+});
+
+/// Sets how recorded screenshots are stored from now on
+pub fn set_screenshot_quality(quality: ScreenshotQuality) {
+    *SCREENSHOT_QUALITY.lock().unwrap() = quality;
+}
+
+/// How recorded screenshots are stored
+pub fn screenshot_quality() -> ScreenshotQuality {
+    *SCREENSHOT_QUALITY.lock().unwrap()
+}
+
+/// Width and height of a screenshot from the length of its raw RGBA data,
+/// as downscaled screenshots keep the aspect ratio of [`SCREENSHOT_SIZE`]
+pub fn screenshot_dimensions(screenshot: &[u8]) -> [usize; 2] {
+    let pixels = (screenshot.len() / 4) as f32;
+    let width = (pixels * SCREENSHOT_SIZE.x / SCREENSHOT_SIZE.y)
+        .sqrt()
+        .round();
+    [width as usize, (pixels / width).round() as usize]
+}
+
+/// Resizes a screenshot to `scale` times [`SCREENSHOT_SIZE`]
+pub fn resize_screenshot(screenshot: &[u8], scale: f32) -> Vec<u8> {
+    let [width, height] = screenshot_dimensions(screenshot);
+    let size = SCREENSHOT_SIZE * scale;
+    if [width, height] == [size.x as usize, size.y as usize] {
+        return screenshot.to_vec();
+    }
+    match ImageBuffer::<Rgba<u8>, &[u8]>::from_raw(width as u32, height as u32, screenshot) {
+        Some(image) => image::imageops::resize(
+            &image,
+            size.x as u32,
+            size.y as u32,
+            image::imageops::FilterType::Triangle,
+        )
+        .into_raw(),
+        None => screenshot.to_vec(),
+    }
+}
+
+/// Crops a `size` by `size` square centered on `center` out of a screenshot taken by [`screenshot`]
+///
+/// `center` is a position on the screen, the square is shifted to stay within the bounds of the screenshot
+pub fn crop_screenshot(
+    screenshot: &[u8],
+    center: Vec2,
+    size: u32,
+) -> Option<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    // Downscaled screenshots are brought back up to size so the target matches the screen during playback
+    let full_size = resize_screenshot(screenshot, 1.);
+    let image: ImageBuffer<Rgba<u8>, &[u8]> = ImageBuffer::from_raw(
+        SCREENSHOT_SIZE.x as u32,
+        SCREENSHOT_SIZE.y as u32,
+        full_size.as_slice(),
+    )?;
+    let center = screen_to_screenshot(center);
+    let size = size.min(image.width()).min(image.height());
+    let x = (center.x - size as f32 / 2.).clamp(0., (image.width() - size) as f32) as u32;
+    let y = (center.y - size as f32 / 2.).clamp(0., (image.height() - size) as f32) as u32;
+    Some(image::imageops::crop_imm(&image, x, y, size, size).to_image())
+}
+
+/// Plays the sound file at `path`, or a short tone if `path` is empty, without blocking
+pub fn play_sound(path: &str) {
+    let path = path.to_string();
+    let _ = std::thread::Builder::new()
+        .name("Sound Thread".to_owned())
+        .spawn(move || {
+            use rodio::Source;
+            let play = || -> Result<(), String> {
+                let (_stream, handle) =
+                    rodio::OutputStream::try_default().map_err(|e| e.to_string())?;
+                let sink = rodio::Sink::try_new(&handle).map_err(|e| e.to_string())?;
+                if path.is_empty() {
+                    sink.append(
+                        rodio::source::SineWave::new(880.)
+                            .take_duration(std::time::Duration::from_millis(200))
+                            .amplify(0.2),
+                    );
+                } else {
+                    let file = std::fs::File::open(&path).map_err(|e| e.to_string())?;
+                    let source = rodio::Decoder::new(std::io::BufReader::new(file))
+                        .map_err(|e| e.to_string())?;
+                    sink.append(source);
+                }
+                sink.sleep_until_end();
+                Ok(())
+            };
+            if let Err(err) = play() {
+                log::error!("Failed to play sound {:?} - {}", path, err);
+            }
+        });
+}
+
+/// Presses the keys together, then releases them in reverse order
+///
+/// Keys that were pressed are still released if the system refuses one of them, and the first error is returned
+fn simulate_shortcut(keys: &[rdev::Key]) -> Result<(), String> {
+    let mut result = Ok(());
+    let mut pressed = 0;
+    for key in keys {
+        result = input::simulate(&rdev::EventType::KeyPress(*key));
+        if result.is_err() {
+            break;
+        }
+        pressed += 1;
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    for key in keys[..pressed].iter().rev() {
+        let released = input::simulate(&rdev::EventType::KeyRelease(*key));
+        result = result.and(released);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    result
+}
+
+/// Position of the current virtual desktop on Windows and how many there are, counting from 1
+///
+/// Explorer keeps the IDs of the desktops in order in the registry, along with the ID of the current one
+fn windows_desktops() -> Option<(u32, u32)> {
+    let read = |value: &str| -> Option<Vec<u8>> {
+        let output = std::process::Command::new("reg")
+            .args([
+                "query",
+                r"HKCU\Software\Microsoft\Windows\CurrentVersion\Explorer\VirtualDesktops",
+                "/v",
+                value,
+            ])
+            .output()
+            .ok()?;
+        // Printed as "    <value>    REG_BINARY    <hex>"
+        let text = String::from_utf8_lossy(&output.stdout);
+        let hex = text
+            .lines()
+            .find_map(|line| line.split("REG_BINARY").nth(1))?
+            .trim();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    };
+    let ids = read("VirtualDesktopIDs")?;
+    let current = read("CurrentVirtualDesktop")?;
+    let position = ids.chunks(16).position(|id| id == current)?;
+    Some((position as u32 + 1, (ids.len() / 16) as u32))
+}
 
-    if let Some(monitor) = monitor {
-        let image: xcap::image::ImageBuffer<xcap::image::Rgba<u8>, Vec<u8>> =
-            monitor.capture_image().unwrap();
-        Some(image.into_raw())
+/// Switches to the virtual desktop numbered `desktop`, counting from 1
+///
+/// Windows only has shortcuts for the next and previous desktop, so it steps from the current desktop read from the registry.
+/// If that can't be read it goes left until it must be on the first desktop and then right.
+/// macOS uses the "Switch to Desktop N" shortcuts, which have to be enabled in its keyboard settings
+/// and only go up to 9. Elsewhere `wmctrl` is used, which works with most X11 window managers.
+pub fn switch_desktop(desktop: u32) -> Result<(), String> {
+    if desktop == 0 {
+        return Err("Desktops are numbered from 1".to_string());
+    }
+    if cfg!(target_os = "windows") {
+        use rdev::Key::{ControlLeft, LeftArrow, MetaLeft, RightArrow};
+        let current = match windows_desktops() {
+            Some((_, count)) if desktop > count => {
+                return Err(format!("There are only {} desktops", count));
+            }
+            Some((current, _)) => current,
+            None => {
+                // Going left past the first desktop does nothing, so this always ends up on the first one
+                for _ in 0..20 {
+                    simulate_shortcut(&[ControlLeft, MetaLeft, LeftArrow])?;
+                }
+                1
+            }
+        };
+        for _ in desktop..current {
+            simulate_shortcut(&[ControlLeft, MetaLeft, LeftArrow])?;
+        }
+        for _ in current..desktop {
+            simulate_shortcut(&[ControlLeft, MetaLeft, RightArrow])?;
+        }
+        Ok(())
+    } else if cfg!(target_os = "macos") {
+        let key = match desktop {
+            1 => rdev::Key::Num1,
+            2 => rdev::Key::Num2,
+            3 => rdev::Key::Num3,
+            4 => rdev::Key::Num4,
+            5 => rdev::Key::Num5,
+            6 => rdev::Key::Num6,
+            7 => rdev::Key::Num7,
+            8 => rdev::Key::Num8,
+            9 => rdev::Key::Num9,
+            _ => return Err("macOS can only switch to desktops 1 to 9".to_string()),
+        };
+        simulate_shortcut(&[rdev::Key::ControlLeft, key])
     } else {
-        None
+        let status = std::process::Command::new("wmctrl")
+            .args(["-s", &(desktop - 1).to_string()])
+            .status()
+            .map_err(|e| format!("Failed to run wmctrl: {}", e))?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(format!("wmctrl failed to switch to desktop {}", desktop)),
+        }
     }
 }
 
+static CALIBRATION: Mutex<Vec<MonitorCalibration>> = Mutex::new(Vec::new());
+
+/// Sets the multi-point calibration used by [`simulate_move`] from now on
+pub fn set_calibration(calibration: Vec<MonitorCalibration>) {
+    *CALIBRATION.lock().unwrap() = calibration;
+}
+
 /// Simulate a mouse move accounting for multiple monitors with the offset
-pub fn simulate_move(pos: &Vec2, offset: &Vec2) {
-    rdev::simulate(&rdev::EventType::MouseMove {
-        x: (pos.x + offset.x) as f64,
-        y: (pos.y + offset.y) as f64,
+///
+/// Positions on a monitor with a multi-point calibration use it instead of the offset
+pub fn simulate_move(pos: &Vec2, offset: &Vec2) -> Result<(), String> {
+    let target = CALIBRATION
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|calibration| calibration.bounds.contains(pos.to_pos2()))
+        .map(|calibration| calibration.apply(*pos))
+        .unwrap_or(*pos + *offset);
+    input::simulate(&rdev::EventType::MouseMove {
+        x: target.x as f64,
+        y: target.y as f64,
     })
-    .expect(
-        "Failed to simulate Mouse Movement (Probably due to a kernel level anti-cheat running)",
-    );
 }
 
 use opencv::core::{Mat, MatTraitConst, Point, VecN};
 
-/// Locates the center of a target image within a screenshot using OpenCV template matching
-pub fn template_match_opencv(target: DynamicImage) -> Option<Vec2> {
-    if let Some(screenshot) = screenshot() {
-        let screenshot: ImageBuffer<Rgba<u8>, Vec<u8>> =
-            ImageBuffer::from_vec(1920, 1080, screenshot).unwrap();
-        let screenshot = DynamicImage::ImageRgba8(screenshot);
-
-        let screenshot_vec = screenshot.to_luma8().to_vec();
-        let screenshot_mat = opencv::core::Mat::new_rows_cols_with_bytes::<u8>(
-            screenshot.height() as i32,
-            screenshot.width() as i32,
-            &screenshot_vec,
-        )
-        .unwrap();
-
-        let target_vec = target.to_luma8().to_vec();
-        let target_mat = opencv::core::Mat::new_rows_cols_with_bytes::<u8>(
-            target.height() as i32,
-            target.width() as i32,
-            &target_vec,
-        )
-        .unwrap();
+/// Highest normalized squared difference between a target image and the screen that still counts as a match,
+/// from `0.0` for an exact match up to `1.0`
+pub const MATCH_THRESHOLD: f64 = 0.1;
 
-        let mut output = Mat::default();
+/// Locates the center of a target image on the screen using OpenCV template matching
+///
+/// Returns `None` if there is no screenshot or the target isn't on screen, see [`locate_template`]
+pub fn template_match_opencv(target: DynamicImage) -> Option<Vec2> {
+    let screenshot = screenshot()?;
+    let screenshot = ImageBuffer::<Rgba<u8>, Vec<u8>>::from_vec(
+        SCREENSHOT_SIZE.x as u32,
+        SCREENSHOT_SIZE.y as u32,
+        screenshot,
+    )?;
+    locate_template(&DynamicImage::ImageRgba8(screenshot), &target).map(screenshot_to_screen)
+}
 
-        opencv::imgproc::match_template_def(&screenshot_mat, &target_mat, &mut output, 0).unwrap();
+/// Locates the center of `target` within `screenshot`, in pixels of the screenshot
+///
+/// The best match only counts if its normalized squared difference is within [`MATCH_THRESHOLD`],
+/// otherwise the target isn't there and `None` is returned.
+pub fn locate_template(screenshot: &DynamicImage, target: &DynamicImage) -> Option<Vec2> {
+    if target.width() > screenshot.width() || target.height() > screenshot.height() {
+        return None;
+    }
+    let screenshot_vec = screenshot.to_luma8().to_vec();
+    let screenshot_mat = opencv::core::Mat::new_rows_cols_with_bytes::<u8>(
+        screenshot.height() as i32,
+        screenshot.width() as i32,
+        &screenshot_vec,
+    )
+    .ok()?;
 
-        let mut min_val: f64 = 0.0;
-        let mut max_val: f64 = 0.0;
-        let mut min_loc: Point = Point::new(0, 0);
-        let mut max_loc: Point = Point::new(0, 0);
+    let target_vec = target.to_luma8().to_vec();
+    let target_mat = opencv::core::Mat::new_rows_cols_with_bytes::<u8>(
+        target.height() as i32,
+        target.width() as i32,
+        &target_vec,
+    )
+    .ok()?;
 
-        opencv::core::min_max_loc(
-            &output,
-            Some(&mut min_val),
-            Some(&mut max_val),
-            Some(&mut min_loc),
-            Some(&mut max_loc),
-            &Mat::default(),
-        )
-        .unwrap();
+    let mut output = Mat::default();
+    opencv::imgproc::match_template_def(
+        &screenshot_mat,
+        &target_mat,
+        &mut output,
+        opencv::imgproc::TM_SQDIFF_NORMED,
+    )
+    .ok()?;
 
-        let top_left = min_loc;
+    let mut min_val: f64 = 0.0;
+    let mut min_loc: Point = Point::new(0, 0);
+    opencv::core::min_max_loc(
+        &output,
+        Some(&mut min_val),
+        None,
+        Some(&mut min_loc),
+        None,
+        &Mat::default(),
+    )
+    .ok()?;
 
-        let pos = vec2(
-            (top_left.x as u32 + target.width() / 2) as f32,
-            (top_left.y as u32 + target.height() / 2) as f32,
-        );
-        // Todo(addis): detect when it failed to find the target image
-        return Some(pos);
+    // The lowest difference is the best match, which may still be nothing like the target
+    if !min_val.is_finite() || min_val > MATCH_THRESHOLD {
+        log::debug!("Best match for the target differs by {:.3}", min_val);
+        return None;
     }
-
-    None
+    Some(vec2(
+        (min_loc.x as u32 + target.width() / 2) as f32,
+        (min_loc.y as u32 + target.height() / 2) as f32,
+    ))
 }
 
 /// Calculates the percentage difference between two images
 ///
 /// 0% is an exact match
+/// `vec1` is resized to the size of `vec2` if the screenshots were stored at different scales
 pub fn image_dif_opencv(vec1: &Vec<u8>, vec2: &Vec<u8>) -> f32 {
-    let src1 =
-        opencv::core::Mat::new_rows_cols_with_bytes::<VecN<u8, 4>>(1920, 1080, &vec1).unwrap();
-    let src2 =
-        opencv::core::Mat::new_rows_cols_with_bytes::<VecN<u8, 4>>(1920, 1080, &vec2).unwrap();
+    let [width, height] = screenshot_dimensions(vec2);
+    let vec1 = resize_screenshot(vec1, width as f32 / SCREENSHOT_SIZE.x);
+    let src1 = opencv::core::Mat::new_rows_cols_with_bytes::<VecN<u8, 4>>(
+        height as i32,
+        width as i32,
+        &vec1,
+    )
+    .unwrap();
+    let src2 = opencv::core::Mat::new_rows_cols_with_bytes::<VecN<u8, 4>>(
+        height as i32,
+        width as i32,
+        &vec2,
+    )
+    .unwrap();
 
     let mut src1x = Mat::default();
     let mut src2x = Mat::default();
@@ -427,3 +1006,39 @@ pub fn image_dif_opencv(vec1: &Vec<u8>, vec2: &Vec<u8>) -> f32 {
     let size = diff.size().unwrap();
     return (result as f32 / size.area() as f32) * 100.;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image of noise, different for each `seed`, so a template cut from it only matches in one place
+    fn noise(width: u32, height: u32, seed: u32) -> DynamicImage {
+        let mut state = seed;
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |_, _| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let value = (state >> 24) as u8;
+            Rgba([value, value, value, 255])
+        }))
+    }
+
+    #[test]
+    fn locates_a_template_on_screen() {
+        let screenshot = noise(200, 120, 1);
+        let target = screenshot.crop_imm(50, 30, 20, 10);
+        assert_eq!(locate_template(&screenshot, &target), Some(vec2(60., 35.)));
+    }
+
+    #[test]
+    fn misses_a_template_not_on_screen() {
+        let screenshot = noise(200, 120, 1);
+        let target = noise(20, 10, 2);
+        assert_eq!(locate_template(&screenshot, &target), None);
+    }
+
+    #[test]
+    fn misses_a_template_larger_than_the_screen() {
+        let screenshot = noise(20, 10, 1);
+        let target = noise(40, 20, 1);
+        assert_eq!(locate_template(&screenshot, &target), None);
+    }
+}